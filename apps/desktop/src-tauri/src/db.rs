@@ -1,5 +1,6 @@
 use sqlx::{sqlite::SqlitePool, Row};
 use agent_core::HistoryRepository;
+use terminal_manager::history::CommandRecord;
 use anyhow::Result;
 use async_trait::async_trait;
 use serde_json::Value;
@@ -64,4 +65,25 @@ impl HistoryRepository for SqliteHistory {
 
         Ok(messages)
     }
+
+    async fn add_command(&self, session_id: &str, record: &CommandRecord) -> Result<()> {
+        sqlx::query("INSERT INTO commands (session_id, record) VALUES ($1, $2)")
+            .bind(session_id.to_string())
+            .bind(serde_json::to_string(record)?)
+            .execute(&self.pool)
+            .await?;
+        Ok(())
+    }
+
+    async fn get_commands(&self, session_id: &str) -> Result<Vec<CommandRecord>> {
+        let rows = sqlx::query("SELECT record FROM commands WHERE session_id = $1 ORDER BY id ASC")
+            .bind(session_id.to_string())
+            .fetch_all(&self.pool)
+            .await?;
+
+        // Skip rows an older build wrote in a different shape rather than failing the lot
+        Ok(rows.into_iter()
+            .filter_map(|row| serde_json::from_str(&row.get::<String, _>("record")).ok())
+            .collect())
+    }
 }
@@ -2,7 +2,7 @@ use tauri_specta::{collect_commands, Builder};
 use specta_typescript::Typescript;
 use std::sync::{Arc, Mutex};
 use tauri::{State, Window, Emitter, Manager};
-use agent_core::{AgentSession, spawn_agent_loop, LLMConfig as AgentLLMConfig};
+use agent_core::{SessionManager, spawn_agent_loop, pick_valid_choice, LLMConfig as AgentLLMConfig, RoleConfig as AgentRoleConfig, RunLimits as AgentRunLimits};
 use common::WorkspaceState;
 use terminal_manager::{common::TerminalState};
 
@@ -10,31 +10,78 @@ mod db;
 use db::SqliteHistory;
 use sqlx::sqlite::SqlitePoolOptions;
 use std::path::{Path, PathBuf};
+use std::collections::HashMap;
+use futures::StreamExt;
 
 // Protocol Imports
 use irongraph_protocol::{
     FileEntry as ApiFileEntry,
     FileContent as ApiFileContent,
+    FileSkeleton as ApiFileSkeleton,
+    OutlineItem as ApiOutlineItem,
     FsError as ApiFsError,
+    FsChange as ApiFsChange,
+    SearchOptions as ApiSearchOptions,
+    SearchMatch as ApiSearchMatch,
+    SyntaxValidation as ApiSyntaxValidation,
+    WorkspaceStats as ApiWorkspaceStats,
+    LanguageStats as ApiLanguageStats,
+    PathSize as ApiPathSize,
+    GitChange as ApiGitChange,
+    GitFileStatus as ApiGitFileStatus,
+    GitStatus as ApiGitStatus,
+    GraphFormat as ApiGraphFormat,
+    GraphNode as ApiGraphNode,
+    GraphEdge as ApiGraphEdge,
+    DependencyGraph as ApiDependencyGraph,
+    GraphExport as ApiGraphExport,
     CommandOutput as ApiCommandOutput,
+    CommandRecord as ApiCommandRecord,
+    CommandSource as ApiCommandSource,
+    TerminalOutput as ApiTerminalOutput,
+    TerminalClosed as ApiTerminalClosed,
+    TerminalSession as ApiTerminalSession,
+    Interrupted as ApiInterrupted,
     ShellError as ApiShellError,
     UpdateProfileReq as ApiUpdateProfileReq,
     UserProfile as ApiUserProfile,
     LLMRequest as ApiLLMRequest,
     LLMResponse as ApiLLMResponse,
+    Choice as ApiChoice,
     LLMConfig as ApiLLMConfig,
+    ChatStreamEvent as ApiChatStreamEvent,
+    ModelInfo as ApiModelInfo,
+    RateLimit as ApiRateLimit,
+    ResponseCacheStats as ApiResponseCacheStats,
     Message as ApiMessage,
-    ToolCall as ApiToolCall
+    ImageSource as ApiImageSource,
+    ToolCall as ApiToolCall,
+    Page,
+    PageRequest,
+    SessionMessage as ApiSessionMessage,
+    ExperimentReport as ApiExperimentReport,
+    VariantOutcome as ApiVariantOutcome,
+    FileChange as ApiFileChange
 };
 
 // Logic Imports
 use workspace_manager::{
     FileEntry as LogicFileEntry,
     FileContent as LogicFileContent,
-    FsError as LogicFsError
+    FsError as LogicFsError,
+    FsChange as LogicFsChange,
+    SearchOptions as LogicSearchOptions,
+    SearchMatch as LogicSearchMatch,
+    WorkspaceStats as LogicWorkspaceStats,
+    GitChange as LogicGitChange,
+    GitStatus as LogicGitStatus,
+    WorkspaceWatcher
 };
 use terminal_manager::{
     CommandOutput as LogicCommandOutput,
+    Interrupted as LogicInterrupted,
+    SessionInfo as LogicSessionInfo,
+    ShellExit as LogicShellExit,
     ShellError as LogicShellError
 };
 use llm_gateway::{
@@ -42,12 +89,32 @@ use llm_gateway::{
     LLMResponse as LogicLLMResponse,
     LLMConfig as LogicLLMConfig,
     Message as LogicMessage,
-    ToolCall as LogicToolCall
+    ModelInfo as LogicModelInfo,
+    ImageSource as LogicImageSource,
+    PromptCache as LogicPromptCache,
+    OpenRouterOptions as LogicOpenRouterOptions,
+    StreamEvent as LogicStreamEvent,
+    CancellationToken,
+    RateLimit as LogicRateLimit,
+    RetryPolicy as LogicRetryPolicy,
+    ToolCall as LogicToolCall,
+    ToolDefinition as LogicToolDefinition
 };
 use shared_db::UserProfile as LogicUserProfile;
+use agent_core::experiment::{ExperimentReport as LogicExperimentReport, ExperimentVariant};
+use agent_core::changes::FileChange as LogicFileChange;
+use terminal_manager::history::{CommandRecord as LogicCommandRecord, CommandSource as LogicCommandSource};
 
 const OPENROUTER_KEY: &str = "";
 
+// Watcher for the current workspace root; replaced when the root changes
+#[derive(Default)]
+struct WatcherState(Mutex<Option<WorkspaceWatcher>>);
+
+// Cancellation handles for in-flight send_chat_stream calls, by request id
+#[derive(Default)]
+struct ChatStreams(Mutex<HashMap<String, CancellationToken>>);
+
 // ============================================================================
 // Mappers
 // ============================================================================
@@ -58,6 +125,12 @@ fn map_fs_error(e: LogicFsError) -> ApiFsError {
         LogicFsError::SecurityViolation => ApiFsError::SecurityViolation,
         LogicFsError::InvalidPath => ApiFsError::InvalidPath,
         LogicFsError::Syntax(msg) => ApiFsError::Syntax(msg),
+        LogicFsError::Edit(msg) => ApiFsError::Edit(msg),
+        LogicFsError::BinaryFile { size, preview } => ApiFsError::BinaryFile {
+            size_kb: size.div_ceil(1024).min(u32::MAX as u64) as u32,
+            preview,
+        },
+        LogicFsError::Conflict(path) => ApiFsError::Conflict(path),
     }
 }
 
@@ -66,6 +139,7 @@ fn map_file_entry(e: LogicFileEntry) -> ApiFileEntry {
         path: e.path.to_string_lossy().to_string(),
         name: e.name,
         is_dir: e.is_dir,
+        is_binary: e.is_binary,
         children: e.children.map(|c| c.into_iter().map(map_file_entry).collect()),
     }
 }
@@ -77,11 +151,107 @@ fn map_file_content(c: LogicFileContent) -> ApiFileContent {
     }
 }
 
+fn map_search_options(o: ApiSearchOptions) -> LogicSearchOptions {
+    LogicSearchOptions {
+        before_context: o.before_context as usize,
+        after_context: o.after_context as usize,
+        include: o.include,
+        exclude: o.exclude,
+        case_insensitive: o.case_insensitive,
+        fixed_string: o.fixed_string,
+        max_results: o.max_results.map(|n| n as usize),
+        multiline: o.multiline,
+    }
+}
+
+fn map_search_match(m: LogicSearchMatch) -> ApiSearchMatch {
+    ApiSearchMatch {
+        path: m.path.to_string_lossy().to_string(),
+        line: m.line as u32,
+        end_line: m.end_line as u32,
+        column: m.column as u32,
+        text: m.text,
+        before: m.before,
+        after: m.after,
+    }
+}
+
+fn map_workspace_stats(s: LogicWorkspaceStats) -> ApiWorkspaceStats {
+    let kb = |bytes: u64| bytes.div_ceil(1024).min(u32::MAX as u64) as u32;
+    ApiWorkspaceStats {
+        files: s.files as u32,
+        lines: s.lines as u32,
+        size_kb: kb(s.bytes),
+        languages: s.languages.into_iter().map(|l| ApiLanguageStats {
+            language: l.language,
+            files: l.files as u32,
+            lines: l.lines as u32,
+        }).collect(),
+        largest_files: s.largest_files.into_iter().map(|f| ApiPathSize {
+            path: f.path.to_string_lossy().to_string(),
+            files: 1,
+            size_kb: kb(f.bytes),
+        }).collect(),
+        directories: s.directories.into_iter().map(|d| ApiPathSize {
+            path: d.path.to_string_lossy().to_string(),
+            files: d.files as u32,
+            size_kb: kb(d.bytes),
+        }).collect(),
+    }
+}
+
+fn map_git_change(c: LogicGitChange) -> ApiGitChange {
+    match c {
+        LogicGitChange::Added => ApiGitChange::Added,
+        LogicGitChange::Modified => ApiGitChange::Modified,
+        LogicGitChange::Deleted => ApiGitChange::Deleted,
+        LogicGitChange::Renamed => ApiGitChange::Renamed,
+        LogicGitChange::TypeChange => ApiGitChange::TypeChange,
+        LogicGitChange::Untracked => ApiGitChange::Untracked,
+        LogicGitChange::Conflicted => ApiGitChange::Conflicted,
+    }
+}
+
+fn map_git_status(s: LogicGitStatus) -> ApiGitStatus {
+    ApiGitStatus {
+        branch: s.branch,
+        files: s.files.into_iter().map(|f| ApiGitFileStatus {
+            path: f.path.to_string_lossy().to_string(),
+            index: f.index.map(map_git_change),
+            worktree: f.worktree.map(map_git_change),
+        }).collect(),
+    }
+}
+
+fn map_dependency_graph(g: &workspace_manager::DependencyGraph) -> ApiDependencyGraph {
+    let files = g.files();
+    let ids: HashMap<&PathBuf, u32> = files.iter().enumerate().map(|(i, f)| (f, i as u32)).collect();
+    ApiDependencyGraph {
+        nodes: files.iter().enumerate().map(|(i, f)| ApiGraphNode {
+            id: i as u32,
+            path: f.to_string_lossy().to_string(),
+            language: workspace_manager::language_of(f).to_string(),
+        }).collect(),
+        edges: g.edges().iter().map(|(from, to)| ApiGraphEdge { from: ids[from], to: ids[to] }).collect(),
+    }
+}
+
+fn map_fs_change(c: &LogicFsChange) -> ApiFsChange {
+    let strings = |paths: &[PathBuf]| paths.iter().map(|p| p.to_string_lossy().to_string()).collect();
+    ApiFsChange {
+        created: strings(&c.created),
+        modified: strings(&c.modified),
+        removed: strings(&c.removed),
+    }
+}
+
 fn map_shell_error(e: LogicShellError) -> ApiShellError {
     match e {
         LogicShellError::Io(msg) => ApiShellError::Io(msg),
         LogicShellError::NotFound(msg) => ApiShellError::NotFound(msg),
         LogicShellError::Pty(msg) => ApiShellError::Pty(msg),
+        LogicShellError::Timeout(secs) => ApiShellError::Timeout(secs.min(u32::MAX as u64) as u32),
+        LogicShellError::Terminated(how) => ApiShellError::Terminated(how),
     }
 }
 
@@ -102,33 +272,149 @@ fn map_user_profile(p: LogicUserProfile) -> ApiUserProfile {
 }
 
 // LLM Mappers - Deep Mapping required
+fn map_message_to_logic(m: ApiMessage) -> LogicMessage {
+    LogicMessage {
+        role: m.role,
+        content: m.content,
+        images: m.images.into_iter().map(|i| match i {
+            ApiImageSource::Url { url } => LogicImageSource::Url { url },
+            ApiImageSource::Base64 { media_type, data } => LogicImageSource::Base64 { media_type, data },
+        }).collect(),
+        tool_call_id: m.tool_call_id,
+        name: m.name,
+        tool_calls: m.tool_calls.map(|t| t.into_iter().map(|tc| LogicToolCall {
+            id: tc.id,
+            name: tc.name,
+            arguments: tc.arguments,
+            raw_arguments: tc.raw_arguments,
+        }).collect()),
+    }
+}
+
+fn map_llm_config_to_logic(config: ApiLLMConfig) -> LogicLLMConfig {
+    LogicLLMConfig {
+        api_key: config.api_key,
+        base_url: config.base_url,
+        model: config.model,
+        temperature: config.temperature,
+        max_tokens: config.max_tokens,
+        top_p: config.top_p,
+        stop: config.stop,
+        frequency_penalty: config.frequency_penalty,
+        presence_penalty: config.presence_penalty,
+        n: config.n,
+        reasoning_effort: None,
+        provider: config.provider,
+        retry: config.max_retries
+            .map(|n| LogicRetryPolicy { max_attempts: n + 1, ..LogicRetryPolicy::default() })
+            .unwrap_or_default(),
+        proxy: config.proxy,
+        no_proxy: config.no_proxy,
+        openrouter: config.openrouter.map(|o| LogicOpenRouterOptions {
+            order: o.order,
+            only: o.only,
+            ignore: o.ignore,
+            allow_fallbacks: o.allow_fallbacks,
+            fallback_models: o.fallback_models,
+            transforms: o.transforms,
+            referer: o.referer,
+            title: o.title,
+        }),
+        fallback_base_urls: config.fallback_base_urls,
+    }
+}
+
 fn map_llm_req_to_logic(req: ApiLLMRequest) -> LogicLLMRequest {
     LogicLLMRequest {
-        messages: req.messages.into_iter().map(|m| LogicMessage {
-            role: m.role,
-            content: m.content,
-        }).collect(),
-        config: LogicLLMConfig {
-            api_key: req.config.api_key,
-            base_url: req.config.base_url,
-            model: req.config.model,
-            temperature: req.config.temperature,
-        }
+        messages: req.messages.into_iter().map(map_message_to_logic).collect(),
+        config: map_llm_config_to_logic(req.config),
+        tools: req.tools.map(|t| t.into_iter().map(|d| LogicToolDefinition {
+            name: d.name,
+            description: d.description,
+            parameters: d.parameters,
+        }).collect()),
+        tool_choice: req.tool_choice,
+        prompt_cache: req.prompt_cache.map(|c| LogicPromptCache {
+            system: c.system,
+            history: c.history,
+        }),
     }
 }
 
+fn map_tool_calls_to_api(calls: Option<Vec<LogicToolCall>>) -> Option<Vec<ApiToolCall>> {
+    calls.map(|t| t.into_iter().map(|tc| ApiToolCall {
+        id: tc.id,
+        name: tc.name,
+        arguments: tc.arguments,
+        raw_arguments: tc.raw_arguments,
+    }).collect())
+}
+
 fn map_llm_res_to_api(res: LogicLLMResponse) -> ApiLLMResponse {
     ApiLLMResponse {
         role: res.role,
         content: res.content,
-        tool_calls: res.tool_calls.map(|t| t.into_iter().map(|tc| ApiToolCall {
-            name: tc.name,
-            arguments: tc.arguments,
-        }).collect()),
+        tool_calls: map_tool_calls_to_api(res.tool_calls),
         usage: res.usage,
+        reasoning: res.reasoning,
+        choices: res.choices.into_iter().map(|c| ApiChoice {
+            content: c.content,
+            tool_calls: map_tool_calls_to_api(c.tool_calls),
+            reasoning: c.reasoning,
+        }).collect(),
+        endpoint: res.endpoint,
     }
 }
 
+fn map_stream_event(e: LogicStreamEvent) -> ApiChatStreamEvent {
+    match e {
+        LogicStreamEvent::Token(t) => ApiChatStreamEvent::Token(t),
+        LogicStreamEvent::ToolStart(name) => ApiChatStreamEvent::ToolStart(name),
+        LogicStreamEvent::ToolArg(name, value) => ApiChatStreamEvent::ToolArg { name, value },
+        LogicStreamEvent::ToolEnd => ApiChatStreamEvent::ToolEnd,
+        LogicStreamEvent::Retrying(attempt, delay_ms, reason) => ApiChatStreamEvent::Retrying {
+            attempt,
+            delay_ms: delay_ms.min(u32::MAX as u64) as u32,
+            reason,
+        },
+        LogicStreamEvent::Usage { prompt_tokens, completion_tokens } => ApiChatStreamEvent::Usage { prompt_tokens, completion_tokens },
+        LogicStreamEvent::Reasoning(t) => ApiChatStreamEvent::Reasoning(t),
+        LogicStreamEvent::Cancelled => ApiChatStreamEvent::Cancelled,
+        LogicStreamEvent::Error(e) => ApiChatStreamEvent::Error(e),
+        LogicStreamEvent::Done => ApiChatStreamEvent::Done,
+    }
+}
+
+fn map_model_info(model: LogicModelInfo) -> ApiModelInfo {
+    ApiModelInfo {
+        id: model.id,
+        name: model.name,
+        context_length: model.context_length,
+        prompt_price: model.prompt_price,
+        completion_price: model.completion_price,
+    }
+}
+
+fn map_history_message(index: usize, msg: serde_json::Value) -> ApiSessionMessage {
+    let role = msg.get("role").and_then(|v| v.as_str()).unwrap_or("user").to_string();
+    let metadata_str = |key: &str| msg.get("metadata")
+        .and_then(|m| m.get(key))
+        .and_then(|v| v.as_str())
+        .map(|s| s.to_string());
+    let persona = metadata_str("persona");
+    let reasoning = metadata_str("reasoning");
+    let content = match msg.get("content").and_then(|v| v.as_str()) {
+        Some(c) => c.to_string(),
+        None => msg.to_string(),
+    };
+    ApiSessionMessage {
+        index: index as u32,
+        role,
+        content,
+        persona,
+        reasoning,
+    }
+}
 
 // ============================================================================
 // Commands
@@ -138,45 +424,7 @@ fn map_llm_res_to_api(res: LogicLLMResponse) -> ApiLLMResponse {
 #[specta::specta]
 async fn list_files(state: State<'_, WorkspaceState>, dir_path: Option<String>) -> Result<Vec<ApiFileEntry>, ApiFsError> {
     let root = state.0.lock().map_err(|_| ApiFsError::Io("Lock poison".into()))?.clone();
-    let start_dir = if let Some(sub) = dir_path {
-         // Re-implement path validation call or just pass string?
-         // Logic `build_file_tree` takes Path.
-         // We need to resolve start_dir relative to root securely.
-         // Wait, `workspace_manager::validate_path` is private.
-         // I should have exposed `validate_path` or made a helper in `workspace_manager`.
-         // Current `workspace_manager` exposes `read_file_internal` which does validation.
-         // `build_file_tree` takes `current_dir: &Path`.
-         // Let's assume input `dir_path` is relative to root.
-         // Ideally `workspace_manager` should handle the safe resolution.
-         // I'll assume for now `build_file_tree` expects absolute path but checks safety?
-         // No, `build_file_tree` in `workspace_manager` assumes `current_dir` is valid.
-
-         // Fix: I need to use `workspace_manager` to resolve the path SAFELY.
-         // But `validate_path` is private.
-         // I will trust the logic in `workspace_manager::read_file_internal` style.
-         // Actually, `workspace_manager::build_file_tree` iterates `current_dir`.
-         // I need to resolve `root.join(dir_path)` securely.
-         // I will modify `workspace_manager` to expose a safe `resolve_path` or `list_files_safe`.
-         // OR I can duplicate the simple check here.
-
-         // Actually, I should update `workspace_manager` to expose a function `list_files_safe(root, relative_path)`.
-         // But for now, to avoid context switching back and forth too much:
-         // I'll implement basic check here or use `std::fs::canonicalize`.
-         let p = root.join(sub);
-         if let Ok(canon) = p.canonicalize() {
-             if !canon.starts_with(&root) {
-                 return Err(ApiFsError::SecurityViolation);
-             }
-             canon
-         } else {
-             // Does not exist?
-             return Err(ApiFsError::InvalidPath);
-         }
-    } else {
-         root.clone()
-    };
-
-    workspace_manager::build_file_tree(&root, &start_dir)
+    workspace_manager::list_files_safe(&root, dir_path.as_deref().unwrap_or(""))
         .map_err(map_fs_error)
         .map(|entries| entries.into_iter().map(map_file_entry).collect())
 }
@@ -192,19 +440,158 @@ async fn read_file(state: State<'_, WorkspaceState>, file_path: String) -> Resul
 
 #[tauri::command]
 #[specta::specta]
-async fn write_file(state: State<'_, WorkspaceState>, file_path: String, content: String) -> Result<ApiFileContent, ApiFsError> {
+async fn set_syntax_validation(config: ApiSyntaxValidation) -> Result<(), String> {
+    workspace_manager::set_validation_config(workspace_manager::ValidationConfig {
+        rust: config.rust,
+        javascript: config.javascript,
+        python: config.python,
+        json: config.json,
+        toml: config.toml,
+        yaml: config.yaml,
+    });
+    Ok(())
+}
+
+// Caps how much of a file one agent read_file call returns
+#[tauri::command]
+#[specta::specta]
+async fn set_max_read_size(kb: u32) -> Result<(), String> {
+    workspace_manager::set_max_read_bytes(kb as usize * 1024);
+    Ok(())
+}
+
+// Returns where the file was moved inside the workspace trash
+#[tauri::command]
+#[specta::specta]
+async fn delete_file(state: State<'_, WorkspaceState>, file_path: String) -> Result<String, ApiFsError> {
+     let root = state.0.lock().map_err(|_| ApiFsError::Io("Lock poison".into()))?.clone();
+     workspace_manager::delete_file_internal(&root, file_path)
+        .map_err(map_fs_error)
+        .map(|p| p.to_string_lossy().to_string())
+}
+
+#[tauri::command]
+#[specta::specta]
+async fn restore_backup(state: State<'_, WorkspaceState>, file_path: String) -> Result<ApiFileContent, ApiFsError> {
      let root = state.0.lock().map_err(|_| ApiFsError::Io("Lock poison".into()))?.clone();
-     workspace_manager::write_file_internal(&root, file_path, content)
+     workspace_manager::restore_backup_internal(&root, file_path)
         .map_err(map_fs_error)
         .map(map_file_content)
 }
 
 #[tauri::command]
 #[specta::specta]
-async fn search_code(state: State<'_, WorkspaceState>, query: String) -> Result<Vec<String>, ApiFsError> {
+async fn create_directory(state: State<'_, WorkspaceState>, dir_path: String) -> Result<(), ApiFsError> {
+     let root = state.0.lock().map_err(|_| ApiFsError::Io("Lock poison".into()))?.clone();
+     workspace_manager::create_directory_internal(&root, dir_path)
+        .map_err(map_fs_error)
+        .map(|_| ())
+}
+
+// Returns the files whose imports were rewritten
+#[tauri::command]
+#[specta::specta]
+async fn move_file(state: State<'_, WorkspaceState>, from: String, to: String) -> Result<Vec<String>, ApiFsError> {
      let root = state.0.lock().map_err(|_| ApiFsError::Io("Lock poison".into()))?.clone();
-     workspace_manager::search_code_internal(&root, &query)
+     workspace_manager::move_file_internal(&root, from, to)
         .map_err(map_fs_error)
+        .map(|outcome| outcome.updated.iter().map(|p| p.to_string_lossy().to_string()).collect())
+}
+
+#[tauri::command]
+#[specta::specta]
+async fn write_file(state: State<'_, WorkspaceState>, file_path: String, content: String, skip_validation: Option<bool>) -> Result<ApiFileContent, ApiFsError> {
+     let root = state.0.lock().map_err(|_| ApiFsError::Io("Lock poison".into()))?.clone();
+     workspace_manager::write_file_internal(&root, file_path, content, skip_validation.unwrap_or(false))
+        .map_err(map_fs_error)
+        .map(map_file_content)
+}
+
+// Unified diff of what write_file would change; nothing is written
+#[tauri::command]
+#[specta::specta]
+async fn preview_write(state: State<'_, WorkspaceState>, file_path: String, content: String, skip_validation: Option<bool>) -> Result<String, ApiFsError> {
+     let root = state.0.lock().map_err(|_| ApiFsError::Io("Lock poison".into()))?.clone();
+     workspace_manager::preview_write(&root, file_path, content, skip_validation.unwrap_or(false))
+        .map_err(map_fs_error)
+}
+
+// Best matches first
+#[tauri::command]
+#[specta::specta]
+async fn fuzzy_find_files(state: State<'_, WorkspaceState>, query: String, limit: Option<u32>) -> Result<Vec<String>, ApiFsError> {
+    let root = state.0.lock().map_err(|_| ApiFsError::Io("Lock poison".into()))?.clone();
+    let limit = limit.map_or(workspace_manager::DEFAULT_FUZZY_LIMIT, |l| l as usize);
+    let matches = workspace_manager::fuzzy_find_files(&root, &query, limit);
+    Ok(matches.into_iter().map(|m| m.path.to_string_lossy().to_string()).collect())
+}
+
+#[tauri::command]
+#[specta::specta]
+async fn search_code(state: State<'_, WorkspaceState>, query: String, options: Option<ApiSearchOptions>, page: Option<PageRequest>) -> Result<Page<ApiSearchMatch>, ApiFsError> {
+     let root = state.0.lock().map_err(|_| ApiFsError::Io("Lock poison".into()))?.clone();
+     let page = page.unwrap_or_default();
+     let options = options.map(map_search_options).unwrap_or_default();
+     workspace_manager::search_code_internal(&root, &query, &options)
+        .map_err(map_fs_error)
+        .map(|matches| Page::from_vec(matches, &page).map(map_search_match))
+}
+
+#[tauri::command]
+#[specta::specta]
+async fn workspace_stats(state: State<'_, WorkspaceState>) -> Result<ApiWorkspaceStats, ApiFsError> {
+    let root = state.0.lock().map_err(|_| ApiFsError::Io("Lock poison".into()))?.clone();
+    Ok(map_workspace_stats(workspace_manager::workspace_stats(&root)))
+}
+
+#[tauri::command]
+#[specta::specta]
+async fn git_status(state: State<'_, WorkspaceState>) -> Result<ApiGitStatus, ApiFsError> {
+    let root = state.0.lock().map_err(|_| ApiFsError::Io("Lock poison".into()))?.clone();
+    workspace_manager::git_status(&root).map(map_git_status).map_err(map_fs_error)
+}
+
+#[tauri::command]
+#[specta::specta]
+async fn git_diff(state: State<'_, WorkspaceState>, file_path: Option<String>, staged: Option<bool>) -> Result<String, ApiFsError> {
+    let root = state.0.lock().map_err(|_| ApiFsError::Io("Lock poison".into()))?.clone();
+    workspace_manager::git_diff(&root, file_path.as_deref(), staged.unwrap_or(false)).map_err(map_fs_error)
+}
+
+fn path_strings(paths: Vec<PathBuf>) -> Vec<String> {
+    paths.into_iter().map(|p| p.to_string_lossy().to_string()).collect()
+}
+
+#[tauri::command]
+#[specta::specta]
+async fn graph_dependencies(state: State<'_, WorkspaceState>, file_path: String) -> Result<Vec<String>, ApiFsError> {
+    let root = state.0.lock().map_err(|_| ApiFsError::Io("Lock poison".into()))?.clone();
+    Ok(path_strings(workspace_manager::graph::with_graph(&root, |g| g.dependencies_of(Path::new(&file_path)))))
+}
+
+#[tauri::command]
+#[specta::specta]
+async fn graph_dependents(state: State<'_, WorkspaceState>, file_path: String) -> Result<Vec<String>, ApiFsError> {
+    let root = state.0.lock().map_err(|_| ApiFsError::Io("Lock poison".into()))?.clone();
+    Ok(path_strings(workspace_manager::graph::with_graph(&root, |g| g.dependents_of(Path::new(&file_path)))))
+}
+
+#[tauri::command]
+#[specta::specta]
+async fn graph_cycles(state: State<'_, WorkspaceState>) -> Result<Vec<Vec<String>>, ApiFsError> {
+    let root = state.0.lock().map_err(|_| ApiFsError::Io("Lock poison".into()))?.clone();
+    let cycles = workspace_manager::graph::with_graph(&root, |g| g.cycles());
+    Ok(cycles.into_iter().map(path_strings).collect())
+}
+
+#[tauri::command]
+#[specta::specta]
+async fn export_graph(state: State<'_, WorkspaceState>, format: ApiGraphFormat) -> Result<ApiGraphExport, ApiFsError> {
+    let root = state.0.lock().map_err(|_| ApiFsError::Io("Lock poison".into()))?.clone();
+    Ok(workspace_manager::graph::with_graph(&root, |g| match format {
+        ApiGraphFormat::Dot => ApiGraphExport::Dot(g.to_dot()),
+        ApiGraphFormat::Json => ApiGraphExport::Json(map_dependency_graph(g)),
+    }))
 }
 
 #[tauri::command]
@@ -217,9 +604,35 @@ async fn read_skeleton(state: State<'_, WorkspaceState>, file_path: String) -> R
 
 #[tauri::command]
 #[specta::specta]
-async fn run_command(state: State<'_, WorkspaceState>, program: String, args: Vec<String>) -> Result<ApiCommandOutput, ApiShellError> {
+async fn read_skeletons(state: State<'_, WorkspaceState>, file_paths: Vec<String>) -> Result<Vec<ApiFileSkeleton>, ApiFsError> {
+    let root = state.0.lock().map_err(|_| ApiFsError::Io("Lock poison".into()))?.clone();
+    Ok(workspace_manager::read_skeletons_internal(&root, file_paths).into_iter().map(|(path, skeleton)| match skeleton {
+        Ok(s) => ApiFileSkeleton { path, skeleton: Some(s), error: None },
+        Err(e) => ApiFileSkeleton { path, skeleton: None, error: Some(e.to_string()) },
+    }).collect())
+}
+
+#[tauri::command]
+#[specta::specta]
+async fn outline(state: State<'_, WorkspaceState>, file_path: String) -> Result<Vec<ApiOutlineItem>, ApiFsError> {
+    let root = state.0.lock().map_err(|_| ApiFsError::Io("Lock poison".into()))?.clone();
+    let items = workspace_manager::outline_internal(&root, file_path).map_err(map_fs_error)?;
+    Ok(items.into_iter().map(|i| ApiOutlineItem {
+        kind: i.kind,
+        name: i.name,
+        start_line: i.start_line as u32,
+        end_line: i.end_line as u32,
+    }).collect())
+}
+
+#[tauri::command]
+#[specta::specta]
+async fn run_command(state: State<'_, WorkspaceState>, program: String, args: Vec<String>, cwd: Option<String>) -> Result<ApiCommandOutput, ApiShellError> {
     let root = state.0.lock().map_err(|_| ApiShellError::Io("Lock poison".into()))?.clone();
-    terminal_manager::run_command_internal(&root, program, args)
+    // Blocks until the command finishes; keep it off the async runtime's workers
+    tauri::async_runtime::spawn_blocking(move || terminal_manager::run_command_internal(&root, program, args, cwd.as_deref()))
+        .await
+        .map_err(|e| ApiShellError::Io(e.to_string()))?
         .map_err(map_shell_error)
         .map(map_command_output)
 }
@@ -235,6 +648,81 @@ async fn write_terminal(
         .map_err(map_shell_error)
 }
 
+#[tauri::command]
+#[specta::specta]
+async fn interrupt_terminal(
+    state: State<'_, Arc<TerminalState>>,
+    session_id: String
+) -> Result<ApiInterrupted, ApiShellError> {
+    let state = state.inner().clone();
+    // Waits for the process to exit; keep it off the async runtime's workers
+    tauri::async_runtime::spawn_blocking(move || terminal_manager::interrupt_foreground(&state, &session_id, std::time::Duration::from_secs(3)))
+        .await
+        .map_err(|e| ApiShellError::Io(e.to_string()))?
+        .map_err(map_shell_error)
+        .map(map_interrupted)
+}
+
+#[tauri::command]
+#[specta::specta]
+async fn restart_terminal(
+    state: State<'_, Arc<TerminalState>>,
+    session_id: String
+) -> Result<(), ApiShellError> {
+    terminal_manager::restart_session(state.inner(), &session_id)
+        .map_err(map_shell_error)
+}
+
+#[tauri::command]
+#[specta::specta]
+async fn list_terminal_sessions(
+    sessions: State<'_, Arc<SessionManager>>,
+    state: State<'_, Arc<TerminalState>>,
+    page: Option<PageRequest>
+) -> Result<Page<ApiTerminalSession>, String> {
+    let owners = sessions.terminal_owners();
+    Ok(Page::from_vec(terminal_manager::list_sessions(state.inner()), &page.unwrap_or_default())
+        .map(|info| {
+            let owner = owners.get(&info.id).cloned();
+            map_terminal_session(info, owner)
+        }))
+}
+
+#[tauri::command]
+#[specta::specta]
+async fn kill_terminal(
+    window: Window,
+    sessions: State<'_, Arc<SessionManager>>,
+    session_id: String
+) -> Result<(), ApiShellError> {
+    sessions.kill_terminal(&session_id).map_err(map_shell_error)?;
+    let _ = window.emit(&format!("agent:terminal:killed:{}", session_id), ());
+    Ok(())
+}
+
+#[tauri::command]
+#[specta::specta]
+async fn get_terminal_buffer(
+    state: State<'_, Arc<TerminalState>>,
+    session_id: String,
+    last_n_bytes: Option<u32>
+) -> Result<String, ApiShellError> {
+    terminal_manager::get_terminal_buffer(state.inner(), &session_id, last_n_bytes.map(|n| n as usize))
+        .map_err(map_shell_error)
+}
+
+#[tauri::command]
+#[specta::specta]
+async fn resize_terminal(
+    state: State<'_, Arc<TerminalState>>,
+    session_id: String,
+    rows: u16,
+    cols: u16
+) -> Result<(), ApiShellError> {
+    terminal_manager::resize_pty(state.inner(), &session_id, rows, cols)
+        .map_err(map_shell_error)
+}
+
 #[tauri::command]
 #[specta::specta]
 async fn update_profile(state: State<'_, shared_db::DbPool>, req: ApiUpdateProfileReq) -> Result<ApiUserProfile, String> {
@@ -246,8 +734,338 @@ async fn update_profile(state: State<'_, shared_db::DbPool>, req: ApiUpdateProfi
 #[specta::specta]
 async fn send_chat(req: ApiLLMRequest) -> Result<ApiLLMResponse, String> {
     let logic_req = map_llm_req_to_logic(req);
-    llm_gateway::send_chat_logic(logic_req).await
-        .map(map_llm_res_to_api)
+    let candidates = logic_req.config.n.unwrap_or(1);
+    let res = llm_gateway::send_chat_logic(logic_req).await?;
+    // With several candidates, answer with one whose file writes parse
+    let res = if candidates > 1 { pick_valid_choice(res) } else { res };
+    Ok(map_llm_res_to_api(res))
+}
+
+// Streams the reply as `chat:stream:{request_id}` events. The caller picks the id so it
+// can subscribe before the first event fires.
+#[tauri::command]
+#[specta::specta]
+async fn send_chat_stream(
+    window: Window,
+    streams: State<'_, Arc<ChatStreams>>,
+    request_id: String,
+    req: ApiLLMRequest
+) -> Result<(), String> {
+    let cancel = CancellationToken::new();
+    {
+        let mut active = streams.0.lock().unwrap();
+        if active.contains_key(&request_id) {
+            return Err(format!("Stream {} is already running", request_id));
+        }
+        active.insert(request_id.clone(), cancel.clone());
+    }
+
+    let streams = streams.inner().clone();
+    let mut stream = llm_gateway::stream_chat_cancellable(map_llm_req_to_logic(req), cancel);
+    tauri::async_runtime::spawn(async move {
+        let event_name = format!("chat:stream:{}", request_id);
+        while let Some(event) = stream.next().await {
+            let _ = window.emit(&event_name, map_stream_event(event));
+        }
+        streams.0.lock().unwrap().remove(&request_id);
+    });
+    Ok(())
+}
+
+// Returns false if no stream with that id is running
+#[tauri::command]
+#[specta::specta]
+async fn cancel_chat_stream(streams: State<'_, Arc<ChatStreams>>, request_id: String) -> Result<bool, String> {
+    match streams.0.lock().unwrap().get(&request_id) {
+        Some(cancel) => {
+            cancel.cancel();
+            Ok(true)
+        }
+        None => Ok(false),
+    }
+}
+
+#[tauri::command]
+#[specta::specta]
+async fn list_models(config: ApiLLMConfig) -> Result<Vec<ApiModelInfo>, String> {
+    llm_gateway::list_models(&map_llm_config_to_logic(config)).await
+        .map(|models| models.into_iter().map(map_model_info).collect())
+}
+
+#[tauri::command]
+#[specta::specta]
+async fn set_rate_limit(provider: String, limit: ApiRateLimit) -> Result<(), String> {
+    llm_gateway::rate_limit::configure(&provider, LogicRateLimit {
+        requests_per_minute: limit.requests_per_minute,
+        tokens_per_minute: limit.tokens_per_minute,
+    });
+    Ok(())
+}
+
+#[tauri::command]
+#[specta::specta]
+async fn count_tokens(model: String, messages: Vec<ApiMessage>) -> Result<u32, String> {
+    let messages: Vec<LogicMessage> = messages.into_iter().map(map_message_to_logic).collect();
+    Ok(llm_gateway::count_tokens(&model, &messages) as u32)
+}
+
+#[tauri::command]
+#[specta::specta]
+async fn get_response_cache_stats() -> Result<ApiResponseCacheStats, String> {
+    let Some(cache) = llm_gateway::response_cache::active() else {
+        return Ok(ApiResponseCacheStats { enabled: false, entries: 0, size_kb: 0, hits: 0, misses: 0 });
+    };
+    let stats = cache.stats();
+    Ok(ApiResponseCacheStats {
+        enabled: true,
+        entries: stats.entries,
+        size_kb: stats.bytes.div_ceil(1024) as u32,
+        hits: stats.hits,
+        misses: stats.misses,
+    })
+}
+
+#[tauri::command]
+#[specta::specta]
+async fn purge_response_cache() -> Result<u32, String> {
+    match llm_gateway::response_cache::active() {
+        Some(cache) => cache.purge().map_err(|e| e.to_string()),
+        None => Ok(0),
+    }
+}
+
+// Returns the log file path while logging is enabled
+#[tauri::command]
+#[specta::specta]
+async fn set_request_logging(app: tauri::AppHandle, enabled: bool) -> Result<Option<String>, String> {
+    if !enabled {
+        llm_gateway::request_log::disable();
+        return Ok(None);
+    }
+    let path = match llm_gateway::request_log::active() {
+        Some(log) => log.path().to_path_buf(),
+        None => {
+            let app_dir = app.path().app_data_dir().map_err(|e| e.to_string())?;
+            let log = llm_gateway::request_log::RequestLog::new(app_dir.join("llm-requests.log"));
+            let path = log.path().to_path_buf();
+            llm_gateway::request_log::enable(log);
+            path
+        }
+    };
+    Ok(Some(path.to_string_lossy().to_string()))
+}
+
+#[tauri::command]
+#[specta::specta]
+async fn list_messages(
+    sessions: State<'_, Arc<SessionManager>>,
+    session_id: Option<String>,
+    page: Option<PageRequest>
+) -> Result<Page<ApiSessionMessage>, String> {
+    let session = sessions.resolve(session_id.as_deref()).await
+        .map_err(|e| e.to_string())?;
+    let history = session.repository.get_history(&session.id).await
+        .map_err(|e| e.to_string())?;
+    let indexed: Vec<_> = history.into_iter().enumerate().collect();
+    Ok(Page::from_vec(indexed, &page.unwrap_or_default())
+        .map(|(i, msg)| map_history_message(i, msg)))
+}
+
+#[tauri::command]
+#[specta::specta]
+async fn answer_agent_question(
+    sessions: State<'_, Arc<SessionManager>>,
+    session_id: String,
+    answer: String
+) -> Result<(), String> {
+    let session = sessions.get(&session_id)
+        .ok_or_else(|| format!("Unknown agent session: {}", session_id))?;
+    session.answer_question(answer)
+}
+
+#[tauri::command]
+#[specta::specta]
+async fn get_session_changes(
+    sessions: State<'_, Arc<SessionManager>>,
+    session_id: String,
+    page: Option<PageRequest>
+) -> Result<Page<ApiFileChange>, String> {
+    let session = sessions.get(&session_id)
+        .ok_or_else(|| format!("Unknown agent session: {}", session_id))?;
+    Ok(Page::from_vec(session.changes.changes(), &page.unwrap_or_default())
+        .map(map_file_change))
+}
+
+#[tauri::command]
+#[specta::specta]
+async fn get_command_history(
+    sessions: State<'_, Arc<SessionManager>>,
+    session_id: String,
+    page: Option<PageRequest>
+) -> Result<Page<ApiCommandRecord>, String> {
+    let session = sessions.open(&session_id).await
+        .map_err(|e| e.to_string())?;
+    session.command_history().await
+        .map(|records| Page::from_vec(records, &page.unwrap_or_default()).map(map_command_record))
+        .map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+#[specta::specta]
+async fn continue_agent(
+    sessions: State<'_, Arc<SessionManager>>,
+    session_id: String,
+    extra_iterations: u32
+) -> Result<(), String> {
+    let session = sessions.get(&session_id)
+        .ok_or_else(|| format!("Unknown agent session: {}", session_id))?;
+    session.continue_run(extra_iterations)
+}
+
+#[tauri::command]
+#[specta::specta]
+async fn stop_agent(
+    window: Window,
+    sessions: State<'_, Arc<SessionManager>>,
+    session_id: String
+) -> Result<(), String> {
+    let session = sessions.get(&session_id)
+        .ok_or_else(|| format!("Unknown agent session: {}", session_id))?;
+    if !session.stop() {
+        return Err("Session is not running".to_string());
+    }
+    let _ = window.emit(&format!("agent:status:{}", session_id), "stopped");
+    Ok(())
+}
+
+#[tauri::command]
+#[specta::specta]
+async fn fork_session(
+    sessions: State<'_, Arc<SessionManager>>,
+    session_id: String,
+    message_index: u32
+) -> Result<String, String> {
+    sessions.fork(&session_id, message_index as usize).await
+        .map(|fork| fork.id.clone())
+        .map_err(|e| e.to_string())
+}
+
+// Reads IRONGRAPH_<ROLE>_MODEL / _TEMPERATURE / _REASONING_EFFORT overrides
+fn role_config_from_env(role: &str) -> AgentRoleConfig {
+    let var = |name: &str| std::env::var(format!("IRONGRAPH_{}_{}", role, name)).ok();
+    AgentRoleConfig {
+        model: var("MODEL"),
+        temperature: var("TEMPERATURE").and_then(|t| t.parse().ok()),
+        reasoning_effort: var("REASONING_EFFORT"),
+    }
+}
+
+fn agent_config_from_env() -> AgentLLMConfig {
+    AgentLLMConfig {
+        api_key: std::env::var("OPENROUTER_API_KEY").unwrap_or(OPENROUTER_KEY.to_string()),
+        model: "deepseek/deepseek-v3.2".to_string(),
+        temperature: None,
+        coder: role_config_from_env("CODER"),
+        verifier: role_config_from_env("VERIFIER"),
+        limits: AgentRunLimits {
+            max_duration_secs: std::env::var("IRONGRAPH_MAX_RUN_SECS").ok().and_then(|v| v.parse().ok()),
+            ..AgentRunLimits::default()
+        },
+        coder_only: false,
+        provider: std::env::var("IRONGRAPH_PROVIDER").ok(),
+        base_url: std::env::var("IRONGRAPH_BASE_URL").ok(),
+        embedding_model: std::env::var("IRONGRAPH_EMBEDDING_MODEL").ok(),
+    }
+}
+
+fn map_interrupted(i: LogicInterrupted) -> ApiInterrupted {
+    ApiInterrupted {
+        process: i.process,
+        signal: i.signal.to_string(),
+        stopped: i.stopped,
+    }
+}
+
+fn map_shell_exit(e: LogicShellExit) -> ApiTerminalClosed {
+    ApiTerminalClosed {
+        exit_code: e.exit_code,
+        signal: e.signal,
+    }
+}
+
+fn map_terminal_session(s: LogicSessionInfo, agent_session_id: Option<String>) -> ApiTerminalSession {
+    ApiTerminalSession {
+        id: s.id,
+        shell: s.shell,
+        cwd: s.cwd.to_string_lossy().to_string(),
+        agent_session_id,
+        alive: s.exit.is_none(),
+        exit: s.exit.map(map_shell_exit),
+    }
+}
+
+fn map_command_record(r: LogicCommandRecord) -> ApiCommandRecord {
+    ApiCommandRecord {
+        command: r.command,
+        source: match r.source {
+            LogicCommandSource::Agent => ApiCommandSource::Agent,
+            LogicCommandSource::User => ApiCommandSource::User,
+        },
+        started_at: r.started_at as f64,
+        duration_ms: r.duration_ms.map(|ms| ms.min(u32::MAX as u64) as u32),
+        exit_code: r.exit_code,
+    }
+}
+
+fn map_file_change(c: LogicFileChange) -> ApiFileChange {
+    ApiFileChange {
+        path: c.path,
+        before: c.before,
+        after: c.after,
+        diff: c.diff,
+    }
+}
+
+fn map_experiment_report(r: LogicExperimentReport) -> ApiExperimentReport {
+    ApiExperimentReport {
+        id: r.id,
+        variants: r.variants.into_iter().map(|v| ApiVariantOutcome {
+            label: v.label,
+            model: v.model,
+            session_id: v.session_id,
+            changed_files: v.changed_files,
+            diff: v.diff,
+        }).collect(),
+        comparison: r.comparison,
+    }
+}
+
+#[tauri::command]
+#[specta::specta]
+async fn start_ab_experiment(
+    window: Window,
+    sessions: State<'_, Arc<SessionManager>>,
+    workspace_state: State<'_, WorkspaceState>,
+    terminal_state: State<'_, Arc<TerminalState>>,
+    prompt: String,
+    model_a: String,
+    model_b: String
+) -> Result<ApiExperimentReport, String> {
+    let root = workspace_state.0.lock().map_err(|_| "Lock poison".to_string())?.clone();
+    let variants = [
+        ExperimentVariant { label: "A".to_string(), model: model_a },
+        ExperimentVariant { label: "B".to_string(), model: model_b },
+    ];
+    agent_core::experiment::run_ab_experiment(
+        window,
+        sessions.inner(),
+        root,
+        terminal_state.inner().clone(),
+        prompt,
+        agent_config_from_env(),
+        variants,
+    ).await
+        .map(map_experiment_report)
 }
 
 // Wrapper command to start agent
@@ -255,12 +1073,14 @@ async fn send_chat(req: ApiLLMRequest) -> Result<ApiLLMResponse, String> {
 #[specta::specta]
 async fn start_agent_loop(
     window: Window,
-    session_state: State<'_, Arc<AgentSession>>,
+    sessions: State<'_, Arc<SessionManager>>,
     workspace_state: State<'_, WorkspaceState>,
     terminal_state: State<'_, Arc<TerminalState>>,
-    prompt: String
+    prompt: String,
+    session_id: Option<String>
 ) -> Result<String, String> {
-    let session = session_state.inner().clone();
+    let session = sessions.resolve(session_id.as_deref()).await
+        .map_err(|e| e.to_string())?;
 
     let is_running = session.status.load(std::sync::atomic::Ordering::Relaxed);
 
@@ -271,10 +1091,7 @@ async fn start_agent_loop(
     }
 
     if !is_running {
-         let config = AgentLLMConfig {
-             api_key: std::env::var("OPENROUTER_API_KEY").unwrap_or(OPENROUTER_KEY.to_string()),
-             model: "deepseek/deepseek-v3.2".to_string(),
-         };
+         let config = agent_config_from_env();
 
          let ws_arc = workspace_state.0.clone();
          let term_arc = terminal_state.inner().clone();
@@ -293,21 +1110,125 @@ async fn start_agent_loop(
 }
 
 
+// Holds the path of the last workspace opened with set_workspace, under the app data dir
+const LAST_WORKSPACE_FILE: &str = "last_workspace";
+
+// Switches the workspace root and emits `workspace:changed` with the canonical path.
+// Sessions already running keep the root they were started with.
+#[tauri::command]
+#[specta::specta]
+async fn set_workspace(app: tauri::AppHandle, state: State<'_, WorkspaceState>, path: String) -> Result<String, ApiFsError> {
+    let root = PathBuf::from(&path).canonicalize().map_err(|e| ApiFsError::Io(format!("{}: {}", path, e)))?;
+    if !root.is_dir() {
+        return Err(ApiFsError::InvalidPath);
+    }
+    let old = {
+        let mut current = state.0.lock().map_err(|_| ApiFsError::Io("Lock poison".into()))?;
+        std::mem::replace(&mut *current, root.clone())
+    };
+    workspace_manager::graph::forget(&old);
+    workspace_manager::search_index::forget(&old);
+    *app.state::<WatcherState>().0.lock().unwrap() = None;
+    start_workspace_watcher(&app, &root);
+
+    if let Ok(app_dir) = app.path().app_data_dir() {
+        let _ = std::fs::create_dir_all(&app_dir);
+        if let Err(e) = std::fs::write(app_dir.join(LAST_WORKSPACE_FILE), root.to_string_lossy().as_bytes()) {
+            println!("Failed to remember workspace {}: {}", root.display(), e);
+        }
+    }
+
+    let root = root.to_string_lossy().to_string();
+    let _ = app.emit("workspace:changed", &root);
+    Ok(root)
+}
+
+// The workspace remembered by set_workspace, if it still exists
+fn last_workspace(app: &tauri::AppHandle) -> Option<PathBuf> {
+    let app_dir = app.path().app_data_dir().ok()?;
+    let path = PathBuf::from(std::fs::read_to_string(app_dir.join(LAST_WORKSPACE_FILE)).ok()?.trim());
+    path.is_dir().then_some(path)
+}
+
+// Pushes debounced external edits under `root` to the frontend as `workspace:fs_changed`
+// and keeps the dependency graph, search index and skeleton cache current
+fn start_workspace_watcher(app: &tauri::AppHandle, root: &Path) {
+    if let Err(e) = workspace_manager::search_index::start(root) {
+        println!("{}", e);
+    }
+    let emitter = app.clone();
+    let graph_root = root.to_path_buf();
+    let watcher = WorkspaceWatcher::start(root, WorkspaceWatcher::DEFAULT_DEBOUNCE, move |change| {
+        workspace_manager::graph::apply_change(&graph_root, change);
+        workspace_manager::search_index::apply_change(&graph_root, change);
+        workspace_manager::skeleton::apply_change(&graph_root, change);
+        let _ = emitter.emit("workspace:fs_changed", map_fs_change(change));
+    });
+    match watcher {
+        Ok(watcher) => *app.state::<WatcherState>().0.lock().unwrap() = Some(watcher),
+        Err(e) => println!("Failed to watch workspace {}: {}", root.display(), e),
+    }
+}
+
 #[cfg_attr(mobile, tauri::mobile_entry_point)]
 pub fn run() {
     let builder = Builder::<tauri::Wry>::new()
         .commands(collect_commands![
             update_profile,
             send_chat,
+            send_chat_stream,
+            cancel_chat_stream,
+            list_models,
+            set_rate_limit,
+            count_tokens,
+            get_response_cache_stats,
+            purge_response_cache,
+            set_request_logging,
+            set_workspace,
             list_files,
             read_file,
+            set_max_read_size,
+            set_syntax_validation,
             write_file,
+            preview_write,
+            delete_file,
+            restore_backup,
+            create_directory,
+            move_file,
+            workspace_stats,
+            git_status,
+            git_diff,
+            graph_dependencies,
+            graph_dependents,
+            graph_cycles,
+            export_graph,
+            fuzzy_find_files,
             search_code,
             read_skeleton,
+            read_skeletons,
+            outline,
             run_command,
             start_agent_loop,
-            write_terminal
-        ]);
+            write_terminal,
+            resize_terminal,
+            get_terminal_buffer,
+            interrupt_terminal,
+            restart_terminal,
+            list_terminal_sessions,
+            kill_terminal,
+            list_messages,
+            answer_agent_question,
+            fork_session,
+            start_ab_experiment,
+            continue_agent,
+            stop_agent,
+            get_session_changes,
+            get_command_history
+        ])
+        .typ::<ApiChatStreamEvent>()
+        .typ::<ApiFsChange>()
+        .typ::<ApiTerminalOutput>()
+        .typ::<ApiTerminalClosed>();
 
     #[cfg(debug_assertions)]
     builder
@@ -319,16 +1240,35 @@ pub fn run() {
         .plugin(tauri_plugin_sql::Builder::default().build())
         .manage(common::WorkspaceState(Arc::new(std::sync::Mutex::new(std::env::current_dir().expect("Failed to get current directory")))))
         .manage(Arc::new(TerminalState::default()))
+        .manage(Arc::new(ChatStreams::default()))
+        .manage(WatcherState::default())
         .setup(move |app| {
             builder.mount_events(app);
 
             let app_handle = app.handle().clone();
 
+            if let Some(last) = last_workspace(&app_handle) {
+                *app.state::<WorkspaceState>().0.lock().unwrap() = last;
+            }
+            let root = app.state::<WorkspaceState>().0.lock().unwrap().clone();
+            start_workspace_watcher(&app_handle, &root);
+
             tauri::async_runtime::block_on(async move {
                 let app_dir = app_handle.path().app_data_dir().expect("failed to get app data dir");
                 if !app_dir.exists() {
                     std::fs::create_dir_all(&app_dir).expect("failed to create app data dir");
                 }
+                if std::env::var("IRONGRAPH_RESPONSE_CACHE").is_ok_and(|v| v == "1") {
+                    llm_gateway::response_cache::enable(
+                        llm_gateway::response_cache::ResponseCache::new(app_dir.join("llm-cache"))
+                    );
+                }
+                if std::env::var("IRONGRAPH_LLM_LOG").is_ok_and(|v| v == "1") {
+                    llm_gateway::request_log::enable(
+                        llm_gateway::request_log::RequestLog::new(app_dir.join("llm-requests.log"))
+                    );
+                }
+
                 let db_path = app_dir.join("irongraph.db");
                 let db_url = format!("sqlite://{}", db_path.to_string_lossy());
 
@@ -346,6 +1286,10 @@ pub fn run() {
                     .execute(&pool)
                     .await
                     .expect("Failed to run migrations");
+                sqlx::query(include_str!("../migrations/20250102_commands.sql"))
+                    .execute(&pool)
+                    .await
+                    .expect("Failed to run migrations");
 
                 let history = SqliteHistory::new(pool.clone());
                 let terminal_state = app_handle.state::<Arc<TerminalState>>();
@@ -354,8 +1298,8 @@ pub fn run() {
                 // Also provide pool to state for feature_profile
                 app_handle.manage(pool);
 
-                let session = AgentSession::new(Box::new(history), ts);
-                app_handle.manage(Arc::new(session));
+                let sessions = SessionManager::new(Box::new(history), ts);
+                app_handle.manage(Arc::new(sessions));
             });
 
             Ok(())
@@ -374,15 +1318,59 @@ mod tests {
             .commands(collect_commands![
                 update_profile,
                 send_chat,
+                send_chat_stream,
+                cancel_chat_stream,
+                list_models,
+                set_rate_limit,
+                count_tokens,
+                get_response_cache_stats,
+                purge_response_cache,
+                set_request_logging,
+                set_workspace,
                 list_files,
                 read_file,
+                set_max_read_size,
+                set_syntax_validation,
                 write_file,
+                preview_write,
+                delete_file,
+                restore_backup,
+                create_directory,
+                move_file,
+                workspace_stats,
+                git_status,
+                git_diff,
+                graph_dependencies,
+                graph_dependents,
+                graph_cycles,
+                export_graph,
+                fuzzy_find_files,
                 search_code,
                 read_skeleton,
+                read_skeletons,
+                outline,
                 run_command,
                 start_agent_loop,
-                write_terminal
-            ]);
+                write_terminal,
+                resize_terminal,
+                get_terminal_buffer,
+                interrupt_terminal,
+                restart_terminal,
+                list_terminal_sessions,
+                kill_terminal,
+                list_messages,
+                answer_agent_question,
+                fork_session,
+                start_ab_experiment,
+                continue_agent,
+                stop_agent,
+                get_session_changes,
+                get_command_history
+            ])
+            .typ::<ApiChatStreamEvent>()
+            .typ::<ApiFsChange>()
+            .typ::<ApiTerminalOutput>()
+            .typ::<ApiTerminalClosed>();
 
         builder
             .export(Typescript::default(), "../src/bindings.ts")
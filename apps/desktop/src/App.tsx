@@ -3,6 +3,7 @@ import "./App.css";
 import { ProfileForm } from "./features/profile/ProfileForm";
 import { AgentChat } from "./features/agent/AgentChat";
 import { FileExplorer } from "./features/files/FileExplorer";
+import { WorkspaceStats } from "./features/files/WorkspaceStats";
 import { CommandRunner } from "./features/terminal/CommandRunner";
 
 const queryClient = new QueryClient();
@@ -30,6 +31,11 @@ function App() {
                 <FileExplorer />
             </div>
         </div>
+        <div className="row" style={{ marginTop: "20px", padding: "0 20px" }}>
+            <div style={{ width: "100%", maxWidth: "1200px" }}>
+                <WorkspaceStats />
+            </div>
+        </div>
       </main>
     </QueryClientProvider>
   );
@@ -21,6 +21,78 @@ async sendChat(req: LLMRequest) : Promise<Result<LLMResponse, string>> {
     else return { status: "error", error: e  as any };
 }
 },
+async sendChatStream(requestId: string, req: LLMRequest) : Promise<Result<null, string>> {
+    try {
+    return { status: "ok", data: await TAURI_INVOKE("send_chat_stream", { requestId, req }) };
+} catch (e) {
+    if(e instanceof Error) throw e;
+    else return { status: "error", error: e  as any };
+}
+},
+async cancelChatStream(requestId: string) : Promise<Result<boolean, string>> {
+    try {
+    return { status: "ok", data: await TAURI_INVOKE("cancel_chat_stream", { requestId }) };
+} catch (e) {
+    if(e instanceof Error) throw e;
+    else return { status: "error", error: e  as any };
+}
+},
+async listModels(config: LLMConfig) : Promise<Result<ModelInfo[], string>> {
+    try {
+    return { status: "ok", data: await TAURI_INVOKE("list_models", { config }) };
+} catch (e) {
+    if(e instanceof Error) throw e;
+    else return { status: "error", error: e  as any };
+}
+},
+async setRateLimit(provider: string, limit: RateLimit) : Promise<Result<null, string>> {
+    try {
+    return { status: "ok", data: await TAURI_INVOKE("set_rate_limit", { provider, limit }) };
+} catch (e) {
+    if(e instanceof Error) throw e;
+    else return { status: "error", error: e  as any };
+}
+},
+async countTokens(model: string, messages: Message[]) : Promise<Result<number, string>> {
+    try {
+    return { status: "ok", data: await TAURI_INVOKE("count_tokens", { model, messages }) };
+} catch (e) {
+    if(e instanceof Error) throw e;
+    else return { status: "error", error: e  as any };
+}
+},
+async getResponseCacheStats() : Promise<Result<ResponseCacheStats, string>> {
+    try {
+    return { status: "ok", data: await TAURI_INVOKE("get_response_cache_stats") };
+} catch (e) {
+    if(e instanceof Error) throw e;
+    else return { status: "error", error: e  as any };
+}
+},
+async purgeResponseCache() : Promise<Result<number, string>> {
+    try {
+    return { status: "ok", data: await TAURI_INVOKE("purge_response_cache") };
+} catch (e) {
+    if(e instanceof Error) throw e;
+    else return { status: "error", error: e  as any };
+}
+},
+async setRequestLogging(enabled: boolean) : Promise<Result<string | null, string>> {
+    try {
+    return { status: "ok", data: await TAURI_INVOKE("set_request_logging", { enabled }) };
+} catch (e) {
+    if(e instanceof Error) throw e;
+    else return { status: "error", error: e  as any };
+}
+},
+async setWorkspace(path: string) : Promise<Result<string, FsError>> {
+    try {
+    return { status: "ok", data: await TAURI_INVOKE("set_workspace", { path }) };
+} catch (e) {
+    if(e instanceof Error) throw e;
+    else return { status: "error", error: e  as any };
+}
+},
 async listFiles(dirPath: string | null) : Promise<Result<FileEntry[], FsError>> {
     try {
     return { status: "ok", data: await TAURI_INVOKE("list_files", { dirPath }) };
@@ -37,17 +109,137 @@ async readFile(filePath: string) : Promise<Result<FileContent, FsError>> {
     else return { status: "error", error: e  as any };
 }
 },
-async writeFile(filePath: string, content: string) : Promise<Result<FileContent, FsError>> {
+async setMaxReadSize(kb: number) : Promise<Result<null, string>> {
+    try {
+    return { status: "ok", data: await TAURI_INVOKE("set_max_read_size", { kb }) };
+} catch (e) {
+    if(e instanceof Error) throw e;
+    else return { status: "error", error: e  as any };
+}
+},
+async setSyntaxValidation(config: SyntaxValidation) : Promise<Result<null, string>> {
+    try {
+    return { status: "ok", data: await TAURI_INVOKE("set_syntax_validation", { config }) };
+} catch (e) {
+    if(e instanceof Error) throw e;
+    else return { status: "error", error: e  as any };
+}
+},
+async writeFile(filePath: string, content: string, skipValidation: boolean | null) : Promise<Result<FileContent, FsError>> {
+    try {
+    return { status: "ok", data: await TAURI_INVOKE("write_file", { filePath, content, skipValidation }) };
+} catch (e) {
+    if(e instanceof Error) throw e;
+    else return { status: "error", error: e  as any };
+}
+},
+async previewWrite(filePath: string, content: string, skipValidation: boolean | null) : Promise<Result<string, FsError>> {
+    try {
+    return { status: "ok", data: await TAURI_INVOKE("preview_write", { filePath, content, skipValidation }) };
+} catch (e) {
+    if(e instanceof Error) throw e;
+    else return { status: "error", error: e  as any };
+}
+},
+async deleteFile(filePath: string) : Promise<Result<string, FsError>> {
+    try {
+    return { status: "ok", data: await TAURI_INVOKE("delete_file", { filePath }) };
+} catch (e) {
+    if(e instanceof Error) throw e;
+    else return { status: "error", error: e  as any };
+}
+},
+async restoreBackup(filePath: string) : Promise<Result<FileContent, FsError>> {
+    try {
+    return { status: "ok", data: await TAURI_INVOKE("restore_backup", { filePath }) };
+} catch (e) {
+    if(e instanceof Error) throw e;
+    else return { status: "error", error: e  as any };
+}
+},
+async createDirectory(dirPath: string) : Promise<Result<null, FsError>> {
+    try {
+    return { status: "ok", data: await TAURI_INVOKE("create_directory", { dirPath }) };
+} catch (e) {
+    if(e instanceof Error) throw e;
+    else return { status: "error", error: e  as any };
+}
+},
+async moveFile(from: string, to: string) : Promise<Result<string[], FsError>> {
+    try {
+    return { status: "ok", data: await TAURI_INVOKE("move_file", { from, to }) };
+} catch (e) {
+    if(e instanceof Error) throw e;
+    else return { status: "error", error: e  as any };
+}
+},
+async workspaceStats() : Promise<Result<WorkspaceStats, FsError>> {
+    try {
+    return { status: "ok", data: await TAURI_INVOKE("workspace_stats") };
+} catch (e) {
+    if(e instanceof Error) throw e;
+    else return { status: "error", error: e  as any };
+}
+},
+async gitStatus() : Promise<Result<GitStatus, FsError>> {
+    try {
+    return { status: "ok", data: await TAURI_INVOKE("git_status") };
+} catch (e) {
+    if(e instanceof Error) throw e;
+    else return { status: "error", error: e  as any };
+}
+},
+async gitDiff(filePath: string | null, staged: boolean | null) : Promise<Result<string, FsError>> {
+    try {
+    return { status: "ok", data: await TAURI_INVOKE("git_diff", { filePath, staged }) };
+} catch (e) {
+    if(e instanceof Error) throw e;
+    else return { status: "error", error: e  as any };
+}
+},
+async graphDependencies(filePath: string) : Promise<Result<string[], FsError>> {
     try {
-    return { status: "ok", data: await TAURI_INVOKE("write_file", { filePath, content }) };
+    return { status: "ok", data: await TAURI_INVOKE("graph_dependencies", { filePath }) };
 } catch (e) {
     if(e instanceof Error) throw e;
     else return { status: "error", error: e  as any };
 }
 },
-async searchCode(query: string) : Promise<Result<string[], FsError>> {
+async graphDependents(filePath: string) : Promise<Result<string[], FsError>> {
     try {
-    return { status: "ok", data: await TAURI_INVOKE("search_code", { query }) };
+    return { status: "ok", data: await TAURI_INVOKE("graph_dependents", { filePath }) };
+} catch (e) {
+    if(e instanceof Error) throw e;
+    else return { status: "error", error: e  as any };
+}
+},
+async graphCycles() : Promise<Result<string[][], FsError>> {
+    try {
+    return { status: "ok", data: await TAURI_INVOKE("graph_cycles") };
+} catch (e) {
+    if(e instanceof Error) throw e;
+    else return { status: "error", error: e  as any };
+}
+},
+async exportGraph(format: GraphFormat) : Promise<Result<GraphExport, FsError>> {
+    try {
+    return { status: "ok", data: await TAURI_INVOKE("export_graph", { format }) };
+} catch (e) {
+    if(e instanceof Error) throw e;
+    else return { status: "error", error: e  as any };
+}
+},
+async fuzzyFindFiles(query: string, limit: number | null) : Promise<Result<string[], FsError>> {
+    try {
+    return { status: "ok", data: await TAURI_INVOKE("fuzzy_find_files", { query, limit }) };
+} catch (e) {
+    if(e instanceof Error) throw e;
+    else return { status: "error", error: e  as any };
+}
+},
+async searchCode(query: string, options: SearchOptions | null, page: PageRequest | null) : Promise<Result<Page<SearchMatch>, FsError>> {
+    try {
+    return { status: "ok", data: await TAURI_INVOKE("search_code", { query, options, page }) };
 } catch (e) {
     if(e instanceof Error) throw e;
     else return { status: "error", error: e  as any };
@@ -61,17 +253,33 @@ async readSkeleton(filePath: string) : Promise<Result<string, FsError>> {
     else return { status: "error", error: e  as any };
 }
 },
-async runCommand(program: string, args: string[]) : Promise<Result<CommandOutput, ShellError>> {
+async readSkeletons(filePaths: string[]) : Promise<Result<FileSkeleton[], FsError>> {
+    try {
+    return { status: "ok", data: await TAURI_INVOKE("read_skeletons", { filePaths }) };
+} catch (e) {
+    if(e instanceof Error) throw e;
+    else return { status: "error", error: e  as any };
+}
+},
+async outline(filePath: string) : Promise<Result<OutlineItem[], FsError>> {
     try {
-    return { status: "ok", data: await TAURI_INVOKE("run_command", { program, args }) };
+    return { status: "ok", data: await TAURI_INVOKE("outline", { filePath }) };
 } catch (e) {
     if(e instanceof Error) throw e;
     else return { status: "error", error: e  as any };
 }
 },
-async startAgentLoop(prompt: string) : Promise<Result<string, string>> {
+async runCommand(program: string, args: string[], cwd: string | null) : Promise<Result<CommandOutput, ShellError>> {
     try {
-    return { status: "ok", data: await TAURI_INVOKE("start_agent_loop", { prompt }) };
+    return { status: "ok", data: await TAURI_INVOKE("run_command", { program, args, cwd }) };
+} catch (e) {
+    if(e instanceof Error) throw e;
+    else return { status: "error", error: e  as any };
+}
+},
+async startAgentLoop(prompt: string, sessionId: string | null) : Promise<Result<string, string>> {
+    try {
+    return { status: "ok", data: await TAURI_INVOKE("start_agent_loop", { prompt, sessionId }) };
 } catch (e) {
     if(e instanceof Error) throw e;
     else return { status: "error", error: e  as any };
@@ -84,6 +292,118 @@ async writeTerminal(sessionId: string, input: string) : Promise<Result<null, She
     if(e instanceof Error) throw e;
     else return { status: "error", error: e  as any };
 }
+},
+async resizeTerminal(sessionId: string, rows: number, cols: number) : Promise<Result<null, ShellError>> {
+    try {
+    return { status: "ok", data: await TAURI_INVOKE("resize_terminal", { sessionId, rows, cols }) };
+} catch (e) {
+    if(e instanceof Error) throw e;
+    else return { status: "error", error: e  as any };
+}
+},
+async getTerminalBuffer(sessionId: string, lastNBytes: number | null) : Promise<Result<string, ShellError>> {
+    try {
+    return { status: "ok", data: await TAURI_INVOKE("get_terminal_buffer", { sessionId, lastNBytes }) };
+} catch (e) {
+    if(e instanceof Error) throw e;
+    else return { status: "error", error: e  as any };
+}
+},
+async interruptTerminal(sessionId: string) : Promise<Result<Interrupted, ShellError>> {
+    try {
+    return { status: "ok", data: await TAURI_INVOKE("interrupt_terminal", { sessionId }) };
+} catch (e) {
+    if(e instanceof Error) throw e;
+    else return { status: "error", error: e  as any };
+}
+},
+async restartTerminal(sessionId: string) : Promise<Result<null, ShellError>> {
+    try {
+    return { status: "ok", data: await TAURI_INVOKE("restart_terminal", { sessionId }) };
+} catch (e) {
+    if(e instanceof Error) throw e;
+    else return { status: "error", error: e  as any };
+}
+},
+async listTerminalSessions(page: PageRequest | null) : Promise<Result<Page<TerminalSession>, string>> {
+    try {
+    return { status: "ok", data: await TAURI_INVOKE("list_terminal_sessions", { page }) };
+} catch (e) {
+    if(e instanceof Error) throw e;
+    else return { status: "error", error: e  as any };
+}
+},
+async killTerminal(sessionId: string) : Promise<Result<null, ShellError>> {
+    try {
+    return { status: "ok", data: await TAURI_INVOKE("kill_terminal", { sessionId }) };
+} catch (e) {
+    if(e instanceof Error) throw e;
+    else return { status: "error", error: e  as any };
+}
+},
+async listMessages(sessionId: string | null, page: PageRequest | null) : Promise<Result<Page<SessionMessage>, string>> {
+    try {
+    return { status: "ok", data: await TAURI_INVOKE("list_messages", { sessionId, page }) };
+} catch (e) {
+    if(e instanceof Error) throw e;
+    else return { status: "error", error: e  as any };
+}
+},
+async answerAgentQuestion(sessionId: string, answer: string) : Promise<Result<null, string>> {
+    try {
+    return { status: "ok", data: await TAURI_INVOKE("answer_agent_question", { sessionId, answer }) };
+} catch (e) {
+    if(e instanceof Error) throw e;
+    else return { status: "error", error: e  as any };
+}
+},
+async forkSession(sessionId: string, messageIndex: number) : Promise<Result<string, string>> {
+    try {
+    return { status: "ok", data: await TAURI_INVOKE("fork_session", { sessionId, messageIndex }) };
+} catch (e) {
+    if(e instanceof Error) throw e;
+    else return { status: "error", error: e  as any };
+}
+},
+async startAbExperiment(prompt: string, modelA: string, modelB: string) : Promise<Result<ExperimentReport, string>> {
+    try {
+    return { status: "ok", data: await TAURI_INVOKE("start_ab_experiment", { prompt, modelA, modelB }) };
+} catch (e) {
+    if(e instanceof Error) throw e;
+    else return { status: "error", error: e  as any };
+}
+},
+async continueAgent(sessionId: string, extraIterations: number) : Promise<Result<null, string>> {
+    try {
+    return { status: "ok", data: await TAURI_INVOKE("continue_agent", { sessionId, extraIterations }) };
+} catch (e) {
+    if(e instanceof Error) throw e;
+    else return { status: "error", error: e  as any };
+}
+},
+async stopAgent(sessionId: string) : Promise<Result<null, string>> {
+    try {
+    return { status: "ok", data: await TAURI_INVOKE("stop_agent", { sessionId }) };
+} catch (e) {
+    if(e instanceof Error) throw e;
+    else return { status: "error", error: e  as any };
+}
+},
+async getSessionChanges(sessionId: string, page: PageRequest | null) : Promise<Result<Page<FileChange>, string>> {
+    try {
+    return { status: "ok", data: await TAURI_INVOKE("get_session_changes", { sessionId, page }) };
+} catch (e) {
+    if(e instanceof Error) throw e;
+    else return { status: "error", error: e  as any };
+}
+},
+async getCommandHistory(sessionId: string, page: PageRequest | null) : Promise<Result<Page<CommandRecord>, string>> {
+    try {
+    return { status: "ok", data: await TAURI_INVOKE("get_command_history", { sessionId, page }) };
+} catch (e) {
+    if(e instanceof Error) throw e;
+    else return { status: "error", error: e  as any };
+}
 }
 }
 
@@ -97,18 +417,61 @@ async writeTerminal(sessionId: string, input: string) : Promise<Result<null, She
 
 /** user-defined types **/
 
+export type ChatStreamEvent = { type: "token"; data: string } | { type: "tool_start"; data: string } | { type: "tool_arg"; data: { name: string; value: string } } | { type: "tool_end" } | { type: "retrying"; data: { attempt: number; delay_ms: number; reason: string } } | { type: "usage"; data: { prompt_tokens: number; completion_tokens: number } } | { type: "reasoning"; data: string } | { type: "cancelled" } | { type: "error"; data: string } | { type: "done" }
+export type Choice = { content: string; tool_calls: ToolCall[] | null; reasoning: string | null }
 export type CommandOutput = { stdout: string; stderr: string; exit_code: number }
+export type CommandRecord = { command: string; source: CommandSource; started_at: number; duration_ms: number | null; exit_code: number | null }
+export type CommandSource = "Agent" | "User"
+/**
+ * Opaque continuation token. Callers must hand it back unchanged.
+ */
+export type Cursor = string
+export type DependencyGraph = { nodes: GraphNode[]; edges: GraphEdge[] }
+export type ExperimentReport = { id: string; variants: VariantOutcome[]; comparison: string }
+export type FileChange = { path: string; before: string | null; after: string | null; diff: string }
 export type FileContent = { path: string; content: string }
-export type FileEntry = { path: string; name: string; is_dir: boolean; children: FileEntry[] | null }
-export type FsError = { Io: string } | "SecurityViolation" | "InvalidPath" | { Syntax: string }
-export type LLMConfig = { api_key: string; base_url: string; model: string; temperature: number }
-export type LLMRequest = { messages: Message[]; config: LLMConfig }
-export type LLMResponse = { role: string; content: string; tool_calls: ToolCall[] | null; usage: Partial<{ [key in string]: number }> | null }
-export type Message = { role: string; content: string }
-export type ShellError = { Io: string } | { NotFound: string } | { Pty: string }
-export type ToolCall = { name: string; arguments: Partial<{ [key in string]: string }> }
+export type FileEntry = { path: string; name: string; is_dir: boolean; is_binary: boolean; children: FileEntry[] | null }
+export type FileSkeleton = { path: string; skeleton: string | null; error: string | null }
+export type FsChange = { created: string[]; modified: string[]; removed: string[] }
+export type FsError = { Io: string } | "SecurityViolation" | "InvalidPath" | { Syntax: string } | { Edit: string } | { BinaryFile: { size_kb: number; preview: string } } | { Conflict: string }
+export type GitChange = "added" | "modified" | "deleted" | "renamed" | "type_change" | "untracked" | "conflicted"
+export type GitFileStatus = { path: string; index: GitChange | null; worktree: GitChange | null }
+export type GitStatus = { branch: string | null; files: GitFileStatus[] }
+export type GraphEdge = { from: number; to: number }
+export type GraphExport = { format: "dot"; data: string } | { format: "json"; data: DependencyGraph }
+export type GraphFormat = "dot" | "json"
+export type GraphNode = { id: number; path: string; language: string }
+export type ImageSource = { type: "url"; url: string } | { type: "base64"; media_type: string; data: string }
+export type Interrupted = { process: string | null; signal: string; stopped: boolean }
+export type JsonValue = null | boolean | number | string | JsonValue[] | { [key in string]: JsonValue }
+export type LLMConfig = { api_key: string; base_url: string; model: string; temperature: number; max_tokens: number | null; top_p: number | null; stop: string[] | null; frequency_penalty: number | null; presence_penalty: number | null; n: number | null; provider: string | null; max_retries: number | null; proxy: string | null; no_proxy: string | null; openrouter: OpenRouterOptions | null; fallback_base_urls: string[] }
+export type LLMRequest = { messages: Message[]; config: LLMConfig; tools: ToolDefinition[] | null; tool_choice: string | null; prompt_cache: PromptCache | null }
+export type LLMResponse = { role: string; content: string; tool_calls: ToolCall[] | null; usage: Partial<{ [key in string]: number }> | null; reasoning: string | null; choices: Choice[]; endpoint: string | null }
+export type LanguageStats = { language: string; files: number; lines: number }
+export type Message = { role: string; content: string; images: ImageSource[]; tool_call_id: string | null; name: string | null; tool_calls: ToolCall[] | null }
+export type ModelInfo = { id: string; name: string | null; context_length: number | null; prompt_price: number | null; completion_price: number | null }
+export type OpenRouterOptions = { order: string[] | null; only: string[] | null; ignore: string[] | null; allow_fallbacks: boolean | null; fallback_models: string[] | null; transforms: string[] | null; referer: string | null; title: string | null }
+export type OutlineItem = { kind: string; name: string; start_line: number; end_line: number }
+export type Page<T> = { items: T[]; next_cursor: Cursor | null; total: number | null }
+export type PageRequest = { cursor: Cursor | null; limit: number | null }
+export type PathSize = { path: string; files: number; size_kb: number }
+export type PromptCache = { system: boolean; history: boolean }
+export type RateLimit = { requests_per_minute: number | null; tokens_per_minute: number | null }
+export type ResponseCacheStats = { enabled: boolean; entries: number; size_kb: number; hits: number; misses: number }
+export type SearchMatch = { path: string; line: number; end_line: number; column: number; text: string; before: string[]; after: string[] }
+export type SearchOptions = { before_context: number; after_context: number; include: string[]; exclude: string[]; case_insensitive: boolean; fixed_string: boolean; max_results: number | null; multiline: boolean }
+export type SessionMessage = { index: number; role: string; content: string; persona: string | null; reasoning: string | null }
+export type ShellError = { Io: string } | { NotFound: string } | { Pty: string } | { Timeout: number } | { Terminated: string }
+export type SyntaxValidation = { rust: boolean; javascript: boolean; python: boolean; json: boolean; toml: boolean; yaml: boolean }
+export type TerminalClosed = { exit_code: number; signal: string | null }
+export type TerminalOutput = { seq: number; data: string }
+export type TerminalSession = { id: string; shell: string; cwd: string; agent_session_id: string | null; alive: boolean; exit: TerminalClosed | null }
+export type ToolCall = { id: string | null; name: string; arguments: Partial<{ [key in string]: string }>; raw_arguments: JsonValue | null }
+export type ToolDefinition = { name: string; description: string; parameters: JsonValue }
 export type UpdateProfileReq = { name: string; bio: string }
 export type UserProfile = { id: number; name: string; bio: string }
+export type VariantOutcome = { label: string; model: string; session_id: string; changed_files: string[]; diff: string }
+export type WorkspaceStats = { files: number; lines: number; size_kb: number; languages: LanguageStats[]; largest_files: PathSize[]; directories: PathSize[] }
 
 /** tauri-specta globals **/
 
@@ -1,11 +1,10 @@
 import { useState, useRef, useEffect } from "react";
-import { useBackendAgent } from "../../hooks/useBackendAgent";
-import { Message } from "../../bindings";
+import { useBackendAgent, ChatMessage } from "../../hooks/useBackendAgent";
 import Database from "@tauri-apps/plugin-sql";
 import { listen } from "@tauri-apps/api/event"; // Add import
 
 // Helper component for message rendering
-const MessageBubble = ({ msg }: { msg: Message }) => {
+const MessageBubble = ({ msg }: { msg: ChatMessage }) => {
   const isUser = msg.role === "user";
   // Identify if it's a tool output/log message.
   // User messages that start with "Tool Output" or "Tool Error" are logs.
@@ -98,7 +97,7 @@ export function AgentChat() {
     const { messages: liveMessages, isLooping, startLoop, stopLoop, sessionId } = useBackendAgent();
     const [input, setInput] = useState("");
     const messagesEndRef = useRef<HTMLDivElement>(null);
-    const [history, setHistory] = useState<Message[]>([]);
+    const [history, setHistory] = useState<ChatMessage[]>([]);
     const [tokenCount, setTokenCount] = useState<number>(0);
 
     useEffect(() => {
@@ -130,7 +129,7 @@ export function AgentChat() {
                 const result = await db.select<any[]>("SELECT role, content FROM messages WHERE session_id = $1 ORDER BY id ASC", [sessionId]);
 
                 // Convert to Message type
-                const loaded: Message[] = result.map(r => {
+                const loaded: ChatMessage[] = result.map(r => {
                     // Check if content is JSON object or string
                     let content = r.content;
                     // If backend saved JSON string, we might want to prettify or just show it?
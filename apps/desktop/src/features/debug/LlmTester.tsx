@@ -17,13 +17,28 @@ export function LlmTester() {
     setError(null);
     try {
       const res = await commands.sendChat({
-        messages: [{ role: "user", content: "Hello" }],
+        messages: [{ role: "user", content: "Hello", images: [], tool_call_id: null, name: null, tool_calls: null }],
         config: {
           base_url: baseUrl,
           api_key: apiKey,
           model: model,
           temperature: 0.7,
+          max_tokens: null,
+          top_p: null,
+          stop: null,
+          frequency_penalty: null,
+          presence_penalty: null,
+          n: null,
+          provider: null,
+          max_retries: null,
+          proxy: null,
+          no_proxy: null,
+          openrouter: null,
+          fallback_base_urls: [],
         },
+        tools: null,
+        tool_choice: null,
+        prompt_cache: null,
       });
 
       if (res.status === "ok") {
@@ -1,4 +1,5 @@
 import { useState, useEffect } from "react";
+import { listen } from "@tauri-apps/api/event";
 import { commands, FileEntry } from "../../bindings";
 
 // Recursive Tree Node Component
@@ -51,6 +52,16 @@ export function FileExplorer() {
 
   useEffect(() => {
     loadFiles();
+    // Reload from scratch when another workspace is opened
+    const unlisten = listen<string>("workspace:changed", (e) => {
+      setSelectedFile(null);
+      setContent("");
+      setStatus(`Opened ${e.payload}`);
+      loadFiles();
+    });
+    return () => {
+      unlisten.then((f) => f());
+    };
   }, []);
 
   async function loadFiles() {
@@ -64,6 +75,10 @@ export function FileExplorer() {
 
   async function handleSelect(entry: FileEntry) {
     if (entry.is_dir) return;
+    if (entry.is_binary) {
+      setStatus(`${entry.name} is a binary file`);
+      return;
+    }
     setSelectedFile(entry);
     setStatus("Loading...");
     const res = await commands.readFile(entry.path);
@@ -78,7 +93,7 @@ export function FileExplorer() {
   async function handleSave() {
     if (!selectedFile) return;
     setStatus("Saving...");
-    const res = await commands.writeFile(selectedFile.path, content);
+    const res = await commands.writeFile(selectedFile.path, content, null);
     if (res.status === "ok") {
       setStatus("Saved!");
     } else {
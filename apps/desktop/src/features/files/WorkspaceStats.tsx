@@ -0,0 +1,77 @@
+import { useState, useEffect } from "react";
+import { commands, WorkspaceStats as Stats } from "../../bindings";
+
+function formatKb(kb: number) {
+  return kb >= 1024 ? `${(kb / 1024).toFixed(1)} MB` : `${kb} KB`;
+}
+
+export function WorkspaceStats() {
+  const [stats, setStats] = useState<Stats | null>(null);
+  const [status, setStatus] = useState("");
+
+  useEffect(() => {
+    loadStats();
+  }, []);
+
+  async function loadStats() {
+    setStatus("Scanning...");
+    const res = await commands.workspaceStats();
+    if (res.status === "ok") {
+      setStats(res.data);
+      setStatus("");
+    } else {
+      setStatus(`Error loading stats: ${JSON.stringify(res.error)}`);
+    }
+  }
+
+  const cell = { padding: "2px 8px" };
+
+  return (
+    <div style={{
+        width: "100%",
+        border: "1px solid #444",
+        background: "#1e1e1e",
+        color: "#ddd",
+        textAlign: "left",
+        borderRadius: "8px",
+        padding: "10px"
+    }}>
+      <div style={{ display: "flex", justifyContent: "space-between", alignItems: "center" }}>
+        <h3 style={{ margin: 0, fontSize: "1em", color: "#bbb" }}>
+          Workspace Stats
+          {stats && ` — ${stats.files} files, ${stats.lines} lines, ${formatKb(stats.size_kb)}`}
+        </h3>
+        <button onClick={loadStats}>Refresh</button>
+      </div>
+      {status && <p style={{ fontSize: "0.8em", color: "#888" }}>{status}</p>}
+      {stats && (
+        <div style={{ display: "flex", gap: "20px", fontSize: "0.85em", marginTop: "10px" }}>
+          <table>
+            <thead><tr><th style={cell}>Language</th><th style={cell}>Files</th><th style={cell}>Lines</th></tr></thead>
+            <tbody>
+              {stats.languages.map((l) => (
+                <tr key={l.language}><td style={cell}>{l.language}</td><td style={cell}>{l.files}</td><td style={cell}>{l.lines}</td></tr>
+              ))}
+            </tbody>
+          </table>
+          <table>
+            <thead><tr><th style={cell}>Largest files</th><th style={cell}>Size</th></tr></thead>
+            <tbody>
+              {stats.largest_files.map((f) => (
+                <tr key={f.path}><td style={{ ...cell, fontFamily: "monospace" }}>{f.path}</td><td style={cell}>{formatKb(f.size_kb)}</td></tr>
+              ))}
+            </tbody>
+          </table>
+          <table>
+            <thead><tr><th style={cell}>Directory</th><th style={cell}>Files</th><th style={cell}>Size</th></tr></thead>
+            <tbody>
+              {stats.directories.map((d) => (
+                <tr key={d.path}><td style={{ ...cell, fontFamily: "monospace" }}>{d.path}</td><td style={cell}>{d.files}</td><td style={cell}>{formatKb(d.size_kb)}</td></tr>
+              ))}
+            </tbody>
+          </table>
+        </div>
+      )}
+    </div>
+  );
+}
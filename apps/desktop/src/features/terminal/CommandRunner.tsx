@@ -18,7 +18,7 @@ export function CommandRunner() {
     const argsList = args.trim().length > 0 ? args.trim().split(/\s+/) : [];
 
     try {
-      const res = await commands.runCommand(program, argsList);
+      const res = await commands.runCommand(program, argsList, null);
       if (res.status === "ok") {
         setOutput(res.data);
       } else {
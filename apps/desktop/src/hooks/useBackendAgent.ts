@@ -11,8 +11,11 @@ interface StreamEvent {
     Done?: null;
 }
 
+// What the chat renders; the rest of a gateway Message is not shown
+export type ChatMessage = Pick<Message, "role" | "content">;
+
 export function useBackendAgent() {
-  const [messages, setMessages] = useState<Message[]>([]);
+  const [messages, setMessages] = useState<ChatMessage[]>([]);
   const [isLooping, setIsLooping] = useState(false);
   const sessionIdRef = useRef<string | null>(null);
 
@@ -143,7 +146,7 @@ export function useBackendAgent() {
 
     try {
         // Start backend loop
-        const res = await commands.startAgentLoop(userPrompt);
+        const res = await commands.startAgentLoop(userPrompt, null);
         if (res.status === "ok") {
             const sid = res.data;
             if (sid !== sessionIdRef.current) {
@@ -13,16 +13,33 @@ You have access to the following tools. YOU MUST USE THEM to explore and edit th
    - Description: Lists files in a directory recursively.
 
 3. read_file
-   - Arguments: <file_path> (string)
-   - Description: Reads the content of a file.
+   - Arguments: <file_path> (string), <start_line> (optional, number), <end_line> (optional, number), <cursor> (optional, string)
+   - Description: Reads the content of a file. With start_line/end_line, returns only those lines, numbered, plus the total line count. Large files are truncated with a note giving the cursor for the next page.
 
 4. write_file
-   - Arguments: <file_path> (string), <content> (string)
-   - Description: Overwrites or creates a file with content. Ensure parent directories exist.
+   - Arguments: <file_path> (string), <content> (string), <skip_validation> (optional, true/false)
+   - Description: Overwrites or creates a file with content. Ensure parent directories exist. Set skip_validation only for files that are intentionally not valid syntax.
+
+5. edit_file
+   - Arguments: <file_path> (string), <old_string> (string), <new_string> (string), <replace_all> (optional, true/false)
+   - Description: Replaces an exact snippet in an existing file. old_string must match exactly once unless replace_all is true. Prefer this over write_file for small changes.
+
+6. insert_lines
+   - Arguments: <file_path> (string), <line> (number, 1-based), <content> (string)
+   - Description: Inserts content before the given line. Use one past the last line to append.
+
+7. replace_lines
+   - Arguments: <file_path> (string), <start_line> (number), <end_line> (number, inclusive), <content> (string)
+   - Description: Replaces a range of lines, e.g. as reported by search_code or read_file with line numbers.
+
+8. delete_lines
+   - Arguments: <file_path> (string), <start_line> (number), <end_line> (number, inclusive)
+   - Description: Deletes a range of lines.
 
 ## PROTOCOL
 To use a tool, output a strictly formatted XML block.
 You can chain multiple tools in one block.
+Wrap argument values that contain markup or code in <![CDATA[ ... ]]>; otherwise escape & and < as &amp; and &lt;.
 
 Example:
 <tool_code>
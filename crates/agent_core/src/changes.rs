@@ -0,0 +1,127 @@
+use std::collections::BTreeMap;
+use std::path::Path;
+use std::sync::Mutex;
+use similar::TextDiff;
+
+/// Tools whose paths (see [`written_paths`]) are snapshotted before and after they run.
+pub const FILE_WRITING_TOOLS: &[&str] = &["write_file", "edit_file", "insert_lines", "replace_lines", "delete_lines", "delete_file", "restore_backup", "move_file"];
+
+/// The workspace paths a file-writing tool call touches: both ends of a move, otherwise `file_path`.
+pub fn written_paths(tool_name: &str, args: &serde_json::Map<String, serde_json::Value>) -> Vec<String> {
+    if !FILE_WRITING_TOOLS.contains(&tool_name) {
+        return Vec::new();
+    }
+    let keys: &[&str] = if tool_name == "move_file" { &["from", "to"] } else { &["file_path"] };
+    keys.iter()
+        .filter_map(|key| args.get(*key).and_then(|v| v.as_str()).map(|p| p.to_string()))
+        .collect()
+}
+
+/// Files a `move_file` call will rewrite to follow the move, besides its two ends. Only
+/// files that already import the moved one, so they can be snapshotted before the move.
+pub fn move_importers(root: &Path, tool_name: &str, args: &serde_json::Map<String, serde_json::Value>) -> Vec<String> {
+    if tool_name != "move_file" {
+        return Vec::new();
+    }
+    let arg = |key: &str| args.get(key).and_then(|v| v.as_str());
+    let (Some(from), Some(to)) = (arg("from"), arg("to")) else { return Vec::new() };
+    workspace_manager::move_importers(root, from, to)
+        .map(|paths| paths.iter().map(|p| p.to_string_lossy().into_owned()).collect())
+        .unwrap_or_default()
+}
+
+struct TrackedFile {
+    // Content when the session first touched the file; None if it did not exist
+    before: Option<String>,
+    after: Option<String>,
+}
+
+#[derive(Debug, Clone)]
+pub struct FileChange {
+    pub path: String,
+    pub before: Option<String>,
+    pub after: Option<String>,
+    pub diff: String,
+}
+
+#[derive(Default)]
+pub struct ChangeTracker {
+    files: Mutex<BTreeMap<String, TrackedFile>>,
+}
+
+impl ChangeTracker {
+    /// Records a write. Returns false when the content did not actually change.
+    pub fn record(&self, path: &str, before: Option<String>, after: Option<String>) -> bool {
+        if before == after {
+            return false;
+        }
+        let mut files = self.files.lock().unwrap();
+        files.entry(path.to_string())
+            .and_modify(|f| f.after = after.clone())
+            .or_insert(TrackedFile { before, after });
+        true
+    }
+
+    pub fn changes(&self) -> Vec<FileChange> {
+        let files = self.files.lock().unwrap();
+        files.iter()
+            .filter(|(_, f)| f.before != f.after)
+            .map(|(path, f)| {
+                let before = f.before.clone().unwrap_or_default();
+                let after = f.after.clone().unwrap_or_default();
+                let diff = TextDiff::from_lines(&before, &after)
+                    .unified_diff()
+                    .header(&format!("a/{}", path), &format!("b/{}", path))
+                    .to_string();
+                FileChange {
+                    path: path.clone(),
+                    before: f.before.clone(),
+                    after: f.after.clone(),
+                    diff,
+                }
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_tracker_keeps_original_content() {
+        let tracker = ChangeTracker::default();
+        assert!(tracker.record("a.rs", None, Some("v1\n".into())));
+        assert!(tracker.record("a.rs", Some("v1\n".into()), Some("v2\n".into())));
+        assert!(!tracker.record("b.rs", Some("same".into()), Some("same".into())));
+
+        let changes = tracker.changes();
+        assert_eq!(changes.len(), 1);
+        assert_eq!(changes[0].before, None);
+        assert_eq!(changes[0].after.as_deref(), Some("v2\n"));
+        assert!(changes[0].diff.contains("+v2"));
+    }
+
+    #[test]
+    fn test_written_paths_covers_both_ends_of_a_move() {
+        let args = serde_json::json!({ "from": "src/a.rs", "to": "src/b.rs" });
+        assert_eq!(written_paths("move_file", args.as_object().unwrap()), vec!["src/a.rs", "src/b.rs"]);
+        let args = serde_json::json!({ "file_path": "src/a.rs" });
+        let args = args.as_object().unwrap();
+        assert_eq!(written_paths("edit_file", args), vec!["src/a.rs"]);
+        assert!(written_paths("read_file", args).is_empty());
+    }
+
+    #[test]
+    fn test_move_importers_are_known_before_the_move() {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::create_dir_all(dir.path().join("src/lib")).unwrap();
+        std::fs::write(dir.path().join("src/app.ts"), "import { a } from './lib/a';\n").unwrap();
+        std::fs::write(dir.path().join("src/lib/a.ts"), "export const a = 1;\n").unwrap();
+
+        let args = serde_json::json!({ "from": "src/lib/a.ts", "to": "src/a.ts" });
+        let args = args.as_object().unwrap();
+        assert_eq!(move_importers(dir.path(), "move_file", args), vec!["src/app.ts"]);
+        assert!(move_importers(dir.path(), "write_file", args).is_empty());
+    }
+}
@@ -0,0 +1,271 @@
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use similar::TextDiff;
+use tauri::{Emitter, Window};
+
+use common::TerminalState;
+use crate::{spawn_agent_loop, AgentRole, LLMConfig, SessionManager};
+
+// A/B experiments: two Coder loops with different models work on isolated copies of the
+// workspace, then the Verifier model compares the resulting changes.
+
+// Per changed file, in the diff shown to the Verifier
+const MAX_FILE_CHARS: usize = 8_000;
+// Wall-clock budget per variant. Nobody may be watching an experiment, so a question, an
+// approval or the iteration limit must not park a variant indefinitely.
+const VARIANT_MAX_SECS: u64 = 20 * 60;
+// Past the budget, for a model call that never returns
+const VARIANT_GRACE_SECS: u64 = 60;
+
+const COMPARE_PROMPT: &str = r#"You are the Adversary (Verifier) judging an experiment.
+Two Coders implemented the same request independently. For each variant you get the files it changed.
+Compare them on correctness, completeness, code quality and risk of regressions.
+Finish with a line of the form `Winner: <label>` (or `Winner: tie`)."#;
+
+#[derive(Debug, Clone)]
+pub struct ExperimentVariant {
+    pub label: String,
+    pub model: String,
+}
+
+#[derive(Debug, Clone)]
+pub struct VariantOutcome {
+    pub label: String,
+    pub model: String,
+    pub session_id: String,
+    pub changed_files: Vec<String>,
+    // Unified diff of the variant's changes; its workspace copy is gone once the experiment ends
+    pub diff: String,
+}
+
+#[derive(Debug, Clone)]
+pub struct ExperimentReport {
+    pub id: String,
+    pub variants: Vec<VariantOutcome>,
+    pub comparison: String,
+}
+
+// Removes the experiment's workspace copies however the experiment ends
+struct SnapshotDir(PathBuf);
+
+impl Drop for SnapshotDir {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_dir_all(&self.0);
+    }
+}
+
+// Files under `root` that the workspace ignore rules (built-ins, .irongraphignore, .gitignore)
+// keep. Dotfiles are included, and .gitignore applies even though copies are not git repos.
+fn collect_files(root: &Path) -> std::io::Result<BTreeMap<String, Vec<u8>>> {
+    let mut files = BTreeMap::new();
+    let mut walker = workspace_manager::ignores::walker(root);
+    walker.hidden(false).require_git(false);
+    for entry in walker.build() {
+        let entry = entry.map_err(std::io::Error::other)?;
+        if !entry.file_type().map_or(false, |ft| ft.is_file()) {
+            continue;
+        }
+        let relative = entry.path().strip_prefix(root).unwrap_or(entry.path()).to_string_lossy().to_string();
+        files.insert(relative, std::fs::read(entry.path())?);
+    }
+    Ok(files)
+}
+
+fn snapshot_workspace(root: &Path, dest: &Path) -> std::io::Result<BTreeMap<String, Vec<u8>>> {
+    let files = collect_files(root)?;
+    for (relative, content) in &files {
+        let target = dest.join(relative);
+        if let Some(parent) = target.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        std::fs::write(target, content)?;
+    }
+    Ok(files)
+}
+
+// The files that differ between `original` and the copy at `snapshot`, with their unified diff
+fn diff_snapshot(original: &BTreeMap<String, Vec<u8>>, snapshot: &Path) -> std::io::Result<(Vec<String>, String)> {
+    let current = collect_files(snapshot)?;
+    let mut changed = Vec::new();
+    let mut diff = String::new();
+    let paths: std::collections::BTreeSet<&String> = original.keys().chain(current.keys()).collect();
+    for path in paths {
+        let (before, after) = (original.get(path), current.get(path));
+        if before == after {
+            continue;
+        }
+        changed.push(if after.is_none() { format!("{} (deleted)", path) } else { path.clone() });
+
+        let before = before.map(|b| String::from_utf8_lossy(b).to_string()).unwrap_or_default();
+        let after = after.map(|a| String::from_utf8_lossy(a).to_string()).unwrap_or_default();
+        let file_diff = TextDiff::from_lines(&before, &after)
+            .unified_diff()
+            .header(&format!("a/{}", path), &format!("b/{}", path))
+            .to_string();
+        let mut chars = file_diff.chars();
+        diff.extend(chars.by_ref().take(MAX_FILE_CHARS));
+        if chars.next().is_some() {
+            diff.push_str(&format!("\n[... diff of {} truncated after {} characters]\n", path, MAX_FILE_CHARS));
+        }
+    }
+    Ok((changed, diff))
+}
+
+fn describe_variant(outcome: &VariantOutcome) -> String {
+    let mut out = format!("## Variant {} ({})\n", outcome.label, outcome.model);
+    if outcome.changed_files.is_empty() {
+        out.push_str("No files changed.\n");
+    } else {
+        out.push_str(&format!("```diff\n{}\n```\n", outcome.diff));
+    }
+    out
+}
+
+pub async fn run_ab_experiment(
+    window: Window,
+    sessions: &SessionManager,
+    workspace_root: PathBuf,
+    terminal_state: Arc<TerminalState>,
+    prompt: String,
+    config: LLMConfig,
+    variants: [ExperimentVariant; 2],
+) -> Result<ExperimentReport, String> {
+    let id = uuid::Uuid::new_v4().to_string();
+    let base_dir = std::env::temp_dir().join("irongraph-experiments").join(&id);
+    let _snapshots = SnapshotDir(base_dir.clone());
+
+    let mut prepared = Vec::new();
+    for variant in &variants {
+        let workspace = base_dir.join(&variant.label);
+        let original = snapshot_workspace(&workspace_root, &workspace)
+            .map_err(|e| format!("Failed to snapshot workspace for {}: {}", variant.label, e))?;
+
+        let mut variant_config = config.clone();
+        variant_config.coder.model = Some(variant.model.clone());
+        variant_config.coder_only = true;
+        // Every wait in the loop ends at the run's deadline
+        variant_config.limits.max_duration_secs = Some(config.limits.max_duration_secs.map_or(VARIANT_MAX_SECS, |secs| secs.min(VARIANT_MAX_SECS)));
+
+        prepared.push((variant.clone(), sessions.create(), workspace, original, variant_config));
+    }
+
+    let _ = window.emit(&format!("experiment:started:{}", id), prepared.iter()
+        .map(|(v, s, ..)| (v.label.clone(), s.id.clone()))
+        .collect::<Vec<_>>());
+
+    let runs = prepared.iter().map(|(_, session, workspace, _, variant_config)| {
+        spawn_agent_loop(
+            window.clone(),
+            session.clone(),
+            Arc::new(Mutex::new(workspace.clone())),
+            terminal_state.clone(),
+            prompt.clone(),
+            variant_config.clone(),
+        )
+    });
+    let hard_limit = std::time::Duration::from_secs(VARIANT_MAX_SECS + VARIANT_GRACE_SECS);
+    if tokio::time::timeout(hard_limit, futures::future::join_all(runs)).await.is_err() {
+        // The unfinished loops were dropped with the timeout; compare what they wrote so far
+        for (_, session, ..) in &prepared {
+            session.stop();
+        }
+    }
+
+    let mut outcomes = Vec::new();
+    for (variant, session, workspace, original, _) in prepared {
+        let (changed, diff) = diff_snapshot(&original, &workspace)
+            .map_err(|e| format!("Failed to diff variant {}: {}", variant.label, e))?;
+        session.release_terminal();
+        outcomes.push(VariantOutcome {
+            label: variant.label,
+            model: variant.model,
+            session_id: session.id.clone(),
+            changed_files: changed,
+            diff,
+        });
+    }
+
+    let mut request = format!("Original request:\n{}\n\n", prompt);
+    for outcome in &outcomes {
+        request.push_str(&describe_variant(outcome));
+        request.push('\n');
+    }
+
+    let gateway_config = config.gateway_config(&AgentRole::Verifier);
+    let verifier = llm_gateway::registry().resolve(&gateway_config)?;
+    let response = verifier.chat(&llm_gateway::LLMRequest {
+        messages: vec![
+            llm_gateway::Message::new("system", COMPARE_PROMPT),
+            llm_gateway::Message::new("user", request),
+        ],
+        config: gateway_config,
+        tools: None,
+        tool_choice: None,
+        prompt_cache: None,
+    }).await.map_err(|e| format!("Verifier comparison failed: {}", e))?;
+    let comparison = response.content;
+
+    let report = ExperimentReport { id, variants: outcomes, comparison };
+    let _ = window.emit(&format!("experiment:finished:{}", report.id), report.comparison.clone());
+    Ok(report)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::tempdir;
+
+    #[test]
+    fn test_snapshot_follows_ignore_rules_and_diffs_changes() {
+        let root = tempdir().unwrap();
+        let write = |path: &str, content: &str| {
+            let path = root.path().join(path);
+            std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+            std::fs::write(path, content).unwrap();
+        };
+        write("src/lib.rs", "fn a() {}\n");
+        write(".env", "KEY=1\n");
+        write(".gitignore", "*.log\n");
+        write("debug.log", "noise\n");
+        write("target/debug/out", "binary\n");
+        write(workspace_manager::IGNORE_FILE, "fixtures/\n");
+        write("fixtures/big.json", "{}\n");
+
+        let base = tempdir().unwrap();
+        let copy = base.path().join("A");
+        let original = snapshot_workspace(root.path(), &copy).unwrap();
+        assert!(copy.join("src/lib.rs").exists());
+        assert!(copy.join(".env").exists());
+        assert!(!copy.join("debug.log").exists());
+        assert!(!copy.join("target").exists());
+        assert!(!copy.join("fixtures").exists());
+
+        std::fs::write(copy.join("src/lib.rs"), "fn b() {}\n").unwrap();
+        std::fs::write(copy.join("src/new.rs"), "fn c() {}\n").unwrap();
+        std::fs::write(copy.join("build.log"), "ignored\n").unwrap();
+        std::fs::remove_file(copy.join(".env")).unwrap();
+
+        let (changed, diff) = diff_snapshot(&original, &copy).unwrap();
+        assert_eq!(changed, [".env (deleted)", "src/lib.rs", "src/new.rs"]);
+        assert!(diff.contains("-fn a() {}"));
+        assert!(diff.contains("+fn b() {}"));
+        assert!(diff.contains("+++ b/src/new.rs"));
+
+        drop(SnapshotDir(copy.clone()));
+        assert!(!copy.exists());
+    }
+
+    #[test]
+    fn test_long_diffs_are_marked_truncated() {
+        let root = tempdir().unwrap();
+        std::fs::write(root.path().join("big.txt"), "").unwrap();
+        let base = tempdir().unwrap();
+        let copy = base.path().join("A");
+        let original = snapshot_workspace(root.path(), &copy).unwrap();
+
+        std::fs::write(copy.join("big.txt"), "line\n".repeat(MAX_FILE_CHARS)).unwrap();
+        let (_, diff) = diff_snapshot(&original, &copy).unwrap();
+        assert!(diff.ends_with(&format!("[... diff of big.txt truncated after {} characters]\n", MAX_FILE_CHARS)));
+    }
+}
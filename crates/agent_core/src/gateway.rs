@@ -0,0 +1,171 @@
+use std::collections::HashMap;
+use std::sync::Arc;
+use async_trait::async_trait;
+use radkit::errors::{AgentError, AgentResult};
+use radkit::models::{BaseLlm, Content, ContentPart, Event, LlmResponse, Role, Thread, TokenUsage};
+use radkit::tools::{BaseToolset, ToolCall};
+use serde_json::Value;
+
+// radkit model backed by the llm_gateway provider registry, so every turn of a run (tool
+// calls, handoffs and reflections alike) goes to the configured provider with its own key.
+
+pub struct GatewayLlm {
+    config: llm_gateway::LLMConfig,
+}
+
+impl GatewayLlm {
+    pub fn new(config: llm_gateway::LLMConfig) -> Self {
+        Self { config }
+    }
+}
+
+// The thread as gateway messages. Tool results carry only their call id, so the tool name
+// is looked up from the call that produced it.
+fn to_messages(thread: &Thread) -> Vec<llm_gateway::Message> {
+    let mut messages = Vec::new();
+    if let Some(system) = thread.system() {
+        messages.push(llm_gateway::Message::new("system", system));
+    }
+    let mut call_names: HashMap<String, String> = HashMap::new();
+    for event in thread.events() {
+        let mut text = String::new();
+        let mut calls = Vec::new();
+        let mut results = Vec::new();
+        for part in event.content().parts() {
+            match part {
+                ContentPart::Text(t) => text.push_str(t),
+                ContentPart::ToolCall(call) => {
+                    call_names.insert(call.id().to_string(), call.name().to_string());
+                    calls.push(llm_gateway::ToolCall::native(Some(call.id().to_string()), call.name().to_string(), &call.arguments().to_string()));
+                }
+                ContentPart::ToolResponse(response) => {
+                    let id = response.tool_call_id().to_string();
+                    let name = call_names.get(&id).cloned().unwrap_or_default();
+                    results.push(llm_gateway::Message::tool_result(id, name, response.result().data().to_string()));
+                }
+                _ => {}
+            }
+        }
+        match event.role() {
+            Role::Assistant => {
+                let mut message = llm_gateway::Message::new("assistant", text);
+                if !calls.is_empty() {
+                    message.tool_calls = Some(calls);
+                }
+                messages.push(message);
+            }
+            Role::System => messages.push(llm_gateway::Message::new("system", text)),
+            _ => {
+                messages.extend(results);
+                if !text.is_empty() {
+                    messages.push(llm_gateway::Message::new("user", text));
+                }
+            }
+        }
+    }
+    messages
+}
+
+async fn tool_definitions(toolset: &dyn BaseToolset) -> Vec<llm_gateway::ToolDefinition> {
+    toolset.get_tools().await.into_iter()
+        .map(|tool| {
+            let declaration = tool.declaration();
+            llm_gateway::ToolDefinition {
+                name: declaration.name().to_string(),
+                description: declaration.description().to_string(),
+                parameters: declaration.parameters().clone(),
+            }
+        })
+        .collect()
+}
+
+fn to_content(res: llm_gateway::LLMResponse) -> Content {
+    let mut parts = Vec::new();
+    if !res.content.is_empty() {
+        parts.push(ContentPart::Text(res.content));
+    }
+    for call in res.tool_calls.into_iter().flatten() {
+        // Ollama, Gemini and XML-protocol calls have no id; results are paired by id
+        let id = call.id.clone().unwrap_or_else(|| format!("call_{}", uuid::Uuid::new_v4().simple()));
+        let arguments = call.raw_arguments.clone().unwrap_or_else(|| {
+            Value::Object(call.arguments.into_iter().map(|(k, v)| (k, Value::String(v))).collect())
+        });
+        parts.push(ContentPart::ToolCall(ToolCall::new(id, call.name, arguments)));
+    }
+    Content::from_parts(parts)
+}
+
+#[async_trait]
+impl BaseLlm for GatewayLlm {
+    fn model_name(&self) -> &str {
+        &self.config.model
+    }
+
+    fn provider_name(&self) -> &str {
+        self.config.provider_name()
+    }
+
+    async fn generate_content(&self, thread: Thread, toolset: Option<Arc<dyn BaseToolset>>) -> AgentResult<LlmResponse> {
+        let tools = match &toolset {
+            Some(toolset) => Some(tool_definitions(toolset.as_ref()).await).filter(|t| !t.is_empty()),
+            None => None,
+        };
+        let provider = llm_gateway::registry().resolve(&self.config)
+            .map_err(|message| AgentError::LlmProvider { provider: self.provider_name().to_string(), message })?;
+        let res = provider.chat(&llm_gateway::LLMRequest {
+            messages: to_messages(&thread),
+            config: self.config.clone(),
+            tools,
+            tool_choice: None,
+            prompt_cache: None,
+        }).await.map_err(|message| AgentError::LlmProvider { provider: self.provider_name().to_string(), message })?;
+
+        let usage = res.usage.clone().unwrap_or_default();
+        let tokens = |key: &str| usage.get(key).copied().unwrap_or(0);
+        let usage = TokenUsage::new(tokens("prompt_tokens"), tokens("completion_tokens"));
+        Ok(LlmResponse::new(to_content(res), usage))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use radkit::tools::{ToolResponse, ToolResult};
+    use serde_json::json;
+
+    #[test]
+    fn test_thread_keeps_calls_and_results_paired() {
+        let call = ToolCall::new("c1", "insert_lines", json!({ "file_path": "a.rs", "line": 3 }));
+        let thread = Thread::from_system("be brief")
+            .add_event(Event::user("add a line"))
+            .add_event(Event::assistant(Content::from_parts(vec![ContentPart::ToolCall(call)])))
+            .add_event(Event::from(ToolResponse::new("c1".to_string(), ToolResult::success("done".into()))));
+
+        let messages = to_messages(&thread);
+        let roles: Vec<&str> = messages.iter().map(|m| m.role.as_str()).collect();
+        assert_eq!(roles, ["system", "user", "assistant", "tool"]);
+        let calls = messages[2].tool_calls.as_ref().unwrap();
+        assert_eq!(calls[0].raw_arguments, Some(json!({ "file_path": "a.rs", "line": 3 })));
+        assert_eq!((messages[3].tool_call_id.as_deref(), messages[3].name.as_deref()), (Some("c1"), Some("insert_lines")));
+    }
+
+    #[test]
+    fn test_response_calls_become_tool_call_parts() {
+        let res = llm_gateway::LLMResponse {
+            role: "assistant".into(),
+            content: "Reading it.".into(),
+            tool_calls: Some(vec![llm_gateway::ToolCall::native(None, "read_file".into(), r#"{"file_path":"a.rs","start_line":2}"#)]),
+            usage: None,
+            reasoning: None,
+            choices: Vec::new(),
+            endpoint: None,
+        };
+        let content = to_content(res);
+        let parts = content.parts();
+        assert!(matches!(&parts[0], ContentPart::Text(t) if t == "Reading it."));
+        let ContentPart::ToolCall(call) = &parts[1] else { panic!("expected a tool call") };
+        assert_eq!(call.name(), "read_file");
+        assert!(!call.id().is_empty());
+        assert_eq!(call.arguments(), &json!({ "file_path": "a.rs", "start_line": 2 }));
+    }
+}
@@ -1,23 +1,42 @@
 use std::sync::{Arc, Mutex};
 use std::sync::atomic::{AtomicBool, Ordering};
 use tauri::{Window, Emitter};
-use tokio::sync::mpsc;
+use tokio::sync::{mpsc, oneshot};
 use async_trait::async_trait;
-use radkit::models::providers::OpenRouterLlm;
 use radkit::models::{BaseLlm, ContentPart, Thread, Event};
-use radkit::tools::{BaseToolset, SimpleToolset, ToolContext, ToolResponse};
+use radkit::tools::{BaseToolset, SimpleToolset, ToolContext, ToolResponse, ToolResult};
 use serde::{Deserialize, Serialize};
 
+pub mod tools;
+use tools::{ask_user, ASK_USER_TOOL};
+
+mod sessions;
+pub use sessions::SessionManager;
+
+pub mod replay;
+
+pub mod experiment;
+
+mod gateway;
+use gateway::GatewayLlm;
+
+pub mod changes;
+use changes::{move_importers, written_paths, ChangeTracker, FILE_WRITING_TOOLS};
+
 // Imports for tools
-use workspace_manager::tools::{read_file, write_file, list_files, read_skeleton, search_code};
-use terminal_manager::tools::{run_command};
-use common::{RadkitState, TerminalState, SessionState, register_session, unregister_session};
+use workspace_manager::tools::{read_file, read_asset, write_file, delete_file, restore_backup, create_directory, move_file, edit_file, insert_lines, replace_lines, delete_lines, list_files, fuzzy_find_files, read_skeleton, read_skeletons, outline, search_code, semantic_search, find_references, workspace_stats, detect_project, list_todos, git_status, git_diff, git_blame, dependencies_of, dependents_of, find_cycles, crate_graph, impact_of};
+use terminal_manager::history::CommandRecord;
+use terminal_manager::policy::PolicyDecision;
+use terminal_manager::tools::{run_command, read_terminal_buffer, interrupt_terminal, start_background, get_background_output, stop_background};
+use common::{Embedder, RadkitState, TerminalState, SessionState, register_session, unregister_session};
 
 // Define HistoryRepository trait for persistence abstraction
 #[async_trait]
 pub trait HistoryRepository: Send + Sync {
     async fn add_message(&self, session_id: &str, message: serde_json::Value) -> anyhow::Result<()>;
     async fn get_history(&self, session_id: &str) -> anyhow::Result<Vec<serde_json::Value>>;
+    async fn add_command(&self, session_id: &str, record: &CommandRecord) -> anyhow::Result<()>;
+    async fn get_commands(&self, session_id: &str) -> anyhow::Result<Vec<CommandRecord>>;
 }
 
 pub struct AgentSession {
@@ -27,19 +46,103 @@ pub struct AgentSession {
     pub terminal_session_id: Mutex<Option<String>>,
     pub command_buffer: Arc<Mutex<Option<mpsc::Sender<String>>>>,
     pub terminal_state: Option<Arc<TerminalState>>,
+    pub pending_answer: Mutex<Option<oneshot::Sender<String>>>,
+    pub pending_continue: Mutex<Option<oneshot::Sender<u32>>>,
+    pub changes: ChangeTracker,
 }
 
 impl AgentSession {
     pub fn new(repository: Box<dyn HistoryRepository>, terminal_state: Arc<TerminalState>) -> Self {
+        Self::with_repository(Arc::new(repository), terminal_state)
+    }
+
+    pub fn with_repository(repository: Arc<Box<dyn HistoryRepository>>, terminal_state: Arc<TerminalState>) -> Self {
+        Self::with_id(uuid::Uuid::new_v4().to_string(), repository, terminal_state)
+    }
+
+    pub fn with_id(id: String, repository: Arc<Box<dyn HistoryRepository>>, terminal_state: Arc<TerminalState>) -> Self {
         Self {
-            id: uuid::Uuid::new_v4().to_string(),
-            repository: Arc::new(repository),
+            id,
+            repository,
             status: AtomicBool::new(false),
             terminal_session_id: Mutex::new(None),
             command_buffer: Arc::new(Mutex::new(None)),
             terminal_state: Some(terminal_state),
+            pending_answer: Mutex::new(None),
+            pending_continue: Mutex::new(None),
+            changes: ChangeTracker::default(),
+        }
+    }
+
+    /// Kills the session's shell so a stopped run does not keep a PTY alive.
+    pub fn release_terminal(&self) {
+        let terminal_id = self.terminal_session_id.lock().unwrap().take();
+        if let (Some(state), Some(id)) = (&self.terminal_state, terminal_id) {
+            let _ = terminal_manager::kill_session(state, &id);
+        }
+    }
+
+    /// Persists what ran in the session's terminal since the last call.
+    pub async fn persist_commands(&self, terminal_session_id: &str) {
+        for record in terminal_manager::history::take(terminal_session_id) {
+            let _ = self.repository.add_command(&self.id, &record).await;
         }
     }
+
+    /// Every command run in this session: persisted ones, then any still waiting.
+    pub async fn command_history(&self) -> anyhow::Result<Vec<CommandRecord>> {
+        let mut records = self.repository.get_commands(&self.id).await?;
+        if let Some(terminal_id) = self.terminal_session_id.lock().unwrap().clone() {
+            records.extend(terminal_manager::history::pending(&terminal_id));
+        }
+        Ok(records)
+    }
+
+    /// Registers a pending question and returns the receiver the loop blocks on.
+    pub fn begin_question(&self) -> oneshot::Receiver<String> {
+        let (tx, rx) = oneshot::channel();
+        *self.pending_answer.lock().unwrap() = Some(tx);
+        rx
+    }
+
+    /// Delivers the user's reply to a loop blocked in `ask_user`.
+    pub fn answer_question(&self, answer: String) -> Result<(), String> {
+        let sender = self.pending_answer.lock().unwrap().take()
+            .ok_or("No question is pending for this session")?;
+        sender.send(answer).map_err(|_| "Agent loop is no longer waiting for an answer".to_string())
+    }
+
+    /// Parks the loop at its iteration limit until `continue_run` grants more budget.
+    pub fn begin_limit_wait(&self) -> oneshot::Receiver<u32> {
+        let (tx, rx) = oneshot::channel();
+        *self.pending_continue.lock().unwrap() = Some(tx);
+        rx
+    }
+
+    pub fn continue_run(&self, extra_iterations: u32) -> Result<(), String> {
+        let sender = self.pending_continue.lock().unwrap().take()
+            .ok_or("Session is not waiting at its iteration limit")?;
+        sender.send(extra_iterations).map_err(|_| "Agent loop is no longer waiting to continue".to_string())
+    }
+
+    /// Ends the run: the loop stops at its next check, and a wait for an answer, an approval
+    /// or more iterations ends at once. False if the session wasn't running.
+    pub fn stop(&self) -> bool {
+        let was_running = self.status.swap(false, Ordering::Relaxed);
+        // Dropping the senders fails the loop's pending receivers
+        let question = self.pending_answer.lock().unwrap().take();
+        let limit = self.pending_continue.lock().unwrap().take();
+        was_running || question.is_some() || limit.is_some()
+    }
+}
+
+// Waits for a reply from the user; None once `stop` dropped the sender or the run's time
+// is up
+async fn await_reply<T>(rx: oneshot::Receiver<T>, deadline: Option<tokio::time::Instant>) -> Option<T> {
+    match deadline {
+        Some(deadline) => tokio::time::timeout_at(deadline, rx).await.ok()?.ok(),
+        None => rx.await.ok(),
+    }
 }
 
 impl Drop for AgentSession {
@@ -62,7 +165,7 @@ pub enum AgentRole {
 }
 
 impl AgentRole {
-    fn as_str(&self) -> &'static str {
+    pub fn as_str(&self) -> &'static str {
         match self {
             AgentRole::Coder => "coder",
             AgentRole::Verifier => "verifier",
@@ -79,13 +182,40 @@ Once you have written the code, the Verifier will take over to test it."#;
 const VERIFIER_PROMPT: &str = r#"You are the Adversary (Verifier).
 Your goal is to PROVE the Coder's implementation is flawed.
 Trust nothing.
-1. Analyze the code just written.
+1. Analyze the code just written. `impact_of` lists the callers and existing tests affected by a changed function.
 2. Write a reproduction script or test case (e.g., test_repro.rs) that targets edge cases or potential bugs.
 3. Run the test using `run_command`.
    - If the test FAILS (Exit Code != 0), you have succeeded. The Coder will be summoned to fix it.
    - If the test PASSES (Exit Code 0), you have failed to break it.
 4. If you cannot break the code and are satisfied it is correct, output the exact tag: <verified />"#;
 
+/// Role transition triggered by a tool call's arguments and result (its JSON-serialized data), if any.
+pub fn next_role(current: &AgentRole, tool_name: &str, arguments: &serde_json::Value, output: &str) -> Option<AgentRole> {
+    match current {
+        // Transition Coder -> Verifier once code has been written; a preview writes nothing
+        AgentRole::Coder if FILE_WRITING_TOOLS.contains(&tool_name) && !is_preview(arguments) => Some(AgentRole::Verifier),
+        // A failing 'run_command' means the Verifier broke it. Back to Coder.
+        // On success the Verifier should see this and output <verified /> next turn.
+        AgentRole::Verifier if tool_name == "run_command" && !command_succeeded(output) => Some(AgentRole::Coder),
+        _ => None,
+    }
+}
+
+// Whether a write_file/edit_file call only previewed its diff
+fn is_preview(arguments: &serde_json::Value) -> bool {
+    parse_tool_args(arguments)
+        .map_or(false, |args| args.get("preview").and_then(|v| v.as_bool()) == Some(true))
+}
+
+// Whether a run_command result reports exit code 0. Histories recorded before results were
+// structured only have the "(Exit Code: N)" text.
+fn command_succeeded(output: &str) -> bool {
+    match serde_json::from_str::<serde_json::Value>(output) {
+        Ok(value) if value.is_object() => terminal_manager::tools::command_exit_code(&value) == Some(0),
+        _ => output.contains("(Exit Code: 0)"),
+    }
+}
+
 fn get_prompt_for_role(role: &AgentRole) -> &'static str {
     match role {
         AgentRole::Coder => CODER_PROMPT,
@@ -93,11 +223,255 @@ fn get_prompt_for_role(role: &AgentRole) -> &'static str {
     }
 }
 
+/// Role prompt followed by what `detect_project` found, so neither role starts blind.
+fn system_prompt(role: &AgentRole, project: &workspace_manager::ProjectInfo) -> String {
+    let mut prompt = get_prompt_for_role(role).to_string();
+    let summary = project.summary();
+    if !summary.is_empty() {
+        prompt.push_str("\n\nProject:\n");
+        prompt.push_str(&summary);
+    }
+    if let (AgentRole::Verifier, Some(command)) = (role, &project.verify_command) {
+        prompt.push_str(&format!("\n\nUnless the task calls for a narrower check, finish by running `{}`.", command));
+    }
+    prompt
+}
+
 // Config struct to allow passing API key
 #[derive(serde::Deserialize, Clone)]
 pub struct LLMConfig {
     pub api_key: String,
     pub model: String,
+    #[serde(default)]
+    pub temperature: Option<f32>,
+    #[serde(default)]
+    pub coder: RoleConfig,
+    #[serde(default)]
+    pub verifier: RoleConfig,
+    #[serde(default)]
+    pub limits: RunLimits,
+    // Never hand over to the Verifier; the run ends when the Coder stops calling tools (used by experiments)
+    #[serde(default)]
+    pub coder_only: bool,
+    // llm_gateway provider for every turn of the run; defaults to OpenRouter
+    #[serde(default)]
+    pub provider: Option<String>,
+    #[serde(default)]
+    pub base_url: Option<String>,
+    // Model for the semantic_search index, served by the same provider
+    #[serde(default)]
+    pub embedding_model: Option<String>,
+}
+
+#[derive(serde::Deserialize, Clone, Debug)]
+pub struct RunLimits {
+    pub max_iterations: u32,
+    // Wall-clock budget for a single run, checked between iterations
+    pub max_duration_secs: Option<u64>,
+    // Verifier -> Coder bounces tolerated before a forced reflection turn
+    pub reflect_after_rejections: u32,
+}
+
+impl Default for RunLimits {
+    fn default() -> Self {
+        Self {
+            max_iterations: 40, // Increased for dual loop
+            max_duration_secs: None,
+            reflect_after_rejections: 2,
+        }
+    }
+}
+
+// Per-role overrides; unset fields fall back to the top-level LLMConfig
+#[derive(serde::Deserialize, Clone, Default, Debug)]
+pub struct RoleConfig {
+    pub model: Option<String>,
+    pub temperature: Option<f32>,
+    pub reasoning_effort: Option<String>,
+}
+
+impl LLMConfig {
+    pub fn for_role(&self, role: &AgentRole) -> RoleConfig {
+        let overrides = match role {
+            AgentRole::Coder => &self.coder,
+            AgentRole::Verifier => &self.verifier,
+        };
+        RoleConfig {
+            model: Some(overrides.model.clone().unwrap_or_else(|| self.model.clone())),
+            temperature: overrides.temperature.or(self.temperature),
+            reasoning_effort: overrides.reasoning_effort.clone(),
+        }
+    }
+
+    // Same model settings, expressed for the llm_gateway provider registry
+    pub fn gateway_config(&self, role: &AgentRole) -> llm_gateway::LLMConfig {
+        let role_config = self.for_role(role);
+        llm_gateway::LLMConfig {
+            api_key: self.api_key.clone(),
+            base_url: self.base_url.clone().unwrap_or_else(|| OPENROUTER_BASE_URL.to_string()),
+            model: role_config.model.unwrap_or_default(),
+            temperature: role_config.temperature.unwrap_or(0.0),
+            reasoning_effort: role_config.reasoning_effort,
+            provider: Some(self.provider.clone().unwrap_or_else(|| "openrouter".to_string())),
+            proxy: std::env::var("IRONGRAPH_PROXY").ok(),
+            openrouter: Some(llm_gateway::OpenRouterOptions {
+                referer: Some("https://irongraph.app".to_string()),
+                title: Some("IronGraph".to_string()),
+                ..Default::default()
+            }),
+            ..Default::default()
+        }
+    }
+
+    pub fn embedding_config(&self) -> llm_gateway::LLMConfig {
+        llm_gateway::LLMConfig {
+            model: self.embedding_model.clone().unwrap_or_else(|| DEFAULT_EMBEDDING_MODEL.to_string()),
+            ..self.gateway_config(&AgentRole::Coder)
+        }
+    }
+}
+
+const OPENROUTER_BASE_URL: &str = "https://openrouter.ai/api/v1";
+const DEFAULT_EMBEDDING_MODEL: &str = "openai/text-embedding-3-small";
+
+// Embeddings for the semantic index, through the llm_gateway provider registry
+struct GatewayEmbedder(llm_gateway::LLMConfig);
+
+#[async_trait]
+impl Embedder for GatewayEmbedder {
+    fn model(&self) -> String {
+        self.0.model.clone()
+    }
+
+    async fn embed(&self, inputs: &[String]) -> Result<Vec<Vec<f32>>, String> {
+        llm_gateway::embed(&self.0, inputs).await
+    }
+}
+
+// For gateway requests with n > 1: promote the first candidate whose file writes parse
+pub fn pick_valid_choice(res: llm_gateway::LLMResponse) -> llm_gateway::LLMResponse {
+    llm_gateway::best_of::pick_valid(res, workspace_manager::validate_syntax)
+}
+
+// Models occasionally send arguments as a JSON-encoded string instead of an object
+fn parse_tool_args(arguments: &serde_json::Value) -> Result<serde_json::Map<String, serde_json::Value>, String> {
+    match arguments {
+        serde_json::Value::Object(map) => Ok(map.clone()),
+        serde_json::Value::Null => Ok(serde_json::Map::new()),
+        serde_json::Value::String(raw) => match serde_json::from_str::<serde_json::Value>(raw) {
+            Ok(serde_json::Value::Object(map)) => Ok(map),
+            Ok(other) => Err(format!("expected a JSON object, got {}", other)),
+            Err(e) => Err(format!("arguments are not valid JSON ({}): {}", e, raw)),
+        },
+        other => Err(format!("expected a JSON object, got {}", other)),
+    }
+}
+
+fn is_approval(answer: &str) -> bool {
+    matches!(answer.trim().trim_end_matches(['.', '!']).to_ascii_lowercase().as_str(), "y" | "yes" | "allow" | "approve" | "approved" | "run it")
+}
+
+const HANDOFF_PROMPT: &str = r#"[SYSTEM]: Another agent is about to take over this task. Do not call tools.
+Write a short handoff summary using exactly these sections:
+Files changed: <paths and one line per change>
+Intent: <what the change is supposed to achieve>
+Known risks: <failing tests, edge cases, open questions>"#;
+
+const REFLECTION_PROMPT: &str = r#"[SYSTEM]: The Verifier has rejected your implementation several times. Do not call tools yet.
+Reflect before trying again:
+1. Why did each previous attempt fail? Look for a common root cause.
+2. Which assumption was wrong?
+3. Propose a different strategy than the ones already tried, step by step."#;
+
+// The part of the conversation a role's thread holds: an unpersisted opening message (the
+// handoff it started from), then the persisted history from an index on
+struct Transcript {
+    opening: Option<String>,
+    from: usize,
+}
+
+impl Transcript {
+    // Persisted messages as plain text turns. Tool calls and results are written out, so no
+    // provider needs tool definitions to accept them; consecutive turns of a role are merged.
+    fn messages(&self, history: &[serde_json::Value]) -> Vec<llm_gateway::Message> {
+        let mut messages: Vec<llm_gateway::Message> = Vec::new();
+        let mut push = |role: &str, text: String| match messages.last_mut() {
+            Some(last) if last.role == role => {
+                last.content.push_str("\n\n");
+                last.content.push_str(&text);
+            }
+            _ => messages.push(llm_gateway::Message::new(role, text)),
+        };
+        if let Some(opening) = &self.opening {
+            push("user", opening.clone());
+        }
+        for msg in history.iter().skip(self.from) {
+            let content = msg.get("content").and_then(|v| v.as_str()).unwrap_or_default();
+            match msg.get("role").and_then(|v| v.as_str()).unwrap_or("user") {
+                "assistant" => {
+                    for call in msg.get("tool_calls").and_then(|v| v.as_array()).into_iter().flatten() {
+                        let function = &call["function"];
+                        push("assistant", format!("[Called {} with {}]", function["name"].as_str().unwrap_or("?"), function["arguments"].as_str().unwrap_or("{}")));
+                    }
+                    if !content.is_empty() {
+                        push("assistant", content.to_string());
+                    }
+                }
+                "tool" => push("user", format!("[Tool result]\n{}", content)),
+                _ if !content.is_empty() => push("user", content.to_string()),
+                _ => {}
+            }
+        }
+        messages
+    }
+}
+
+// Runs a tool-less turn: the role's system prompt, the transcript, then `instruction`.
+// None on failure or an empty answer.
+async fn generate_text(llm: &dyn BaseLlm, system: String, transcript: Vec<llm_gateway::Message>, instruction: &str) -> Option<String> {
+    let mut thread = Thread::from_system(system.as_str());
+    for message in transcript {
+        thread = thread.add_event(match message.role.as_str() {
+            "assistant" => Event::assistant(message.content),
+            _ => Event::user(message.content),
+        });
+    }
+    thread = thread.add_event(Event::user(instruction));
+    let content = llm.generate_content(thread, None).await.ok()?.into_content();
+
+    let text: String = content.parts().iter()
+        .filter_map(|part| match part {
+            ContentPart::Text(t) => Some(t.as_str()),
+            _ => None,
+        })
+        .collect();
+    let summary = text.trim();
+    if summary.is_empty() { None } else { Some(summary.to_string()) }
+}
+
+// Where a run reports progress: the frontend window, or a log for replays to inspect
+#[derive(Clone)]
+pub(crate) enum EventSink {
+    Window(Window),
+    Log(Arc<Mutex<Vec<(String, serde_json::Value)>>>),
+}
+
+impl EventSink {
+    fn emit<S: Serialize + Clone>(&self, event: &str, payload: S) -> tauri::Result<()> {
+        match self {
+            EventSink::Window(window) => window.emit(event, payload),
+            EventSink::Log(log) => {
+                log.lock().unwrap().push((event.to_string(), serde_json::to_value(payload).unwrap_or_default()));
+                Ok(())
+            }
+        }
+    }
+}
+
+// The model behind each role
+pub(crate) struct Models {
+    pub coder: Arc<dyn BaseLlm>,
+    pub verifier: Arc<dyn BaseLlm>,
 }
 
 pub async fn spawn_agent_loop(
@@ -107,6 +481,27 @@ pub async fn spawn_agent_loop(
     terminal_state: Arc<TerminalState>,
     initial_prompt: String,
     config: LLMConfig,
+) {
+    // One client per role, built once and reused across role transitions
+    let models = Models {
+        coder: Arc::new(GatewayLlm::new(config.gateway_config(&AgentRole::Coder))),
+        verifier: Arc::new(GatewayLlm::new(config.gateway_config(&AgentRole::Verifier))),
+    };
+    run_agent_loop(EventSink::Window(window), session, workspace_state, terminal_state, initial_prompt, config, models, None).await
+}
+
+// The agent loop proper. Replays pass `replay` to stand in for the user and, in stub
+// mode, for the tools.
+#[allow(clippy::too_many_arguments)]
+pub(crate) async fn run_agent_loop(
+    window: EventSink,
+    session: Arc<AgentSession>,
+    workspace_state: Arc<Mutex<std::path::PathBuf>>,
+    terminal_state: Arc<TerminalState>,
+    initial_prompt: String,
+    config: LLMConfig,
+    models: Models,
+    replay: Option<&replay::ReplayHooks>,
 ) {
     let session_id = session.id.clone();
     let session_clone = session.clone();
@@ -118,15 +513,24 @@ pub async fn spawn_agent_loop(
             let root = workspace_state.lock().unwrap().clone();
             let (tx, mut rx) = mpsc::channel(100);
 
-            match terminal_manager::start_terminal_session(&root, &terminal_state, tx) {
+            let target = workspace_manager::WorkspaceConfig::load(&root).terminal.target;
+            let closed_window = window.clone();
+            let on_exit: terminal_manager::ExitHook = Arc::new(move |tid: &str, exit: &terminal_manager::ShellExit| {
+                let _ = closed_window.emit(&format!("agent:terminal:closed:{}", tid), exit.clone());
+            });
+            match terminal_manager::start_terminal_session(&root, &terminal_state, tx, &target, on_exit) {
                 Ok(tid) => {
                     *ts_lock = Some(tid.clone());
+                    let _ = window.emit(&format!("agent:terminal:started:{}", session_id), tid.clone());
                     let win_clone = window.clone();
                     let buffer_arc = session_clone.command_buffer.clone();
 
                     tokio::spawn(async move {
-                         while let Some(out) = rx.recv().await {
-                             let _ = win_clone.emit(&format!("agent:terminal:output:{}", tid), out.clone());
+                         let mut seq = 0;
+                         while let Some(out) = terminal_manager::next_output_batch(&mut rx, terminal_manager::OUTPUT_BATCH_WINDOW, terminal_manager::OUTPUT_BATCH_BYTES).await {
+                             let event = terminal_manager::TerminalOutput { seq, data: out.clone() };
+                             let _ = win_clone.emit(&format!("agent:terminal:output:{}", tid), event);
+                             seq = seq.wrapping_add(1);
 
                              let sender_opt = {
                                  buffer_arc.lock().unwrap().clone()
@@ -156,28 +560,56 @@ pub async fn spawn_agent_loop(
     let agent_state = Arc::new(RadkitState {
         root: root_path.clone(),
         terminal_state: terminal_state.clone(),
-        session_id: terminal_sid,
+        session_id: terminal_sid.clone(),
         command_buffer: session.command_buffer.clone(),
+        embedder: Some(Arc::new(GatewayEmbedder(config.embedding_config()))),
+        file_versions: Default::default(),
     });
     register_session(session_id.clone(), agent_state);
 
     // Prepare Light State
     let light_state = SessionState::new(session_id.clone());
 
-    // Use config
-    let llm = OpenRouterLlm::new(config.model, config.api_key)
-        .with_site_url("https://irongraph.app")
-        .with_app_name("IronGraph");
-
     // Setup Tools
     use radkit::tools::BaseTool;
     let tools: Vec<Box<dyn BaseTool>> = vec![
         Box::new(read_file),
+        Box::new(read_asset),
         Box::new(write_file),
+        Box::new(edit_file),
+        Box::new(delete_file),
+        Box::new(restore_backup),
+        Box::new(create_directory),
+        Box::new(move_file),
+        Box::new(insert_lines),
+        Box::new(replace_lines),
+        Box::new(delete_lines),
         Box::new(list_files),
+        Box::new(fuzzy_find_files),
         Box::new(read_skeleton),
+        Box::new(read_skeletons),
+        Box::new(outline),
         Box::new(search_code),
+        Box::new(semantic_search),
+        Box::new(find_references),
+        Box::new(workspace_stats),
+        Box::new(detect_project),
+        Box::new(list_todos),
+        Box::new(git_status),
+        Box::new(git_diff),
+        Box::new(git_blame),
+        Box::new(dependencies_of),
+        Box::new(dependents_of),
+        Box::new(find_cycles),
+        Box::new(crate_graph),
+        Box::new(impact_of),
         Box::new(run_command),
+        Box::new(read_terminal_buffer),
+        Box::new(interrupt_terminal),
+        Box::new(start_background),
+        Box::new(get_background_output),
+        Box::new(stop_background),
+        Box::new(ask_user),
     ];
     let toolset = Arc::new(SimpleToolset::new(tools)) as Arc<dyn BaseToolset>;
 
@@ -194,9 +626,15 @@ pub async fn spawn_agent_loop(
     let mut current_role = AgentRole::Coder;
     let mut verification_attempts = 0;
     const MAX_VERIFICATION_ATTEMPTS: i32 = 5;
+    let mut rejections = 0;
+    // History index of the first bounce since the last reflection
+    let mut first_bounce: Option<usize> = None;
 
     // Load History
-    let mut thread = Thread::from_system(get_prompt_for_role(&current_role));
+    let project = workspace_manager::detect_project(&root_path);
+    let mut thread = Thread::from_system(system_prompt(&current_role, &project).as_str());
+    // What `thread` holds, for the text-only handoff and reflection turns
+    let mut transcript = Transcript { opening: None, from: 0 };
 
     // Load from DB
     if let Ok(history) = session.repository.get_history(&session_id).await {
@@ -224,7 +662,10 @@ pub async fn spawn_agent_loop(
     });
     let _ = session.repository.add_message(&session_id, user_msg_json).await;
 
-    let max_iterations = 40; // Increased for dual loop
+    let mut max_iterations = config.limits.max_iterations;
+    let max_duration = config.limits.max_duration_secs.map(std::time::Duration::from_secs);
+    let started_at = std::time::Instant::now();
+    let deadline = max_duration.map(|limit| tokio::time::Instant::from_std(started_at + limit));
     let mut iterations = 0;
 
     loop {
@@ -232,12 +673,54 @@ pub async fn spawn_agent_loop(
             break;
         }
 
+        if let Some(limit) = max_duration {
+            if started_at.elapsed() >= limit {
+                let msg = serde_json::json!({
+                    "role": "assistant",
+                    "content": format!("[IronGraph] Run timed out after {}s.", limit.as_secs()),
+                    "metadata": { "persona": "system" }
+                });
+                let _ = session.repository.add_message(&session_id, msg).await;
+
+                session.release_terminal();
+                session.status.store(false, Ordering::Relaxed);
+                let _ = window.emit(&format!("agent:status:{}", session_id), "timed_out");
+                break;
+            }
+        }
+
         iterations += 1;
         if iterations > max_iterations {
-            let _ = window.emit(&format!("agent:error:{}", session_id), "Max iterations reached");
-            break;
+            // Park with Thread, role and terminal intact until the user grants more budget
+            let continue_rx = session.begin_limit_wait();
+            let _ = window.emit(&format!("agent:limit:{}", session_id), max_iterations);
+            let _ = window.emit(&format!("agent:status:{}", session_id), "limit_reached");
+
+            match await_reply(continue_rx, deadline).await {
+                Some(extra) if extra > 0 => {
+                    max_iterations += extra;
+                    let _ = window.emit(&format!("agent:status:{}", session_id), "running");
+                }
+                // Stopped while parked
+                None if !session.status.load(Ordering::Relaxed) => break,
+                // Out of time while parked: the check at the top of the loop ends the run
+                None if max_duration.is_some_and(|limit| started_at.elapsed() >= limit) => {
+                    session.pending_continue.lock().unwrap().take();
+                    continue;
+                }
+                _ => {
+                    let _ = window.emit(&format!("agent:error:{}", session_id), "Max iterations reached");
+                    session.status.store(false, Ordering::Relaxed);
+                    break;
+                }
+            }
         }
 
+        let llm = match current_role {
+            AgentRole::Coder => &models.coder,
+            AgentRole::Verifier => &models.verifier,
+        };
+
         match llm.generate_content(thread.clone(), Some(toolset.clone())).await {
             Ok(response) => {
                 let content = response.into_content();
@@ -310,68 +793,183 @@ pub async fn spawn_agent_loop(
 
                 // Execute Tools
                 let tools_map = toolset.get_tools().await; // Returns Vec<&dyn BaseTool>
+                let mut user_answers = Vec::new();
+                let mut question_aborted = false;
 
                 for call in tool_calls {
-                    // Find tool
-                    if let Some(tool) = tools_map.iter().find(|t| t.name() == call.name()) {
-                        let args_res = call.arguments().as_object().ok_or("Args not object");
-                        if let Some(args_map) = args_res.ok().map(|m| m.iter().map(|(k,v)| (k.clone(), v.clone())).collect()) {
-                             let result = tool.run_async(args_map, &tool_context).await;
-                             let output_data = result.data().to_string();
+                    // ask_user is a round trip to the frontend, not a regular tool
+                    if call.name() == ASK_USER_TOOL {
+                        let question = call.arguments().get("question")
+                            .and_then(|v| v.as_str())
+                            .unwrap_or_default()
+                            .to_string();
 
-                             let output_display = format!("Tool Output:\n{}", output_data);
-                             let _ = window.emit(&format!("agent:tool_output:{}", session_id), output_display);
+                        let answer_rx = session.begin_question();
+                        let _ = window.emit(&format!("agent:question:{}", session_id), question);
+                        let _ = window.emit(&format!("agent:status:{}", session_id), "awaiting_input");
 
-                             let response = ToolResponse::new(call.id().to_string(), result);
+                        let reply = match replay {
+                            Some(hooks) => hooks.next_answer(),
+                            None => await_reply(answer_rx, deadline).await,
+                        };
+                        let answer = match reply {
+                            Some(a) => a,
+                            None => {
+                                question_aborted = true;
+                                break;
+                            }
+                        };
+                        let _ = window.emit(&format!("agent:status:{}", session_id), "running");
 
-                             // Add Tool Response to Thread
-                             thread = thread.add_event(Event::from(response.clone()));
+                        let result = ToolResult::success("Question delivered. The user's answer follows.".into());
+                        thread = thread.add_event(Event::from(ToolResponse::new(call.id().to_string(), result)));
 
-                             // Persist result
-                             let msg = serde_json::json!({
+                        let msg = serde_json::json!({
+                            "role": "user",
+                            "content": answer,
+                            "metadata": { "persona": "user" }
+                        });
+                        let _ = session.repository.add_message(&session_id, msg).await;
+                        user_answers.push(answer);
+                        continue;
+                    }
+
+                    // Find tool
+                    let checked = match tools_map.iter().find(|t| t.name() == call.name()) {
+                        None => Err(format!(
+                            "Tool not found: {}. Available tools: {}",
+                            call.name(),
+                            tools_map.iter().map(|t| t.name().to_string()).collect::<Vec<_>>().join(", ")
+                        )),
+                        Some(tool) => parse_tool_args(call.arguments())
+                            .map(|args| (tool, args))
+                            .map_err(|e| format!("Invalid arguments for tool '{}': {}", call.name(), e)),
+                    };
+
+                    let (tool, args) = match checked {
+                        Ok(pair) => pair,
+                        Err(error) => {
+                            // Feed the problem back to the model so it can self-correct on the next turn
+                            let _ = window.emit(&format!("agent:error:{}", session_id), error.clone());
+                            let response = ToolResponse::new(call.id().to_string(), ToolResult::error(error.clone()));
+                            thread = thread.add_event(Event::from(response));
+
+                            let msg = serde_json::json!({
                                 "role": "tool",
                                 "tool_call_id": call.id(),
-                                "content": output_data.clone(),
+                                "content": error,
                                 "metadata": { "persona": current_role.as_str() }
-                             });
-                             let _ = session.repository.add_message(&session_id, msg).await;
-
-                             // --- STATE MACHINE LOGIC ---
-                             match current_role {
-                                 AgentRole::Coder => {
-                                     // Transition Coder -> Verifier on 'write_file'
-                                     if call.name() == "write_file" {
-                                         role_transition = Some(AgentRole::Verifier);
-                                     }
-                                 },
-                                 AgentRole::Verifier => {
-                                     // Check for 'run_command' results
-                                     if call.name() == "run_command" {
-                                         // Check exit code
-                                         if output_data.contains("(Exit Code: 0)") {
-                                             // Passed.
-                                             // Verifier should see this and output <verified /> next turn.
-                                         } else {
-                                             // Failed (Exit Code != 0).
-                                             // Verifier succeeded in breaking it. Back to Coder.
-                                             role_transition = Some(AgentRole::Coder);
-                                         }
-                                     }
-                                 }
-                             }
+                            });
+                            let _ = session.repository.add_message(&session_id, msg).await;
+                            continue;
+                        }
+                    };
+
+                    // Commands the terminal policy denies only run once the user approves them;
+                    // the tool itself refuses them otherwise
+                    if let Some(command) = terminal_manager::policy::tool_command(call.name(), &args) {
+                        if let PolicyDecision::Denied(reason) = terminal_manager::policy::check(&root_path, &command) {
+                            let answer_rx = session.begin_question();
+                            let question = format!("The agent wants to run `{}`, which needs your approval because {}. Run it? (yes/no)", command, reason);
+                            let _ = window.emit(&format!("agent:question:{}", session_id), question);
+                            let _ = window.emit(&format!("agent:status:{}", session_id), "awaiting_input");
+                            let reply = match replay {
+                                Some(hooks) => Some(hooks.approves(call.id())),
+                                None => await_reply(answer_rx, deadline).await.map(|answer| is_approval(&answer)),
+                            };
+                            let approved = match reply {
+                                Some(approved) => approved,
+                                None => {
+                                    question_aborted = true;
+                                    break;
+                                }
+                            };
+                            let _ = window.emit(&format!("agent:status:{}", session_id), "running");
+
+                            if approved {
+                                terminal_manager::policy::approve(&terminal_sid, &command);
+                            } else {
+                                let error = format!("Policy violation: `{}` was not run because {}, and the user did not approve it. Find another way or ask the user.", command, reason);
+                                let response = ToolResponse::new(call.id().to_string(), ToolResult::error(error.clone()));
+                                thread = thread.add_event(Event::from(response));
+
+                                let msg = serde_json::json!({
+                                    "role": "tool",
+                                    "tool_call_id": call.id(),
+                                    "content": error,
+                                    "metadata": { "persona": current_role.as_str() }
+                                });
+                                let _ = session.repository.add_message(&session_id, msg).await;
+                                continue;
+                            }
+                        }
+                    }
+
+                    // Snapshot files the tool may modify so the UI can show a live diff
+                    let mut touched = written_paths(call.name(), &args);
+                    touched.extend(move_importers(&root_path, call.name(), &args));
+                    let written: Vec<(String, Option<String>)> = touched
+                        .into_iter()
+                        .map(|p| {
+                            let before = std::fs::read_to_string(root_path.join(&p)).ok();
+                            (p, before)
+                        })
+                        .collect();
 
-                        } else {
-                             // Arg parse error
-                             let _ = window.emit(&format!("agent:error:{}", session_id), "Tool args parse error");
+                    let result = match replay.and_then(|hooks| hooks.stub_output(call.id())) {
+                        Some(recorded) => recorded,
+                        None => tool.run_async(args.into_iter().collect(), &tool_context).await,
+                    };
+                    let output_data = result.data().to_string();
+                    session.persist_commands(&terminal_sid).await;
+
+                    for (path, before) in written {
+                        let after = std::fs::read_to_string(root_path.join(&path)).ok();
+                        if session.changes.record(&path, before, after) {
+                            let _ = window.emit(&format!("agent:file_changed:{}", session_id), path);
                         }
-                    } else {
-                        // Tool not found
-                        let _ = window.emit(&format!("agent:error:{}", session_id), format!("Tool not found: {}", call.name()));
+                    }
+
+                    let output_display = format!("Tool Output:\n{}", output_data);
+                    let _ = window.emit(&format!("agent:tool_output:{}", session_id), output_display);
+
+                    let response = ToolResponse::new(call.id().to_string(), result);
+
+                    // Add Tool Response to Thread
+                    thread = thread.add_event(Event::from(response.clone()));
+
+                    // Persist result
+                    let msg = serde_json::json!({
+                       "role": "tool",
+                       "tool_call_id": call.id(),
+                       "content": output_data.clone(),
+                       "metadata": { "persona": current_role.as_str() }
+                    });
+                    let _ = session.repository.add_message(&session_id, msg).await;
+
+                    // --- STATE MACHINE LOGIC ---
+                    if let Some(next) = next_role(&current_role, call.name(), call.arguments(), &output_data) {
+                        role_transition = Some(next);
                     }
                 }
 
+                if question_aborted {
+                    // Out of time or stopped: the checks at the top of the loop end the run
+                    if !session.status.load(Ordering::Relaxed) || max_duration.is_some_and(|limit| started_at.elapsed() >= limit) {
+                        continue;
+                    }
+                    let _ = window.emit(&format!("agent:status:{}", session_id), "waiting");
+                    session.status.store(false, Ordering::Relaxed);
+                    break;
+                }
+
+                // Answers are injected after all tool responses so the call/response pairing stays intact
+                for answer in user_answers {
+                    thread = thread.add_event(Event::user(answer));
+                }
+
                 // Handle Transitions
-                if let Some(new_role) = role_transition {
+                if let Some(new_role) = role_transition.filter(|_| !config.coder_only) {
                     if new_role != current_role {
                         if new_role == AgentRole::Verifier {
                             // Coder -> Verifier
@@ -383,8 +981,10 @@ pub async fn spawn_agent_loop(
                              }
                         }
 
-                        current_role = new_role;
-                        let prompt = get_prompt_for_role(&current_role);
+                        let outgoing_role = std::mem::replace(&mut current_role, new_role);
+                        let prompt = system_prompt(&current_role, &project);
+                        let history = session.repository.get_history(&session_id).await.unwrap_or_default();
+                        let bounce_at = history.len();
                         // Inject System Prompt for new role
                         // Radkit Thread is immutable, so we add a system message event if supported or simulate it
                         // Since `Event::system` might not be exposed or standard in this version of radkit,
@@ -393,8 +993,75 @@ pub async fn spawn_agent_loop(
                         // However, radkit `Thread` usually starts with system.
                         // Let's add a User message that ACTS as a system instruction to enforce the role.
 
-                        let role_msg = format!("\n[SYSTEM]: SWITCHING ROLE.\n{}", prompt);
-                        thread = thread.add_event(Event::user(role_msg.clone()));
+                        // Summarize the outgoing role's work so the new role starts from a compact
+                        // handoff instead of the raw transcript. Fall back to the transcript on failure.
+                        let outgoing_llm = match outgoing_role {
+                            AgentRole::Coder => &models.coder,
+                            AgentRole::Verifier => &models.verifier,
+                        };
+                        let handoff = generate_text(
+                            outgoing_llm.as_ref(),
+                            system_prompt(&outgoing_role, &project),
+                            transcript.messages(&history),
+                            HANDOFF_PROMPT,
+                        ).await;
+                        match handoff {
+                            Some(summary) => {
+                                let _ = window.emit(&format!("agent:handoff:{}", session_id), summary.clone());
+                                let msg = serde_json::json!({
+                                    "role": "assistant",
+                                    "content": summary,
+                                    "metadata": { "persona": "handoff" }
+                                });
+                                let _ = session.repository.add_message(&session_id, msg).await;
+
+                                let role_msg = format!(
+                                    "[SYSTEM]: You are taking over this task.\n\nOriginal request:\n{}\n\nHandoff summary from the previous role:\n{}",
+                                    initial_prompt, summary
+                                );
+                                thread = Thread::from_system(prompt.as_str()).add_event(Event::user(role_msg.clone()));
+                                // The summary just persisted is part of role_msg
+                                transcript = Transcript { opening: Some(role_msg), from: history.len() + 1 };
+                            }
+                            None => {
+                                let role_msg = format!("\n[SYSTEM]: SWITCHING ROLE.\n{}", prompt);
+                                thread = thread.add_event(Event::user(role_msg.clone()));
+                            }
+                        }
+
+                        if current_role == AgentRole::Coder {
+                            // Verifier -> Coder: force a strategy change after repeated failures
+                            rejections += 1;
+                            let since = *first_bounce.get_or_insert(bounce_at);
+                            if rejections > config.limits.reflect_after_rejections {
+                                // Every attempt since the first bounce, not just the current role's
+                                // transcript, which a handoff restarts
+                                let history = session.repository.get_history(&session_id).await.unwrap_or_default();
+                                let attempts = Transcript { opening: Some(format!("Original request:\n{}", initial_prompt)), from: since };
+                                let reflection = generate_text(
+                                    models.coder.as_ref(),
+                                    prompt.clone(),
+                                    attempts.messages(&history),
+                                    REFLECTION_PROMPT,
+                                ).await;
+                                // The next reflection needs as many new rejections
+                                rejections = 0;
+                                first_bounce = None;
+                                if let Some(reflection) = reflection {
+                                    let _ = window.emit(&format!("agent:reflection:{}", session_id), reflection.clone());
+                                    let msg = serde_json::json!({
+                                        "role": "assistant",
+                                        "content": reflection,
+                                        "metadata": { "persona": "reflection" }
+                                    });
+                                    let _ = session.repository.add_message(&session_id, msg).await;
+
+                                    thread = thread
+                                        .add_event(Event::user(REFLECTION_PROMPT))
+                                        .add_event(Event::assistant(reflection));
+                                }
+                            }
+                        }
 
                         println!("[Agent Loop] Switching Role to: {}", current_role.as_str());
 
@@ -410,4 +1077,6 @@ pub async fn spawn_agent_loop(
             }
         }
     }
+
+    session.persist_commands(&terminal_sid).await;
 }
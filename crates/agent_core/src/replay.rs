@@ -0,0 +1,505 @@
+use std::collections::{HashMap, VecDeque};
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use async_trait::async_trait;
+use radkit::errors::{AgentError, AgentResult};
+use radkit::models::{BaseLlm, Content, ContentPart, LlmResponse, Thread, TokenUsage};
+use radkit::tools::{BaseToolset, ToolCall, ToolResult};
+use serde_json::Value;
+use terminal_manager::history::CommandRecord;
+use common::TerminalState;
+
+use crate::{run_agent_loop, AgentRole, AgentSession, EventSink, HistoryRepository, LLMConfig, Models, RunLimits};
+
+// Deterministic replay of persisted sessions.
+// The recorded assistant turns stand in for the LLM and the real agent loop runs against
+// them, with tools either re-executed against the current code or stubbed with their
+// recorded output, so regressions show up as divergences instead of network flakiness.
+#[derive(Debug, Clone, PartialEq)]
+pub struct RecordedToolCall {
+    pub id: String,
+    pub name: String,
+    pub arguments: Value,
+    pub persona: Option<String>,
+    pub recorded_output: Option<String>,
+}
+
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct RecordedTurn {
+    pub text: Option<String>,
+    pub tool_calls: Vec<RecordedToolCall>,
+}
+
+// Assistant messages the loop writes itself rather than from a tool-calling turn
+const LOOP_PERSONAS: [&str; 3] = ["handoff", "reflection", "system"];
+
+fn persona(msg: &Value) -> Option<&str> {
+    msg.get("metadata").and_then(|m| m.get("persona")).and_then(|v| v.as_str())
+}
+
+/// Groups persisted history messages back into the assistant turns that produced them.
+/// Handoff summaries, reflections and loop notices are not turns and are left out.
+pub fn script_from_history(history: &[Value]) -> Vec<RecordedTurn> {
+    let mut turns = Vec::new();
+    let mut current = RecordedTurn::default();
+    let mut results_seen = false;
+
+    fn flush(turns: &mut Vec<RecordedTurn>, current: &mut RecordedTurn, results_seen: &mut bool) {
+        if current.text.is_some() || !current.tool_calls.is_empty() {
+            turns.push(std::mem::take(current));
+        }
+        *results_seen = false;
+    }
+
+    for msg in history {
+        let role = msg.get("role").and_then(|v| v.as_str()).unwrap_or("user");
+        if role == "assistant" && persona(msg).is_some_and(|p| LOOP_PERSONAS.contains(&p)) {
+            continue;
+        }
+        match role {
+            "assistant" => {
+                if let Some(calls) = msg.get("tool_calls").and_then(|v| v.as_array()) {
+                    if results_seen || current.text.is_some() {
+                        flush(&mut turns, &mut current, &mut results_seen);
+                    }
+                    let persona = persona(msg).map(|s| s.to_string());
+                    for call in calls {
+                        let function = call.get("function");
+                        let arguments = function
+                            .and_then(|f| f.get("arguments"))
+                            .map(|a| match a.as_str() {
+                                Some(raw) => serde_json::from_str(raw).unwrap_or_else(|_| a.clone()),
+                                None => a.clone(),
+                            })
+                            .unwrap_or(Value::Null);
+                        current.tool_calls.push(RecordedToolCall {
+                            id: call.get("id").and_then(|v| v.as_str()).unwrap_or_default().to_string(),
+                            name: function.and_then(|f| f.get("name")).and_then(|v| v.as_str()).unwrap_or_default().to_string(),
+                            arguments,
+                            persona: persona.clone(),
+                            recorded_output: None,
+                        });
+                    }
+                } else if let Some(text) = msg.get("content").and_then(|v| v.as_str()) {
+                    if results_seen || current.text.is_some() {
+                        flush(&mut turns, &mut current, &mut results_seen);
+                    }
+                    current.text = Some(text.to_string());
+                }
+            }
+            "tool" => {
+                // Results are persisted in call order, so pair them FIFO
+                let output = msg.get("content").and_then(|v| v.as_str()).unwrap_or_default().to_string();
+                if let Some(call) = current.tool_calls.iter_mut().find(|c| c.recorded_output.is_none()) {
+                    call.recorded_output = Some(output);
+                }
+                results_seen = true;
+            }
+            _ => flush(&mut turns, &mut current, &mut results_seen),
+        }
+    }
+    flush(&mut turns, &mut current, &mut results_seen);
+    turns
+}
+
+/// Mock LLM that answers every tool-calling request with the next recorded assistant turn,
+/// and every tool-less one (handoffs, reflections) with the next recorded summary.
+pub struct ScriptedLlm {
+    turns: Mutex<VecDeque<RecordedTurn>>,
+    summaries: Mutex<VecDeque<String>>,
+    served: AtomicUsize,
+}
+
+impl ScriptedLlm {
+    pub fn new(turns: Vec<RecordedTurn>, summaries: Vec<String>) -> Self {
+        Self {
+            turns: Mutex::new(turns.into()),
+            summaries: Mutex::new(summaries.into()),
+            served: AtomicUsize::new(0),
+        }
+    }
+
+    pub fn from_history(history: &[Value]) -> Self {
+        let summaries = history.iter()
+            .filter(|msg| matches!(persona(msg), Some("handoff" | "reflection")))
+            .filter_map(|msg| msg.get("content").and_then(|v| v.as_str()))
+            .map(|s| s.to_string())
+            .collect();
+        Self::new(script_from_history(history), summaries)
+    }
+
+    /// Recorded turns handed out so far.
+    pub fn served(&self) -> usize {
+        self.served.load(Ordering::Relaxed)
+    }
+
+    pub fn remaining(&self) -> usize {
+        self.turns.lock().unwrap().len()
+    }
+}
+
+#[async_trait]
+impl BaseLlm for ScriptedLlm {
+    fn model_name(&self) -> &str {
+        "recorded"
+    }
+
+    fn provider_name(&self) -> &str {
+        "replay"
+    }
+
+    async fn generate_content(&self, _thread: Thread, toolset: Option<Arc<dyn BaseToolset>>) -> AgentResult<LlmResponse> {
+        let mut parts = Vec::new();
+        if toolset.is_none() {
+            let summary = self.summaries.lock().unwrap().pop_front().ok_or_else(|| AgentError::LlmProvider {
+                provider: self.provider_name().to_string(),
+                message: "no recorded summary left".to_string(),
+            })?;
+            parts.push(ContentPart::Text(summary));
+        } else if let Some(turn) = self.turns.lock().unwrap().pop_front() {
+            self.served.fetch_add(1, Ordering::Relaxed);
+            parts.extend(turn.text.map(ContentPart::Text));
+            for call in turn.tool_calls {
+                parts.push(ContentPart::ToolCall(ToolCall::new(call.id, call.name, call.arguments)));
+            }
+        }
+        // Out of turns: an empty answer, which ends the run like a model with nothing to add
+        Ok(LlmResponse::new(Content::from_parts(parts), TokenUsage::new(0, 0)))
+    }
+}
+
+// What the loop asks the user and the tools for during a replay
+pub(crate) struct ReplayHooks {
+    // Recorded tool outputs by call id
+    recorded: HashMap<String, String>,
+    // Hand back the recorded outputs instead of running the tools
+    stub: bool,
+    // The user's messages after the opening prompt, in order
+    answers: Mutex<VecDeque<String>>,
+}
+
+impl ReplayHooks {
+    fn new(history: &[Value], script: &[RecordedTurn], stub: bool) -> Self {
+        let recorded = script.iter()
+            .flat_map(|turn| &turn.tool_calls)
+            .filter_map(|call| Some((call.id.clone(), call.recorded_output.clone()?)))
+            .collect();
+        let answers = history.iter()
+            .filter(|msg| msg.get("role").and_then(|v| v.as_str()) == Some("user"))
+            .filter_map(|msg| msg.get("content").and_then(|v| v.as_str()))
+            .skip(1)
+            .map(|s| s.to_string())
+            .collect();
+        Self { recorded, stub, answers: Mutex::new(answers) }
+    }
+
+    pub(crate) fn next_answer(&self) -> Option<String> {
+        self.answers.lock().unwrap().pop_front()
+    }
+
+    /// Whether the user approved the command of `call_id`: the loop records a refusal as a
+    /// policy violation, anything else means it ran.
+    pub(crate) fn approves(&self, call_id: &str) -> bool {
+        self.recorded.get(call_id).map_or(true, |output| !output.starts_with("Policy violation"))
+    }
+
+    pub(crate) fn stub_output(&self, call_id: &str) -> Option<ToolResult> {
+        if !self.stub {
+            return None;
+        }
+        let output = self.recorded.get(call_id).cloned().unwrap_or_default();
+        // Outputs are persisted as the JSON of the tool's data
+        let data = serde_json::from_str(&output).unwrap_or(Value::String(output));
+        Some(ToolResult::success(data))
+    }
+}
+
+pub enum ToolMode {
+    /// Return the recorded output without touching the workspace.
+    Stub,
+    /// Run the tools again in `root` and compare against the recording.
+    Execute { root: PathBuf },
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Divergence {
+    Output { call_id: String, tool: String, expected: String, actual: String },
+    Role { call_id: String, expected: String, actual: AgentRole },
+    MissingTool { call_id: String, tool: String },
+    InvalidArguments { call_id: String, tool: String, error: String },
+}
+
+#[derive(Debug, Clone)]
+pub struct ReplayReport {
+    pub turns: usize,
+    // Recorded turns the run ended before reaching
+    pub unplayed: usize,
+    pub tool_calls: usize,
+    pub final_role: AgentRole,
+    pub verified: bool,
+    pub divergences: Vec<Divergence>,
+}
+
+impl ReplayReport {
+    pub fn is_clean(&self) -> bool {
+        self.divergences.is_empty() && self.unplayed == 0
+    }
+}
+
+// Holds the replayed session's history
+#[derive(Default)]
+struct ReplayHistory {
+    messages: Mutex<Vec<Value>>,
+}
+
+#[async_trait]
+impl HistoryRepository for ReplayHistory {
+    async fn add_message(&self, _session_id: &str, message: Value) -> anyhow::Result<()> {
+        self.messages.lock().unwrap().push(message);
+        Ok(())
+    }
+
+    async fn get_history(&self, _session_id: &str) -> anyhow::Result<Vec<Value>> {
+        Ok(self.messages.lock().unwrap().clone())
+    }
+
+    async fn add_command(&self, _session_id: &str, _record: &CommandRecord) -> anyhow::Result<()> {
+        Ok(())
+    }
+
+    async fn get_commands(&self, _session_id: &str) -> anyhow::Result<Vec<CommandRecord>> {
+        Ok(Vec::new())
+    }
+}
+
+// Scratch workspace for stub replays, removed when the replay ends
+struct ScratchDir(PathBuf);
+
+impl Drop for ScratchDir {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_dir_all(&self.0);
+    }
+}
+
+/// Runs the agent loop over a persisted session, with the recorded turns as the model,
+/// and reports where the run no longer matches the recording.
+pub async fn replay(history: &[Value], mode: ToolMode) -> ReplayReport {
+    let script = script_from_history(history);
+    let total_turns = script.len();
+    let hooks = ReplayHooks::new(history, &script, matches!(mode, ToolMode::Stub));
+    let llm = Arc::new(ScriptedLlm::from_history(history));
+    let prompt = history.iter()
+        .find(|msg| msg.get("role").and_then(|v| v.as_str()) == Some("user"))
+        .and_then(|msg| msg.get("content").and_then(|v| v.as_str()))
+        .unwrap_or_default()
+        .to_string();
+
+    let (root, _scratch) = match mode {
+        ToolMode::Execute { root } => (root, None),
+        ToolMode::Stub => {
+            let dir = std::env::temp_dir().join("irongraph-replay").join(uuid::Uuid::new_v4().to_string());
+            let _ = std::fs::create_dir_all(&dir);
+            (dir.clone(), Some(ScratchDir(dir)))
+        }
+    };
+
+    let terminal_state = Arc::new(TerminalState::default());
+    let session = Arc::new(AgentSession::new(Box::new(ReplayHistory::default()), terminal_state.clone()));
+    if hooks.stub {
+        // Nothing runs, so no shell is started
+        *session.terminal_session_id.lock().unwrap() = Some(format!("replay-{}", session.id));
+    }
+
+    let config = LLMConfig {
+        api_key: String::new(),
+        model: llm.model_name().to_string(),
+        temperature: None,
+        coder: Default::default(),
+        verifier: Default::default(),
+        // One iteration per recorded turn, plus the one that finds the script exhausted
+        limits: RunLimits { max_iterations: total_turns as u32 + 1, ..Default::default() },
+        coder_only: false,
+        provider: None,
+        base_url: None,
+        embedding_model: None,
+    };
+    let models = Models { coder: llm.clone(), verifier: llm.clone() };
+    let events = Arc::new(Mutex::new(Vec::new()));
+    run_agent_loop(
+        EventSink::Log(events.clone()),
+        session.clone(),
+        Arc::new(Mutex::new(root)),
+        terminal_state,
+        prompt,
+        config,
+        models,
+        Some(&hooks),
+    ).await;
+    session.release_terminal();
+
+    let replayed = session.repository.get_history(&session.id).await.unwrap_or_default();
+    let role_event = format!("agent:debug:role:{}", session.id);
+    let final_role = events.lock().unwrap().iter()
+        .rev()
+        .find(|(event, _)| *event == role_event)
+        .map_or(AgentRole::Coder, |(_, role)| match role.as_str() {
+            Some("verifier") => AgentRole::Verifier,
+            _ => AgentRole::Coder,
+        });
+    let mut report = ReplayReport {
+        turns: llm.served(),
+        unplayed: total_turns - llm.served(),
+        tool_calls: 0,
+        final_role,
+        verified: false,
+        divergences: Vec::new(),
+    };
+
+    let recorded: HashMap<&str, &RecordedToolCall> = script.iter()
+        .flat_map(|turn| &turn.tool_calls)
+        .map(|call| (call.id.as_str(), call))
+        .collect();
+    let mut names: HashMap<String, String> = HashMap::new();
+    for msg in &replayed {
+        let role = msg.get("role").and_then(|v| v.as_str()).unwrap_or_default();
+        let content = msg.get("content").and_then(|v| v.as_str());
+        match role {
+            "assistant" => {
+                if persona(msg) == Some("verifier") && content.is_some_and(|t| t.contains("<verified />")) {
+                    report.verified = true;
+                }
+                for call in msg.get("tool_calls").and_then(|v| v.as_array()).into_iter().flatten() {
+                    report.tool_calls += 1;
+                    let id = call.get("id").and_then(|v| v.as_str()).unwrap_or_default().to_string();
+                    let name = call.get("function").and_then(|f| f.get("name")).and_then(|v| v.as_str()).unwrap_or_default();
+                    names.insert(id.clone(), name.to_string());
+
+                    let actual = match persona(msg) {
+                        Some("verifier") => AgentRole::Verifier,
+                        _ => AgentRole::Coder,
+                    };
+                    if let Some(expected) = recorded.get(id.as_str()).and_then(|c| c.persona.as_ref()) {
+                        if expected != actual.as_str() {
+                            report.divergences.push(Divergence::Role { call_id: id, expected: expected.clone(), actual });
+                        }
+                    }
+                }
+            }
+            "tool" => {
+                let call_id = msg.get("tool_call_id").and_then(|v| v.as_str()).unwrap_or_default().to_string();
+                let tool = names.get(&call_id).cloned().unwrap_or_default();
+                let actual = content.unwrap_or_default().to_string();
+                if actual.starts_with("Tool not found:") {
+                    report.divergences.push(Divergence::MissingTool { call_id, tool });
+                } else if actual.starts_with("Invalid arguments for tool") {
+                    report.divergences.push(Divergence::InvalidArguments { call_id, tool, error: actual });
+                } else if !hooks.stub {
+                    if let Some(expected) = hooks.recorded.get(&call_id).filter(|expected| **expected != actual) {
+                        report.divergences.push(Divergence::Output { call_id, tool, expected: expected.clone(), actual });
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+    report
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::next_role;
+    use serde_json::json;
+
+    fn call(id: &str, name: &str, args: Value, persona: &str) -> Value {
+        json!({
+            "role": "assistant",
+            "tool_calls": [{
+                "id": id,
+                "type": "function",
+                "function": { "name": name, "arguments": args.to_string() }
+            }],
+            "metadata": { "persona": persona }
+        })
+    }
+
+    fn sample_history() -> Vec<Value> {
+        vec![
+            json!({ "role": "user", "content": "add a test" }),
+            call("1", "write_file", json!({ "file_path": "a.rs", "content": "fn a() {}" }), "coder"),
+            json!({ "role": "tool", "content": "\"Successfully wrote file.\"" }),
+            call("2", "run_command", json!({ "program": "cargo", "args": "test" }), "verifier"),
+            json!({ "role": "tool", "content": "\"ok\\n(Exit Code: 0)\"" }),
+            json!({ "role": "assistant", "content": "All good <verified />" }),
+        ]
+    }
+
+    #[test]
+    fn test_script_groups_turns() {
+        let script = script_from_history(&sample_history());
+        assert_eq!(script.len(), 3);
+        assert_eq!(script[0].tool_calls[0].name, "write_file");
+        assert_eq!(script[0].tool_calls[0].arguments["file_path"], "a.rs");
+        assert_eq!(script[1].tool_calls[0].recorded_output.as_deref(), Some("\"ok\\n(Exit Code: 0)\""));
+        assert!(script[2].text.as_deref().unwrap().contains("<verified />"));
+    }
+
+    #[tokio::test]
+    async fn test_handoff_summaries_are_not_turns() {
+        let mut history = sample_history();
+        history.insert(3, json!({ "role": "assistant", "content": "Files changed: a.rs", "metadata": { "persona": "handoff" } }));
+        let script = script_from_history(&history);
+        assert_eq!(script.len(), 3);
+        assert!(script.iter().all(|turn| turn.text.as_deref() != Some("Files changed: a.rs")));
+
+        // The summary answers the loop's tool-less handoff request instead
+        let llm = ScriptedLlm::from_history(&history);
+        let summary = llm.generate_content(Thread::from_system("handoff"), None).await.unwrap().into_content();
+        assert!(matches!(&summary.parts()[0], ContentPart::Text(t) if t == "Files changed: a.rs"));
+        assert_eq!((llm.served(), llm.remaining()), (0, 3));
+
+        let report = replay(&history, ToolMode::Stub).await;
+        assert!(report.is_clean(), "{:?}", report.divergences);
+        assert!(report.verified);
+    }
+
+    #[tokio::test]
+    async fn test_stub_replay_follows_recorded_roles() {
+        let report = replay(&sample_history(), ToolMode::Stub).await;
+        assert!(report.is_clean(), "{:?}", report.divergences);
+        assert!(report.verified);
+        assert_eq!(report.tool_calls, 2);
+        assert_eq!(report.final_role, AgentRole::Verifier);
+    }
+
+    #[tokio::test]
+    async fn test_stub_replay_reports_role_divergence() {
+        let mut history = sample_history();
+        history[3] = call("2", "run_command", json!({ "program": "cargo" }), "coder");
+        let report = replay(&history, ToolMode::Stub).await;
+        assert!(matches!(report.divergences.as_slice(), [Divergence::Role { .. }]));
+    }
+
+    #[tokio::test]
+    async fn test_structured_command_results() {
+        let mut history = sample_history();
+        history[4] = json!({ "role": "tool", "content": json!({ "status": "completed", "exit_code": 0, "duration_ms": 812, "truncated": false, "output": "ok" }).to_string() });
+        let report = replay(&history, ToolMode::Stub).await;
+        assert!(report.verified);
+
+        history[4] = json!({ "role": "tool", "content": json!({ "status": "timeout", "timeout_secs": 60, "output": "" }).to_string() });
+        assert_eq!(next_role(&AgentRole::Verifier, "run_command", &json!({}), history[4]["content"].as_str().unwrap()), Some(AgentRole::Coder));
+    }
+
+    #[test]
+    fn test_previews_do_not_hand_off() {
+        let args = json!({ "file_path": "src/lib.rs", "content": "", "preview": true });
+        assert_eq!(next_role(&AgentRole::Coder, "write_file", &args, "\"Preview: no changes.\""), None);
+        let args = json!({ "file_path": "src/lib.rs", "old_string": "a", "new_string": "b", "preview": true });
+        assert_eq!(next_role(&AgentRole::Coder, "edit_file", &args, "\"Preview (nothing was written):\\n\""), None);
+        // Only the argument decides: a real write whose output happens to start with "Preview" hands off
+        let args = json!({ "file_path": "Preview.md", "old_string": "a", "new_string": "b" });
+        assert_eq!(next_role(&AgentRole::Coder, "edit_file", &args, "\"Preview.md edited.\""), Some(AgentRole::Verifier));
+    }
+}
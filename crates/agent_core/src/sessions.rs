@@ -0,0 +1,200 @@
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use anyhow::anyhow;
+
+use common::TerminalState;
+use crate::{AgentSession, HistoryRepository};
+
+/// Owns every agent session of the app. History lives in the repository, so a
+/// session id that is not loaded yet (e.g. after a restart) can be reopened.
+/// New sessions only come from `create` and `fork`.
+pub struct SessionManager {
+    repository: Arc<Box<dyn HistoryRepository>>,
+    terminal_state: Arc<TerminalState>,
+    sessions: Mutex<HashMap<String, Arc<AgentSession>>>,
+    default_id: String,
+}
+
+impl SessionManager {
+    pub fn new(repository: Box<dyn HistoryRepository>, terminal_state: Arc<TerminalState>) -> Self {
+        let repository = Arc::new(repository);
+        let default_session = Arc::new(AgentSession::with_repository(repository.clone(), terminal_state.clone()));
+        let default_id = default_session.id.clone();
+
+        let mut sessions = HashMap::new();
+        sessions.insert(default_id.clone(), default_session);
+
+        Self {
+            repository,
+            terminal_state,
+            sessions: Mutex::new(sessions),
+            default_id,
+        }
+    }
+
+    pub fn default_session(&self) -> Arc<AgentSession> {
+        self.get(&self.default_id).expect("default session is never removed")
+    }
+
+    pub fn get(&self, id: &str) -> Option<Arc<AgentSession>> {
+        self.sessions.lock().unwrap().get(id).cloned()
+    }
+
+    /// Returns the loaded session, reopening it from persisted history if needed.
+    /// Fails for ids that have neither a loaded session nor any persisted history.
+    pub async fn open(&self, id: &str) -> anyhow::Result<Arc<AgentSession>> {
+        if let Some(session) = self.get(id) {
+            return Ok(session);
+        }
+        let persisted = !self.repository.get_history(id).await?.is_empty()
+            || !self.repository.get_commands(id).await?.is_empty();
+        if !persisted {
+            return Err(anyhow!("Unknown agent session: {}", id));
+        }
+
+        let mut sessions = self.sessions.lock().unwrap();
+        Ok(sessions.entry(id.to_string())
+            .or_insert_with(|| Arc::new(AgentSession::with_id(
+                id.to_string(),
+                self.repository.clone(),
+                self.terminal_state.clone(),
+            )))
+            .clone())
+    }
+
+    /// Resolves an optional session id coming from the frontend.
+    pub async fn resolve(&self, id: Option<&str>) -> anyhow::Result<Arc<AgentSession>> {
+        match id {
+            Some(id) => self.open(id).await,
+            None => Ok(self.default_session()),
+        }
+    }
+
+    pub fn create(&self) -> Arc<AgentSession> {
+        let session = Arc::new(AgentSession::with_repository(self.repository.clone(), self.terminal_state.clone()));
+        self.sessions.lock().unwrap().insert(session.id.clone(), session.clone());
+        session
+    }
+
+    /// Copies history up to and including `message_index` into a brand new session.
+    pub async fn fork(&self, session_id: &str, message_index: usize) -> anyhow::Result<Arc<AgentSession>> {
+        let history = self.repository.get_history(session_id).await?;
+        if message_index >= history.len() {
+            return Err(anyhow!(
+                "Message index {} out of range (session has {} messages)",
+                message_index,
+                history.len()
+            ));
+        }
+
+        let fork = self.create();
+        for message in history.into_iter().take(message_index + 1) {
+            self.repository.add_message(&fork.id, message).await?;
+        }
+        Ok(fork)
+    }
+
+    /// The agent session using each terminal, keyed by terminal session id.
+    pub fn terminal_owners(&self) -> HashMap<String, String> {
+        self.sessions.lock().unwrap().values()
+            .filter_map(|s| s.terminal_session_id.lock().unwrap().clone().map(|tid| (tid, s.id.clone())))
+            .collect()
+    }
+
+    /// Kills a terminal, detaching it from its agent session so that session's next run
+    /// starts a new one.
+    pub fn kill_terminal(&self, terminal_id: &str) -> Result<(), terminal_manager::ShellError> {
+        for session in self.sessions.lock().unwrap().values() {
+            let mut owned = session.terminal_session_id.lock().unwrap();
+            if owned.as_deref() == Some(terminal_id) {
+                *owned = None;
+            }
+        }
+        terminal_manager::kill_session(&self.terminal_state, terminal_id)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use async_trait::async_trait;
+    use std::sync::atomic::Ordering;
+    use terminal_manager::history::CommandRecord;
+
+    #[derive(Default)]
+    struct MemoryHistory {
+        messages: Mutex<HashMap<String, Vec<serde_json::Value>>>,
+    }
+
+    #[async_trait]
+    impl HistoryRepository for MemoryHistory {
+        async fn add_message(&self, session_id: &str, message: serde_json::Value) -> anyhow::Result<()> {
+            self.messages.lock().unwrap().entry(session_id.to_string()).or_default().push(message);
+            Ok(())
+        }
+
+        async fn get_history(&self, session_id: &str) -> anyhow::Result<Vec<serde_json::Value>> {
+            Ok(self.messages.lock().unwrap().get(session_id).cloned().unwrap_or_default())
+        }
+
+        async fn add_command(&self, _session_id: &str, _record: &CommandRecord) -> anyhow::Result<()> {
+            Ok(())
+        }
+
+        async fn get_commands(&self, _session_id: &str) -> anyhow::Result<Vec<CommandRecord>> {
+            Ok(Vec::new())
+        }
+    }
+
+    fn manager() -> SessionManager {
+        SessionManager::new(Box::new(MemoryHistory::default()), Arc::new(TerminalState::default()))
+    }
+
+    #[tokio::test]
+    async fn test_open_rejects_unknown_ids() {
+        let manager = manager();
+        assert!(manager.open("missing").await.is_err());
+        assert!(manager.get("missing").is_none());
+
+        manager.repository.add_message("persisted", serde_json::json!({ "role": "user", "content": "hi" })).await.unwrap();
+        assert_eq!(manager.open("persisted").await.unwrap().id, "persisted");
+
+        let created = manager.create();
+        assert!(Arc::ptr_eq(&manager.open(&created.id).await.unwrap(), &created));
+    }
+
+    #[tokio::test]
+    async fn test_fork_copies_history_into_an_independent_session() {
+        let manager = manager();
+        let source = manager.create();
+        for content in ["one", "two", "three"] {
+            manager.repository.add_message(&source.id, serde_json::json!({ "role": "user", "content": content })).await.unwrap();
+        }
+        assert!(manager.fork(&source.id, 3).await.is_err());
+
+        let fork = manager.fork(&source.id, 1).await.unwrap();
+        assert_ne!(fork.id, source.id);
+        let history = manager.repository.get_history(&fork.id).await.unwrap();
+        let contents: Vec<_> = history.iter().map(|m| m["content"].as_str().unwrap()).collect();
+        assert_eq!(contents, ["one", "two"]);
+
+        manager.repository.add_message(&fork.id, serde_json::json!({ "role": "user", "content": "fork only" })).await.unwrap();
+        source.status.store(true, Ordering::Relaxed);
+        assert_eq!(manager.repository.get_history(&source.id).await.unwrap().len(), 3);
+        assert!(!fork.status.load(Ordering::Relaxed));
+        assert!(Arc::ptr_eq(&manager.open(&fork.id).await.unwrap(), &fork));
+    }
+
+    #[tokio::test]
+    async fn test_stop_ends_pending_waits() {
+        let session = manager().create();
+        assert!(!session.stop());
+
+        session.status.store(true, Ordering::Relaxed);
+        let answer_rx = session.begin_question();
+        assert!(session.stop());
+        assert!(!session.status.load(Ordering::Relaxed));
+        assert!(answer_rx.await.is_err());
+        assert!(session.answer_question("yes".into()).is_err());
+    }
+}
@@ -0,0 +1,28 @@
+use radkit::macros::tool;
+use radkit::tools::{ToolResult, ToolContext};
+use schemars::JsonSchema;
+use serde::Deserialize;
+
+// Hack for missing to_value
+trait ToValueExt {
+    fn to_value(&self) -> serde_json::Value;
+}
+impl ToValueExt for schemars::schema::RootSchema {
+    fn to_value(&self) -> serde_json::Value {
+        serde_json::to_value(self).unwrap()
+    }
+}
+
+pub const ASK_USER_TOOL: &str = "ask_user";
+
+#[derive(Deserialize, JsonSchema)]
+pub struct AskUserArgs {
+    pub question: String,
+}
+
+// The agent loop intercepts this tool and performs the round trip to the frontend itself.
+// Reaching this body means the tool was executed outside of a loop.
+#[tool(description = "Ask the user a clarifying question and wait for their answer. Use this instead of guessing when requirements are ambiguous.")]
+pub async fn ask_user(args: AskUserArgs, _ctx: &ToolContext<'_>) -> ToolResult {
+    ToolResult::error(format!("Cannot ask '{}': no user is attached to this session", args.question))
+}
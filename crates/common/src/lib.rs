@@ -3,7 +3,7 @@ use std::sync::{Arc, Mutex, OnceLock};
 use serde::{Deserialize, Serialize};
 use specta::Type;
 use tokio::sync::mpsc;
-use portable_pty::{Child};
+use portable_pty::{Child, ExitStatus, MasterPty};
 use std::collections::HashMap;
 use std::io::{Write};
 use radkit::tools::ExecutionState;
@@ -11,7 +11,15 @@ use serde_json::Value;
 
 pub struct PtySession {
     pub writer: Box<dyn Write + Send>,
+    // Kept so the terminal can be resized to match the frontend's view
+    pub master: Box<dyn MasterPty + Send>,
+    // Program the session runs, which decides how commands are wrapped
+    pub shell: String,
+    // Shared with the reader thread, which appends everything the shell prints
+    pub scrollback: Arc<Mutex<Scrollback>>,
     pub child: Box<dyn Child + Send + Sync>,
+    // Set by the exit watcher once the shell has exited; the session is dead from then on
+    pub exit_status: Option<ExitStatus>,
 }
 
 impl Drop for PtySession {
@@ -20,6 +28,40 @@ impl Drop for PtySession {
     }
 }
 
+// The most recent output of a terminal, bounded to `capacity` bytes
+pub struct Scrollback {
+    text: String,
+    capacity: usize,
+}
+
+impl Scrollback {
+    pub fn new(capacity: usize) -> Self {
+        Self { text: String::new(), capacity }
+    }
+
+    pub fn push(&mut self, chunk: &str) {
+        self.text.push_str(chunk);
+        // Trim in batches rather than shifting the buffer on every chunk
+        if self.text.len() > self.capacity + self.capacity / 4 {
+            let mut cut = self.text.len() - self.capacity;
+            while !self.text.is_char_boundary(cut) {
+                cut += 1;
+            }
+            self.text.drain(..cut);
+        }
+    }
+
+    /// The last `n` bytes kept (fewer if cut mid-character), or everything kept.
+    pub fn tail(&self, n: Option<usize>) -> &str {
+        let n = n.unwrap_or(self.capacity).min(self.capacity);
+        let mut start = self.text.len().saturating_sub(n);
+        while !self.text.is_char_boundary(start) {
+            start += 1;
+        }
+        &self.text[start..]
+    }
+}
+
 pub struct TerminalState {
     pub sessions: Mutex<HashMap<String, Arc<Mutex<PtySession>>>>,
 }
@@ -57,6 +99,20 @@ pub struct RadkitState {
     pub terminal_state: Arc<TerminalState>,
     pub session_id: String,
     pub command_buffer: Arc<Mutex<Option<mpsc::Sender<String>>>>,
+    // Backs semantic_search; None when the session has no embedding model
+    pub embedder: Option<Arc<dyn Embedder>>,
+    // Content hash of each file as the agent last read or wrote it, for conflict detection
+    pub file_versions: Mutex<HashMap<PathBuf, String>>,
+}
+
+// Turns text into vectors for the semantic index. Lives here so workspace tools can use
+// the session's model without depending on llm_gateway.
+#[async_trait::async_trait]
+pub trait Embedder: Send + Sync {
+    // Vectors from different models are not comparable, so indexes record this
+    fn model(&self) -> String;
+
+    async fn embed(&self, inputs: &[String]) -> Result<Vec<Vec<f32>>, String>;
 }
 
 // Lightweight JSON State (Passed to Radkit)
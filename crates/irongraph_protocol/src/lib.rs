@@ -11,6 +11,8 @@ pub struct FileEntry {
     pub path: String,
     pub name: String,
     pub is_dir: bool,
+    #[serde(default)]
+    pub is_binary: bool,
     pub children: Option<Vec<FileEntry>>,
 }
 
@@ -20,12 +22,174 @@ pub struct FileContent {
     pub content: String,
 }
 
+// One entry of `read_skeletons`; exactly one of skeleton and error is set
+#[derive(Type, Serialize, Deserialize, Debug, Clone)]
+pub struct FileSkeleton {
+    pub path: String,
+    pub skeleton: Option<String>,
+    pub error: Option<String>,
+}
+
+// A top-level item of a file; lines are 1-based and inclusive
+#[derive(Type, Serialize, Deserialize, Debug, Clone)]
+pub struct OutlineItem {
+    pub kind: String,
+    pub name: String,
+    pub start_line: u32,
+    pub end_line: u32,
+}
+
+// Payload of `workspace:fs_changed`; paths are relative to the workspace root
+#[derive(Type, Serialize, Deserialize, Debug, Clone)]
+pub struct FsChange {
+    pub created: Vec<String>,
+    pub modified: Vec<String>,
+    pub removed: Vec<String>,
+}
+
+#[derive(Type, Serialize, Deserialize, Debug, Clone, Default)]
+pub struct SearchOptions {
+    #[serde(default)]
+    pub before_context: u32,
+    #[serde(default)]
+    pub after_context: u32,
+    // Globs relative to the workspace root
+    #[serde(default)]
+    pub include: Vec<String>,
+    #[serde(default)]
+    pub exclude: Vec<String>,
+    #[serde(default)]
+    pub case_insensitive: bool,
+    #[serde(default)]
+    pub fixed_string: bool,
+    #[serde(default)]
+    pub max_results: Option<u32>,
+    // Matches may span lines
+    #[serde(default)]
+    pub multiline: bool,
+}
+
+#[derive(Type, Serialize, Deserialize, Debug, Clone)]
+pub struct SearchMatch {
+    pub path: String,
+    // 1-based; end_line differs from line only for multiline matches
+    pub line: u32,
+    pub end_line: u32,
+    pub column: u32,
+    pub text: String,
+    #[serde(default)]
+    pub before: Vec<String>,
+    #[serde(default)]
+    pub after: Vec<String>,
+}
+
+// Which languages are syntax-checked before a write
+#[derive(Type, Serialize, Deserialize, Debug, Clone)]
+pub struct SyntaxValidation {
+    pub rust: bool,
+    pub javascript: bool,
+    pub python: bool,
+    pub json: bool,
+    pub toml: bool,
+    pub yaml: bool,
+}
+
+#[derive(Type, Serialize, Deserialize, Debug, Clone)]
+pub struct LanguageStats {
+    pub language: String,
+    pub files: u32,
+    pub lines: u32,
+}
+
+// Sizes are in KiB, rounded up (u64 byte counts don't cross the specta boundary)
+#[derive(Type, Serialize, Deserialize, Debug, Clone)]
+pub struct PathSize {
+    pub path: String,
+    pub files: u32,
+    pub size_kb: u32,
+}
+
+#[derive(Type, Serialize, Deserialize, Debug, Clone)]
+pub struct WorkspaceStats {
+    pub files: u32,
+    pub lines: u32,
+    pub size_kb: u32,
+    pub languages: Vec<LanguageStats>,
+    pub largest_files: Vec<PathSize>,
+    pub directories: Vec<PathSize>,
+}
+
+#[derive(Type, Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum GitChange {
+    Added,
+    Modified,
+    Deleted,
+    Renamed,
+    TypeChange,
+    Untracked,
+    Conflicted,
+}
+
+// `index` is the staged change, `worktree` the unstaged one
+#[derive(Type, Serialize, Deserialize, Debug, Clone)]
+pub struct GitFileStatus {
+    pub path: String,
+    pub index: Option<GitChange>,
+    pub worktree: Option<GitChange>,
+}
+
+#[derive(Type, Serialize, Deserialize, Debug, Clone)]
+pub struct GitStatus {
+    pub branch: Option<String>,
+    pub files: Vec<GitFileStatus>,
+}
+
+#[derive(Type, Serialize, Deserialize, Debug, Clone, Copy)]
+#[serde(rename_all = "snake_case")]
+pub enum GraphFormat {
+    Dot,
+    Json,
+}
+
+#[derive(Type, Serialize, Deserialize, Debug, Clone)]
+pub struct GraphNode {
+    pub id: u32,
+    pub path: String,
+    pub language: String,
+}
+
+// `from` imports `to`; both are GraphNode ids
+#[derive(Type, Serialize, Deserialize, Debug, Clone)]
+pub struct GraphEdge {
+    pub from: u32,
+    pub to: u32,
+}
+
+#[derive(Type, Serialize, Deserialize, Debug, Clone)]
+pub struct DependencyGraph {
+    pub nodes: Vec<GraphNode>,
+    pub edges: Vec<GraphEdge>,
+}
+
+#[derive(Type, Serialize, Deserialize, Debug, Clone)]
+#[serde(tag = "format", content = "data", rename_all = "snake_case")]
+pub enum GraphExport {
+    Dot(String),
+    Json(DependencyGraph),
+}
+
 #[derive(Debug, Serialize, Type)]
 pub enum FsError {
     Io(String),
     SecurityViolation,
     InvalidPath,
     Syntax(String),
+    Edit(String),
+    // Size rounded up to KiB; preview is the first bytes in hex
+    BinaryFile { size_kb: u32, preview: String },
+    // The file changed on disk since the agent last read it
+    Conflict(String),
 }
 
 // ==========================================
@@ -39,11 +203,68 @@ pub struct CommandOutput {
     pub exit_code: i32,
 }
 
+#[derive(Type, Serialize, Deserialize, Debug, Clone)]
+pub struct Interrupted {
+    // Command line of what was running, when the platform can tell
+    pub process: Option<String>,
+    // "SIGINT" if Ctrl-C stopped it, "SIGKILL" if it had to be killed
+    pub signal: String,
+    pub stopped: bool,
+}
+
+// Payload of the `agent:terminal:output:{terminal_id}` events; `seq` counts up per terminal
+#[derive(Type, Serialize, Deserialize, Debug, Clone)]
+pub struct TerminalOutput {
+    pub seq: u32,
+    pub data: String,
+}
+
+// Payload of the `agent:terminal:closed:{terminal_id}` event, sent once when the shell exits
+#[derive(Type, Serialize, Deserialize, Debug, Clone)]
+pub struct TerminalClosed {
+    pub exit_code: u32,
+    // Set when a signal killed the shell
+    pub signal: Option<String>,
+}
+
+#[derive(Type, Serialize, Deserialize, Debug, Clone)]
+pub struct TerminalSession {
+    pub id: String,
+    pub shell: String,
+    // Current directory where the platform can tell, else the workspace root
+    pub cwd: String,
+    // Agent session using the terminal, if any
+    pub agent_session_id: Option<String>,
+    pub alive: bool,
+    // How the shell ended, once it has
+    pub exit: Option<TerminalClosed>,
+}
+
+#[derive(Type, Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub enum CommandSource {
+    Agent,
+    User,
+}
+
+#[derive(Type, Serialize, Deserialize, Debug, Clone)]
+pub struct CommandRecord {
+    pub command: String,
+    pub source: CommandSource,
+    // Milliseconds since the Unix epoch, as a JS timestamp
+    pub started_at: f64,
+    pub duration_ms: Option<u32>,
+    pub exit_code: Option<i32>,
+}
+
 #[derive(Debug, Serialize, Type)]
 pub enum ShellError {
     Io(String),
     NotFound(String),
     Pty(String),
+    // Seconds waited before giving up
+    Timeout(u32),
+    // The session's shell has exited, and how
+    Terminated(String),
 }
 
 // ==========================================
@@ -73,24 +294,116 @@ pub struct LLMConfig {
     pub base_url: String,
     pub model: String,
     pub temperature: f32,
+    #[serde(default)]
+    pub max_tokens: Option<u32>,
+    #[serde(default)]
+    pub top_p: Option<f32>,
+    #[serde(default)]
+    pub stop: Option<Vec<String>>,
+    #[serde(default)]
+    pub frequency_penalty: Option<f32>,
+    #[serde(default)]
+    pub presence_penalty: Option<f32>,
+    // Number of candidate completions
+    #[serde(default)]
+    pub n: Option<u32>,
+    #[serde(default)]
+    pub provider: Option<String>,
+    // Retries for 408/429/5xx and connection errors; None keeps the gateway default
+    #[serde(default)]
+    pub max_retries: Option<u32>,
+    #[serde(default)]
+    pub proxy: Option<String>,
+    #[serde(default)]
+    pub no_proxy: Option<String>,
+    #[serde(default)]
+    pub openrouter: Option<OpenRouterOptions>,
+    // Tried in order when base_url is unreachable
+    #[serde(default)]
+    pub fallback_base_urls: Vec<String>,
+}
+
+// Upstream provider routing and attribution headers for OpenRouter
+#[derive(Type, Serialize, Deserialize, Debug, Clone, Default)]
+pub struct OpenRouterOptions {
+    #[serde(default)]
+    pub order: Option<Vec<String>>,
+    #[serde(default)]
+    pub only: Option<Vec<String>>,
+    #[serde(default)]
+    pub ignore: Option<Vec<String>>,
+    #[serde(default)]
+    pub allow_fallbacks: Option<bool>,
+    #[serde(default)]
+    pub fallback_models: Option<Vec<String>>,
+    #[serde(default)]
+    pub transforms: Option<Vec<String>>,
+    #[serde(default)]
+    pub referer: Option<String>,
+    #[serde(default)]
+    pub title: Option<String>,
 }
 
 #[derive(Type, Serialize, Deserialize, Debug, Clone)]
 pub struct Message {
     pub role: String,
     pub content: String,
+    #[serde(default)]
+    pub images: Vec<ImageSource>,
+    // Tool result messages (role "tool") reference the call they answer
+    #[serde(default)]
+    pub tool_call_id: Option<String>,
+    #[serde(default)]
+    pub name: Option<String>,
+    // Native tool calls made by an assistant turn
+    #[serde(default)]
+    pub tool_calls: Option<Vec<ToolCall>>,
+}
+
+#[derive(Type, Serialize, Deserialize, Debug, Clone)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum ImageSource {
+    Url { url: String },
+    Base64 { media_type: String, data: String },
 }
 
 #[derive(Type, Serialize, Deserialize, Debug, Clone)]
 pub struct LLMRequest {
     pub messages: Vec<Message>,
     pub config: LLMConfig,
+    #[serde(default)]
+    pub tools: Option<Vec<ToolDefinition>>,
+    #[serde(default)]
+    pub tool_choice: Option<String>,
+    #[serde(default)]
+    pub prompt_cache: Option<PromptCache>,
+}
+
+// Where to place prompt cache breakpoints (Anthropic / OpenRouter)
+#[derive(Type, Serialize, Deserialize, Debug, Clone, Default)]
+pub struct PromptCache {
+    #[serde(default)]
+    pub system: bool,
+    #[serde(default)]
+    pub history: bool,
+}
+
+#[derive(Type, Serialize, Deserialize, Debug, Clone)]
+pub struct ToolDefinition {
+    pub name: String,
+    pub description: String,
+    pub parameters: serde_json::Value,
 }
 
 #[derive(Type, Serialize, Deserialize, Debug, Clone)]
 pub struct ToolCall {
+    #[serde(default)]
+    pub id: Option<String>,
     pub name: String,
     pub arguments: HashMap<String, String>,
+    // Typed arguments as the provider sent them, when the call was native.
+    #[serde(default)]
+    pub raw_arguments: Option<serde_json::Value>,
 }
 
 #[derive(Type, Serialize, Deserialize, Debug, Clone)]
@@ -99,4 +412,178 @@ pub struct LLMResponse {
     pub content: String,
     pub tool_calls: Option<Vec<ToolCall>>,
     pub usage: Option<HashMap<String, u32>>,
+    #[serde(default)]
+    pub reasoning: Option<String>,
+    // All candidates when n > 1; the fields above are the selected one
+    #[serde(default)]
+    pub choices: Vec<Choice>,
+    // Base URL that served the request
+    #[serde(default)]
+    pub endpoint: Option<String>,
+}
+
+#[derive(Type, Serialize, Deserialize, Debug, Clone)]
+pub struct Choice {
+    pub content: String,
+    pub tool_calls: Option<Vec<ToolCall>>,
+    #[serde(default)]
+    pub reasoning: Option<String>,
+}
+
+// Payload of the `chat:stream:{request_id}` events emitted by send_chat_stream
+#[derive(Type, Serialize, Deserialize, Debug, Clone)]
+#[serde(tag = "type", content = "data", rename_all = "snake_case")]
+pub enum ChatStreamEvent {
+    Token(String),
+    ToolStart(String),
+    ToolArg { name: String, value: String },
+    ToolEnd,
+    Retrying { attempt: u32, delay_ms: u32, reason: String },
+    Usage { prompt_tokens: u32, completion_tokens: u32 },
+    Reasoning(String),
+    Cancelled,
+    Error(String),
+    Done,
+}
+
+#[derive(Type, Serialize, Deserialize, Debug, Clone)]
+pub struct ModelInfo {
+    pub id: String,
+    pub name: Option<String>,
+    pub context_length: Option<u32>,
+    // USD per token
+    pub prompt_price: Option<f64>,
+    pub completion_price: Option<f64>,
+}
+
+// Client-side throttling for one provider; None leaves that dimension unlimited
+#[derive(Type, Serialize, Deserialize, Debug, Clone, Default)]
+pub struct RateLimit {
+    #[serde(default)]
+    pub requests_per_minute: Option<u32>,
+    #[serde(default)]
+    pub tokens_per_minute: Option<u32>,
+}
+
+#[derive(Type, Serialize, Deserialize, Debug, Clone)]
+pub struct ResponseCacheStats {
+    pub enabled: bool,
+    pub entries: u32,
+    // Total size in KiB (u64 byte counts don't cross the specta boundary)
+    pub size_kb: u32,
+    pub hits: u32,
+    pub misses: u32,
+}
+
+// ==========================================
+// Pagination Protocols
+// ==========================================
+
+/// Opaque continuation token. Callers must hand it back unchanged.
+#[derive(Type, Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Cursor(pub String);
+
+impl Cursor {
+    pub fn from_offset(offset: usize) -> Self {
+        Cursor(offset.to_string())
+    }
+
+    pub fn offset(&self) -> Option<usize> {
+        self.0.parse().ok()
+    }
+}
+
+#[derive(Type, Serialize, Deserialize, Debug, Clone, Default)]
+pub struct PageRequest {
+    pub cursor: Option<Cursor>,
+    pub limit: Option<u32>,
+}
+
+impl PageRequest {
+    pub const DEFAULT_LIMIT: u32 = 50;
+    pub const MAX_LIMIT: u32 = 500;
+
+    pub fn new(limit: u32) -> Self {
+        Self { cursor: None, limit: Some(limit) }
+    }
+
+    pub fn offset(&self) -> usize {
+        self.cursor.as_ref().and_then(|c| c.offset()).unwrap_or(0)
+    }
+
+    pub fn limit(&self) -> usize {
+        self.limit.unwrap_or(Self::DEFAULT_LIMIT).clamp(1, Self::MAX_LIMIT) as usize
+    }
+}
+
+#[derive(Type, Serialize, Deserialize, Debug, Clone)]
+pub struct Page<T> {
+    pub items: Vec<T>,
+    pub next_cursor: Option<Cursor>,
+    pub total: Option<u32>,
+}
+
+impl<T> Page<T> {
+    pub fn empty() -> Self {
+        Self { items: Vec::new(), next_cursor: None, total: Some(0) }
+    }
+
+    /// Slices an already materialized list according to `req`.
+    pub fn from_vec(all: Vec<T>, req: &PageRequest) -> Self {
+        let total = all.len();
+        let offset = req.offset().min(total);
+        let end = offset.saturating_add(req.limit()).min(total);
+        let items: Vec<T> = all.into_iter().skip(offset).take(end - offset).collect();
+        Self {
+            items,
+            next_cursor: if end < total { Some(Cursor::from_offset(end)) } else { None },
+            total: Some(total as u32),
+        }
+    }
+
+    pub fn map<U, F: FnMut(T) -> U>(self, f: F) -> Page<U> {
+        Page {
+            items: self.items.into_iter().map(f).collect(),
+            next_cursor: self.next_cursor,
+            total: self.total,
+        }
+    }
+}
+
+// ==========================================
+// Agent Session Protocols
+// ==========================================
+
+#[derive(Type, Serialize, Deserialize, Debug, Clone)]
+pub struct SessionMessage {
+    pub index: u32,
+    pub role: String,
+    pub content: String,
+    pub persona: Option<String>,
+    // Thinking text stored under metadata.reasoning, if the message has any
+    pub reasoning: Option<String>,
+}
+
+#[derive(Type, Serialize, Deserialize, Debug, Clone)]
+pub struct VariantOutcome {
+    pub label: String,
+    pub model: String,
+    pub session_id: String,
+    pub changed_files: Vec<String>,
+    pub diff: String,
+}
+
+#[derive(Type, Serialize, Deserialize, Debug, Clone)]
+pub struct ExperimentReport {
+    pub id: String,
+    pub variants: Vec<VariantOutcome>,
+    pub comparison: String,
+}
+
+#[derive(Type, Serialize, Deserialize, Debug, Clone)]
+pub struct FileChange {
+    pub path: String,
+    pub before: Option<String>,
+    pub after: Option<String>,
+    pub diff: String,
 }
@@ -0,0 +1,523 @@
+use std::collections::BTreeMap;
+use std::pin::Pin;
+use futures::Stream;
+use reqwest::Client;
+use serde::Deserialize;
+
+use crate::prompt_cache::{breakpoints, content_parts, usage_map};
+use crate::retry;
+use crate::sse::SseDecoder;
+use crate::{flatten_arguments, insert_some, ImageSource, LLMConfig, LLMRequest, LLMResponse, ModelInfo, Parser, StreamEvent, ToolCall};
+
+// Anthropic Messages API
+// https://docs.anthropic.com/en/api/messages
+
+const DEFAULT_BASE_URL: &str = "https://api.anthropic.com/v1";
+const API_VERSION: &str = "2023-06-01";
+const DEFAULT_MAX_TOKENS: u32 = 4096;
+
+fn base_url(config: &LLMConfig) -> &str {
+    let base = if config.base_url.is_empty() { DEFAULT_BASE_URL } else { config.base_url.as_str() };
+    base.trim_end_matches('/')
+}
+
+fn endpoint(req: &LLMRequest) -> String {
+    format!("{}/messages", base_url(&req.config))
+}
+
+fn image_block(image: &ImageSource) -> serde_json::Value {
+    let source = match image {
+        ImageSource::Url { url } => serde_json::json!({ "type": "url", "url": url }),
+        ImageSource::Base64 { media_type, data } => serde_json::json!({ "type": "base64", "media_type": media_type, "data": data }),
+    };
+    serde_json::json!({ "type": "image", "source": source })
+}
+
+// System messages are a top-level field; everything else must alternate user/assistant
+fn build_body(req: &LLMRequest, stream: bool) -> serde_json::Value {
+    let system: Vec<&str> = req.messages.iter()
+        .filter(|m| m.role == "system")
+        .map(|m| m.content.as_str())
+        .collect();
+
+    let marks = breakpoints(&req.messages, req.prompt_cache.as_ref());
+    let mut messages: Vec<serde_json::Value> = Vec::new();
+    for (i, m) in req.messages.iter().enumerate().filter(|(_, m)| m.role != "system") {
+        if m.role == "tool" {
+            let block = serde_json::json!({
+                "type": "tool_result",
+                "tool_use_id": m.tool_call_id,
+                "content": m.content,
+            });
+            // Parallel tool results belong in a single user turn
+            if let Some(blocks) = messages.last_mut()
+                .filter(|prev| prev["role"] == "user")
+                .and_then(|prev| prev["content"].as_array_mut())
+                .filter(|blocks| blocks.iter().all(|b| b["type"] == "tool_result"))
+            {
+                blocks.push(block);
+            } else {
+                messages.push(serde_json::json!({ "role": "user", "content": [block] }));
+            }
+            continue;
+        }
+
+        let role = if m.role == "assistant" { "assistant" } else { "user" };
+        let calls = m.native_tool_calls();
+        let content = if m.images.is_empty() && calls.is_empty() && !marks.contains(&i) {
+            serde_json::json!(m.content)
+        } else {
+            // Images go first, as recommended for Claude
+            let mut parts: Vec<serde_json::Value> = m.images.iter().map(image_block).collect();
+            if !m.content.is_empty() || calls.is_empty() {
+                parts.push(serde_json::json!({ "type": "text", "text": m.content }));
+            }
+            parts.extend(calls.iter().map(|c| serde_json::json!({
+                "type": "tool_use",
+                "id": c.id,
+                "name": c.name,
+                "input": c.arguments_json(),
+            })));
+            content_parts(parts, marks.contains(&i))
+        };
+        messages.push(serde_json::json!({ "role": role, "content": content }));
+    }
+
+    let mut body = serde_json::json!({
+        "model": req.config.model,
+        "max_tokens": req.config.max_tokens.unwrap_or(DEFAULT_MAX_TOKENS),
+        "messages": messages,
+        "temperature": req.config.temperature,
+        "stream": stream
+    });
+    insert_some(&mut body, "top_p", &req.config.top_p);
+    insert_some(&mut body, "stop_sequences", &req.config.stop);
+    if !system.is_empty() {
+        let system = system.join("\n\n");
+        let cache_system = marks.iter().any(|&i| req.messages[i].role == "system");
+        body["system"] = if cache_system {
+            content_parts(vec![serde_json::json!({ "type": "text", "text": system })], true)
+        } else {
+            serde_json::Value::String(system)
+        };
+    }
+    if let Some(tools) = req.tools.as_ref().filter(|t| !t.is_empty()) {
+        body["tools"] = serde_json::Value::Array(tools.iter().map(|t| serde_json::json!({
+            "name": t.name,
+            "description": t.description,
+            "input_schema": t.parameters,
+        })).collect());
+        if let Some(choice) = &req.tool_choice {
+            body["tool_choice"] = match choice.as_str() {
+                "auto" => serde_json::json!({ "type": "auto" }),
+                "required" => serde_json::json!({ "type": "any" }),
+                "none" => serde_json::json!({ "type": "none" }),
+                name => serde_json::json!({ "type": "tool", "name": name }),
+            };
+        }
+    }
+    body
+}
+
+fn request(client: &Client, req: &LLMRequest, stream: bool) -> reqwest::RequestBuilder {
+    client.post(endpoint(req))
+        .header("x-api-key", &req.config.api_key)
+        .header("anthropic-version", API_VERSION)
+        .header("Content-Type", "application/json")
+        .json(&build_body(req, stream))
+}
+
+#[derive(Deserialize)]
+struct MessagesResponse {
+    role: String,
+    content: Vec<ContentBlock>,
+    #[serde(default)]
+    usage: serde_json::Value,
+}
+
+#[derive(Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+enum ContentBlock {
+    Text { text: String },
+    Thinking { thinking: String },
+    ToolUse { id: String, name: String, input: serde_json::Value },
+    #[serde(other)]
+    Other,
+}
+
+pub(crate) async fn send_chat(req: &LLMRequest) -> Result<LLMResponse, String> {
+    let client = req.config.http_client()?;
+    let res = retry::send(&req.config.retry, || request(&client, req, false)).await?;
+
+    let parsed: MessagesResponse = res.json().await.map_err(|e| e.to_string())?;
+    Ok(parse_response(parsed))
+}
+
+fn parse_response(parsed: MessagesResponse) -> LLMResponse {
+    let mut content = String::new();
+    let mut reasoning: Option<String> = None;
+    let mut tools = Vec::new();
+    for block in parsed.content {
+        match block {
+            ContentBlock::Text { text } => content.push_str(&text),
+            ContentBlock::Thinking { thinking } => reasoning.get_or_insert_with(String::new).push_str(&thinking),
+            ContentBlock::ToolUse { id, name, input } => tools.push(ToolCall::native(Some(id), name, &input.to_string())),
+            ContentBlock::Other => {}
+        }
+    }
+
+    if tools.is_empty() {
+        let mut parser = Parser::new();
+        tools = crate::collect_tool_calls(parser.process_chunk(&content));
+    }
+
+    LLMResponse {
+        role: parsed.role,
+        content,
+        tool_calls: Some(tools),
+        usage: usage_map(&parsed.usage),
+        reasoning,
+        choices: Vec::new(),
+        endpoint: None,
+    }
+}
+
+#[derive(Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+enum StreamPayload {
+    MessageStart { message: StreamMessage },
+    MessageDelta { #[serde(default)] usage: StreamUsage },
+    ContentBlockStart { index: usize, content_block: StreamBlock },
+    ContentBlockDelta { index: usize, delta: BlockDelta },
+    ContentBlockStop { index: usize },
+    MessageStop,
+    Error { error: serde_json::Value },
+    #[serde(other)]
+    Other,
+}
+
+#[derive(Deserialize)]
+struct StreamMessage {
+    #[serde(default)]
+    usage: StreamUsage,
+}
+
+// input_tokens arrives with message_start, output_tokens with message_delta
+#[derive(Deserialize, Default)]
+struct StreamUsage {
+    input_tokens: Option<u32>,
+    output_tokens: Option<u32>,
+}
+
+#[derive(Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+enum StreamBlock {
+    ToolUse { name: String },
+    #[serde(other)]
+    Other,
+}
+
+#[derive(Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+enum BlockDelta {
+    TextDelta { text: String },
+    ThinkingDelta { thinking: String },
+    InputJsonDelta { partial_json: String },
+    #[serde(other)]
+    Other,
+}
+
+// Turns stream payloads into events. Tool input arrives as partial JSON per content block and
+// is emitted as arguments once the block closes.
+struct StreamState {
+    parser: Parser,
+    tool_inputs: BTreeMap<usize, String>,
+    prompt_tokens: u32,
+    completion_tokens: u32,
+}
+
+impl StreamState {
+    fn new() -> Self {
+        Self { parser: Parser::new(), tool_inputs: BTreeMap::new(), prompt_tokens: 0, completion_tokens: 0 }
+    }
+
+    fn handle(&mut self, payload: StreamPayload) -> Vec<StreamEvent> {
+        match payload {
+            StreamPayload::ContentBlockStart { index, content_block: StreamBlock::ToolUse { name } } => {
+                self.tool_inputs.insert(index, String::new());
+                vec![StreamEvent::ToolStart(name)]
+            }
+            StreamPayload::ContentBlockDelta { delta: BlockDelta::ThinkingDelta { thinking }, .. } => {
+                vec![StreamEvent::Reasoning(thinking)]
+            }
+            StreamPayload::ContentBlockDelta { delta: BlockDelta::TextDelta { text }, .. } => {
+                self.parser.process_chunk(&text)
+            }
+            StreamPayload::ContentBlockDelta { index, delta: BlockDelta::InputJsonDelta { partial_json } } => {
+                if let Some(input) = self.tool_inputs.get_mut(&index) {
+                    input.push_str(&partial_json);
+                }
+                Vec::new()
+            }
+            StreamPayload::ContentBlockStop { index } => match self.tool_inputs.remove(&index) {
+                Some(input) => flatten_arguments(&input).into_iter()
+                    .map(|(key, value)| StreamEvent::ToolArg(key, value))
+                    .chain(std::iter::once(StreamEvent::ToolEnd))
+                    .collect(),
+                None => Vec::new(),
+            },
+            StreamPayload::MessageStart { message } => {
+                self.prompt_tokens = message.usage.input_tokens.unwrap_or(self.prompt_tokens);
+                Vec::new()
+            }
+            StreamPayload::MessageDelta { usage } => {
+                self.completion_tokens = usage.output_tokens.unwrap_or(self.completion_tokens);
+                Vec::new()
+            }
+            StreamPayload::MessageStop => vec![
+                StreamEvent::Usage { prompt_tokens: self.prompt_tokens, completion_tokens: self.completion_tokens },
+                StreamEvent::Done,
+            ],
+            StreamPayload::Error { error } => vec![StreamEvent::Error(error.to_string())],
+            _ => Vec::new(),
+        }
+    }
+}
+
+pub(crate) fn stream_chat(req: LLMRequest) -> Pin<Box<dyn Stream<Item = StreamEvent> + Send>> {
+    let client = match req.config.http_client() {
+        Ok(client) => client,
+        Err(e) => return crate::error_stream(e),
+    };
+    let policy = req.config.retry.clone();
+    retry::stream(policy, move || request(&client, &req, true), |mut res| async_stream::stream! {
+        let mut state = StreamState::new();
+        let mut decoder = SseDecoder::new();
+
+        let mut ended = false;
+        while !ended {
+            let received = match res.chunk().await {
+                Ok(Some(chunk)) => decoder.push(&chunk),
+                // The last event may arrive without its closing blank line
+                Ok(None) => {
+                    ended = true;
+                    decoder.finish().into_iter().collect()
+                }
+                Err(e) => {
+                    yield StreamEvent::Error(e.to_string());
+                    continue;
+                }
+            };
+            for sse in received {
+                let Ok(payload) = serde_json::from_str::<StreamPayload>(&sse.data) else { continue };
+                let events = state.handle(payload);
+                let done = events.last() == Some(&StreamEvent::Done);
+                for event in events { yield event; }
+                if done { return; }
+            }
+        }
+    })
+}
+
+#[derive(Deserialize)]
+struct ModelsResponse {
+    data: Vec<ModelEntry>,
+}
+
+#[derive(Deserialize)]
+struct ModelEntry {
+    id: String,
+    display_name: Option<String>,
+}
+
+pub(crate) async fn list_models(config: &LLMConfig) -> Result<Vec<ModelInfo>, String> {
+    let res = config.http_client()?.get(format!("{}/models", base_url(config)))
+        .header("x-api-key", &config.api_key)
+        .header("anthropic-version", API_VERSION)
+        .send()
+        .await
+        .map_err(|e| format!("Request failed: {}", e))?;
+
+    if !res.status().is_success() {
+        return Err(format!("API Error: {}", res.status()));
+    }
+
+    let models: ModelsResponse = res.json().await.map_err(|e| e.to_string())?;
+    Ok(models.data.into_iter().map(|m| ModelInfo { name: m.display_name, ..ModelInfo::from_id(m.id) }).collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Message, ToolDefinition};
+    use futures::StreamExt;
+    use std::collections::HashMap;
+
+    fn call(id: &str, path: &str) -> ToolCall {
+        ToolCall {
+            id: Some(id.to_string()),
+            name: "read_file".to_string(),
+            arguments: HashMap::from([("file_path".to_string(), path.to_string())]),
+            raw_arguments: None,
+        }
+    }
+
+    fn request(messages: Vec<Message>) -> LLMRequest {
+        LLMRequest {
+            messages,
+            config: LLMConfig { model: "claude-sonnet-4".to_string(), ..Default::default() },
+            tools: None,
+            tool_choice: None,
+            prompt_cache: None,
+        }
+    }
+
+    #[test]
+    fn test_system_is_top_level_and_tool_results_share_a_turn() {
+        let assistant = Message { role: "assistant".to_string(), tool_calls: Some(vec![call("c1", "a.rs"), call("c2", "b.rs")]), ..Default::default() };
+        let body = build_body(&request(vec![
+            Message::new("system", "Be brief."),
+            Message::new("system", "Use tools."),
+            Message::new("user", "hi"),
+            assistant,
+            Message::tool_result("c1", "read_file", "fn a() {}"),
+            Message::tool_result("c2", "read_file", "fn b() {}"),
+            Message::new("user", "next"),
+        ]), false);
+
+        assert_eq!(body["system"], "Be brief.\n\nUse tools.");
+        assert_eq!(body["max_tokens"], DEFAULT_MAX_TOKENS);
+        let messages = body["messages"].as_array().unwrap();
+        assert_eq!(messages.len(), 4);
+        assert_eq!(messages[0], serde_json::json!({ "role": "user", "content": "hi" }));
+        assert_eq!(messages[1]["content"], serde_json::json!([
+            { "type": "tool_use", "id": "c1", "name": "read_file", "input": { "file_path": "a.rs" } },
+            { "type": "tool_use", "id": "c2", "name": "read_file", "input": { "file_path": "b.rs" } },
+        ]));
+        assert_eq!(messages[2], serde_json::json!({ "role": "user", "content": [
+            { "type": "tool_result", "tool_use_id": "c1", "content": "fn a() {}" },
+            { "type": "tool_result", "tool_use_id": "c2", "content": "fn b() {}" },
+        ] }));
+        assert_eq!(messages[3], serde_json::json!({ "role": "user", "content": "next" }));
+    }
+
+    #[test]
+    fn test_tool_choice_mapping() {
+        let mut req = request(vec![Message::new("user", "hi")]);
+        req.tool_choice = Some("required".to_string());
+        assert!(build_body(&req, false).get("tool_choice").is_none());
+
+        let schema = serde_json::json!({ "type": "object", "properties": { "file_path": { "type": "string" } } });
+        req.tools = Some(vec![ToolDefinition { name: "read_file".to_string(), description: "Read".to_string(), parameters: schema.clone() }]);
+        let body = build_body(&req, true);
+        assert_eq!(body["stream"], true);
+        assert_eq!(body["tools"][0]["input_schema"], schema);
+        assert_eq!(body["tool_choice"], serde_json::json!({ "type": "any" }));
+
+        for (choice, expected) in [
+            ("auto", serde_json::json!({ "type": "auto" })),
+            ("none", serde_json::json!({ "type": "none" })),
+            ("read_file", serde_json::json!({ "type": "tool", "name": "read_file" })),
+        ] {
+            req.tool_choice = Some(choice.to_string());
+            assert_eq!(build_body(&req, false)["tool_choice"], expected);
+        }
+    }
+
+    #[test]
+    fn test_parse_response_blocks() {
+        let parsed: MessagesResponse = serde_json::from_value(serde_json::json!({
+            "role": "assistant",
+            "content": [
+                { "type": "thinking", "thinking": "Look first.", "signature": "sig" },
+                { "type": "redacted_thinking", "data": "..." },
+                { "type": "text", "text": "Reading it." },
+                { "type": "tool_use", "id": "toolu_1", "name": "read_file", "input": { "file_path": "src/lib.rs" } },
+            ],
+            "usage": { "input_tokens": 12, "output_tokens": 5, "cache_read_input_tokens": 8 },
+        })).unwrap();
+        let res = parse_response(parsed);
+
+        assert_eq!(res.content, "Reading it.");
+        assert_eq!(res.reasoning.as_deref(), Some("Look first."));
+        let calls = res.tool_calls.unwrap();
+        assert_eq!(calls[0].id.as_deref(), Some("toolu_1"));
+        assert_eq!(calls[0].arguments["file_path"], "src/lib.rs");
+        let usage = res.usage.unwrap();
+        assert_eq!((usage["input_tokens"], usage["cache_read_tokens"]), (12, 8));
+    }
+
+    #[test]
+    fn test_stream_state_assembles_tool_input() {
+        let mut state = StreamState::new();
+        let mut events = Vec::new();
+        for data in [
+            r#"{"type":"message_start","message":{"usage":{"input_tokens":20}}}"#,
+            r#"{"type":"content_block_start","index":0,"content_block":{"type":"thinking","thinking":""}}"#,
+            r#"{"type":"content_block_delta","index":0,"delta":{"type":"thinking_delta","thinking":"Hmm"}}"#,
+            r#"{"type":"content_block_stop","index":0}"#,
+            r#"{"type":"content_block_start","index":1,"content_block":{"type":"tool_use","id":"toolu_1","name":"read_file","input":{}}}"#,
+            r#"{"type":"content_block_delta","index":1,"delta":{"type":"input_json_delta","partial_json":"{\"file_path\": \"src/"}}"#,
+            r#"{"type":"content_block_delta","index":1,"delta":{"type":"input_json_delta","partial_json":"lib.rs\"}"}}"#,
+            r#"{"type":"content_block_stop","index":1}"#,
+            r#"{"type":"message_delta","delta":{"stop_reason":"tool_use"},"usage":{"output_tokens":7}}"#,
+            r#"{"type":"message_stop"}"#,
+        ] {
+            events.extend(state.handle(serde_json::from_str(data).unwrap()));
+        }
+
+        assert_eq!(events, vec![
+            StreamEvent::Reasoning("Hmm".into()),
+            StreamEvent::ToolStart("read_file".into()),
+            StreamEvent::ToolArg("file_path".into(), "src/lib.rs".into()),
+            StreamEvent::ToolEnd,
+            StreamEvent::Usage { prompt_tokens: 20, completion_tokens: 7 },
+            StreamEvent::Done,
+        ]);
+    }
+
+    // Answers one request with `body` as an SSE stream, then closes the connection
+    async fn serve_once(body: &'static str) -> String {
+        use tokio::io::{AsyncReadExt, AsyncWriteExt};
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move {
+            let (mut socket, _) = listener.accept().await.unwrap();
+            let mut request = Vec::new();
+            let mut buf = [0u8; 4096];
+            // Read the whole request so closing the socket does not reset it
+            loop {
+                let n = socket.read(&mut buf).await.unwrap();
+                request.extend_from_slice(&buf[..n]);
+                let text = String::from_utf8_lossy(&request);
+                if let Some(end) = text.find("\r\n\r\n") {
+                    let length = text[..end].lines()
+                        .find_map(|l| l.to_ascii_lowercase().strip_prefix("content-length:").map(|v| v.trim().parse::<usize>().unwrap()))
+                        .unwrap_or(0);
+                    if request.len() >= end + 4 + length { break; }
+                }
+                if n == 0 { break; }
+            }
+            let head = "HTTP/1.1 200 OK\r\nContent-Type: text/event-stream\r\nConnection: close\r\n\r\n";
+            socket.write_all(head.as_bytes()).await.unwrap();
+            socket.write_all(body.as_bytes()).await.unwrap();
+        });
+        format!("http://{}", addr)
+    }
+
+    #[tokio::test]
+    async fn test_stream_flushes_final_event_without_blank_line() {
+        let base_url = serve_once(concat!(
+            "event: message_start\ndata: {\"type\":\"message_start\",\"message\":{\"usage\":{\"input_tokens\":3}}}\n\n",
+            "event: message_stop\ndata: {\"type\":\"message_stop\"}",
+        )).await;
+        let mut req = request(vec![Message::new("user", "hi")]);
+        req.config.base_url = base_url;
+
+        let events: Vec<StreamEvent> = stream_chat(req).collect().await;
+        assert_eq!(events, vec![
+            StreamEvent::Usage { prompt_tokens: 3, completion_tokens: 0 },
+            StreamEvent::Done,
+        ]);
+    }
+}
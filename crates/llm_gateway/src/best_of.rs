@@ -0,0 +1,82 @@
+use crate::{Choice, LLMResponse};
+
+// Picking among the candidates of an n > 1 request. The gateway has no opinion on what a
+// good answer is, so callers pass the check (typically syntax validation of the files a
+// turn would write).
+
+// Tool arguments that carry a destination path and the full new file content
+const PATH_ARGS: &[&str] = &["file_path", "path"];
+const CONTENT_ARG: &str = "content";
+
+/// The first candidate that passes `accept`, or the first candidate if none does.
+pub fn select(res: &LLMResponse, accept: impl Fn(&Choice) -> bool) -> Choice {
+    let choices = res.all_choices();
+    choices.iter()
+        .find(|c| accept(c))
+        .or_else(|| choices.first())
+        .cloned()
+        .unwrap_or_default()
+}
+
+/// True when every file a choice would write passes `validate(path, content)`.
+pub fn writes_are_valid(choice: &Choice, validate: impl Fn(&str, &str) -> Result<(), String>) -> bool {
+    choice.tool_calls.iter().flatten().all(|call| {
+        let path = PATH_ARGS.iter().find_map(|k| call.arguments.get(*k));
+        match (path, call.arguments.get(CONTENT_ARG)) {
+            (Some(path), Some(content)) => validate(path, content).is_ok(),
+            _ => true,
+        }
+    })
+}
+
+/// Replaces the primary answer of `res` with the first candidate whose writes validate.
+pub fn pick_valid(res: LLMResponse, validate: impl Fn(&str, &str) -> Result<(), String>) -> LLMResponse {
+    let best = select(&res, |c| writes_are_valid(c, &validate));
+    res.with_primary(best)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ToolCall;
+    use std::collections::HashMap;
+
+    fn write(content: &str) -> Choice {
+        Choice {
+            content: String::new(),
+            tool_calls: Some(vec![ToolCall {
+                id: None,
+                name: "write_file".to_string(),
+                arguments: HashMap::from([
+                    ("file_path".to_string(), "src/lib.rs".to_string()),
+                    ("content".to_string(), content.to_string()),
+                ]),
+                raw_arguments: None,
+            }]),
+            reasoning: None,
+        }
+    }
+
+    #[test]
+    fn test_pick_valid_skips_broken_candidates() {
+        let res = LLMResponse {
+            role: "assistant".to_string(),
+            content: String::new(),
+            tool_calls: write("fn main( {").tool_calls,
+            usage: None,
+            reasoning: None,
+            choices: vec![write("fn main( {"), write("fn main() {}")],
+            endpoint: None,
+        };
+        let balanced = |_: &str, content: &str| {
+            if content.matches('(').count() == content.matches(')').count() { Ok(()) } else { Err("unbalanced".to_string()) }
+        };
+
+        let picked = pick_valid(res.clone(), balanced);
+        assert_eq!(picked.tool_calls.unwrap()[0].arguments["content"], "fn main() {}");
+        assert_eq!(picked.choices.len(), 2);
+
+        let none_valid = pick_valid(res, |_: &str, _: &str| Err("no".to_string()));
+        assert_eq!(none_valid.tool_calls.unwrap()[0].arguments["content"], "fn main( {");
+    }
+}
@@ -0,0 +1,455 @@
+use std::collections::HashMap;
+use std::pin::Pin;
+use futures::Stream;
+use reqwest::Client;
+use serde::Deserialize;
+
+use crate::retry;
+use crate::sse::SseDecoder;
+use crate::{collect_tool_calls, insert_some, ImageSource, LLMConfig, LLMRequest, LLMResponse, ModelInfo, Parser, StreamEvent, ToolCall};
+
+// Google Gemini generateContent / streamGenerateContent
+// https://ai.google.dev/api/generate-content
+
+const DEFAULT_BASE_URL: &str = "https://generativelanguage.googleapis.com/v1beta";
+
+fn base_url(config: &LLMConfig) -> &str {
+    let base = if config.base_url.is_empty() { DEFAULT_BASE_URL } else { config.base_url.as_str() };
+    base.trim_end_matches('/')
+}
+
+fn endpoint(req: &LLMRequest, method: &str) -> String {
+    format!("{}/models/{}:{}", base_url(&req.config), req.config.model, method)
+}
+
+// Gemini rejects a few JSON Schema keywords that schemars emits
+fn sanitize_schema(schema: &serde_json::Value) -> serde_json::Value {
+    match schema {
+        serde_json::Value::Object(map) => serde_json::Value::Object(map.iter()
+            .filter(|(k, _)| !matches!(k.as_str(), "$schema" | "additionalProperties" | "definitions" | "title"))
+            .map(|(k, v)| (k.clone(), sanitize_schema(v)))
+            .collect()),
+        serde_json::Value::Array(items) => serde_json::Value::Array(items.iter().map(sanitize_schema).collect()),
+        other => other.clone(),
+    }
+}
+
+fn build_body(req: &LLMRequest) -> serde_json::Value {
+    let system: Vec<&str> = req.messages.iter()
+        .filter(|m| m.role == "system")
+        .map(|m| m.content.as_str())
+        .collect();
+
+    // Gemini only knows "user" and "model"; tool results are functionResponse parts of a
+    // user turn and are matched to calls by name
+    let mut contents: Vec<serde_json::Value> = Vec::new();
+    for m in req.messages.iter().filter(|m| m.role != "system") {
+        if m.role == "tool" {
+            let part = serde_json::json!({ "functionResponse": {
+                "name": m.name.clone().unwrap_or_default(),
+                "response": { "content": m.content },
+            }});
+            if let Some(parts) = contents.last_mut()
+                .and_then(|prev| prev["parts"].as_array_mut())
+                .filter(|parts| parts.iter().all(|p| p.get("functionResponse").is_some()))
+            {
+                parts.push(part);
+            } else {
+                contents.push(serde_json::json!({ "role": "user", "parts": [part] }));
+            }
+            continue;
+        }
+
+        let role = if m.role == "assistant" { "model" } else { "user" };
+        let calls = m.native_tool_calls();
+        let mut parts = Vec::new();
+        if !m.content.is_empty() || calls.is_empty() {
+            parts.push(serde_json::json!({ "text": m.content }));
+        }
+        parts.extend(m.images.iter().map(|image| match image {
+            ImageSource::Base64 { media_type, data } => serde_json::json!({ "inlineData": { "mimeType": media_type, "data": data } }),
+            ImageSource::Url { url } => serde_json::json!({ "fileData": { "mimeType": image.media_type(), "fileUri": url } }),
+        }));
+        parts.extend(calls.iter().map(|c| serde_json::json!({
+            "functionCall": { "name": c.name, "args": c.arguments_json() }
+        })));
+        contents.push(serde_json::json!({ "role": role, "parts": parts }));
+    }
+
+    let mut body = serde_json::json!({
+        "contents": contents,
+        "generationConfig": { "temperature": req.config.temperature }
+    });
+    let generation = &mut body["generationConfig"];
+    insert_some(generation, "maxOutputTokens", &req.config.max_tokens);
+    insert_some(generation, "topP", &req.config.top_p);
+    insert_some(generation, "stopSequences", &req.config.stop);
+    insert_some(generation, "frequencyPenalty", &req.config.frequency_penalty);
+    insert_some(generation, "presencePenalty", &req.config.presence_penalty);
+    if !system.is_empty() {
+        body["systemInstruction"] = serde_json::json!({ "parts": [{ "text": system.join("\n\n") }] });
+    }
+    if let Some(tools) = req.tools.as_ref().filter(|t| !t.is_empty()) {
+        body["tools"] = serde_json::json!([{
+            "functionDeclarations": tools.iter().map(|t| serde_json::json!({
+                "name": t.name,
+                "description": t.description,
+                "parameters": sanitize_schema(&t.parameters),
+            })).collect::<Vec<_>>()
+        }]);
+        if let Some(choice) = &req.tool_choice {
+            body["toolConfig"] = match choice.as_str() {
+                "auto" => serde_json::json!({ "functionCallingConfig": { "mode": "AUTO" } }),
+                "required" => serde_json::json!({ "functionCallingConfig": { "mode": "ANY" } }),
+                "none" => serde_json::json!({ "functionCallingConfig": { "mode": "NONE" } }),
+                name => serde_json::json!({ "functionCallingConfig": { "mode": "ANY", "allowedFunctionNames": [name] } }),
+            };
+        }
+    }
+    body
+}
+
+fn request(client: &Client, req: &LLMRequest, url: String) -> reqwest::RequestBuilder {
+    client.post(url)
+        .header("x-goog-api-key", &req.config.api_key)
+        .json(&build_body(req))
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct GenerateResponse {
+    #[serde(default)]
+    candidates: Vec<Candidate>,
+    usage_metadata: Option<UsageMetadata>,
+}
+
+#[derive(Deserialize)]
+struct Candidate {
+    content: Option<CandidateContent>,
+}
+
+#[derive(Deserialize)]
+struct CandidateContent {
+    #[serde(default)]
+    parts: Vec<Part>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct Part {
+    text: Option<String>,
+    // Thought summaries (includeThoughts) arrive as text parts flagged with thought: true
+    #[serde(default)]
+    thought: bool,
+    function_call: Option<FunctionCall>,
+}
+
+#[derive(Deserialize)]
+struct FunctionCall {
+    name: String,
+    #[serde(default)]
+    args: serde_json::Value,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct UsageMetadata {
+    prompt_token_count: Option<u32>,
+    candidates_token_count: Option<u32>,
+    total_token_count: Option<u32>,
+}
+
+impl UsageMetadata {
+    fn to_map(&self) -> HashMap<String, u32> {
+        let mut usage = HashMap::new();
+        if let Some(p) = self.prompt_token_count { usage.insert("prompt_tokens".to_string(), p); }
+        if let Some(c) = self.candidates_token_count { usage.insert("completion_tokens".to_string(), c); }
+        if let Some(t) = self.total_token_count { usage.insert("total_tokens".to_string(), t); }
+        usage
+    }
+}
+
+fn parts_of(response: GenerateResponse) -> Vec<Part> {
+    response.candidates.into_iter()
+        .next()
+        .and_then(|c| c.content)
+        .map(|c| c.parts)
+        .unwrap_or_default()
+}
+
+fn to_tool_call(call: FunctionCall) -> ToolCall {
+    ToolCall::native(None, call.name, &call.args.to_string())
+}
+
+pub(crate) async fn send_chat(req: &LLMRequest) -> Result<LLMResponse, String> {
+    let client = req.config.http_client()?;
+    let url = endpoint(req, "generateContent");
+    let res = retry::send(&req.config.retry, || request(&client, req, url.clone())).await?;
+
+    let parsed: GenerateResponse = res.json().await.map_err(|e| e.to_string())?;
+    Ok(parse_response(parsed))
+}
+
+fn parse_response(parsed: GenerateResponse) -> LLMResponse {
+    let usage = parsed.usage_metadata.as_ref().map(|u| u.to_map());
+
+    let mut content = String::new();
+    let mut tools = Vec::new();
+    let mut reasoning: Option<String> = None;
+    for part in parts_of(parsed) {
+        if let Some(text) = part.text {
+            if part.thought {
+                reasoning.get_or_insert_with(String::new).push_str(&text);
+            } else {
+                content.push_str(&text);
+            }
+        }
+        if let Some(call) = part.function_call {
+            tools.push(to_tool_call(call));
+        }
+    }
+
+    if tools.is_empty() {
+        let mut parser = Parser::new();
+        tools = collect_tool_calls(parser.process_chunk(&content));
+    }
+
+    LLMResponse {
+        role: "assistant".to_string(),
+        content,
+        tool_calls: Some(tools),
+        usage,
+        reasoning,
+        choices: Vec::new(),
+        endpoint: None,
+    }
+}
+
+// Events for one streamed part. Function calls are never split across chunks.
+fn part_events(parser: &mut Parser, part: Part) -> Vec<StreamEvent> {
+    let mut events = Vec::new();
+    if let Some(text) = part.text {
+        if part.thought {
+            events.push(StreamEvent::Reasoning(text));
+        } else {
+            events.extend(parser.process_chunk(&text));
+        }
+    }
+    if let Some(call) = part.function_call {
+        let call = to_tool_call(call);
+        events.push(StreamEvent::ToolStart(call.name));
+        events.extend(call.arguments.into_iter().map(|(key, value)| StreamEvent::ToolArg(key, value)));
+        events.push(StreamEvent::ToolEnd);
+    }
+    events
+}
+
+pub(crate) fn stream_chat(req: LLMRequest) -> Pin<Box<dyn Stream<Item = StreamEvent> + Send>> {
+    let client = match req.config.http_client() {
+        Ok(client) => client,
+        Err(e) => return crate::error_stream(e),
+    };
+    let policy = req.config.retry.clone();
+    let url = format!("{}?alt=sse", endpoint(&req, "streamGenerateContent"));
+    retry::stream(policy, move || request(&client, &req, url.clone()), |mut res| async_stream::stream! {
+        let mut parser = Parser::new();
+        let mut decoder = SseDecoder::new();
+        let mut usage = None;
+        let mut ended = false;
+        while !ended {
+            let received = match res.chunk().await {
+                Ok(Some(chunk)) => decoder.push(&chunk),
+                // The last event may arrive without its closing blank line
+                Ok(None) => {
+                    ended = true;
+                    decoder.finish().into_iter().collect()
+                }
+                Err(e) => {
+                    yield StreamEvent::Error(e.to_string());
+                    continue;
+                }
+            };
+            for sse in received {
+                let Ok(data) = serde_json::from_str::<GenerateResponse>(&sse.data) else { continue };
+                // Every chunk reports cumulative usage; the last one wins
+                if let Some(meta) = &data.usage_metadata {
+                    usage = Some((meta.prompt_token_count.unwrap_or(0), meta.candidates_token_count.unwrap_or(0)));
+                }
+                for part in parts_of(data) {
+                    for event in part_events(&mut parser, part) { yield event; }
+                }
+            }
+        }
+        if let Some((prompt_tokens, completion_tokens)) = usage {
+            yield StreamEvent::Usage { prompt_tokens, completion_tokens };
+        }
+        yield StreamEvent::Done;
+    })
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct ModelsResponse {
+    #[serde(default)]
+    models: Vec<ModelEntry>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct ModelEntry {
+    // "models/gemini-2.0-flash"
+    name: String,
+    display_name: Option<String>,
+    input_token_limit: Option<u32>,
+    #[serde(default)]
+    supported_generation_methods: Vec<String>,
+}
+
+pub(crate) async fn list_models(config: &LLMConfig) -> Result<Vec<ModelInfo>, String> {
+    let res = config.http_client()?.get(format!("{}/models", base_url(config)))
+        .header("x-goog-api-key", &config.api_key)
+        .send()
+        .await
+        .map_err(|e| format!("Request failed: {}", e))?;
+
+    if !res.status().is_success() {
+        return Err(format!("API Error: {}", res.status()));
+    }
+
+    let models: ModelsResponse = res.json().await.map_err(|e| e.to_string())?;
+    Ok(models.models.into_iter()
+        // Embedding-only models can't chat
+        .filter(|m| m.supported_generation_methods.iter().any(|g| g == "generateContent"))
+        .map(|m| ModelInfo {
+            id: m.name.trim_start_matches("models/").to_string(),
+            name: m.display_name,
+            context_length: m.input_token_limit,
+            prompt_price: None,
+            completion_price: None,
+        })
+        .collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Message, ToolDefinition};
+
+    fn request(messages: Vec<Message>) -> LLMRequest {
+        LLMRequest {
+            messages,
+            config: LLMConfig { model: "gemini-2.0-flash".to_string(), max_tokens: Some(512), ..Default::default() },
+            tools: None,
+            tool_choice: None,
+            prompt_cache: None,
+        }
+    }
+
+    fn call(name: &str, path: &str) -> ToolCall {
+        ToolCall {
+            id: None,
+            name: name.to_string(),
+            arguments: HashMap::from([("file_path".to_string(), path.to_string())]),
+            raw_arguments: None,
+        }
+    }
+
+    #[test]
+    fn test_system_instruction_and_function_responses() {
+        let assistant = Message { role: "assistant".to_string(), tool_calls: Some(vec![call("read_file", "a.rs"), call("outline", "b.rs")]), ..Default::default() };
+        let body = build_body(&request(vec![
+            Message::new("system", "Be brief."),
+            Message::new("system", "Use tools."),
+            Message::new("user", "hi"),
+            assistant,
+            Message::tool_result("1", "read_file", "fn a() {}"),
+            Message::tool_result("2", "outline", "fn b"),
+        ]));
+
+        assert_eq!(body["systemInstruction"], serde_json::json!({ "parts": [{ "text": "Be brief.\n\nUse tools." }] }));
+        assert_eq!(body["generationConfig"]["maxOutputTokens"], 512);
+        let contents = body["contents"].as_array().unwrap();
+        assert_eq!(contents.len(), 3);
+        assert_eq!(contents[0], serde_json::json!({ "role": "user", "parts": [{ "text": "hi" }] }));
+        assert_eq!(contents[1], serde_json::json!({ "role": "model", "parts": [
+            { "functionCall": { "name": "read_file", "args": { "file_path": "a.rs" } } },
+            { "functionCall": { "name": "outline", "args": { "file_path": "b.rs" } } },
+        ] }));
+        assert_eq!(contents[2], serde_json::json!({ "role": "user", "parts": [
+            { "functionResponse": { "name": "read_file", "response": { "content": "fn a() {}" } } },
+            { "functionResponse": { "name": "outline", "response": { "content": "fn b" } } },
+        ] }));
+    }
+
+    #[test]
+    fn test_tool_schema_and_choice() {
+        let mut req = request(vec![Message::new("user", "hi")]);
+        req.tools = Some(vec![ToolDefinition {
+            name: "read_file".to_string(),
+            description: "Read".to_string(),
+            parameters: serde_json::json!({
+                "$schema": "http://json-schema.org/draft-07/schema#",
+                "title": "ReadFileArgs",
+                "type": "object",
+                "additionalProperties": false,
+                "properties": { "file_path": { "type": "string", "title": "Path" } },
+                "definitions": {},
+            }),
+        }]);
+        let body = build_body(&req);
+        assert_eq!(body["tools"][0]["functionDeclarations"][0]["parameters"], serde_json::json!({
+            "type": "object",
+            "properties": { "file_path": { "type": "string" } },
+        }));
+        assert!(body.get("toolConfig").is_none());
+
+        for (choice, expected) in [
+            ("auto", serde_json::json!({ "mode": "AUTO" })),
+            ("required", serde_json::json!({ "mode": "ANY" })),
+            ("none", serde_json::json!({ "mode": "NONE" })),
+            ("read_file", serde_json::json!({ "mode": "ANY", "allowedFunctionNames": ["read_file"] })),
+        ] {
+            req.tool_choice = Some(choice.to_string());
+            assert_eq!(build_body(&req)["toolConfig"]["functionCallingConfig"], expected);
+        }
+    }
+
+    #[test]
+    fn test_parse_response_parts() {
+        let parsed: GenerateResponse = serde_json::from_value(serde_json::json!({
+            "candidates": [{ "content": { "role": "model", "parts": [
+                { "text": "Check the file.", "thought": true },
+                { "text": "Reading it." },
+                { "functionCall": { "name": "read_file", "args": { "file_path": "src/lib.rs" } } },
+            ] } }],
+            "usageMetadata": { "promptTokenCount": 40, "candidatesTokenCount": 6, "totalTokenCount": 46 },
+        })).unwrap();
+        let res = parse_response(parsed);
+
+        assert_eq!(res.content, "Reading it.");
+        assert_eq!(res.reasoning.as_deref(), Some("Check the file."));
+        assert_eq!(res.tool_calls.unwrap()[0].arguments["file_path"], "src/lib.rs");
+        let usage = res.usage.unwrap();
+        assert_eq!((usage["prompt_tokens"], usage["completion_tokens"], usage["total_tokens"]), (40, 6, 46));
+
+        let empty: GenerateResponse = serde_json::from_str(r#"{"candidates":[]}"#).unwrap();
+        assert_eq!(parse_response(empty).content, "");
+    }
+
+    #[test]
+    fn test_part_events() {
+        let mut parser = Parser::new();
+        let data: GenerateResponse = serde_json::from_str(
+            r#"{"candidates":[{"content":{"parts":[{"text":"Hmm","thought":true},{"functionCall":{"name":"read_file","args":{"file_path":"a.rs"}}}]}}]}"#,
+        ).unwrap();
+        let events: Vec<StreamEvent> = parts_of(data).into_iter()
+            .flat_map(|part| part_events(&mut parser, part))
+            .collect();
+        assert_eq!(events, vec![
+            StreamEvent::Reasoning("Hmm".into()),
+            StreamEvent::ToolStart("read_file".into()),
+            StreamEvent::ToolArg("file_path".into(), "a.rs".into()),
+            StreamEvent::ToolEnd,
+        ]);
+    }
+}
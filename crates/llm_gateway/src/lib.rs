@@ -1,34 +1,314 @@
 use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
 use std::pin::Pin;
-use futures::Stream;
-use futures::StreamExt;
+use futures::{Stream, StreamExt};
 use reqwest::Client;
-
-#[derive(Serialize, Deserialize, Debug, Clone)]
+pub use tokio_util::sync::CancellationToken;
+
+pub mod provider;
+pub mod sse;
+pub mod retry;
+pub use retry::RetryPolicy;
+mod tokens;
+pub mod prompt_cache;
+pub use prompt_cache::PromptCache;
+pub mod response_cache;
+pub mod rate_limit;
+pub mod request_log;
+pub mod best_of;
+pub use rate_limit::RateLimit;
+pub use tokens::count_tokens;
+pub use provider::{registry, LlmProvider, ProviderRegistry};
+
+mod openai;
+mod anthropic;
+mod ollama;
+mod gemini;
+mod mock;
+
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
 pub struct LLMConfig {
     pub api_key: String,
     pub base_url: String,
     pub model: String,
     pub temperature: f32,
+    // Optional sampling parameters; unset ones are left out of the request body.
+    // Providers that lack one (Anthropic has no penalties) ignore it.
+    #[serde(default)]
+    pub max_tokens: Option<u32>,
+    #[serde(default)]
+    pub top_p: Option<f32>,
+    #[serde(default)]
+    pub stop: Option<Vec<String>>,
+    #[serde(default)]
+    pub frequency_penalty: Option<f32>,
+    #[serde(default)]
+    pub presence_penalty: Option<f32>,
+    // Candidate completions to generate. OpenAI produces them in one request; other
+    // providers get one request per candidate. See `LLMResponse::choices`.
+    #[serde(default)]
+    pub n: Option<u32>,
+    // "low", "medium" or "high" for reasoning models; only OpenAI-compatible APIs take it
+    #[serde(default)]
+    pub reasoning_effort: Option<String>,
+    // Backend name: "openai" (any OpenAI-compatible API, the default), "anthropic", "ollama" or "gemini"
+    #[serde(default)]
+    pub provider: Option<String>,
+    #[serde(default)]
+    pub retry: RetryPolicy,
+    // http://, https:// or socks5:// URL. HTTP_PROXY / HTTPS_PROXY / ALL_PROXY / NO_PROXY
+    // from the environment are honored even when this is unset.
+    #[serde(default)]
+    pub proxy: Option<String>,
+    // Comma separated hosts that bypass `proxy`
+    #[serde(default)]
+    pub no_proxy: Option<String>,
+    // Only used when provider is "openrouter"
+    #[serde(default)]
+    pub openrouter: Option<OpenRouterOptions>,
+    // Tried in order when `base_url` can't be reached (connection failures and timeouts
+    // that outlast the retry policy; API errors don't fail over)
+    #[serde(default)]
+    pub fallback_base_urls: Vec<String>,
 }
 
-#[derive(Serialize, Deserialize, Debug, Clone)]
+// https://openrouter.ai/docs/features/provider-routing
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub struct OpenRouterOptions {
+    // Upstream providers to try, in order (e.g. ["Anthropic", "Together"])
+    #[serde(default)]
+    pub order: Option<Vec<String>>,
+    // Allow list: only these providers may serve the request
+    #[serde(default)]
+    pub only: Option<Vec<String>>,
+    // Deny list
+    #[serde(default)]
+    pub ignore: Option<Vec<String>>,
+    // false pins the request to `order`/`only` instead of falling back to other providers
+    #[serde(default)]
+    pub allow_fallbacks: Option<bool>,
+    // Models to try if `model` is unavailable
+    #[serde(default)]
+    pub fallback_models: Option<Vec<String>>,
+    // e.g. ["middle-out"]
+    #[serde(default)]
+    pub transforms: Option<Vec<String>>,
+    // App attribution, sent as HTTP-Referer / X-Title
+    #[serde(default)]
+    pub referer: Option<String>,
+    #[serde(default)]
+    pub title: Option<String>,
+}
+
+impl LLMConfig {
+    pub fn provider_name(&self) -> &str {
+        self.provider.as_deref().unwrap_or("openai")
+    }
+
+    /// `base_url` followed by the fallbacks, without duplicates.
+    pub fn endpoints(&self) -> Vec<String> {
+        let mut endpoints = vec![self.base_url.clone()];
+        for url in &self.fallback_base_urls {
+            if !endpoints.contains(url) {
+                endpoints.push(url.clone());
+            }
+        }
+        endpoints
+    }
+
+    pub(crate) fn http_client(&self) -> Result<Client, String> {
+        let mut builder = Client::builder();
+        if let Some(url) = self.proxy.as_deref().filter(|p| !p.is_empty()) {
+            let proxy = reqwest::Proxy::all(url)
+                .map_err(|e| format!("Invalid proxy {}: {}", url, e))?
+                .no_proxy(self.no_proxy.as_deref().and_then(reqwest::NoProxy::from_string));
+            builder = builder.proxy(proxy);
+        }
+        builder.build().map_err(|e| format!("Failed to build HTTP client: {}", e))
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
 pub struct Message {
     pub role: String,
     pub content: String,
+    // Images shown alongside the text (screenshots, mockups). Each provider
+    // serializes them as its own content parts.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub images: Vec<ImageSource>,
+    // On "tool" messages: the call this result answers
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub tool_call_id: Option<String>,
+    // On "tool" messages: the tool that produced the result
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub name: Option<String>,
+    // On "assistant" messages: native tool calls made in that turn
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub tool_calls: Option<Vec<ToolCall>>,
+}
+
+impl Message {
+    pub fn new(role: impl Into<String>, content: impl Into<String>) -> Self {
+        Self { role: role.into(), content: content.into(), ..Default::default() }
+    }
+
+    /// Result of the tool call `tool_call_id`, to send back to the model.
+    pub fn tool_result(tool_call_id: impl Into<String>, name: impl Into<String>, content: impl Into<String>) -> Self {
+        Self {
+            role: "tool".to_string(),
+            content: content.into(),
+            tool_call_id: Some(tool_call_id.into()),
+            name: Some(name.into()),
+            ..Default::default()
+        }
+    }
+
+    pub(crate) fn native_tool_calls(&self) -> &[ToolCall] {
+        self.tool_calls.as_deref().unwrap_or(&[])
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum ImageSource {
+    Url { url: String },
+    Base64 { media_type: String, data: String },
+}
+
+impl ImageSource {
+    // data: URLs are accepted wherever an image URL is
+    pub fn to_url(&self) -> String {
+        match self {
+            ImageSource::Url { url } => url.clone(),
+            ImageSource::Base64 { media_type, data } => format!("data:{};base64,{}", media_type, data),
+        }
+    }
+
+    pub fn media_type(&self) -> String {
+        match self {
+            ImageSource::Base64 { media_type, .. } => media_type.clone(),
+            ImageSource::Url { url } => {
+                let ext = url.rsplit('.').next().unwrap_or("").to_ascii_lowercase();
+                match ext.as_str() {
+                    "jpg" | "jpeg" => "image/jpeg",
+                    "gif" => "image/gif",
+                    "webp" => "image/webp",
+                    _ => "image/png",
+                }.to_string()
+            }
+        }
+    }
 }
 
 #[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct LLMRequest {
     pub messages: Vec<Message>,
     pub config: LLMConfig,
+    // Native function calling. When absent, tools are expected in the XML protocol.
+    #[serde(default)]
+    pub tools: Option<Vec<ToolDefinition>>,
+    // "auto", "none", "required" or a tool name to force
+    #[serde(default)]
+    pub tool_choice: Option<String>,
+    // Cache breakpoints for providers that need them explicitly; ignored elsewhere
+    #[serde(default)]
+    pub prompt_cache: Option<PromptCache>,
+}
+
+impl LLMRequest {
+    /// The same request aimed at another endpoint.
+    pub fn with_base_url(&self, base_url: &str) -> Self {
+        let mut req = self.clone();
+        req.config.base_url = base_url.to_string();
+        req
+    }
 }
 
 #[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct ToolDefinition {
+    pub name: String,
+    pub description: String,
+    // JSON Schema of the arguments object
+    pub parameters: serde_json::Value,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
 pub struct ToolCall {
+    #[serde(default)]
+    pub id: Option<String>,
     pub name: String,
+    // Every value as a string, for display and the XML tool protocol
     pub arguments: HashMap<String, String>,
+    // The arguments object of a native call exactly as the model sent it, so numbers,
+    // booleans and nested values keep their types when the call is replayed
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub raw_arguments: Option<serde_json::Value>,
+}
+
+impl ToolCall {
+    /// A native tool call from its JSON-encoded arguments object.
+    pub fn native(id: Option<String>, name: String, raw: &str) -> Self {
+        Self {
+            id,
+            name,
+            arguments: flatten_arguments(raw),
+            raw_arguments: serde_json::from_str::<serde_json::Value>(raw).ok().filter(|v| v.is_object()),
+        }
+    }
+
+    /// Arguments as the JSON object providers expect when a call is replayed in history.
+    pub(crate) fn arguments_json(&self) -> serde_json::Value {
+        if let Some(raw) = &self.raw_arguments {
+            return raw.clone();
+        }
+        serde_json::Value::Object(self.arguments.iter()
+            .map(|(k, v)| (k.clone(), serde_json::Value::String(v.clone())))
+            .collect())
+    }
+}
+
+// Sets `object[key]` only when the value is present
+fn insert_some<T: Serialize>(object: &mut serde_json::Value, key: &str, value: &Option<T>) {
+    if let Some(value) = value {
+        object[key] = serde_json::json!(value);
+    }
+}
+
+// Flattens a JSON arguments object into the string map used by ToolCall
+fn flatten_arguments(raw: &str) -> HashMap<String, String> {
+    match serde_json::from_str::<serde_json::Value>(raw) {
+        Ok(serde_json::Value::Object(map)) => map.into_iter()
+            .map(|(k, v)| {
+                let value = match v {
+                    serde_json::Value::String(s) => s,
+                    other => other.to_string(),
+                };
+                (k, value)
+            })
+            .collect(),
+        _ => HashMap::new(),
+    }
+}
+
+fn collect_tool_calls(events: Vec<StreamEvent>) -> Vec<ToolCall> {
+    let mut tools = Vec::new();
+    let mut current_tool_name = None;
+    let mut current_args = HashMap::new();
+
+    for e in events {
+        match e {
+            StreamEvent::ToolStart(n) => { current_tool_name = Some(n); current_args.clear(); }
+            // Values may arrive in several fragments
+            StreamEvent::ToolArg(k, v) => { current_args.entry(k).or_insert_with(String::new).push_str(&v); }
+            StreamEvent::ToolEnd => {
+                if let Some(n) = current_tool_name.take() {
+                    tools.push(ToolCall { id: None, name: n, arguments: current_args.clone(), raw_arguments: None });
+                }
+            }
+            _ => {}
+        }
+    }
+    tools
 }
 
 #[derive(Debug, Clone, Serialize, PartialEq)]
@@ -37,31 +317,90 @@ pub enum StreamEvent {
     ToolStart(String), // tool name
     ToolArg(String, String), // key, value chunk
     ToolEnd,
+    Retrying(u32, u64, String), // next attempt, delay in ms, reason
+    Usage { prompt_tokens: u32, completion_tokens: u32 },
+    Cancelled,
+    Reasoning(String), // chain-of-thought from reasoning models, kept apart from the answer
     Error(String),
     Done,
 }
 
-#[derive(Deserialize)]
-struct OpenAIStreamChunk {
-    choices: Vec<OpenAIStreamChoice>,
+// State Machine for XML Parsing
+enum ParserState {
+    Text,
+    InTag(String), // Buffer accumulating tag name/attrs
+    InToolArg(String), // Arg name
+    InArgValue(ArgValue),
 }
 
-#[derive(Deserialize)]
-struct OpenAIStreamChoice {
-    delta: OpenAIStreamDelta,
-    finish_reason: Option<String>,
+// An argument whose value is being streamed out as ToolArg fragments
+struct ArgValue {
+    name: String,
+    closing_tag: String,
+    in_cdata: bool,
+    // Set once a fragment has been emitted for this argument
+    emitted: bool,
+    // Whether the last thing seen was a CDATA section
+    after_cdata: bool,
+    // Whitespace-only text, held until we know whether it is indentation around CDATA
+    held_ws: String,
 }
 
-#[derive(Deserialize)]
-struct OpenAIStreamDelta {
-    content: Option<String>,
+impl ArgValue {
+    fn new(name: &str) -> Self {
+        Self {
+            name: name.to_string(),
+            closing_tag: format!("</{}>", name),
+            in_cdata: false,
+            emitted: false,
+            after_cdata: false,
+            held_ws: String::new(),
+        }
+    }
+
+    fn emit(&mut self, text: &str, events: &mut Vec<StreamEvent>) {
+        let mut fragment = std::mem::take(&mut self.held_ws);
+        fragment.push_str(text);
+        if !fragment.is_empty() {
+            events.push(StreamEvent::ToolArg(self.name.clone(), fragment));
+            self.emitted = true;
+        }
+    }
 }
 
-// State Machine for XML Parsing
-enum ParserState {
-    Text,
-    InTag(String), // Buffer accumulating tag name/attrs
-    InToolArg(String), // Arg name
+const CDATA_START: &str = "<![CDATA[";
+const CDATA_END: &str = "]]>";
+
+// Decodes the predefined XML entities and numeric character references.
+// Anything unrecognised (including a bare '&') is kept verbatim.
+fn decode_entities(text: &str) -> String {
+    let mut out = String::with_capacity(text.len());
+    let mut rest = text;
+    while let Some(amp) = rest.find('&') {
+        out.push_str(&rest[..amp]);
+        rest = &rest[amp..];
+        let decoded = rest.find(';').and_then(|semi| {
+            let entity = &rest[1..semi];
+            let c = match entity {
+                "lt" => Some('<'),
+                "gt" => Some('>'),
+                "amp" => Some('&'),
+                "quot" => Some('"'),
+                "apos" => Some('\''),
+                _ => entity.strip_prefix("#x").or_else(|| entity.strip_prefix("#X"))
+                    .and_then(|hex| u32::from_str_radix(hex, 16).ok())
+                    .or_else(|| entity.strip_prefix('#').and_then(|dec| dec.parse().ok()))
+                    .and_then(char::from_u32),
+            };
+            c.map(|c| (c, semi + 1))
+        });
+        match decoded {
+            Some((c, len)) => { out.push(c); rest = &rest[len..]; }
+            None => { out.push('&'); rest = &rest[1..]; }
+        }
+    }
+    out.push_str(rest);
+    out
 }
 
 pub struct Parser {
@@ -138,7 +477,9 @@ impl Parser {
                     break;
                 },
                 ParserState::InToolArg(_) => {
-                    if let Some(tool_end) = self.buffer.find("</tool>") {
+                    // Only a </tool> in tag position ends the tool; one inside an argument value is data
+                    let next_tag = self.buffer.find('<');
+                    if let Some(tool_end) = next_tag.filter(|&i| self.buffer[i..].starts_with("</tool>")) {
                          events.push(StreamEvent::ToolEnd);
                          self.current_tool = None;
                          self.buffer = self.buffer[tool_end+7..].to_string();
@@ -151,192 +492,442 @@ impl Parser {
                               let tag_full = &self.buffer[start_tag_idx..start_tag_idx+end_tag_idx+1];
                               if !tag_full.starts_with("</") {
                                   let arg_name = tag_full.trim_matches(|c| c == '<' || c == '>');
-                                  let closing_tag = format!("</{}>", arg_name);
-
-                                  if let Some(closing_idx) = self.buffer.find(&closing_tag) {
-                                      let val = &self.buffer[start_tag_idx+end_tag_idx+1..closing_idx];
-                                      events.push(StreamEvent::ToolArg(arg_name.to_string(), val.to_string()));
-                                      self.buffer = self.buffer[closing_idx+closing_tag.len()..].to_string();
-                                      continue;
-                                  }
+
+                                  self.state = ParserState::InArgValue(ArgValue::new(arg_name));
+                                  self.buffer = self.buffer[start_tag_idx+end_tag_idx+1..].to_string();
+                                  continue;
                               }
                          }
                     }
                     break;
+                },
+                ParserState::InArgValue(_) => {
+                    let ParserState::InArgValue(mut arg) = std::mem::replace(&mut self.state, ParserState::Text) else { unreachable!() };
+                    if self.stream_arg_value(&mut arg, &mut events) {
+                        self.state = ParserState::InToolArg("".to_string());
+                        continue;
+                    }
+                    self.state = ParserState::InArgValue(arg);
+                    break;
                 }
             }
         }
         events
     }
+
+    // Emits as much of the current argument value as is unambiguous. Text is entity-decoded,
+    // CDATA sections are passed through verbatim (so code containing `</content>` or `&&`
+    // survives), and a '<' that starts neither CDATA nor the closing tag is kept literally since
+    // models rarely escape code. Returns true once the closing tag has been consumed.
+    fn stream_arg_value(&mut self, arg: &mut ArgValue, events: &mut Vec<StreamEvent>) -> bool {
+        let mut consumed = 0;
+        let done = loop {
+            let rest = &self.buffer[consumed..];
+            if arg.in_cdata {
+                if let Some(end) = rest.find(CDATA_END) {
+                    arg.emit(&rest[..end], events);
+                    consumed += end + CDATA_END.len();
+                    arg.in_cdata = false;
+                    arg.after_cdata = true;
+                    continue;
+                }
+                // Hold back what could be the start of "]]>"
+                let mut safe = rest.len().saturating_sub(CDATA_END.len() - 1);
+                while !rest.is_char_boundary(safe) { safe -= 1; }
+                arg.emit(&rest[..safe], events);
+                consumed += safe;
+                break false;
+            }
+            if rest.starts_with(CDATA_START) {
+                // <content>\n  <![CDATA[...]]>: the indentation isn't part of the value
+                if !arg.emitted { arg.held_ws.clear(); }
+                arg.in_cdata = true;
+                consumed += CDATA_START.len();
+                continue;
+            }
+            if rest.starts_with(arg.closing_tag.as_str()) {
+                if arg.after_cdata { arg.held_ws.clear(); }
+                arg.emit("", events);
+                if !arg.emitted {
+                    events.push(StreamEvent::ToolArg(arg.name.clone(), String::new()));
+                }
+                consumed += arg.closing_tag.len();
+                break true;
+            }
+            if rest.starts_with('<') {
+                if CDATA_START.starts_with(rest) || arg.closing_tag.starts_with(rest) {
+                    break false;
+                }
+                arg.emit("<", events);
+                arg.after_cdata = false;
+                consumed += 1;
+                continue;
+            }
+            // Text up to the next tag, minus a trailing entity that may still be incomplete
+            let end = rest.find('<').unwrap_or_else(|| {
+                rest.rfind('&')
+                    .filter(|&i| !rest[i..].contains(';') && rest.len() - i < 12)
+                    .unwrap_or(rest.len())
+            });
+            if end == 0 {
+                break false;
+            }
+            let text = decode_entities(&rest[..end]);
+            consumed += end;
+            if text.trim().is_empty() {
+                arg.held_ws.push_str(&text);
+            } else {
+                arg.emit(&text, events);
+                arg.after_cdata = false;
+            }
+        };
+        self.buffer = self.buffer[consumed..].to_string();
+        done
+    }
 }
 
 pub fn stream_chat(req: LLMRequest) -> Pin<Box<dyn Stream<Item = StreamEvent> + Send>> {
+    let provider = match provider::resolve(&req.config) {
+        Ok(provider) => provider,
+        Err(e) => return error_stream(e),
+    };
+    // Only keep a copy of the request around when there is somewhere to log it
+    let logged = request_log::active().map(|_| req.clone());
+    let endpoints = req.config.endpoints();
     Box::pin(async_stream::stream! {
-        if req.config.base_url.contains("mock") {
-            let mock_text = "Checking filesystem... \n<tool_code><tool name=\"run_command\"><program>ls</program><args>-la</args></tool></tool_code>";
-            let chunk_size = 5;
-            let mut parser = Parser::new();
-            for chunk in mock_text.chars().collect::<Vec<char>>().chunks(chunk_size) {
-                 let s: String = chunk.iter().collect();
-                 tokio::time::sleep(tokio::time::Duration::from_millis(50)).await;
-                 let events = parser.process_chunk(&s);
-                 for event in events { yield event; }
+        rate_limit::limiter().acquire(&req.config, || request_cost(provider.as_ref(), &req)).await;
+        if let Some(logged) = &logged {
+            request_log::log_request(logged);
+        }
+        let mut transcript = request_log::StreamTranscript::default();
+        'endpoints: for (i, endpoint) in endpoints.iter().enumerate() {
+            let has_fallback = i + 1 < endpoints.len();
+            let mut inner = provider.chat_stream(req.with_base_url(endpoint));
+            // Failing over is only safe before any output has been delivered
+            let mut started = false;
+            while let Some(event) = inner.next().await {
+                match &event {
+                    StreamEvent::Error(e) if !started && has_fallback && retry::is_connection_error(e) => continue 'endpoints,
+                    StreamEvent::Retrying(..) => {}
+                    _ => started = true,
+                }
+                if let Some(logged) = &logged {
+                    transcript.observe(logged, &event);
+                }
+                yield event;
             }
-             let events = parser.process_chunk("");
-             for event in events { yield event; }
-             yield StreamEvent::Done;
-             return;
+            break;
         }
+    })
+}
 
-        let client = Client::new();
-        let url = format!("{}/chat/completions", req.config.base_url.trim_end_matches('/'));
-
-        let body = serde_json::json!({
-            "model": req.config.model,
-            "messages": req.messages,
-            "temperature": req.config.temperature,
-            "stream": true
-        });
-
-        let mut res = match client.post(&url)
-            .header("Authorization", format!("Bearer {}", req.config.api_key))
-            .header("Content-Type", "application/json")
-            .json(&body)
-            .send()
-            .await {
-                Ok(r) => r,
-                Err(e) => { yield StreamEvent::Error(e.to_string()); return; }
-            };
+async fn chat_with_failover(provider: &dyn LlmProvider, req: &LLMRequest) -> Result<LLMResponse, String> {
+    let endpoints = req.config.endpoints();
+    for (i, endpoint) in endpoints.iter().enumerate() {
+        let routed = (i > 0).then(|| req.with_base_url(endpoint));
+        let req = routed.as_ref().unwrap_or(req);
+        rate_limit::limiter().acquire(&req.config, || request_cost(provider, req)).await;
+        match provider.chat(req).await {
+            Ok(mut res) => {
+                res.endpoint = Some(endpoint.clone()).filter(|e| !e.is_empty());
+                return Ok(res);
+            }
+            Err(e) if i + 1 < endpoints.len() && retry::is_connection_error(&e) => continue,
+            Err(e) => return Err(e),
+        }
+    }
+    unreachable!("endpoints() always contains base_url")
+}
 
-        if !res.status().is_success() {
-             yield StreamEvent::Error(format!("API Error: {}", res.status()));
-             return;
+// Tops up `res` to `wanted` candidates with parallel single-choice requests, for providers
+// without a native `n`. Usage counters are summed across the requests.
+async fn fill_choices(provider: &dyn LlmProvider, req: &LLMRequest, mut res: LLMResponse, wanted: usize) -> Result<LLMResponse, String> {
+    let mut choices = res.all_choices();
+    let mut single = req.clone();
+    single.config.n = None;
+    let single = &single;
+    let extra = futures::future::join_all((choices.len()..wanted)
+        .map(|_| chat_with_failover(provider, single))).await;
+
+    for candidate in extra {
+        let candidate = candidate?;
+        if let Some(usage) = candidate.usage.as_ref() {
+            let total = res.usage.get_or_insert_with(HashMap::new);
+            for (key, value) in usage {
+                *total.entry(key.clone()).or_insert(0) += value;
+            }
         }
+        choices.extend(candidate.all_choices());
+    }
+    res.choices = choices;
+    Ok(res)
+}
 
-        let mut parser = Parser::new();
-        while let Some(chunk_res) = res.chunk().await.transpose() {
-             match chunk_res {
-                 Ok(chunk) => {
-                     let s = String::from_utf8_lossy(&chunk);
-                     for line in s.lines() {
-                         if line.starts_with("data: ") {
-                             let json_str = &line[6..];
-                             if json_str == "[DONE]" { yield StreamEvent::Done; return; }
-                             if let Ok(data) = serde_json::from_str::<OpenAIStreamChunk>(json_str) {
-                                 if let Some(choice) = data.choices.first() {
-                                     if let Some(content) = &choice.delta.content {
-                                         let events = parser.process_chunk(content);
-                                         for event in events { yield event; }
-                                     }
-                                 }
-                             }
-                         }
-                     }
-                 },
-                 Err(e) => { yield StreamEvent::Error(e.to_string()); }
-             }
+// Tokens a request counts against a TPM limit: the prompt plus the completion budget
+fn request_cost(provider: &dyn LlmProvider, req: &LLMRequest) -> u32 {
+    let prompt = provider.count_tokens(&req.config.model, &req.messages) as u32;
+    prompt.saturating_add(req.config.max_tokens.unwrap_or(0))
+}
+
+/// Like `stream_chat`, but stops as soon as `cancel` fires: the provider stream (and with it
+/// the HTTP connection) is dropped and `StreamEvent::Cancelled` is the final event.
+pub fn stream_chat_cancellable(req: LLMRequest, cancel: CancellationToken) -> Pin<Box<dyn Stream<Item = StreamEvent> + Send>> {
+    let mut inner = stream_chat(req);
+    Box::pin(async_stream::stream! {
+        loop {
+            tokio::select! {
+                biased;
+                _ = cancel.cancelled() => {
+                    yield StreamEvent::Cancelled;
+                    return;
+                }
+                event = inner.next() => match event {
+                    Some(event) => yield event,
+                    None => return,
+                },
+            }
         }
     })
 }
 
+pub(crate) fn error_stream(error: String) -> Pin<Box<dyn Stream<Item = StreamEvent> + Send>> {
+    Box::pin(futures::stream::once(async move { StreamEvent::Error(error) }))
+}
+
 #[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct LLMResponse {
     pub role: String,
     pub content: String,
     pub tool_calls: Option<Vec<ToolCall>>,
     pub usage: Option<HashMap<String, u32>>,
+    // Reasoning/thinking text, when the provider returns it separately
+    #[serde(default)]
+    pub reasoning: Option<String>,
+    // Every candidate when more than one was requested (`LLMConfig::n`); the fields above
+    // mirror the first
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub choices: Vec<Choice>,
+    // Base URL that served the request, set by the gateway when one is configured
+    #[serde(default)]
+    pub endpoint: Option<String>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub struct Choice {
+    pub content: String,
+    pub tool_calls: Option<Vec<ToolCall>>,
+    #[serde(default)]
+    pub reasoning: Option<String>,
+}
+
+impl LLMResponse {
+    /// The candidates in order, including the primary one when only one was generated.
+    pub fn all_choices(&self) -> Vec<Choice> {
+        if !self.choices.is_empty() {
+            return self.choices.clone();
+        }
+        vec![Choice {
+            content: self.content.clone(),
+            tool_calls: self.tool_calls.clone(),
+            reasoning: self.reasoning.clone(),
+        }]
+    }
+
+    /// Makes `choice` the primary answer, keeping role, usage and the candidate list.
+    pub fn with_primary(mut self, choice: Choice) -> Self {
+        self.content = choice.content;
+        self.tool_calls = choice.tool_calls;
+        self.reasoning = choice.reasoning;
+        self
+    }
 }
 
 pub async fn send_chat_logic(req: LLMRequest) -> Result<LLMResponse, String> {
-    if req.config.base_url.contains("mock") {
-             let content = "Checking filesystem... \n<tool_code><tool name=\"run_command\"><program>ls</program><args>-la</args></tool></tool_code>".to_string();
-             let mut parser = Parser::new();
-             let events = parser.process_chunk(&content);
-             let mut tools = Vec::new();
-             let mut current_tool_name = None;
-             let mut current_args = HashMap::new();
-
-             for e in events {
-                 match e {
-                     StreamEvent::ToolStart(n) => { current_tool_name = Some(n); current_args.clear(); }
-                     StreamEvent::ToolArg(k, v) => { current_args.insert(k, v); }
-                     StreamEvent::ToolEnd => {
-                         if let Some(n) = current_tool_name.take() {
-                             tools.push(ToolCall { name: n, arguments: current_args.clone() });
-                         }
-                     }
-                     _ => {}
-                 }
-             }
-
-             return Ok(LLMResponse {
-                 role: "assistant".to_string(),
-                 content,
-                 tool_calls: Some(tools),
-                 usage: None,
-             });
-    }
-
-    let client = Client::new();
-    let url = format!("{}/chat/completions", req.config.base_url.trim_end_matches('/'));
-    let body = serde_json::json!({
-        "model": req.config.model,
-        "messages": req.messages,
-        "temperature": req.config.temperature
-    });
-
-    let res = client.post(&url)
-        .header("Authorization", format!("Bearer {}", req.config.api_key))
-        .header("Content-Type", "application/json")
-        .json(&body)
-        .send()
-        .await
-        .map_err(|e| format!("Request failed: {}", e))?;
-
-    if !res.status().is_success() {
-            return Err(format!("API Error: {}", res.status()));
-    }
-
-    #[derive(Deserialize)]
-    struct LocalOpenAIResponse {
-        choices: Vec<LocalOpenAIChoice>,
-        #[serde(default)]
-        usage: Option<HashMap<String, u32>>,
-    }
-    #[derive(Deserialize)]
-    struct LocalOpenAIChoice {
-        message: Message,
-    }
-
-    let open_ai_res: LocalOpenAIResponse = res.json().await.map_err(|e| e.to_string())?;
-
-    let (role, content) = open_ai_res.choices.first()
-        .map(|c| (c.message.role.clone(), c.message.content.clone()))
-        .unwrap_or(("assistant".to_string(), "".to_string()));
-
-    let mut parser = Parser::new();
-    let events = parser.process_chunk(&content);
-    let mut tools = Vec::new();
-    let mut current_tool_name = None;
-    let mut current_args = HashMap::new();
+    request_log::log_request(&req);
+    let result = send_chat_cached(&req).await;
+    request_log::log_result(&req, &result);
+    result
+}
 
-    for e in events {
-            match e {
-                StreamEvent::ToolStart(n) => { current_tool_name = Some(n); current_args.clear(); }
-                StreamEvent::ToolArg(k, v) => { current_args.insert(k, v); }
-                StreamEvent::ToolEnd => {
-                    if let Some(n) = current_tool_name.take() {
-                        tools.push(ToolCall { name: n, arguments: current_args.clone() });
-                    }
+async fn send_chat_cached(req: &LLMRequest) -> Result<LLMResponse, String> {
+    let cache = response_cache::active();
+    if let Some(hit) = cache.as_ref().and_then(|c| c.get(req)) {
+        return Ok(hit);
+    }
+    let provider = provider::resolve(&req.config)?;
+    let mut res = chat_with_failover(provider.as_ref(), req).await?;
+    let wanted = req.config.n.unwrap_or(1) as usize;
+    if res.all_choices().len() < wanted {
+        res = fill_choices(provider.as_ref(), req, res, wanted).await?;
+    }
+    if let Some(cache) = cache {
+        // A failed write only costs a future cache miss
+        let _ = cache.put(req, &res);
+    }
+    Ok(res)
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct ModelInfo {
+    pub id: String,
+    pub name: Option<String>,
+    pub context_length: Option<u32>,
+    // USD per token, as reported by OpenRouter
+    pub prompt_price: Option<f64>,
+    pub completion_price: Option<f64>,
+}
+
+impl ModelInfo {
+    pub(crate) fn from_id(id: String) -> Self {
+        Self { id, name: None, context_length: None, prompt_price: None, completion_price: None }
+    }
+}
+
+/// Models offered by the configured provider, with whatever metadata its listing includes.
+pub async fn list_models(config: &LLMConfig) -> Result<Vec<ModelInfo>, String> {
+    provider::resolve(config)?.list_models(config).await
+}
+
+/// Embeddings for `inputs` from the configured provider; `config.model` names the
+/// embedding model (e.g. "text-embedding-3-small", or "nomic-embed-text" on Ollama).
+pub async fn embed(config: &LLMConfig, inputs: &[String]) -> Result<Vec<Vec<f32>>, String> {
+    provider::resolve(config)?.embed(config, inputs).await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse_all(input: &str) -> Vec<StreamEvent> {
+        let mut parser = Parser::new();
+        let mut events = Vec::new();
+        // Feed in small pieces to exercise chunk boundaries
+        for chunk in input.chars().collect::<Vec<_>>().chunks(3) {
+            events.extend(parser.process_chunk(&chunk.iter().collect::<String>()));
+        }
+        events
+    }
+
+    // Joins consecutive fragments of the same argument
+    fn tool_args(events: &[StreamEvent]) -> Vec<(String, String)> {
+        let mut args: Vec<(String, String)> = Vec::new();
+        for event in events {
+            if let StreamEvent::ToolArg(k, v) = event {
+                match args.last_mut() {
+                    Some((last, value)) if last == k => value.push_str(v),
+                    _ => args.push((k.clone(), v.clone())),
                 }
-                _ => {}
             }
+        }
+        args
     }
 
-    Ok(LLMResponse {
-        role,
-        content,
-        tool_calls: Some(tools),
-        usage: open_ai_res.usage,
-    })
+    #[test]
+    fn test_native_tool_call_keeps_typed_arguments() {
+        let call = ToolCall::native(Some("c".into()), "insert_lines".into(), r#"{"line":3,"opts":{"a":true}}"#);
+        assert_eq!(call.arguments.get("line").map(String::as_str), Some("3"));
+        assert_eq!(call.arguments_json(), serde_json::json!({"line": 3, "opts": {"a": true}}));
+
+        let parsed = ToolCall { name: "x".into(), arguments: HashMap::from([("line".into(), "3".into())]), ..Default::default() };
+        assert_eq!(parsed.arguments_json(), serde_json::json!({"line": "3"}));
+    }
+
+    #[test]
+    fn test_parser_cdata_and_entities_round_trip() {
+        let code = "if a < b && c > d { s.push_str(\"</content></tool>\"); }";
+        let input = format!(
+            "<tool_code><tool name=\"write_file\"><file_path>a&amp;b.rs</file_path>\n<content>\n<![CDATA[{}]]>\n</content><args>x &lt; y &#x26; z</args></tool></tool_code>",
+            code
+        );
+        assert_eq!(tool_args(&parse_all(&input)), vec![
+            ("file_path".to_string(), "a&b.rs".to_string()),
+            ("content".to_string(), code.to_string()),
+            ("args".to_string(), "x < y & z".to_string()),
+        ]);
+    }
+
+    #[test]
+    fn test_parser_streams_argument_fragments() {
+        let mut parser = Parser::new();
+        let mut events = parser.process_chunk("<tool_code><tool name=\"write_file\"><content>fn main() {");
+        assert_eq!(events.pop(), Some(StreamEvent::ToolArg("content".into(), "fn main() {".into())));
+
+        let events = parser.process_chunk(" }</content><args></args></tool></tool_code>");
+        assert_eq!(events, vec![
+            StreamEvent::ToolArg("content".into(), " }".into()),
+            StreamEvent::ToolArg("args".into(), "".into()),
+            StreamEvent::ToolEnd,
+        ]);
+    }
+
+    #[test]
+    fn test_parser_keeps_unescaped_code() {
+        let input = "<tool_code><tool name=\"run_command\"><program>sh</program><args>-c 'a && b < c'</args></tool></tool_code>";
+        assert_eq!(tool_args(&parse_all(input)), vec![
+            ("program".to_string(), "sh".to_string()),
+            ("args".to_string(), "-c 'a && b < c'".to_string()),
+        ]);
+    }
+
+    #[tokio::test]
+    async fn test_cancelled_stream_ends_with_cancelled() {
+        let req = LLMRequest {
+            messages: vec![],
+            config: LLMConfig {
+                api_key: String::new(),
+                base_url: "mock".to_string(),
+                model: "m".to_string(),
+                temperature: 0.0,
+                ..Default::default()
+            },
+            tools: None,
+            tool_choice: None,
+            prompt_cache: None,
+        };
+        let cancel = CancellationToken::new();
+        let mut stream = stream_chat_cancellable(req, cancel.clone());
+
+        assert!(matches!(stream.next().await, Some(StreamEvent::Token(_))));
+        cancel.cancel();
+        assert_eq!(stream.next().await, Some(StreamEvent::Cancelled));
+        assert_eq!(stream.next().await, None);
+    }
+
+    // Unreachable everywhere except base_url "http://backup"
+    struct Flaky;
+
+    #[async_trait::async_trait]
+    impl LlmProvider for Flaky {
+        async fn chat(&self, req: &LLMRequest) -> Result<LLMResponse, String> {
+            match req.config.base_url.as_str() {
+                "http://backup" => mock::send_chat(req).await,
+                "http://rejects" => Err("API Error: 401 Unauthorized".to_string()),
+                _ => Err("Request failed: connection refused".to_string()),
+            }
+        }
+
+        fn chat_stream(&self, _req: LLMRequest) -> Pin<Box<dyn Stream<Item = StreamEvent> + Send>> {
+            error_stream("unused".to_string())
+        }
+    }
+
+    #[tokio::test]
+    async fn test_failover_on_connection_errors_only() {
+        let mut req = LLMRequest {
+            messages: vec![Message::new("user", "hi")],
+            config: LLMConfig {
+                base_url: "http://primary".to_string(),
+                fallback_base_urls: vec!["http://backup".to_string()],
+                ..Default::default()
+            },
+            tools: None,
+            tool_choice: None,
+            prompt_cache: None,
+        };
+        let res = chat_with_failover(&Flaky, &req).await.unwrap();
+        assert_eq!(res.endpoint.as_deref(), Some("http://backup"));
+
+        req.config.base_url = "http://rejects".to_string();
+        assert_eq!(chat_with_failover(&Flaky, &req).await.err(), Some("API Error: 401 Unauthorized".to_string()));
+    }
 }
@@ -0,0 +1,48 @@
+use std::pin::Pin;
+use futures::Stream;
+
+use crate::{collect_tool_calls, LLMRequest, LLMResponse, Parser, StreamEvent};
+
+// Canned responses for offline development; selected whenever base_url contains "mock".
+
+const MOCK_TEXT: &str = "Checking filesystem... \n<tool_code><tool name=\"run_command\"><program>ls</program><args>-la</args></tool></tool_code>";
+
+pub(crate) async fn send_chat(_req: &LLMRequest) -> Result<LLMResponse, String> {
+    let mut parser = Parser::new();
+    Ok(LLMResponse {
+        role: "assistant".to_string(),
+        content: MOCK_TEXT.to_string(),
+        tool_calls: Some(collect_tool_calls(parser.process_chunk(MOCK_TEXT))),
+        usage: None,
+        reasoning: None,
+        choices: Vec::new(),
+        endpoint: None,
+    })
+}
+
+pub(crate) fn stream_chat(_req: LLMRequest) -> Pin<Box<dyn Stream<Item = StreamEvent> + Send>> {
+    Box::pin(async_stream::stream! {
+        let mut parser = Parser::new();
+        for chunk in MOCK_TEXT.chars().collect::<Vec<char>>().chunks(5) {
+            let s: String = chunk.iter().collect();
+            tokio::time::sleep(tokio::time::Duration::from_millis(50)).await;
+            for event in parser.process_chunk(&s) { yield event; }
+        }
+        for event in parser.process_chunk("") { yield event; }
+        yield StreamEvent::Done;
+    })
+}
+
+const MOCK_DIMENSIONS: usize = 64;
+
+// Hashed bag of words, so texts sharing words come out similar without a model
+pub(crate) async fn embed(inputs: &[String]) -> Result<Vec<Vec<f32>>, String> {
+    Ok(inputs.iter().map(|text| {
+        let mut vector = vec![0.0; MOCK_DIMENSIONS];
+        for word in text.split(|c: char| !c.is_alphanumeric()).filter(|w| !w.is_empty()) {
+            let hash = word.to_lowercase().bytes().fold(5381usize, |h, b| h.wrapping_mul(33) ^ b as usize);
+            vector[hash % MOCK_DIMENSIONS] += 1.0;
+        }
+        vector
+    }).collect())
+}
@@ -0,0 +1,406 @@
+use std::collections::HashMap;
+use std::pin::Pin;
+use futures::Stream;
+use reqwest::Client;
+use serde::Deserialize;
+
+use crate::openai::tools_to_openai;
+use crate::retry;
+use crate::{collect_tool_calls, insert_some, ImageSource, LLMRequest, LLMResponse, LLMConfig, ModelInfo, Parser, StreamEvent, ToolCall};
+
+// Ollama native API (/api/chat streams newline-delimited JSON, not SSE)
+// https://github.com/ollama/ollama/blob/main/docs/api.md
+
+const DEFAULT_BASE_URL: &str = "http://localhost:11434";
+
+fn base_url(config: &LLMConfig) -> String {
+    let base = if config.base_url.is_empty() { DEFAULT_BASE_URL } else { config.base_url.as_str() };
+    base.trim_end_matches('/').to_string()
+}
+
+// Ollama takes raw base64 in a per-message "images" list; URLs would have to be fetched
+// first, so they are dropped
+fn messages_json(req: &LLMRequest) -> serde_json::Value {
+    serde_json::Value::Array(req.messages.iter().map(|m| {
+        let mut message = serde_json::json!({ "role": m.role, "content": m.content });
+        let images: Vec<&str> = m.images.iter()
+            .filter_map(|image| match image {
+                ImageSource::Base64 { data, .. } => Some(data.as_str()),
+                ImageSource::Url { .. } => None,
+            })
+            .collect();
+        if !images.is_empty() {
+            message["images"] = serde_json::json!(images);
+        }
+        // Ollama follows OpenAI's "tool" role but takes arguments as an object and
+        // identifies results by tool name
+        insert_some(&mut message, "tool_name", &m.name);
+        let calls = m.native_tool_calls();
+        if !calls.is_empty() {
+            message["tool_calls"] = serde_json::Value::Array(calls.iter().map(|c| serde_json::json!({
+                "function": { "name": c.name, "arguments": c.arguments_json() }
+            })).collect());
+        }
+        message
+    }).collect())
+}
+
+fn build_body(req: &LLMRequest, stream: bool) -> serde_json::Value {
+    let mut body = serde_json::json!({
+        "model": req.config.model,
+        "messages": messages_json(req),
+        "stream": stream,
+        "options": { "temperature": req.config.temperature }
+    });
+    let options = &mut body["options"];
+    insert_some(options, "num_predict", &req.config.max_tokens);
+    insert_some(options, "top_p", &req.config.top_p);
+    insert_some(options, "stop", &req.config.stop);
+    insert_some(options, "frequency_penalty", &req.config.frequency_penalty);
+    insert_some(options, "presence_penalty", &req.config.presence_penalty);
+    if let Some(tools) = req.tools.as_ref().filter(|t| !t.is_empty()) {
+        body["tools"] = tools_to_openai(tools);
+    }
+    body
+}
+
+fn request(client: &Client, req: &LLMRequest, stream: bool) -> reqwest::RequestBuilder {
+    client.post(format!("{}/api/chat", base_url(&req.config)))
+        .json(&build_body(req, stream))
+}
+
+#[derive(Deserialize)]
+struct ChatChunk {
+    message: Option<ChatMessage>,
+    #[serde(default)]
+    done: bool,
+    prompt_eval_count: Option<u32>,
+    eval_count: Option<u32>,
+    error: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct ChatMessage {
+    role: String,
+    #[serde(default)]
+    content: String,
+    // Filled when the request sets "think": true
+    #[serde(default)]
+    thinking: String,
+    #[serde(default)]
+    tool_calls: Vec<OllamaToolCall>,
+}
+
+#[derive(Deserialize)]
+struct OllamaToolCall {
+    function: OllamaFunction,
+}
+
+#[derive(Deserialize)]
+struct OllamaFunction {
+    name: String,
+    // Unlike OpenAI, Ollama returns arguments as a JSON object
+    #[serde(default)]
+    arguments: serde_json::Value,
+}
+
+fn to_tool_call(call: OllamaToolCall) -> ToolCall {
+    ToolCall::native(None, call.function.name, &call.function.arguments.to_string())
+}
+
+fn usage_of(chunk: &ChatChunk) -> Option<HashMap<String, u32>> {
+    let mut usage = HashMap::new();
+    if let Some(p) = chunk.prompt_eval_count { usage.insert("prompt_tokens".to_string(), p); }
+    if let Some(c) = chunk.eval_count { usage.insert("completion_tokens".to_string(), c); }
+    if usage.is_empty() { None } else { Some(usage) }
+}
+
+pub(crate) async fn send_chat(req: &LLMRequest) -> Result<LLMResponse, String> {
+    let client = req.config.http_client()?;
+    let res = retry::send(&req.config.retry, || request(&client, req, false)).await?;
+
+    let chunk: ChatChunk = res.json().await.map_err(|e| e.to_string())?;
+    parse_response(chunk)
+}
+
+fn parse_response(chunk: ChatChunk) -> Result<LLMResponse, String> {
+    if let Some(error) = chunk.error {
+        return Err(error);
+    }
+    let usage = usage_of(&chunk);
+    let message = chunk.message.ok_or("Ollama returned no message")?;
+
+    let tools = if !message.tool_calls.is_empty() {
+        message.tool_calls.into_iter().map(to_tool_call).collect()
+    } else {
+        let mut parser = Parser::new();
+        collect_tool_calls(parser.process_chunk(&message.content))
+    };
+
+    Ok(LLMResponse {
+        role: message.role,
+        content: message.content,
+        tool_calls: Some(tools),
+        usage,
+        reasoning: Some(message.thinking).filter(|t| !t.is_empty()),
+        choices: Vec::new(),
+        endpoint: None,
+    })
+}
+
+// Events for one streamed line. A finished stream ends with Done (or Error).
+fn chunk_events(parser: &mut Parser, chunk: ChatChunk) -> Vec<StreamEvent> {
+    if let Some(error) = chunk.error {
+        return vec![StreamEvent::Error(error)];
+    }
+    let mut events = Vec::new();
+    if let Some(message) = chunk.message {
+        if !message.thinking.is_empty() {
+            events.push(StreamEvent::Reasoning(message.thinking));
+        }
+        if !message.content.is_empty() {
+            events.extend(parser.process_chunk(&message.content));
+        }
+        for call in message.tool_calls {
+            let call = to_tool_call(call);
+            events.push(StreamEvent::ToolStart(call.name));
+            events.extend(call.arguments.into_iter().map(|(key, value)| StreamEvent::ToolArg(key, value)));
+            events.push(StreamEvent::ToolEnd);
+        }
+    }
+    if chunk.done {
+        events.push(StreamEvent::Usage {
+            prompt_tokens: chunk.prompt_eval_count.unwrap_or(0),
+            completion_tokens: chunk.eval_count.unwrap_or(0),
+        });
+        events.push(StreamEvent::Done);
+    }
+    events
+}
+
+// NDJSON framing. Bytes are kept until their line is complete, so a chunk that ends
+// inside a multi-byte character is not decoded early.
+#[derive(Default)]
+struct LineBuffer {
+    pending: Vec<u8>,
+}
+
+impl LineBuffer {
+    fn push(&mut self, bytes: &[u8]) -> Vec<String> {
+        self.pending.extend_from_slice(bytes);
+        let mut lines = Vec::new();
+        while let Some(newline) = self.pending.iter().position(|&b| b == b'\n') {
+            let line: Vec<u8> = self.pending.drain(..=newline).take(newline).collect();
+            lines.push(String::from_utf8_lossy(&line).into_owned());
+        }
+        lines
+    }
+
+    fn finish(&mut self) -> Option<String> {
+        if self.pending.is_empty() {
+            return None;
+        }
+        Some(String::from_utf8_lossy(&std::mem::take(&mut self.pending)).into_owned())
+    }
+}
+
+pub(crate) fn stream_chat(req: LLMRequest) -> Pin<Box<dyn Stream<Item = StreamEvent> + Send>> {
+    let client = match req.config.http_client() {
+        Ok(client) => client,
+        Err(e) => return crate::error_stream(e),
+    };
+    let policy = req.config.retry.clone();
+    retry::stream(policy, move || request(&client, &req, true), |mut res| async_stream::stream! {
+        let mut parser = Parser::new();
+        let mut lines = LineBuffer::default();
+        let mut ended = false;
+        while !ended {
+            let received = match res.chunk().await {
+                Ok(Some(bytes)) => lines.push(&bytes),
+                // The last line may not end with a newline
+                Ok(None) => {
+                    ended = true;
+                    lines.finish().into_iter().collect()
+                }
+                Err(e) => {
+                    yield StreamEvent::Error(e.to_string());
+                    continue;
+                }
+            };
+            for line in received {
+                let line = line.trim();
+                if line.is_empty() { continue; }
+                let Ok(chunk) = serde_json::from_str::<ChatChunk>(line) else { continue };
+                let events = chunk_events(&mut parser, chunk);
+                let finished = matches!(events.last(), Some(StreamEvent::Done | StreamEvent::Error(_)));
+                for event in events { yield event; }
+                if finished { return; }
+            }
+        }
+    })
+}
+
+#[derive(Deserialize)]
+struct TagsResponse {
+    models: Vec<TagEntry>,
+}
+
+#[derive(Deserialize)]
+struct TagEntry {
+    name: String,
+}
+
+/// Models pulled into the local Ollama instance.
+pub(crate) async fn list_models(config: &LLMConfig) -> Result<Vec<ModelInfo>, String> {
+    let res = config.http_client()?.get(format!("{}/api/tags", base_url(config)))
+        .send()
+        .await
+        .map_err(|e| format!("Request failed: {}", e))?;
+
+    if !res.status().is_success() {
+        return Err(format!("API Error: {}", res.status()));
+    }
+
+    let tags: TagsResponse = res.json().await.map_err(|e| e.to_string())?;
+    Ok(tags.models.into_iter().map(|m| ModelInfo::from_id(m.name)).collect())
+}
+
+#[derive(Deserialize)]
+struct EmbedResponse {
+    embeddings: Vec<Vec<f32>>,
+}
+
+pub(crate) async fn embed(config: &LLMConfig, inputs: &[String]) -> Result<Vec<Vec<f32>>, String> {
+    let res = config.http_client()?.post(format!("{}/api/embed", base_url(config)))
+        .json(&serde_json::json!({ "model": config.model, "input": inputs }))
+        .send()
+        .await
+        .map_err(|e| format!("Request failed: {}", e))?;
+
+    if !res.status().is_success() {
+        return Err(format!("API Error: {}", res.status()));
+    }
+
+    let body: EmbedResponse = res.json().await.map_err(|e| e.to_string())?;
+    Ok(body.embeddings)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Message, ToolDefinition};
+
+    fn request(messages: Vec<Message>) -> LLMRequest {
+        LLMRequest {
+            messages,
+            config: LLMConfig { model: "qwen2.5-coder".to_string(), max_tokens: Some(256), ..Default::default() },
+            tools: None,
+            tool_choice: None,
+            prompt_cache: None,
+        }
+    }
+
+    #[test]
+    fn test_messages_keep_roles_and_send_object_arguments() {
+        let call = ToolCall {
+            id: Some("call_1".to_string()),
+            name: "read_file".to_string(),
+            arguments: HashMap::from([("file_path".to_string(), "src/lib.rs".to_string())]),
+            raw_arguments: None,
+        };
+        let image = ImageSource::Base64 { media_type: "image/png".to_string(), data: "aGk=".to_string() };
+        let url = ImageSource::Url { url: "https://example.com/a.png".to_string() };
+        let body = build_body(&request(vec![
+            Message::new("system", "Be brief."),
+            Message { role: "user".to_string(), content: "look".to_string(), images: vec![image, url], ..Default::default() },
+            Message { role: "assistant".to_string(), tool_calls: Some(vec![call]), ..Default::default() },
+            Message::tool_result("call_1", "read_file", "fn main() {}"),
+        ]), false);
+
+        let messages = body["messages"].as_array().unwrap();
+        assert_eq!(messages[0], serde_json::json!({ "role": "system", "content": "Be brief." }));
+        assert_eq!(messages[1]["images"], serde_json::json!(["aGk="]));
+        assert_eq!(messages[2]["tool_calls"][0]["function"]["arguments"], serde_json::json!({ "file_path": "src/lib.rs" }));
+        assert_eq!(messages[3], serde_json::json!({ "role": "tool", "content": "fn main() {}", "tool_name": "read_file" }));
+        assert_eq!(body["options"]["num_predict"], 256);
+        assert!(body["options"].get("top_p").is_none());
+    }
+
+    #[test]
+    fn test_tools_use_openai_schema_without_tool_choice() {
+        let mut req = request(vec![Message::new("user", "hi")]);
+        req.tool_choice = Some("required".to_string());
+        assert!(build_body(&req, false).get("tools").is_none());
+
+        req.tools = Some(vec![ToolDefinition {
+            name: "read_file".to_string(),
+            description: "Read".to_string(),
+            parameters: serde_json::json!({ "type": "object" }),
+        }]);
+        let body = build_body(&req, true);
+        assert_eq!(body["stream"], true);
+        assert_eq!(body["tools"][0]["function"]["name"], "read_file");
+        // Ollama has no tool_choice; the model always decides
+        assert!(body.get("tool_choice").is_none());
+    }
+
+    #[test]
+    fn test_parse_response() {
+        let chunk: ChatChunk = serde_json::from_value(serde_json::json!({
+            "message": {
+                "role": "assistant",
+                "content": "",
+                "thinking": "Check the file.",
+                "tool_calls": [{ "function": { "name": "read_file", "arguments": { "file_path": "src/lib.rs" } } }],
+            },
+            "done": true,
+            "prompt_eval_count": 30,
+            "eval_count": 9,
+        })).unwrap();
+        let res = parse_response(chunk).unwrap();
+        assert_eq!(res.reasoning.as_deref(), Some("Check the file."));
+        let calls = res.tool_calls.unwrap();
+        assert_eq!((calls[0].name.as_str(), calls[0].arguments["file_path"].as_str()), ("read_file", "src/lib.rs"));
+        assert_eq!(res.usage.unwrap()["completion_tokens"], 9);
+
+        let error: ChatChunk = serde_json::from_str(r#"{"error":"model not found"}"#).unwrap();
+        assert_eq!(parse_response(error).err().as_deref(), Some("model not found"));
+    }
+
+    #[test]
+    fn test_chunk_events() {
+        let mut parser = Parser::new();
+        let mut events = Vec::new();
+        for line in [
+            r#"{"message":{"role":"assistant","content":"","thinking":"Hmm"},"done":false}"#,
+            r#"{"message":{"role":"assistant","content":"","tool_calls":[{"function":{"name":"read_file","arguments":{"file_path":"a.rs"}}}]},"done":false}"#,
+            r#"{"message":{"role":"assistant","content":""},"done":true,"prompt_eval_count":4,"eval_count":2}"#,
+        ] {
+            events.extend(chunk_events(&mut parser, serde_json::from_str(line).unwrap()));
+        }
+        assert_eq!(events, vec![
+            StreamEvent::Reasoning("Hmm".into()),
+            StreamEvent::ToolStart("read_file".into()),
+            StreamEvent::ToolArg("file_path".into(), "a.rs".into()),
+            StreamEvent::ToolEnd,
+            StreamEvent::Usage { prompt_tokens: 4, completion_tokens: 2 },
+            StreamEvent::Done,
+        ]);
+
+        let error = chunk_events(&mut parser, serde_json::from_str(r#"{"error":"out of memory"}"#).unwrap());
+        assert_eq!(error, vec![StreamEvent::Error("out of memory".into())]);
+    }
+
+    #[test]
+    fn test_line_buffer_splits_on_bytes() {
+        let mut lines = LineBuffer::default();
+        let text = "{\"content\":\"héllo\"}\n{\"done\":true}";
+        let bytes = text.as_bytes();
+        // Split inside the two-byte 'é'
+        let cut = text.find('é').unwrap() + 1;
+        assert!(lines.push(&bytes[..cut]).is_empty());
+        assert_eq!(lines.push(&bytes[cut..]), vec!["{\"content\":\"héllo\"}".to_string()]);
+        assert_eq!(lines.finish().as_deref(), Some("{\"done\":true}"));
+        assert_eq!(lines.finish(), None);
+    }
+}
@@ -0,0 +1,599 @@
+use std::pin::Pin;
+use futures::Stream;
+use reqwest::Client;
+use serde::Deserialize;
+
+use crate::prompt_cache::{breakpoints, content_parts, usage_map};
+use crate::retry;
+use crate::sse::SseDecoder;
+use crate::{collect_tool_calls, flatten_arguments, Choice, insert_some, LLMConfig, LLMRequest, LLMResponse, Message, ModelInfo, OpenRouterOptions, Parser, StreamEvent, ToolCall, ToolDefinition};
+
+// OpenAI Chat Completions, also spoken by OpenRouter, LM Studio, vLLM, ...
+
+// OpenAI wire format for `tools`
+pub(crate) fn tools_to_openai(tools: &[ToolDefinition]) -> serde_json::Value {
+    serde_json::Value::Array(tools.iter().map(|t| serde_json::json!({
+        "type": "function",
+        "function": {
+            "name": t.name,
+            "description": t.description,
+            "parameters": t.parameters,
+        }
+    })).collect())
+}
+
+fn tool_choice_to_openai(choice: &str) -> serde_json::Value {
+    match choice {
+        "auto" | "none" | "required" => serde_json::Value::String(choice.to_string()),
+        name => serde_json::json!({ "type": "function", "function": { "name": name } }),
+    }
+}
+
+fn message_json(m: &Message, cache: bool) -> serde_json::Value {
+    let mut message = if m.images.is_empty() && !cache {
+        serde_json::json!({ "role": m.role, "content": m.content })
+    } else {
+        let mut parts = vec![serde_json::json!({ "type": "text", "text": m.content })];
+        parts.extend(m.images.iter().map(|image| serde_json::json!({
+            "type": "image_url",
+            "image_url": { "url": image.to_url() }
+        })));
+        serde_json::json!({ "role": m.role, "content": content_parts(parts, cache) })
+    };
+    insert_some(&mut message, "tool_call_id", &m.tool_call_id);
+    insert_some(&mut message, "name", &m.name);
+    let calls = m.native_tool_calls();
+    if !calls.is_empty() {
+        message["tool_calls"] = serde_json::Value::Array(calls.iter().map(|c| serde_json::json!({
+            "id": c.id,
+            "type": "function",
+            "function": { "name": c.name, "arguments": c.arguments_json().to_string() }
+        })).collect());
+        // A tool-calling turn with no text is sent as null content, as OpenAI returns it
+        if m.content.is_empty() {
+            message["content"] = serde_json::Value::Null;
+        }
+    }
+    message
+}
+
+// Plain OpenAI caches automatically; cache_control breakpoints are an OpenRouter extension
+fn messages_json(req: &LLMRequest) -> serde_json::Value {
+    let marks = if req.config.provider_name() == "openrouter" {
+        breakpoints(&req.messages, req.prompt_cache.as_ref())
+    } else {
+        Vec::new()
+    };
+    serde_json::Value::Array(req.messages.iter().enumerate()
+        .map(|(i, m)| message_json(m, marks.contains(&i)))
+        .collect())
+}
+
+fn build_body(req: &LLMRequest, stream: bool) -> serde_json::Value {
+    let mut body = serde_json::json!({
+        "model": req.config.model,
+        "messages": messages_json(req),
+        "temperature": req.config.temperature
+    });
+    insert_some(&mut body, "max_tokens", &req.config.max_tokens);
+    insert_some(&mut body, "top_p", &req.config.top_p);
+    insert_some(&mut body, "stop", &req.config.stop);
+    insert_some(&mut body, "frequency_penalty", &req.config.frequency_penalty);
+    insert_some(&mut body, "presence_penalty", &req.config.presence_penalty);
+    if let Some(effort) = &req.config.reasoning_effort {
+        // OpenRouter normalizes reasoning settings across upstreams under `reasoning`
+        if req.config.provider_name() == "openrouter" {
+            body["reasoning"] = serde_json::json!({ "effort": effort });
+        } else {
+            body["reasoning_effort"] = serde_json::json!(effort);
+        }
+    }
+    // OpenRouter doesn't generate multiple choices; send_chat_logic issues separate requests
+    if req.config.provider_name() != "openrouter" && req.config.n.is_some_and(|n| n > 1) {
+        insert_some(&mut body, "n", &req.config.n);
+    }
+    if stream {
+        body["stream"] = serde_json::Value::Bool(true);
+        // Adds a final chunk with empty choices and the token usage
+        body["stream_options"] = serde_json::json!({ "include_usage": true });
+    }
+    if let Some(tools) = req.tools.as_ref().filter(|t| !t.is_empty()) {
+        body["tools"] = tools_to_openai(tools);
+        if let Some(choice) = &req.tool_choice {
+            body["tool_choice"] = tool_choice_to_openai(choice);
+        }
+    }
+    if let Some(options) = openrouter_options(req) {
+        add_routing(&mut body, options);
+    }
+    body
+}
+
+fn openrouter_options(req: &LLMRequest) -> Option<&OpenRouterOptions> {
+    req.config.openrouter.as_ref().filter(|_| req.config.provider_name() == "openrouter")
+}
+
+fn add_routing(body: &mut serde_json::Value, options: &OpenRouterOptions) {
+    let mut provider = serde_json::json!({});
+    insert_some(&mut provider, "order", &options.order);
+    insert_some(&mut provider, "only", &options.only);
+    insert_some(&mut provider, "ignore", &options.ignore);
+    insert_some(&mut provider, "allow_fallbacks", &options.allow_fallbacks);
+    if provider.as_object().is_some_and(|p| !p.is_empty()) {
+        body["provider"] = provider;
+    }
+    insert_some(body, "models", &options.fallback_models);
+    insert_some(body, "transforms", &options.transforms);
+}
+
+fn request(client: &Client, req: &LLMRequest, stream: bool) -> reqwest::RequestBuilder {
+    let mut builder = client.post(format!("{}/chat/completions", req.config.base_url.trim_end_matches('/')))
+        .header("Authorization", format!("Bearer {}", req.config.api_key))
+        .header("Content-Type", "application/json");
+    if let Some(options) = openrouter_options(req) {
+        if let Some(referer) = &options.referer {
+            builder = builder.header("HTTP-Referer", referer);
+        }
+        if let Some(title) = &options.title {
+            builder = builder.header("X-Title", title);
+        }
+    }
+    builder.json(&build_body(req, stream))
+}
+
+#[derive(Deserialize)]
+struct OpenAIStreamChunk {
+    #[serde(default)]
+    choices: Vec<OpenAIStreamChoice>,
+    usage: Option<OpenAIStreamUsage>,
+}
+
+#[derive(Deserialize)]
+struct OpenAIStreamUsage {
+    prompt_tokens: u32,
+    completion_tokens: u32,
+}
+
+#[derive(Deserialize)]
+struct OpenAIStreamChoice {
+    delta: OpenAIStreamDelta,
+    finish_reason: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct OpenAIStreamDelta {
+    content: Option<String>,
+    // DeepSeek names it reasoning_content, OpenRouter normalizes to reasoning; some
+    // proxies send both, so they are separate fields merged by `merge_reasoning`
+    reasoning: Option<String>,
+    reasoning_content: Option<String>,
+    #[serde(default)]
+    tool_calls: Option<Vec<OpenAIToolCallDelta>>,
+}
+
+// Combines the two reasoning fields, dropping empty ones and the duplicate when both carry the same text
+fn merge_reasoning(reasoning: Option<String>, reasoning_content: Option<String>) -> Option<String> {
+    match (reasoning.filter(|r| !r.is_empty()), reasoning_content.filter(|r| !r.is_empty())) {
+        (Some(a), Some(b)) if a != b => Some(format!("{}{}", a, b)),
+        (Some(a), _) => Some(a),
+        (None, b) => b,
+    }
+}
+
+#[derive(Deserialize)]
+struct OpenAIToolCallDelta {
+    #[serde(default)]
+    index: usize,
+    id: Option<String>,
+    function: Option<OpenAIFunctionDelta>,
+}
+
+#[derive(Deserialize)]
+struct OpenAIFunctionDelta {
+    name: Option<String>,
+    arguments: Option<String>,
+}
+
+#[derive(Default)]
+struct PartialToolCall {
+    id: Option<String>,
+    name: Option<String>,
+    arguments: String,
+    started: bool,
+}
+
+// Native tool calls arrive as id/name/argument fragments spread over many deltas.
+// Arguments are a JSON document, so ToolArg events can only be produced once it is complete.
+#[derive(Default)]
+struct ToolCallAccumulator {
+    calls: std::collections::BTreeMap<usize, PartialToolCall>,
+}
+
+impl ToolCallAccumulator {
+    fn push(&mut self, deltas: &[OpenAIToolCallDelta]) -> Vec<StreamEvent> {
+        let mut events = Vec::new();
+        for delta in deltas {
+            let call = self.calls.entry(delta.index).or_default();
+            if let Some(id) = &delta.id {
+                call.id = Some(id.clone());
+            }
+            if let Some(function) = &delta.function {
+                if let Some(name) = &function.name {
+                    call.name.get_or_insert_with(String::new).push_str(name);
+                }
+                if let Some(args) = &function.arguments {
+                    call.arguments.push_str(args);
+                }
+            }
+            if !call.started {
+                if let Some(name) = &call.name {
+                    events.push(StreamEvent::ToolStart(name.clone()));
+                    call.started = true;
+                }
+            }
+        }
+        events
+    }
+
+    fn finish(&mut self) -> Vec<StreamEvent> {
+        let mut events = Vec::new();
+        for (_, call) in std::mem::take(&mut self.calls) {
+            let Some(name) = call.name else { continue };
+            if !call.started {
+                events.push(StreamEvent::ToolStart(name));
+            }
+            let mut args: Vec<_> = flatten_arguments(&call.arguments).into_iter().collect();
+            args.sort();
+            for (key, value) in args {
+                events.push(StreamEvent::ToolArg(key, value));
+            }
+            events.push(StreamEvent::ToolEnd);
+        }
+        events
+    }
+}
+
+pub(crate) async fn send_chat(req: &LLMRequest) -> Result<LLMResponse, String> {
+    let client = req.config.http_client()?;
+    let res = retry::send(&req.config.retry, || request(&client, req, false)).await?;
+
+    #[derive(Deserialize)]
+    struct LocalOpenAIResponse {
+        choices: Vec<LocalOpenAIChoice>,
+        #[serde(default)]
+        usage: serde_json::Value,
+    }
+    #[derive(Deserialize)]
+    struct LocalOpenAIChoice {
+        message: LocalOpenAIMessage,
+    }
+    #[derive(Deserialize)]
+    struct LocalOpenAIMessage {
+        role: String,
+        #[serde(default)]
+        content: Option<String>,
+        #[serde(default)]
+        reasoning: Option<String>,
+        #[serde(default)]
+        reasoning_content: Option<String>,
+        #[serde(default)]
+        tool_calls: Option<Vec<LocalOpenAIToolCall>>,
+    }
+    #[derive(Deserialize)]
+    struct LocalOpenAIToolCall {
+        id: String,
+        function: LocalOpenAIFunction,
+    }
+    #[derive(Deserialize)]
+    struct LocalOpenAIFunction {
+        name: String,
+        #[serde(default)]
+        arguments: String,
+    }
+
+    let open_ai_res: LocalOpenAIResponse = res.json().await.map_err(|e| e.to_string())?;
+
+    let role = open_ai_res.choices.first()
+        .map(|c| c.message.role.clone())
+        .unwrap_or_else(|| "assistant".to_string());
+    let mut choices: Vec<Choice> = open_ai_res.choices.into_iter().map(|c| {
+        let content = c.message.content.unwrap_or_default();
+        let native_calls = c.message.tool_calls.unwrap_or_default();
+        // Prefer native tool calls; fall back to the XML protocol for models without tool support
+        let tools = if !native_calls.is_empty() {
+            native_calls.into_iter()
+                .map(|c| ToolCall::native(Some(c.id), c.function.name, &c.function.arguments))
+                .collect()
+        } else {
+            let mut parser = Parser::new();
+            collect_tool_calls(parser.process_chunk(&content))
+        };
+        let reasoning = merge_reasoning(c.message.reasoning, c.message.reasoning_content);
+        Choice { content, tool_calls: Some(tools), reasoning }
+    }).collect();
+    let primary = if choices.is_empty() {
+        Choice { tool_calls: Some(Vec::new()), ..Default::default() }
+    } else {
+        choices[0].clone()
+    };
+    if choices.len() < 2 {
+        choices.clear();
+    }
+
+    Ok(LLMResponse {
+        role,
+        content: primary.content,
+        tool_calls: primary.tool_calls,
+        usage: usage_map(&open_ai_res.usage),
+        reasoning: primary.reasoning,
+        choices,
+        endpoint: None,
+    })
+}
+
+pub(crate) fn stream_chat(req: LLMRequest) -> Pin<Box<dyn Stream<Item = StreamEvent> + Send>> {
+    let client = match req.config.http_client() {
+        Ok(client) => client,
+        Err(e) => return crate::error_stream(e),
+    };
+    let policy = req.config.retry.clone();
+    retry::stream(policy, move || request(&client, &req, true), |mut res| async_stream::stream! {
+        let mut parser = Parser::new();
+        let mut native_tools = ToolCallAccumulator::default();
+        let mut decoder = SseDecoder::new();
+        let mut ended = false;
+        while !ended {
+            let received = match res.chunk().await {
+                Ok(Some(chunk)) => decoder.push(&chunk),
+                // The last event may arrive without its closing blank line
+                Ok(None) => {
+                    ended = true;
+                    decoder.finish().into_iter().collect()
+                }
+                Err(e) => {
+                    yield StreamEvent::Error(e.to_string());
+                    continue;
+                }
+            };
+            for sse in received {
+                if sse.data == "[DONE]" {
+                    for event in native_tools.finish() { yield event; }
+                    yield StreamEvent::Done;
+                    return;
+                }
+                let Ok(data) = serde_json::from_str::<OpenAIStreamChunk>(&sse.data) else { continue };
+                if let Some(choice) = data.choices.first() {
+                    if let Some(reasoning) = merge_reasoning(choice.delta.reasoning.clone(), choice.delta.reasoning_content.clone()) {
+                        yield StreamEvent::Reasoning(reasoning);
+                    }
+                    if let Some(content) = &choice.delta.content {
+                        for event in parser.process_chunk(content) { yield event; }
+                    }
+                    if let Some(deltas) = &choice.delta.tool_calls {
+                        for event in native_tools.push(deltas) { yield event; }
+                    }
+                    if choice.finish_reason.is_some() {
+                        for event in native_tools.finish() { yield event; }
+                    }
+                }
+                if let Some(usage) = data.usage {
+                    yield StreamEvent::Usage {
+                        prompt_tokens: usage.prompt_tokens,
+                        completion_tokens: usage.completion_tokens,
+                    };
+                }
+            }
+        }
+    })
+}
+
+#[derive(Deserialize)]
+struct ModelsResponse {
+    data: Vec<ModelEntry>,
+}
+
+// OpenAI lists bare ids; OpenRouter adds name, context_length and pricing
+#[derive(Deserialize)]
+struct ModelEntry {
+    id: String,
+    name: Option<String>,
+    context_length: Option<u32>,
+    pricing: Option<ModelPricing>,
+}
+
+// Prices are decimal strings, e.g. "0.0000025"
+#[derive(Deserialize)]
+struct ModelPricing {
+    prompt: Option<String>,
+    completion: Option<String>,
+}
+
+fn price(value: &Option<String>) -> Option<f64> {
+    value.as_deref().and_then(|p| p.parse().ok())
+}
+
+fn model_info(m: ModelEntry) -> ModelInfo {
+    ModelInfo {
+        prompt_price: m.pricing.as_ref().and_then(|p| price(&p.prompt)),
+        completion_price: m.pricing.as_ref().and_then(|p| price(&p.completion)),
+        id: m.id,
+        name: m.name,
+        context_length: m.context_length,
+    }
+}
+
+pub(crate) async fn list_models(config: &LLMConfig) -> Result<Vec<ModelInfo>, String> {
+    let res = config.http_client()?.get(format!("{}/models", config.base_url.trim_end_matches('/')))
+        .header("Authorization", format!("Bearer {}", config.api_key))
+        .send()
+        .await
+        .map_err(|e| format!("Request failed: {}", e))?;
+
+    if !res.status().is_success() {
+        return Err(format!("API Error: {}", res.status()));
+    }
+
+    let models: ModelsResponse = res.json().await.map_err(|e| e.to_string())?;
+    Ok(models.data.into_iter().map(model_info).collect())
+}
+
+#[derive(Deserialize)]
+struct EmbeddingsResponse {
+    data: Vec<EmbeddingEntry>,
+}
+
+#[derive(Deserialize)]
+struct EmbeddingEntry {
+    index: usize,
+    embedding: Vec<f32>,
+}
+
+/// One vector per input, in input order, from `/embeddings`.
+pub(crate) async fn embed(config: &LLMConfig, inputs: &[String]) -> Result<Vec<Vec<f32>>, String> {
+    let res = config.http_client()?.post(format!("{}/embeddings", config.base_url.trim_end_matches('/')))
+        .header("Authorization", format!("Bearer {}", config.api_key))
+        .json(&serde_json::json!({ "model": config.model, "input": inputs }))
+        .send()
+        .await
+        .map_err(|e| format!("Request failed: {}", e))?;
+
+    if !res.status().is_success() {
+        return Err(format!("API Error: {}", res.status()));
+    }
+
+    let mut body: EmbeddingsResponse = res.json().await.map_err(|e| e.to_string())?;
+    body.data.sort_by_key(|e| e.index);
+    Ok(body.data.into_iter().map(|e| e.embedding).collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn delta(index: usize, id: Option<&str>, name: Option<&str>, args: Option<&str>) -> OpenAIToolCallDelta {
+        OpenAIToolCallDelta {
+            index,
+            id: id.map(|s| s.to_string()),
+            function: Some(OpenAIFunctionDelta {
+                name: name.map(|s| s.to_string()),
+                arguments: args.map(|s| s.to_string()),
+            }),
+        }
+    }
+
+    #[test]
+    fn test_tool_call_accumulator_joins_fragments() {
+        let mut acc = ToolCallAccumulator::default();
+        let mut events = acc.push(&[delta(0, Some("call_1"), Some("read_file"), Some("{\"file_"))]);
+        events.extend(acc.push(&[delta(0, None, None, Some("path\": \"src/lib.rs\"}"))]));
+        events.extend(acc.finish());
+
+        assert_eq!(events, vec![
+            StreamEvent::ToolStart("read_file".into()),
+            StreamEvent::ToolArg("file_path".into(), "src/lib.rs".into()),
+            StreamEvent::ToolEnd,
+        ]);
+        assert!(acc.finish().is_empty());
+    }
+
+    #[test]
+    fn test_stream_delta_accepts_both_reasoning_keys() {
+        let chunk: OpenAIStreamChunk = serde_json::from_str(
+            r#"{"choices":[{"delta":{"reasoning":"think","reasoning_content":"think"},"finish_reason":null}]}"#,
+        ).unwrap();
+        let delta = &chunk.choices[0].delta;
+        assert_eq!(merge_reasoning(delta.reasoning.clone(), delta.reasoning_content.clone()).as_deref(), Some("think"));
+
+        let chunk: OpenAIStreamChunk = serde_json::from_str(
+            r#"{"choices":[{"delta":{"reasoning":"","reasoning_content":"deep"},"finish_reason":null}]}"#,
+        ).unwrap();
+        let delta = &chunk.choices[0].delta;
+        assert_eq!(merge_reasoning(delta.reasoning.clone(), delta.reasoning_content.clone()).as_deref(), Some("deep"));
+        assert_eq!(merge_reasoning(None, None), None);
+    }
+
+    #[test]
+    fn test_model_entries_from_openai_and_openrouter() {
+        let raw = r#"{"data": [
+            {"id": "gpt-4o", "object": "model", "owned_by": "openai"},
+            {"id": "anthropic/claude-3.5-sonnet", "name": "Claude 3.5 Sonnet", "context_length": 200000,
+             "pricing": {"prompt": "0.000003", "completion": "0.000015", "image": "0.0048"}}
+        ]}"#;
+        let models: Vec<ModelInfo> = serde_json::from_str::<ModelsResponse>(raw).unwrap()
+            .data.into_iter().map(model_info).collect();
+
+        assert_eq!(models[0], ModelInfo::from_id("gpt-4o".to_string()));
+        assert_eq!(models[1].name.as_deref(), Some("Claude 3.5 Sonnet"));
+        assert_eq!(models[1].context_length, Some(200_000));
+        assert_eq!(models[1].prompt_price, Some(0.000003));
+        assert_eq!(models[1].completion_price, Some(0.000015));
+    }
+
+    #[test]
+    fn test_tool_turns_use_openai_schema() {
+        let call = ToolCall {
+            id: Some("call_1".to_string()),
+            name: "read_file".to_string(),
+            arguments: std::collections::HashMap::from([("file_path".to_string(), "src/lib.rs".to_string())]),
+            raw_arguments: None,
+        };
+        let assistant = Message { role: "assistant".to_string(), tool_calls: Some(vec![call]), ..Default::default() };
+        let json = message_json(&assistant, false);
+        assert!(json["content"].is_null());
+        assert_eq!(json["tool_calls"][0]["id"], "call_1");
+        assert_eq!(json["tool_calls"][0]["function"]["arguments"], r#"{"file_path":"src/lib.rs"}"#);
+
+        let result = message_json(&Message::tool_result("call_1", "read_file", "fn main() {}"), false);
+        assert_eq!(result, serde_json::json!({
+            "role": "tool",
+            "content": "fn main() {}",
+            "tool_call_id": "call_1",
+            "name": "read_file",
+        }));
+    }
+
+    #[test]
+    fn test_openrouter_routing_only_for_openrouter() {
+        let mut req = LLMRequest {
+            messages: vec![Message::new("user", "hi")],
+            config: LLMConfig {
+                model: "anthropic/claude-3.5-sonnet".to_string(),
+                provider: Some("openrouter".to_string()),
+                openrouter: Some(OpenRouterOptions {
+                    order: Some(vec!["Anthropic".to_string()]),
+                    allow_fallbacks: Some(false),
+                    transforms: Some(vec!["middle-out".to_string()]),
+                    ..Default::default()
+                }),
+                ..Default::default()
+            },
+            tools: None,
+            tool_choice: None,
+            prompt_cache: None,
+        };
+        let body = build_body(&req, false);
+        assert_eq!(body["provider"], serde_json::json!({ "order": ["Anthropic"], "allow_fallbacks": false }));
+        assert_eq!(body["transforms"], serde_json::json!(["middle-out"]));
+        assert!(body.get("models").is_none());
+
+        req.config.provider = Some("openai".to_string());
+        assert!(build_body(&req, false).get("provider").is_none());
+    }
+
+    #[test]
+    fn test_reasoning_effort_per_provider() {
+        let mut req = LLMRequest {
+            messages: vec![Message::new("user", "hi")],
+            config: LLMConfig { reasoning_effort: Some("high".to_string()), ..Default::default() },
+            tools: None,
+            tool_choice: None,
+            prompt_cache: None,
+        };
+        assert_eq!(build_body(&req, false)["reasoning_effort"], "high");
+
+        req.config.provider = Some("openrouter".to_string());
+        let body = build_body(&req, false);
+        assert_eq!(body["reasoning"], serde_json::json!({ "effort": "high" }));
+        assert!(body.get("reasoning_effort").is_none());
+    }
+}
@@ -0,0 +1,96 @@
+use std::collections::HashMap;
+use serde::{Deserialize, Serialize};
+
+use crate::Message;
+
+// Prompt caching (Anthropic, and Anthropic/Gemini models behind OpenRouter) works through
+// explicit `cache_control` breakpoints: everything up to a breakpoint is cached and reused
+// by later requests with the same prefix. Agent sessions resend a long, stable prefix every
+// turn, so two breakpoints cover most of the savings.
+
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub struct PromptCache {
+    // Breakpoint at the end of the system prompt
+    #[serde(default)]
+    pub system: bool,
+    // Breakpoint on the message preceding the newest user turn (the stable history prefix)
+    #[serde(default)]
+    pub history: bool,
+}
+
+/// Indices into `messages` that should carry a cache breakpoint.
+pub(crate) fn breakpoints(messages: &[Message], cache: Option<&PromptCache>) -> Vec<usize> {
+    let Some(cache) = cache else { return Vec::new() };
+    let mut marks = Vec::new();
+    if cache.system {
+        if let Some(i) = messages.iter().rposition(|m| m.role == "system") {
+            marks.push(i);
+        }
+    }
+    if cache.history {
+        if let Some(last_user) = messages.iter().rposition(|m| m.role == "user") {
+            if let Some(i) = (0..last_user).rev().find(|&i| messages[i].role != "system") {
+                marks.push(i);
+            }
+        }
+    }
+    marks
+}
+
+/// Content parts as a JSON array, with a breakpoint on the last part when `cache` is set.
+pub(crate) fn content_parts(mut parts: Vec<serde_json::Value>, cache: bool) -> serde_json::Value {
+    if cache {
+        if let Some(last) = parts.last_mut() {
+            last["cache_control"] = serde_json::json!({ "type": "ephemeral" });
+        }
+    }
+    serde_json::Value::Array(parts)
+}
+
+/// Flattens a provider usage object into the `LLMResponse::usage` map. Numeric top-level
+/// fields are kept as-is; cache statistics are normalized to `cache_read_tokens` and
+/// `cache_write_tokens` whatever the provider calls them.
+pub(crate) fn usage_map(usage: &serde_json::Value) -> Option<HashMap<String, u32>> {
+    let object = usage.as_object()?;
+    let mut map: HashMap<String, u32> = object.iter()
+        .filter_map(|(k, v)| v.as_u64().map(|n| (k.clone(), n as u32)))
+        .collect();
+
+    let cache_read = usage.pointer("/prompt_tokens_details/cached_tokens")
+        .or_else(|| usage.get("cache_read_input_tokens"))
+        .and_then(|v| v.as_u64());
+    if let Some(n) = cache_read {
+        map.insert("cache_read_tokens".to_string(), n as u32);
+    }
+    if let Some(n) = usage.get("cache_creation_input_tokens").and_then(|v| v.as_u64()) {
+        map.insert("cache_write_tokens".to_string(), n as u32);
+    }
+    Some(map)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn msg(role: &str) -> Message {
+        Message::new(role, "")
+    }
+
+    #[test]
+    fn test_breakpoints_and_usage() {
+        let messages = vec![msg("system"), msg("user"), msg("assistant"), msg("user")];
+        let cache = PromptCache { system: true, history: true };
+        assert_eq!(breakpoints(&messages, Some(&cache)), vec![0, 2]);
+        assert!(breakpoints(&messages, None).is_empty());
+
+        let usage = serde_json::json!({
+            "prompt_tokens": 120,
+            "prompt_tokens_details": { "cached_tokens": 100 },
+            "service_tier": "default"
+        });
+        let map = usage_map(&usage).unwrap();
+        assert_eq!(map.get("prompt_tokens"), Some(&120));
+        assert_eq!(map.get("cache_read_tokens"), Some(&100));
+        assert!(!map.contains_key("service_tier"));
+    }
+}
@@ -0,0 +1,163 @@
+use std::collections::HashMap;
+use std::pin::Pin;
+use std::sync::{Arc, OnceLock, RwLock};
+use async_trait::async_trait;
+use futures::Stream;
+
+use crate::{anthropic, gemini, mock, ollama, openai, LLMConfig, LLMRequest, LLMResponse, Message, ModelInfo, StreamEvent};
+
+/// A chat backend. Implementations translate `LLMRequest` into their own wire format and
+/// report results in the gateway's `LLMResponse` / `StreamEvent` vocabulary.
+#[async_trait]
+pub trait LlmProvider: Send + Sync {
+    async fn chat(&self, req: &LLMRequest) -> Result<LLMResponse, String>;
+
+    fn chat_stream(&self, req: LLMRequest) -> Pin<Box<dyn Stream<Item = StreamEvent> + Send>>;
+
+    /// Prompt size in tokens. The default uses tiktoken when it knows the model and a
+    /// character-based estimate otherwise.
+    fn count_tokens(&self, model: &str, messages: &[Message]) -> usize {
+        crate::count_tokens(model, messages)
+    }
+
+    async fn list_models(&self, _config: &LLMConfig) -> Result<Vec<ModelInfo>, String> {
+        Err("Model listing is not supported by this provider".to_string())
+    }
+
+    /// One embedding vector per input, using `config.model` as the embedding model.
+    async fn embed(&self, _config: &LLMConfig, _inputs: &[String]) -> Result<Vec<Vec<f32>>, String> {
+        Err("Embeddings are not supported by this provider".to_string())
+    }
+}
+
+struct OpenAi;
+struct Anthropic;
+struct Ollama;
+struct Gemini;
+struct Mock;
+
+#[async_trait]
+impl LlmProvider for OpenAi {
+    async fn chat(&self, req: &LLMRequest) -> Result<LLMResponse, String> { openai::send_chat(req).await }
+    fn chat_stream(&self, req: LLMRequest) -> Pin<Box<dyn Stream<Item = StreamEvent> + Send>> { openai::stream_chat(req) }
+    async fn list_models(&self, config: &LLMConfig) -> Result<Vec<ModelInfo>, String> { openai::list_models(config).await }
+    async fn embed(&self, config: &LLMConfig, inputs: &[String]) -> Result<Vec<Vec<f32>>, String> { openai::embed(config, inputs).await }
+}
+
+#[async_trait]
+impl LlmProvider for Anthropic {
+    async fn chat(&self, req: &LLMRequest) -> Result<LLMResponse, String> { anthropic::send_chat(req).await }
+    fn chat_stream(&self, req: LLMRequest) -> Pin<Box<dyn Stream<Item = StreamEvent> + Send>> { anthropic::stream_chat(req) }
+    async fn list_models(&self, config: &LLMConfig) -> Result<Vec<ModelInfo>, String> { anthropic::list_models(config).await }
+}
+
+#[async_trait]
+impl LlmProvider for Ollama {
+    async fn chat(&self, req: &LLMRequest) -> Result<LLMResponse, String> { ollama::send_chat(req).await }
+    fn chat_stream(&self, req: LLMRequest) -> Pin<Box<dyn Stream<Item = StreamEvent> + Send>> { ollama::stream_chat(req) }
+    async fn list_models(&self, config: &LLMConfig) -> Result<Vec<ModelInfo>, String> { ollama::list_models(config).await }
+    async fn embed(&self, config: &LLMConfig, inputs: &[String]) -> Result<Vec<Vec<f32>>, String> { ollama::embed(config, inputs).await }
+}
+
+#[async_trait]
+impl LlmProvider for Gemini {
+    async fn chat(&self, req: &LLMRequest) -> Result<LLMResponse, String> { gemini::send_chat(req).await }
+    fn chat_stream(&self, req: LLMRequest) -> Pin<Box<dyn Stream<Item = StreamEvent> + Send>> { gemini::stream_chat(req) }
+    async fn list_models(&self, config: &LLMConfig) -> Result<Vec<ModelInfo>, String> { gemini::list_models(config).await }
+}
+
+#[async_trait]
+impl LlmProvider for Mock {
+    async fn chat(&self, req: &LLMRequest) -> Result<LLMResponse, String> { mock::send_chat(req).await }
+    fn chat_stream(&self, req: LLMRequest) -> Pin<Box<dyn Stream<Item = StreamEvent> + Send>> { mock::stream_chat(req) }
+    async fn embed(&self, _config: &LLMConfig, inputs: &[String]) -> Result<Vec<Vec<f32>>, String> { mock::embed(inputs).await }
+}
+
+/// Providers by name. Built-ins are registered up front; embedders can add or replace
+/// entries at runtime with `register`.
+pub struct ProviderRegistry {
+    providers: RwLock<HashMap<String, Arc<dyn LlmProvider>>>,
+}
+
+impl ProviderRegistry {
+    pub fn new() -> Self {
+        Self { providers: RwLock::new(HashMap::new()) }
+    }
+
+    pub fn with_defaults() -> Self {
+        let registry = Self::new();
+        let openai: Arc<dyn LlmProvider> = Arc::new(OpenAi);
+        registry.register("openai", openai.clone());
+        registry.register("openrouter", openai);
+        registry.register("anthropic", Arc::new(Anthropic));
+        registry.register("ollama", Arc::new(Ollama));
+        registry.register("gemini", Arc::new(Gemini));
+        registry.register("mock", Arc::new(Mock));
+        registry
+    }
+
+    pub fn register(&self, name: impl Into<String>, provider: Arc<dyn LlmProvider>) {
+        self.providers.write().unwrap().insert(name.into(), provider);
+    }
+
+    pub fn get(&self, name: &str) -> Option<Arc<dyn LlmProvider>> {
+        self.providers.read().unwrap().get(name).cloned()
+    }
+
+    pub fn names(&self) -> Vec<String> {
+        let mut names: Vec<String> = self.providers.read().unwrap().keys().cloned().collect();
+        names.sort();
+        names
+    }
+
+    /// Picks the provider for a config. A base_url containing "mock" always wins so
+    /// offline development keeps working regardless of the provider setting.
+    pub fn resolve(&self, config: &LLMConfig) -> Result<Arc<dyn LlmProvider>, String> {
+        let name = if config.base_url.contains("mock") { "mock" } else { config.provider_name() };
+        self.get(name).ok_or_else(|| format!("Unknown LLM provider: {}", name))
+    }
+}
+
+impl Default for ProviderRegistry {
+    fn default() -> Self {
+        Self::with_defaults()
+    }
+}
+
+static REGISTRY: OnceLock<ProviderRegistry> = OnceLock::new();
+
+/// The process-wide registry used by `send_chat_logic` and `stream_chat`.
+pub fn registry() -> &'static ProviderRegistry {
+    REGISTRY.get_or_init(ProviderRegistry::with_defaults)
+}
+
+pub(crate) fn resolve(config: &LLMConfig) -> Result<Arc<dyn LlmProvider>, String> {
+    registry().resolve(config)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn config(provider: Option<&str>, base_url: &str) -> LLMConfig {
+        LLMConfig {
+            api_key: String::new(),
+            base_url: base_url.to_string(),
+            model: "m".to_string(),
+            provider: provider.map(|p| p.to_string()),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_resolve_known_and_unknown_providers() {
+        let registry = ProviderRegistry::with_defaults();
+        assert!(registry.resolve(&config(None, "https://api.openai.com/v1")).is_ok());
+        assert!(registry.resolve(&config(Some("anthropic"), "")).is_ok());
+        assert!(registry.resolve(&config(Some("bogus"), "http://mock")).is_ok());
+        assert_eq!(
+            registry.resolve(&config(Some("bogus"), "")).err(),
+            Some("Unknown LLM provider: bogus".to_string())
+        );
+    }
+}
@@ -0,0 +1,190 @@
+use std::collections::HashMap;
+use std::sync::{Mutex, OnceLock, RwLock};
+use std::time::{Duration, Instant};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
+use crate::LLMConfig;
+
+// Client-side token buckets so parallel agents sharing a key queue up locally instead of
+// tripping the provider's 429s. Limits are set per provider; each provider/API key pair
+// gets its own buckets. Providers without limits are not throttled.
+
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq)]
+pub struct RateLimit {
+    pub requests_per_minute: Option<u32>,
+    pub tokens_per_minute: Option<u32>,
+}
+
+struct Bucket {
+    capacity: f64,
+    available: f64,
+    refill_per_sec: f64,
+    updated: Instant,
+}
+
+impl Bucket {
+    fn per_minute(limit: u32, now: Instant) -> Self {
+        let capacity = limit.max(1) as f64;
+        Self { capacity, available: capacity, refill_per_sec: capacity / 60.0, updated: now }
+    }
+
+    fn refill(&mut self, now: Instant) {
+        let elapsed = now.saturating_duration_since(self.updated).as_secs_f64();
+        self.available = (self.available + elapsed * self.refill_per_sec).min(self.capacity);
+        self.updated = now;
+    }
+
+    /// Time until `amount` is available. Requests larger than the whole bucket only wait
+    /// for a full bucket, otherwise they could never proceed.
+    fn wait_for(&mut self, amount: f64, now: Instant) -> Duration {
+        self.refill(now);
+        let missing = amount.min(self.capacity) - self.available;
+        if missing <= 0.0 {
+            Duration::ZERO
+        } else {
+            Duration::from_secs_f64(missing / self.refill_per_sec)
+        }
+    }
+
+    // May go negative for oversized requests, which delays whoever comes next
+    fn take(&mut self, amount: f64) {
+        self.available -= amount;
+    }
+}
+
+struct Buckets {
+    limit: RateLimit,
+    requests: Option<Bucket>,
+    tokens: Option<Bucket>,
+}
+
+impl Buckets {
+    fn new(limit: RateLimit, now: Instant) -> Self {
+        Self {
+            requests: limit.requests_per_minute.map(|n| Bucket::per_minute(n, now)),
+            tokens: limit.tokens_per_minute.map(|n| Bucket::per_minute(n, now)),
+            limit,
+        }
+    }
+
+    /// Takes one request and `tokens` tokens if both are available, otherwise returns how
+    /// long to wait before trying again.
+    fn try_acquire(&mut self, tokens: u32, now: Instant) -> Result<(), Duration> {
+        let tokens = tokens as f64;
+        let wait = [
+            self.requests.as_mut().map(|b| b.wait_for(1.0, now)),
+            self.tokens.as_mut().map(|b| b.wait_for(tokens, now)),
+        ].into_iter().flatten().max().unwrap_or(Duration::ZERO);
+
+        if !wait.is_zero() {
+            return Err(wait);
+        }
+        if let Some(b) = self.requests.as_mut() { b.take(1.0) }
+        if let Some(b) = self.tokens.as_mut() { b.take(tokens) }
+        Ok(())
+    }
+}
+
+#[derive(Default)]
+pub struct RateLimiter {
+    limits: RwLock<HashMap<String, RateLimit>>,
+    buckets: Mutex<HashMap<String, Buckets>>,
+}
+
+impl RateLimiter {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Sets the limits for a provider name (as in `LLMConfig::provider`). A limit with
+    /// neither field set removes throttling for that provider.
+    pub fn configure(&self, provider: &str, limit: RateLimit) {
+        let mut limits = self.limits.write().unwrap();
+        if limit == RateLimit::default() {
+            limits.remove(provider);
+        } else {
+            limits.insert(provider.to_string(), limit);
+        }
+    }
+
+    pub fn limit(&self, provider: &str) -> Option<RateLimit> {
+        self.limits.read().unwrap().get(provider).cloned()
+    }
+
+    // Keys are hashed so the limiter never holds on to credentials
+    fn bucket_key(config: &LLMConfig) -> String {
+        let digest = Sha256::digest(config.api_key.as_bytes());
+        let key: String = digest.iter().take(8).map(|b| format!("{:02x}", b)).collect();
+        format!("{}:{}", config.provider_name(), key)
+    }
+
+    /// Waits until the provider/key pair in `config` may send one request of roughly
+    /// `tokens()` tokens, then reserves it. `tokens` is only evaluated when the provider
+    /// has a limit, so unthrottled providers skip counting.
+    pub async fn acquire(&self, config: &LLMConfig, tokens: impl FnOnce() -> u32) {
+        let Some(limit) = self.limit(config.provider_name()) else { return };
+        let tokens = tokens();
+        let key = Self::bucket_key(config);
+        loop {
+            let wait = {
+                let now = Instant::now();
+                let mut buckets = self.buckets.lock().unwrap();
+                let entry = buckets.entry(key.clone()).or_insert_with(|| Buckets::new(limit.clone(), now));
+                if entry.limit != limit {
+                    *entry = Buckets::new(limit.clone(), now);
+                }
+                entry.try_acquire(tokens, now)
+            };
+            match wait {
+                Ok(()) => return,
+                Err(delay) => tokio::time::sleep(delay).await,
+            }
+        }
+    }
+}
+
+static LIMITER: OnceLock<RateLimiter> = OnceLock::new();
+
+/// The process-wide limiter shared by `send_chat_logic` and `stream_chat`.
+pub fn limiter() -> &'static RateLimiter {
+    LIMITER.get_or_init(RateLimiter::new)
+}
+
+pub fn configure(provider: &str, limit: RateLimit) {
+    limiter().configure(provider, limit)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn secs(wait: Result<(), Duration>) -> Result<(), u64> {
+        wait.map_err(|d| d.as_secs_f64().round() as u64)
+    }
+
+    #[test]
+    fn test_buckets_wait_and_refill() {
+        let start = Instant::now();
+        let limit = RateLimit { requests_per_minute: Some(2), tokens_per_minute: Some(600) };
+        let mut buckets = Buckets::new(limit, start);
+
+        assert!(buckets.try_acquire(300, start).is_ok());
+        assert!(buckets.try_acquire(100, start).is_ok());
+        // Requests exhausted: one refills every 30s
+        assert_eq!(secs(buckets.try_acquire(0, start)), Err(30));
+
+        let later = start + Duration::from_secs(30);
+        // 200 + 300 refilled tokens; 550 is 50 short at 10 tokens/s
+        assert_eq!(secs(buckets.try_acquire(550, later)), Err(5));
+        assert!(buckets.try_acquire(500, later).is_ok());
+    }
+
+    #[test]
+    fn test_oversized_request_waits_for_full_bucket() {
+        let start = Instant::now();
+        let mut buckets = Buckets::new(RateLimit { requests_per_minute: None, tokens_per_minute: Some(60) }, start);
+        assert!(buckets.try_acquire(1_000, start).is_ok());
+        assert!(buckets.try_acquire(1, start + Duration::from_secs(10)).is_err());
+    }
+}
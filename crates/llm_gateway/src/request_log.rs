@@ -0,0 +1,257 @@
+use std::fs::{File, OpenOptions};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex, RwLock};
+use serde_json::Value;
+
+use crate::{LLMRequest, LLMResponse, StreamEvent};
+
+// Optional JSON-lines log of gateway traffic for debugging agent behavior. Credentials are
+// redacted before anything is written: api_key/Authorization-style fields are masked
+// wherever they appear, and the request's own key is scrubbed from free text such as error
+// messages. Off until `enable` is called.
+
+const REDACTED: &str = "[REDACTED]";
+const SECRET_KEYS: &[&str] = &["api_key", "apikey", "authorization", "x-api-key", "x-goog-api-key", "proxy"];
+
+pub struct RequestLog {
+    path: PathBuf,
+    max_bytes: u64,
+    // Rotated files kept next to the active one: log.1 (newest) .. log.N
+    keep: u32,
+    file: Mutex<Option<File>>,
+}
+
+impl RequestLog {
+    pub const DEFAULT_MAX_BYTES: u64 = 10 * 1024 * 1024;
+    pub const DEFAULT_KEEP: u32 = 3;
+
+    pub fn new(path: impl Into<PathBuf>) -> Self {
+        Self::with_rotation(path, Self::DEFAULT_MAX_BYTES, Self::DEFAULT_KEEP)
+    }
+
+    pub fn with_rotation(path: impl Into<PathBuf>, max_bytes: u64, keep: u32) -> Self {
+        Self { path: path.into(), max_bytes, keep, file: Mutex::new(None) }
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    fn rotated(&self, n: u32) -> PathBuf {
+        let mut name = self.path.clone().into_os_string();
+        name.push(format!(".{}", n));
+        PathBuf::from(name)
+    }
+
+    fn rotate(&self) -> std::io::Result<()> {
+        if self.keep == 0 {
+            return std::fs::remove_file(&self.path);
+        }
+        let _ = std::fs::remove_file(self.rotated(self.keep));
+        for n in (1..self.keep).rev() {
+            let from = self.rotated(n);
+            if from.exists() {
+                std::fs::rename(from, self.rotated(n + 1))?;
+            }
+        }
+        std::fs::rename(&self.path, self.rotated(1))
+    }
+
+    pub fn write(&self, entry: &Value) -> std::io::Result<()> {
+        let mut line = entry.to_string();
+        line.push('\n');
+
+        let mut file = self.file.lock().unwrap();
+        let size = std::fs::metadata(&self.path).map(|m| m.len()).unwrap_or(0);
+        if size > 0 && size + line.len() as u64 > self.max_bytes {
+            *file = None;
+            self.rotate()?;
+        }
+        if file.is_none() {
+            if let Some(parent) = self.path.parent() {
+                std::fs::create_dir_all(parent)?;
+            }
+            *file = Some(OpenOptions::new().create(true).append(true).open(&self.path)?);
+        }
+        file.as_mut().unwrap().write_all(line.as_bytes())
+    }
+}
+
+/// Masks credential fields in place, at any depth.
+pub fn redact(value: &mut Value) {
+    match value {
+        Value::Object(map) => {
+            for (key, v) in map.iter_mut() {
+                if SECRET_KEYS.contains(&key.to_ascii_lowercase().as_str()) {
+                    if !v.is_null() {
+                        *v = Value::String(REDACTED.to_string());
+                    }
+                } else {
+                    redact(v);
+                }
+            }
+        }
+        Value::Array(items) => items.iter_mut().for_each(redact),
+        _ => {}
+    }
+}
+
+/// Replaces every occurrence of `secret` inside string values.
+fn scrub(value: &mut Value, secret: &str) {
+    match value {
+        Value::String(s) if s.contains(secret) => *s = s.replace(secret, REDACTED),
+        Value::Object(map) => map.values_mut().for_each(|v| scrub(v, secret)),
+        Value::Array(items) => items.iter_mut().for_each(|v| scrub(v, secret)),
+        _ => {}
+    }
+}
+
+fn timestamp_ms() -> u128 {
+    std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map(|d| d.as_millis())
+        .unwrap_or(0)
+}
+
+/// Builds a redacted log entry for one event about `req`.
+pub fn entry(req: &LLMRequest, kind: &str, body: Value) -> Value {
+    let mut entry = serde_json::json!({
+        "ts": timestamp_ms() as u64,
+        "kind": kind,
+        "provider": req.config.provider_name(),
+        "model": req.config.model,
+        "body": body,
+    });
+    redact(&mut entry);
+    if !req.config.api_key.is_empty() {
+        scrub(&mut entry, &req.config.api_key);
+    }
+    entry
+}
+
+static LOG: RwLock<Option<Arc<RequestLog>>> = RwLock::new(None);
+
+/// Turns on logging for `send_chat_logic` and `stream_chat`.
+pub fn enable(log: RequestLog) {
+    *LOG.write().unwrap() = Some(Arc::new(log));
+}
+
+pub fn disable() {
+    *LOG.write().unwrap() = None;
+}
+
+pub fn active() -> Option<Arc<RequestLog>> {
+    LOG.read().unwrap().clone()
+}
+
+// Logging must never fail a chat call, so write errors are dropped
+pub(crate) fn log_request(req: &LLMRequest) {
+    if let Some(log) = active() {
+        let _ = log.write(&entry(req, "request", serde_json::to_value(req).unwrap_or(Value::Null)));
+    }
+}
+
+pub(crate) fn log_result(req: &LLMRequest, result: &Result<LLMResponse, String>) {
+    if let Some(log) = active() {
+        let entry = match result {
+            Ok(res) => entry(req, "response", serde_json::to_value(res).unwrap_or(Value::Null)),
+            Err(e) => entry(req, "error", Value::String(e.clone())),
+        };
+        let _ = log.write(&entry);
+    }
+}
+
+/// Accumulates a streamed reply so it can be logged as a single response entry.
+#[derive(Default)]
+pub(crate) struct StreamTranscript {
+    content: String,
+    reasoning: String,
+    tools: Vec<Value>,
+    usage: Option<Value>,
+}
+
+impl StreamTranscript {
+    /// Records `event`, writing the log entry once the stream finishes or fails.
+    pub(crate) fn observe(&mut self, req: &LLMRequest, event: &StreamEvent) {
+        match event {
+            StreamEvent::Token(t) => self.content.push_str(t),
+            StreamEvent::Reasoning(t) => self.reasoning.push_str(t),
+            StreamEvent::ToolStart(name) => self.tools.push(serde_json::json!({"name": name, "arguments": {}})),
+            StreamEvent::ToolArg(key, value) => {
+                if let Some(args) = self.tools.last_mut().and_then(|t| t["arguments"].as_object_mut()) {
+                    let arg = args.entry(key.clone()).or_insert_with(|| Value::String(String::new()));
+                    if let Value::String(s) = arg {
+                        s.push_str(value);
+                    }
+                }
+            }
+            StreamEvent::Usage { prompt_tokens, completion_tokens } => {
+                self.usage = Some(serde_json::json!({"prompt_tokens": prompt_tokens, "completion_tokens": completion_tokens}));
+            }
+            StreamEvent::Error(e) => log_result(req, &Err(e.clone())),
+            StreamEvent::Done => {
+                if let Some(log) = active() {
+                    let body = serde_json::json!({
+                        "content": self.content,
+                        "reasoning": self.reasoning,
+                        "tool_calls": self.tools,
+                        "usage": self.usage,
+                    });
+                    let _ = log.write(&entry(req, "stream_response", body));
+                }
+            }
+            _ => {}
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{LLMConfig, Message};
+
+    fn request() -> LLMRequest {
+        LLMRequest {
+            messages: vec![Message::new("user", "hi")],
+            config: LLMConfig {
+                api_key: "sk-secret".to_string(),
+                model: "m".to_string(),
+                ..Default::default()
+            },
+            tools: None,
+            tool_choice: None,
+            prompt_cache: None,
+        }
+    }
+
+    #[test]
+    fn test_entry_redacts_keys_and_headers() {
+        let req = request();
+        let logged = entry(&req, "request", serde_json::to_value(&req).unwrap()).to_string();
+        assert!(!logged.contains("sk-secret"));
+        assert!(logged.contains(REDACTED));
+
+        let mut headers = serde_json::json!({"headers": {"Authorization": "Bearer abc", "Accept": "text/event-stream"}});
+        redact(&mut headers);
+        assert_eq!(headers["headers"]["Authorization"], REDACTED);
+        assert_eq!(headers["headers"]["Accept"], "text/event-stream");
+
+        let error = entry(&req, "error", Value::String("401 for key sk-secret".to_string()));
+        assert_eq!(error["body"], format!("401 for key {}", REDACTED));
+    }
+
+    #[test]
+    fn test_rotation_keeps_limited_files() {
+        let dir = std::env::temp_dir().join(format!("irongraph-request-log-{}", std::process::id()));
+        let log = RequestLog::with_rotation(dir.join("llm.log"), 64, 2);
+        for i in 0..10 {
+            log.write(&serde_json::json!({"n": i, "pad": "xxxxxxxxxxxxxxxxxxxxxxxxxxxxxx"})).unwrap();
+        }
+        assert!(log.path().exists());
+        assert!(log.rotated(1).exists());
+        assert!(log.rotated(2).exists());
+        assert!(!log.rotated(3).exists());
+        let _ = std::fs::remove_dir_all(dir);
+    }
+}
@@ -0,0 +1,167 @@
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicU32, Ordering};
+use std::sync::{Arc, RwLock};
+use sha2::{Digest, Sha256};
+
+use crate::{LLMRequest, LLMResponse};
+
+// Optional on-disk cache for non-streaming completions, one JSON file per request hash.
+// Meant for deterministic replays, tests and repeated identical calls; it is off until
+// `enable` is called.
+
+pub struct ResponseCache {
+    dir: PathBuf,
+    hits: AtomicU32,
+    misses: AtomicU32,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct CacheStats {
+    pub entries: u32,
+    pub bytes: u64,
+    pub hits: u32,
+    pub misses: u32,
+}
+
+impl ResponseCache {
+    pub fn new(dir: impl Into<PathBuf>) -> Self {
+        Self { dir: dir.into(), hits: AtomicU32::new(0), misses: AtomicU32::new(0) }
+    }
+
+    /// Hash of everything that determines the answer: endpoint, model, sampling parameters, messages and tools.
+    pub fn key(req: &LLMRequest) -> String {
+        let identity = serde_json::json!({
+            "provider": req.config.provider_name(),
+            "base_url": req.config.base_url,
+            "model": req.config.model,
+            "temperature": req.config.temperature,
+            "max_tokens": req.config.max_tokens,
+            "top_p": req.config.top_p,
+            "stop": req.config.stop,
+            "frequency_penalty": req.config.frequency_penalty,
+            "presence_penalty": req.config.presence_penalty,
+            "n": req.config.n,
+            "openrouter": req.config.openrouter,
+            "messages": req.messages,
+            "tools": req.tools,
+            "tool_choice": req.tool_choice,
+        });
+        let digest = Sha256::digest(identity.to_string().as_bytes());
+        digest.iter().map(|b| format!("{:02x}", b)).collect()
+    }
+
+    fn path(&self, key: &str) -> PathBuf {
+        self.dir.join(format!("{}.json", key))
+    }
+
+    pub fn get(&self, req: &LLMRequest) -> Option<LLMResponse> {
+        let cached = std::fs::read_to_string(self.path(&Self::key(req))).ok()
+            .and_then(|raw| serde_json::from_str(&raw).ok());
+        let counter = if cached.is_some() { &self.hits } else { &self.misses };
+        counter.fetch_add(1, Ordering::Relaxed);
+        cached
+    }
+
+    pub fn put(&self, req: &LLMRequest, res: &LLMResponse) -> std::io::Result<()> {
+        std::fs::create_dir_all(&self.dir)?;
+        let json = serde_json::to_string(res).map_err(std::io::Error::other)?;
+        std::fs::write(self.path(&Self::key(req)), json)
+    }
+
+    fn entries(&self) -> Vec<std::fs::DirEntry> {
+        std::fs::read_dir(&self.dir)
+            .map(|dir| dir.flatten()
+                .filter(|e| e.path().extension().is_some_and(|ext| ext == "json"))
+                .collect())
+            .unwrap_or_default()
+    }
+
+    pub fn stats(&self) -> CacheStats {
+        let entries = self.entries();
+        CacheStats {
+            entries: entries.len() as u32,
+            bytes: entries.iter().filter_map(|e| e.metadata().ok()).map(|m| m.len()).sum(),
+            hits: self.hits.load(Ordering::Relaxed),
+            misses: self.misses.load(Ordering::Relaxed),
+        }
+    }
+
+    /// Deletes every cached response and resets the counters. Returns the number removed.
+    pub fn purge(&self) -> std::io::Result<u32> {
+        let mut removed = 0;
+        for entry in self.entries() {
+            std::fs::remove_file(entry.path())?;
+            removed += 1;
+        }
+        self.hits.store(0, Ordering::Relaxed);
+        self.misses.store(0, Ordering::Relaxed);
+        Ok(removed)
+    }
+}
+
+static CACHE: RwLock<Option<Arc<ResponseCache>>> = RwLock::new(None);
+
+/// Turns on caching for `send_chat_logic`.
+pub fn enable(cache: ResponseCache) {
+    *CACHE.write().unwrap() = Some(Arc::new(cache));
+}
+
+pub fn disable() {
+    *CACHE.write().unwrap() = None;
+}
+
+pub fn active() -> Option<Arc<ResponseCache>> {
+    CACHE.read().unwrap().clone()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{LLMConfig, Message};
+
+    fn request(content: &str) -> LLMRequest {
+        LLMRequest {
+            messages: vec![Message::new("user", content)],
+            config: LLMConfig {
+                api_key: "secret".to_string(),
+                base_url: String::new(),
+                model: "m".to_string(),
+                ..Default::default()
+            },
+            tools: None,
+            tool_choice: None,
+            prompt_cache: None,
+        }
+    }
+
+    #[test]
+    fn test_round_trip_stats_and_purge() {
+        let dir = std::env::temp_dir().join(format!("irongraph-response-cache-{}", std::process::id()));
+        let cache = ResponseCache::new(&dir);
+        let res = LLMResponse { role: "assistant".to_string(), content: "hi".to_string(), tool_calls: None, usage: None, reasoning: None, choices: Vec::new(), endpoint: None };
+
+        assert!(cache.get(&request("a")).is_none());
+        cache.put(&request("a"), &res).unwrap();
+        assert_eq!(cache.get(&request("a")).map(|r| r.content), Some("hi".to_string()));
+        assert!(cache.get(&request("b")).is_none());
+
+        let stats = cache.stats();
+        assert_eq!((stats.entries, stats.hits, stats.misses), (1, 1, 2));
+        assert_eq!(cache.purge().unwrap(), 1);
+        assert_eq!(cache.stats().entries, 0);
+        let _ = std::fs::remove_dir_all(dir);
+    }
+
+    #[test]
+    fn test_key_depends_on_endpoint() {
+        let local = request("a");
+        let mut remote = request("a");
+        remote.config.base_url = "https://api.example.com/v1".to_string();
+        assert_ne!(ResponseCache::key(&local), ResponseCache::key(&remote));
+
+        // The API key is not part of the answer
+        let mut other_key = request("a");
+        other_key.config.api_key = "other".to_string();
+        assert_eq!(ResponseCache::key(&local), ResponseCache::key(&other_key));
+    }
+}
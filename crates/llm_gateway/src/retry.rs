@@ -0,0 +1,166 @@
+use std::pin::Pin;
+use std::time::Duration;
+use futures::{Stream, StreamExt};
+use reqwest::{RequestBuilder, Response, StatusCode};
+use serde::{Deserialize, Serialize};
+
+use crate::StreamEvent;
+
+// Retries transient failures (connection errors, 408, 429, 5xx) with exponential backoff.
+// Only the request itself is retried: once a stream has started delivering tokens a
+// failure is reported as-is, since replaying it would duplicate output.
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(default)]
+pub struct RetryPolicy {
+    // Total attempts including the first one; 1 disables retrying
+    pub max_attempts: u32,
+    pub base_delay_ms: u64,
+    pub max_delay_ms: u64,
+    // Randomizes each delay between 50% and 100% so parallel agents don't retry in lockstep
+    pub jitter: bool,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        Self {
+            max_attempts: 3,
+            base_delay_ms: 500,
+            max_delay_ms: 30_000,
+            jitter: true,
+        }
+    }
+}
+
+impl RetryPolicy {
+    fn backoff(&self, attempt: u32) -> Duration {
+        let exponential = self.base_delay_ms.saturating_mul(1u64 << (attempt - 1).min(16));
+        let mut delay = exponential.min(self.max_delay_ms);
+        if self.jitter {
+            let nanos = std::time::SystemTime::now()
+                .duration_since(std::time::UNIX_EPOCH)
+                .map(|d| d.subsec_nanos() as u64)
+                .unwrap_or(0);
+            delay = delay / 2 + nanos % (delay / 2 + 1);
+        }
+        Duration::from_millis(delay)
+    }
+}
+
+fn is_retryable(status: StatusCode) -> bool {
+    status == StatusCode::REQUEST_TIMEOUT || status == StatusCode::TOO_MANY_REQUESTS || status.is_server_error()
+}
+
+// Only the delta-seconds form; HTTP dates are rare from API providers
+fn retry_after(res: &Response) -> Option<Duration> {
+    let value = res.headers().get(reqwest::header::RETRY_AFTER)?.to_str().ok()?;
+    value.trim().parse::<u64>().ok().map(Duration::from_secs)
+}
+
+// Prefix of errors where no HTTP response was received
+const REQUEST_FAILED: &str = "Request failed";
+
+/// True for transport failures (no response at all), as opposed to API errors.
+pub(crate) fn is_connection_error(error: &str) -> bool {
+    error.starts_with(REQUEST_FAILED)
+}
+
+pub(crate) enum Attempt {
+    Success(Response),
+    Retry { delay: Duration, reason: String },
+    Fail(String),
+}
+
+/// Classifies the outcome of attempt number `attempt` (1-based).
+pub(crate) fn evaluate(policy: &RetryPolicy, attempt: u32, result: reqwest::Result<Response>) -> Attempt {
+    let can_retry = attempt < policy.max_attempts;
+    match result {
+        Ok(res) if res.status().is_success() => Attempt::Success(res),
+        Ok(res) => {
+            let reason = format!("API Error: {}", res.status());
+            if can_retry && is_retryable(res.status()) {
+                let delay = retry_after(&res)
+                    .map(|d| d.min(Duration::from_millis(policy.max_delay_ms)))
+                    .unwrap_or_else(|| policy.backoff(attempt));
+                Attempt::Retry { delay, reason }
+            } else {
+                Attempt::Fail(reason)
+            }
+        }
+        Err(e) => {
+            let reason = format!("{}: {}", REQUEST_FAILED, e);
+            if can_retry && (e.is_connect() || e.is_timeout() || e.is_request()) {
+                Attempt::Retry { delay: policy.backoff(attempt), reason }
+            } else {
+                Attempt::Fail(reason)
+            }
+        }
+    }
+}
+
+/// Sends the request built by `build`, retrying per `policy`. Non-success statuses become errors.
+pub(crate) async fn send(policy: &RetryPolicy, build: impl Fn() -> RequestBuilder) -> Result<Response, String> {
+    let mut attempt = 1;
+    loop {
+        match evaluate(policy, attempt, build().send().await) {
+            Attempt::Success(res) => return Ok(res),
+            Attempt::Retry { delay, .. } => {
+                tokio::time::sleep(delay).await;
+                attempt += 1;
+            }
+            Attempt::Fail(e) => return Err(e),
+        }
+    }
+}
+
+/// Streaming counterpart of `send`: emits `StreamEvent::Retrying` before every retry, then
+/// hands the successful response to `body` and forwards its events.
+pub(crate) fn stream<B, S>(
+    policy: RetryPolicy,
+    build: impl Fn() -> RequestBuilder + Send + 'static,
+    body: B,
+) -> Pin<Box<dyn Stream<Item = StreamEvent> + Send>>
+where
+    B: FnOnce(Response) -> S + Send + 'static,
+    S: Stream<Item = StreamEvent> + Send + 'static,
+{
+    Box::pin(async_stream::stream! {
+        let mut attempt = 1;
+        let res = loop {
+            match evaluate(&policy, attempt, build().send().await) {
+                Attempt::Success(res) => break res,
+                Attempt::Retry { delay, reason } => {
+                    attempt += 1;
+                    yield StreamEvent::Retrying(attempt, delay.as_millis() as u64, reason);
+                    tokio::time::sleep(delay).await;
+                }
+                Attempt::Fail(e) => {
+                    yield StreamEvent::Error(e);
+                    return;
+                }
+            }
+        };
+
+        let mut inner = Box::pin(body(res));
+        while let Some(event) = inner.next().await {
+            yield event;
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_backoff_grows_and_caps() {
+        let policy = RetryPolicy { jitter: false, base_delay_ms: 100, max_delay_ms: 1_000, ..RetryPolicy::default() };
+        assert_eq!(policy.backoff(1), Duration::from_millis(100));
+        assert_eq!(policy.backoff(3), Duration::from_millis(400));
+        assert_eq!(policy.backoff(10), Duration::from_millis(1_000));
+
+        let jittered = RetryPolicy { jitter: true, ..policy };
+        let delay = jittered.backoff(2);
+        assert!(delay >= Duration::from_millis(100) && delay <= Duration::from_millis(200));
+    }
+}
@@ -0,0 +1,107 @@
+// Server-Sent Events decoder
+// https://html.spec.whatwg.org/multipage/server-sent-events.html#event-stream-interpretation
+//
+// Network chunks have no relation to event boundaries: a chunk can end mid-line or even
+// mid-UTF-8 sequence, so incomplete input is carried over to the next `push`.
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct SseEvent {
+    pub event: Option<String>,
+    pub data: String,
+}
+
+#[derive(Default)]
+pub struct SseDecoder {
+    pending: Vec<u8>,
+    event: Option<String>,
+    data: Vec<String>,
+}
+
+impl SseDecoder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Feeds raw bytes and returns every event completed by them.
+    pub fn push(&mut self, bytes: &[u8]) -> Vec<SseEvent> {
+        self.pending.extend_from_slice(bytes);
+        let mut events = Vec::new();
+
+        while let Some(newline) = self.pending.iter().position(|&b| b == b'\n' || b == b'\r') {
+            // A lone '\r' at the end might be the first half of "\r\n"
+            if self.pending[newline] == b'\r' && newline + 1 == self.pending.len() {
+                break;
+            }
+            let skip = if self.pending[newline] == b'\r' && self.pending.get(newline + 1) == Some(&b'\n') { 2 } else { 1 };
+            let line: Vec<u8> = self.pending.drain(..newline + skip).take(newline).collect();
+            let line = String::from_utf8_lossy(&line);
+            if let Some(event) = self.process_line(&line) {
+                events.push(event);
+            }
+        }
+        events
+    }
+
+    /// Flushes an event left open when the stream ends without a trailing blank line.
+    pub fn finish(&mut self) -> Option<SseEvent> {
+        if !self.pending.is_empty() {
+            let line = String::from_utf8_lossy(&std::mem::take(&mut self.pending)).to_string();
+            if let Some(event) = self.process_line(&line) {
+                return Some(event);
+            }
+        }
+        self.dispatch()
+    }
+
+    fn process_line(&mut self, line: &str) -> Option<SseEvent> {
+        if line.is_empty() {
+            return self.dispatch();
+        }
+        // Comments, typically keepalives such as ": OPENROUTER PROCESSING"
+        if line.starts_with(':') {
+            return None;
+        }
+        let (field, value) = match line.split_once(':') {
+            Some((field, value)) => (field, value.strip_prefix(' ').unwrap_or(value)),
+            None => (line, ""),
+        };
+        match field {
+            "data" => self.data.push(value.to_string()),
+            "event" => self.event = Some(value.to_string()),
+            _ => {} // id / retry are not used by any provider
+        }
+        None
+    }
+
+    fn dispatch(&mut self) -> Option<SseEvent> {
+        let event = self.event.take();
+        if self.data.is_empty() {
+            return None;
+        }
+        Some(SseEvent { event, data: std::mem::take(&mut self.data).join("\n") })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_events_split_across_chunks() {
+        let mut decoder = SseDecoder::new();
+        assert!(decoder.push(b": keepalive\n\ndata: {\"a\":").is_empty());
+        assert_eq!(decoder.push(b" 1}\r\n\r\nevent: stop\ndata: x\ndata: y\n\n"), vec![
+            SseEvent { event: None, data: "{\"a\": 1}".into() },
+            SseEvent { event: Some("stop".into()), data: "x\ny".into() },
+        ]);
+    }
+
+    #[test]
+    fn test_utf8_split_and_unterminated_event() {
+        let mut decoder = SseDecoder::new();
+        let bytes = "data: héllo".as_bytes();
+        assert!(decoder.push(&bytes[..8]).is_empty());
+        assert!(decoder.push(&bytes[8..]).is_empty());
+        assert_eq!(decoder.finish(), Some(SseEvent { event: None, data: "héllo".into() }));
+    }
+}
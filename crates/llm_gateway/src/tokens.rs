@@ -0,0 +1,60 @@
+use tiktoken_rs::tokenizer::{get_tokenizer, Tokenizer};
+
+use crate::Message;
+
+// Token counting for context budgeting. Exact for models whose BPE ships with tiktoken
+// (OpenAI families, including OpenRouter ids such as "openai/gpt-4o"); a ~4 chars/token
+// estimate for everything else.
+
+// Every chat message carries a few tokens of framing (role, separators)
+const TOKENS_PER_MESSAGE: usize = 3;
+// Replies are primed with <|start|>assistant<|message|>
+const REPLY_PRIMING: usize = 3;
+
+fn bpe_len(model: &str, text: &str) -> Option<usize> {
+    let model = model.rsplit('/').next().unwrap_or(model);
+    let bpe = match get_tokenizer(model)? {
+        Tokenizer::O200kBase => tiktoken_rs::o200k_base_singleton(),
+        Tokenizer::Cl100kBase => tiktoken_rs::cl100k_base_singleton(),
+        _ => return None,
+    };
+    let len = bpe.lock().encode_with_special_tokens(text).len();
+    Some(len)
+}
+
+pub(crate) fn estimate(messages: &[Message]) -> usize {
+    messages.iter()
+        .map(|m| (m.role.len() + m.content.len()).div_ceil(4) + TOKENS_PER_MESSAGE)
+        .sum::<usize>() + REPLY_PRIMING
+}
+
+/// Prompt size of `messages` for `model`, as the provider would bill it.
+pub fn count_tokens(model: &str, messages: &[Message]) -> usize {
+    let mut total = REPLY_PRIMING;
+    for message in messages {
+        let exact = bpe_len(model, &message.role)
+            .and_then(|role| bpe_len(model, &message.content).map(|content| role + content));
+        match exact {
+            Some(n) => total += n + TOKENS_PER_MESSAGE,
+            None => return estimate(messages),
+        }
+    }
+    total
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn msg(role: &str, content: &str) -> Message {
+        Message::new(role, content)
+    }
+
+    #[test]
+    fn test_exact_and_estimated_counts() {
+        let messages = vec![msg("user", "hello world")];
+        // "user" and "hello world" are 1 and 2 cl100k tokens
+        assert_eq!(count_tokens("openai/gpt-4", &messages), 1 + 2 + TOKENS_PER_MESSAGE + REPLY_PRIMING);
+        assert_eq!(count_tokens("llama3:8b", &messages), 4 + TOKENS_PER_MESSAGE + REPLY_PRIMING);
+    }
+}
@@ -0,0 +1,239 @@
+use std::collections::HashMap;
+use std::io::{Read, Write};
+use std::path::Path;
+use std::sync::{Arc, Mutex, OnceLock};
+use std::time::{Duration, Instant};
+
+use portable_pty::{Child, MasterPty, NativePtySystem, PtySize, PtySystem};
+use workspace_manager::WorkspaceConfig;
+
+use crate::target::target_command;
+use crate::{strip_ansi, ShellError};
+
+// Long-running processes (dev servers, watchers) the agent starts and polls instead of
+// blocking on in run_command. Each runs in its own PTY so it line-buffers as it would in a
+// terminal, and belongs to the terminal session that started it, which stops it on exit.
+
+/// Output kept per process; older output is dropped and reported as skipped.
+pub const MAX_LOG_BYTES: usize = 256 * 1024;
+
+/// How long `stop` waits after Ctrl-C before killing the process.
+const STOP_GRACE: Duration = Duration::from_secs(3);
+
+// Everything the process printed, trimmed from the front, with a read cursor
+#[derive(Default)]
+struct OutputLog {
+    text: String,
+    // Byte offset of `text` in the whole stream
+    start: usize,
+    // Stream offset up to which output has been returned
+    read: usize,
+}
+
+impl OutputLog {
+    fn push(&mut self, chunk: &str) {
+        self.text.push_str(chunk);
+        if self.text.len() > MAX_LOG_BYTES {
+            let mut cut = self.text.len() - MAX_LOG_BYTES;
+            while !self.text.is_char_boundary(cut) {
+                cut += 1;
+            }
+            self.text.drain(..cut);
+            self.start += cut;
+        }
+    }
+
+    // Output since the last call, and how many bytes of it were dropped before being read
+    fn take_new(&mut self) -> (String, usize) {
+        let from = self.read.max(self.start);
+        let skipped = from - self.read;
+        self.read = self.start + self.text.len();
+        (self.text[from - self.start..].to_string(), skipped)
+    }
+}
+
+struct BackgroundProcess {
+    // Terminal session that started it
+    owner: String,
+    command: String,
+    started: Instant,
+    child: Box<dyn Child + Send + Sync>,
+    writer: Box<dyn Write + Send>,
+    // Dropping it hangs up the PTY
+    _master: Box<dyn MasterPty + Send>,
+    log: Arc<Mutex<OutputLog>>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct BackgroundOutput {
+    pub id: String,
+    pub command: String,
+    // None while running
+    pub exit_code: Option<i32>,
+    pub uptime_secs: u64,
+    // ANSI-stripped output since the previous poll
+    pub output: String,
+    pub skipped_bytes: usize,
+}
+
+static PROCESSES: OnceLock<Mutex<HashMap<String, BackgroundProcess>>> = OnceLock::new();
+
+fn processes() -> &'static Mutex<HashMap<String, BackgroundProcess>> {
+    PROCESSES.get_or_init(|| Mutex::new(HashMap::new()))
+}
+
+/// Starts `command` through the workspace shell in `root`, on the configured terminal target,
+/// and returns its id.
+pub fn start(root: &Path, owner: &str, command: &str) -> Result<String, ShellError> {
+    let pair = NativePtySystem::default().openpty(PtySize {
+        rows: 24,
+        cols: 200,
+        pixel_width: 0,
+        pixel_height: 0,
+    }).map_err(|e| ShellError::Pty(e.to_string()))?;
+
+    let target = WorkspaceConfig::load(root).terminal.target;
+    let (cmd, _) = target_command(root, &target, Some(command))?;
+
+    let child = pair.slave.spawn_command(cmd).map_err(|e| ShellError::Pty(e.to_string()))?;
+    drop(pair.slave);
+    let mut reader = pair.master.try_clone_reader().map_err(|e| ShellError::Pty(e.to_string()))?;
+    let writer = pair.master.take_writer().map_err(|e| ShellError::Pty(e.to_string()))?;
+
+    let log = Arc::new(Mutex::new(OutputLog::default()));
+    let sink = log.clone();
+    std::thread::spawn(move || {
+        let mut buffer = [0u8; 4096];
+        while let Ok(n) = reader.read(&mut buffer) {
+            if n == 0 {
+                break;
+            }
+            sink.lock().unwrap().push(&String::from_utf8_lossy(&buffer[..n]));
+        }
+    });
+
+    let id = format!("bg-{}", &uuid::Uuid::new_v4().simple().to_string()[..8]);
+    processes().lock().unwrap().insert(id.clone(), BackgroundProcess {
+        owner: owner.to_string(),
+        command: command.to_string(),
+        started: Instant::now(),
+        child,
+        writer,
+        _master: pair.master,
+        log,
+    });
+    Ok(id)
+}
+
+fn exit_code(process: &mut BackgroundProcess) -> Option<i32> {
+    process.child.try_wait().ok().flatten().map(|status| status.exit_code() as i32)
+}
+
+fn snapshot(id: &str, process: &mut BackgroundProcess) -> BackgroundOutput {
+    let (output, skipped_bytes) = process.log.lock().unwrap().take_new();
+    BackgroundOutput {
+        id: id.to_string(),
+        command: process.command.clone(),
+        exit_code: exit_code(process),
+        uptime_secs: process.started.elapsed().as_secs(),
+        output: strip_ansi(&output).replace("\r\n", "\n"),
+        skipped_bytes,
+    }
+}
+
+/// Status of process `id` and the output it printed since the last call.
+pub fn output(owner: &str, id: &str) -> Result<BackgroundOutput, ShellError> {
+    let mut processes = processes().lock().unwrap();
+    let process = processes.get_mut(id)
+        .filter(|p| p.owner == owner)
+        .ok_or_else(|| ShellError::NotFound(format!("Background process {}", id)))?;
+    Ok(snapshot(id, process))
+}
+
+/// Processes started by `owner`, without consuming their output.
+pub fn list(owner: &str) -> Vec<(String, String, Option<i32>)> {
+    let mut processes = processes().lock().unwrap();
+    let mut out: Vec<_> = processes.iter_mut()
+        .filter(|(_, p)| p.owner == owner)
+        .map(|(id, p)| (id.clone(), p.command.clone(), exit_code(p)))
+        .collect();
+    out.sort();
+    out
+}
+
+/// Interrupts process `id` like Ctrl-C in a terminal, kills it if it is still running after
+/// a grace period, and returns its final output.
+pub fn stop(owner: &str, id: &str) -> Result<BackgroundOutput, ShellError> {
+    let mut process = {
+        let mut processes = processes().lock().unwrap();
+        match processes.get(id) {
+            Some(p) if p.owner == owner => processes.remove(id).unwrap(),
+            _ => return Err(ShellError::NotFound(format!("Background process {}", id))),
+        }
+    };
+    if exit_code(&mut process).is_none() {
+        let _ = process.writer.write_all(b"\x03").and_then(|_| process.writer.flush());
+        let deadline = Instant::now() + STOP_GRACE;
+        while exit_code(&mut process).is_none() && Instant::now() < deadline {
+            std::thread::sleep(Duration::from_millis(50));
+        }
+        if exit_code(&mut process).is_none() {
+            let _ = process.child.kill();
+            let _ = process.child.wait();
+        }
+    }
+    Ok(snapshot(id, &mut process))
+}
+
+/// Kills every process started by `owner`.
+pub fn stop_all(owner: &str) {
+    let stopped: Vec<BackgroundProcess> = {
+        let mut processes = processes().lock().unwrap();
+        let ids: Vec<String> = processes.iter().filter(|(_, p)| p.owner == owner).map(|(id, _)| id.clone()).collect();
+        ids.iter().filter_map(|id| processes.remove(id)).collect()
+    };
+    for mut process in stopped {
+        let _ = process.child.kill();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_output_log() {
+        let mut log = OutputLog::default();
+        log.push("ready\n");
+        assert_eq!(log.take_new(), ("ready\n".to_string(), 0));
+        assert_eq!(log.take_new(), (String::new(), 0));
+
+        log.push(&"x".repeat(MAX_LOG_BYTES));
+        log.push("tail");
+        let (text, skipped) = log.take_new();
+        assert_eq!(skipped, 4);
+        assert!(text.ends_with("xtail"));
+        assert_eq!(text.len(), MAX_LOG_BYTES);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_start_poll_stop() {
+        let dir = tempfile::tempdir().unwrap();
+        let id = start(dir.path(), "owner", "echo started; sleep 30").unwrap();
+        let deadline = Instant::now() + Duration::from_secs(10);
+        let mut seen = String::new();
+        while !seen.contains("started") && Instant::now() < deadline {
+            seen.push_str(&output("owner", &id).unwrap().output);
+            std::thread::sleep(Duration::from_millis(50));
+        }
+        assert!(seen.contains("started"));
+        assert!(output("someone else", &id).is_err());
+        assert_eq!(list("owner").len(), 1);
+
+        let stopped = stop("owner", &id).unwrap();
+        assert!(stopped.exit_code.is_some());
+        assert!(list("owner").is_empty());
+        assert!(stop("owner", &id).is_err());
+    }
+}
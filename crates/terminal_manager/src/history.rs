@@ -0,0 +1,113 @@
+use std::collections::HashMap;
+use std::sync::{Mutex, OnceLock};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use serde::{Deserialize, Serialize};
+
+// What was executed in each terminal session, for auditing. Records wait here, keyed by
+// terminal session id, until the agent session they belong to persists them with `take`.
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub enum CommandSource {
+    // run_command
+    Agent,
+    // Typed into the terminal through write_to_pty
+    User,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct CommandRecord {
+    pub command: String,
+    pub source: CommandSource,
+    // Milliseconds since the Unix epoch
+    pub started_at: u64,
+    // Only known for agent commands that finished; None for typed input and timeouts
+    pub duration_ms: Option<u64>,
+    pub exit_code: Option<i32>,
+}
+
+#[derive(Default)]
+struct SessionHistory {
+    records: Vec<CommandRecord>,
+    // Typed input not yet ended by Enter
+    pending_line: String,
+}
+
+static HISTORY: OnceLock<Mutex<HashMap<String, SessionHistory>>> = OnceLock::new();
+
+fn history() -> &'static Mutex<HashMap<String, SessionHistory>> {
+    HISTORY.get_or_init(|| Mutex::new(HashMap::new()))
+}
+
+pub fn now_millis() -> u64 {
+    SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_millis() as u64).unwrap_or(0)
+}
+
+pub fn record(session_id: &str, record: CommandRecord) {
+    history().lock().unwrap().entry(session_id.to_string()).or_default().records.push(record);
+}
+
+/// Records each line of typed `input` completed by Enter. Backspace edits the pending line;
+/// other control characters (arrows, Ctrl-C) are not part of a command.
+pub fn record_input(session_id: &str, input: &str) {
+    let mut history = history().lock().unwrap();
+    let session = history.entry(session_id.to_string()).or_default();
+    let mut chars = input.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            '\r' | '\n' => {
+                let line = std::mem::take(&mut session.pending_line);
+                if !line.trim().is_empty() {
+                    session.records.push(CommandRecord {
+                        command: line.trim().to_string(),
+                        source: CommandSource::User,
+                        started_at: now_millis(),
+                        duration_ms: None,
+                        exit_code: None,
+                    });
+                }
+            }
+            '\x7f' | '\x08' => {
+                session.pending_line.pop();
+            }
+            // Ctrl-C abandons the line
+            '\x03' => session.pending_line.clear(),
+            // Escape sequences (arrow keys etc.): skip to the final byte
+            '\x1b' => {
+                for c in chars.by_ref() {
+                    if c.is_ascii_alphabetic() || c == '~' {
+                        break;
+                    }
+                }
+            }
+            c if c.is_control() => {}
+            c => session.pending_line.push(c),
+        }
+    }
+}
+
+/// Records not yet taken, oldest first, without removing them.
+pub fn pending(session_id: &str) -> Vec<CommandRecord> {
+    history().lock().unwrap().get(session_id).map(|h| h.records.clone()).unwrap_or_default()
+}
+
+/// Removes and returns the records waiting to be persisted.
+pub fn take(session_id: &str) -> Vec<CommandRecord> {
+    history().lock().unwrap().get_mut(session_id).map(|h| std::mem::take(&mut h.records)).unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_record_input_lines() {
+        record_input("typed", "ls -la\r");
+        record_input("typed", "cargo tset\x7f\x7f\x7fest");
+        record_input("typed", "\x1b[A\r\n");
+        record_input("typed", "rm -rf build\x03git status\r");
+        let commands: Vec<String> = take("typed").into_iter().map(|r| r.command).collect();
+        assert_eq!(commands, vec!["ls -la", "cargo test", "git status"]);
+        assert!(pending("typed").is_empty());
+    }
+}
@@ -1,16 +1,27 @@
 pub use portable_pty::{CommandBuilder, NativePtySystem, PtySystem, PtySize};
 use serde::{Deserialize, Serialize};
+use std::ffi::OsString;
 use std::io::{Read};
-use std::path::PathBuf;
-use std::sync::{Arc, Mutex};
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::RecvTimeoutError;
+use std::sync::{Arc, Mutex, OnceLock, Weak};
+use std::time::{Duration, Instant};
+use regex::Regex;
 use thiserror::Error;
 use common::WorkspaceState;
-use tokio::sync::mpsc::Sender;
+use workspace_manager::{TerminalConfig, WorkspaceConfig};
+pub use target::TerminalTarget;
+use tokio::sync::mpsc::{Receiver, Sender};
 
 // We use types from common now
-pub use common::{TerminalState, PtySession};
+pub use common::{TerminalState, PtySession, Scrollback};
 pub use common; // Re-export common to make it accessible
 
+pub mod background;
+pub mod history;
+pub mod policy;
+pub mod target;
 pub mod tools;
 
 #[derive(Serialize, Deserialize, Debug, Clone)]
@@ -28,14 +39,222 @@ pub enum ShellError {
     NotFound(String),
     #[error("PTY Error: {0}")]
     Pty(String),
+    #[error("Command timed out after {0}s")]
+    Timeout(u64),
+    #[error("Shell terminated: {0}")]
+    Terminated(String),
 }
 
-// Spawns a persistent shell (bash/cmd) and pipes output to `output_tx`.
+/// PATH for a workspace shell: the `path_prepend` entries, resolved against `root`, ahead
+/// of `inherited`. None when there is nothing to prepend.
+pub fn session_path(root: &Path, config: &TerminalConfig, inherited: Option<OsString>) -> Option<OsString> {
+    if config.path_prepend.is_empty() {
+        return None;
+    }
+    let prepend = config.path_prepend.iter().map(|p| root.join(p));
+    let inherited: Vec<PathBuf> = inherited.map(|p| std::env::split_paths(&p).collect()).unwrap_or_default();
+    std::env::join_paths(prepend.chain(inherited)).ok()
+}
+
+// The `[terminal]` section of the workspace config
+fn apply_workspace_env(cmd: &mut CommandBuilder, root: &Path) {
+    let config = WorkspaceConfig::load(root).terminal;
+    for (key, value) in &config.env {
+        cmd.env(key, value);
+    }
+    let inherited = cmd.get_env("PATH").map(|p| p.to_os_string());
+    if let Some(path) = session_path(root, &config, inherited) {
+        cmd.env("PATH", path);
+    }
+}
+
+/// The user's shell: `$SHELL` on Unix. Windows has no such setting and `%COMSPEC%` is always
+/// cmd.exe, so PowerShell is preferred there: PowerShell 7 if installed, else the built-in one.
+pub fn default_shell() -> String {
+    #[cfg(target_os = "windows")]
+    let preferred = ["pwsh.exe", "powershell.exe"].into_iter().find(|p| find_in_path(p).is_some()).map(str::to_string);
+    #[cfg(not(target_os = "windows"))]
+    let preferred = None;
+    let (var, fallback) = if cfg!(target_os = "windows") { ("COMSPEC", "cmd.exe") } else { ("SHELL", "/bin/bash") };
+    preferred
+        .or_else(|| std::env::var(var).ok().filter(|s| !s.trim().is_empty()))
+        .unwrap_or_else(|| fallback.to_string())
+}
+
+#[cfg(target_os = "windows")]
+fn find_in_path(program: &str) -> Option<PathBuf> {
+    std::env::split_paths(&std::env::var_os("PATH")?)
+        .map(|dir| dir.join(program))
+        .find(|path| path.is_file())
+}
+
+/// The shell program for terminals in `root`: `[terminal] shell` from the workspace config,
+/// else the user's login shell, with the sentinel format that goes with it.
+pub fn workspace_shell(root: &Path) -> (String, tools::ShellType) {
+    let program = WorkspaceConfig::load(root).terminal.shell
+        .filter(|s| !s.trim().is_empty())
+        .unwrap_or_else(default_shell);
+    let shell_type = tools::ShellType::from_program(&program);
+    (program, shell_type)
+}
+
+/// Terminal output is forwarded in batches collected over this window, so the webview gets a
+/// few events per frame rather than one per PTY read.
+pub const OUTPUT_BATCH_WINDOW: Duration = Duration::from_millis(16);
+
+/// A batch is sent early once it reaches this size.
+pub const OUTPUT_BATCH_BYTES: usize = 64 * 1024;
+
+/// Payload of `agent:terminal:output:{id}` events. `seq` counts up from 0 per session, so the
+/// frontend can put events back in order and notice gaps.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct TerminalOutput {
+    pub seq: u32,
+    pub data: String,
+}
+
+/// Waits for output, then keeps collecting whatever arrives within `window` (up to
+/// `max_bytes`). None once the session's output has closed and been drained.
+pub async fn next_output_batch(rx: &mut Receiver<String>, window: Duration, max_bytes: usize) -> Option<String> {
+    let mut batch = rx.recv().await?;
+    let deadline = tokio::time::Instant::now() + window;
+    while batch.len() < max_bytes {
+        match tokio::time::timeout_at(deadline, rx.recv()).await {
+            Ok(Some(chunk)) => batch.push_str(&chunk),
+            Ok(None) | Err(_) => break,
+        }
+    }
+    Some(batch)
+}
+
+/// Output kept per terminal session for `get_terminal_buffer`.
+pub const SCROLLBACK_BYTES: usize = 512 * 1024;
+
+/// How often the exit watcher checks whether a session's shell is still alive.
+const EXIT_POLL: Duration = Duration::from_millis(250);
+
+/// Payload of `agent:terminal:closed:{id}` events: how a session's shell ended.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct ShellExit {
+    pub exit_code: u32,
+    // Set when a signal killed it, e.g. "Killed" after an OOM kill
+    pub signal: Option<String>,
+}
+
+impl ShellExit {
+    pub fn from_status(status: &portable_pty::ExitStatus) -> Self {
+        Self { exit_code: status.exit_code(), signal: status.signal().map(str::to_string) }
+    }
+
+    pub fn describe(&self) -> String {
+        match &self.signal {
+            Some(signal) => format!("killed by signal ({})", signal),
+            None => format!("exited with code {}", self.exit_code),
+        }
+    }
+}
+
+/// Called once, from the watcher thread, when a session's shell exits on its own (not when
+/// the session is killed).
+pub type ExitHook = Arc<dyn Fn(&str, &ShellExit) + Send + Sync>;
+
+// What a session was started with, so `restart_session` can start it again
+#[derive(Clone)]
+struct SessionSpec {
+    root: PathBuf,
+    target: TerminalTarget,
+    output_tx: Sender<String>,
+    on_exit: ExitHook,
+}
+
+fn specs() -> &'static Mutex<HashMap<String, SessionSpec>> {
+    static SPECS: OnceLock<Mutex<HashMap<String, SessionSpec>>> = OnceLock::new();
+    SPECS.get_or_init(|| Mutex::new(HashMap::new()))
+}
+
+// Spawns a persistent shell on `target` and pipes output to `output_tx`. `on_exit` hears
+// when the shell exits, after which writes to the session fail with `Terminated`.
 pub fn start_terminal_session(
     root: &PathBuf,
     state: &Arc<TerminalState>,
     output_tx: Sender<String>,
+    target: &TerminalTarget,
+    on_exit: ExitHook,
 ) -> Result<String, ShellError> {
+    let id = uuid::Uuid::new_v4().to_string();
+    let spec = SessionSpec { root: root.clone(), target: target.clone(), output_tx, on_exit };
+    let scrollback = Arc::new(Mutex::new(Scrollback::new(SCROLLBACK_BYTES)));
+    spawn_shell(state, &id, &spec, scrollback)?;
+    specs().lock().unwrap().insert(id.clone(), spec);
+    Ok(id)
+}
+
+/// Starts a new shell for a session whose shell has exited, under the same id, output
+/// channel and scrollback, so the agent and the frontend carry on where they were.
+pub fn restart_session(state: &Arc<TerminalState>, session_id: &str) -> Result<(), ShellError> {
+    let spec = specs().lock().unwrap().get(session_id).cloned()
+        .ok_or_else(|| ShellError::NotFound("Session ID".into()))?;
+    let scrollback = state.sessions.lock().unwrap().get(session_id)
+        .map(|session| session.lock().unwrap().scrollback.clone())
+        .unwrap_or_else(|| Arc::new(Mutex::new(Scrollback::new(SCROLLBACK_BYTES))));
+    spawn_shell(state, session_id, &spec, scrollback)
+}
+
+/// How the session's shell ended, or None while it runs.
+pub fn session_exit(state: &Arc<TerminalState>, session_id: &str) -> Option<ShellExit> {
+    let sessions = state.sessions.lock().unwrap();
+    let session = sessions.get(session_id)?.lock().unwrap();
+    session.exit_status.as_ref().map(ShellExit::from_status)
+}
+
+/// A terminal session as `list_sessions` reports it.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct SessionInfo {
+    pub id: String,
+    pub shell: String,
+    // The shell's current directory where the platform can tell (local shells on Linux),
+    // else the workspace root it was started for
+    pub cwd: PathBuf,
+    // None while the shell runs
+    pub exit: Option<ShellExit>,
+}
+
+/// Every session in `state`, dead ones included until they are killed, ordered by id.
+pub fn list_sessions(state: &Arc<TerminalState>) -> Vec<SessionInfo> {
+    let specs = specs().lock().unwrap();
+    let sessions = state.sessions.lock().unwrap();
+    let mut out: Vec<SessionInfo> = sessions.iter()
+        .map(|(id, session)| {
+            let session = session.lock().unwrap();
+            let spec = specs.get(id);
+            let live_cwd = match spec.map(|s| &s.target) {
+                Some(TerminalTarget::Local) if session.exit_status.is_none() => session.child.process_id().and_then(process_cwd),
+                _ => None,
+            };
+            SessionInfo {
+                id: id.clone(),
+                shell: session.shell.clone(),
+                cwd: live_cwd.or_else(|| spec.map(|s| s.root.clone())).unwrap_or_default(),
+                exit: session.exit_status.as_ref().map(ShellExit::from_status),
+            }
+        })
+        .collect();
+    out.sort_by(|a, b| a.id.cmp(&b.id));
+    out
+}
+
+#[cfg(target_os = "linux")]
+fn process_cwd(pid: u32) -> Option<PathBuf> {
+    std::fs::read_link(format!("/proc/{}/cwd", pid)).ok()
+}
+
+#[cfg(not(target_os = "linux"))]
+fn process_cwd(_pid: u32) -> Option<PathBuf> {
+    None
+}
+
+// Opens a PTY running the spec's shell and (re)places session `id` with it
+fn spawn_shell(state: &Arc<TerminalState>, id: &str, spec: &SessionSpec, scrollback: Arc<Mutex<Scrollback>>) -> Result<(), ShellError> {
     let pty_system = NativePtySystem::default();
     let pair = pty_system.openpty(PtySize {
         rows: 24,
@@ -44,23 +263,19 @@ pub fn start_terminal_session(
         pixel_height: 0,
     }).map_err(|e| ShellError::Pty(e.to_string()))?;
 
-    #[cfg(target_os = "windows")]
-    let cmd = CommandBuilder::new("cmd.exe");
-    #[cfg(not(target_os = "windows"))]
-    let mut cmd = CommandBuilder::new("/bin/bash");
-
-    cmd.cwd(root);
+    let (cmd, shell) = target::target_command(&spec.root, &spec.target, None)?;
 
     let child = pair.slave.spawn_command(cmd)
         .map_err(|e| ShellError::Pty(e.to_string()))?;
 
     drop(pair.slave);
 
-    let id = uuid::Uuid::new_v4().to_string();
-
     let mut reader = pair.master.try_clone_reader().map_err(|e| ShellError::Pty(e.to_string()))?;
     let writer = pair.master.take_writer().map_err(|e| ShellError::Pty(e.to_string()))?;
 
+    let sink = scrollback.clone();
+    let output_tx = spec.output_tx.clone();
+
     // Spawn Reader Thread
     std::thread::spawn(move || {
         let mut buffer = [0u8; 1024];
@@ -69,6 +284,7 @@ pub fn start_terminal_session(
                 Ok(0) => break, // EOF
                 Ok(n) => {
                     let s = String::from_utf8_lossy(&buffer[..n]).to_string();
+                    sink.lock().unwrap().push(&s);
                     if output_tx.blocking_send(s).is_err() {
                         break; // Receiver dropped
                     }
@@ -78,20 +294,58 @@ pub fn start_terminal_session(
         }
     });
 
-    let session = PtySession {
+    let session = Arc::new(Mutex::new(PtySession {
         writer,
+        master: pair.master,
+        shell,
+        scrollback,
         child,
-    };
+        exit_status: None,
+    }));
+    watch_exit(Arc::downgrade(&session), id.to_string(), spec.on_exit.clone());
 
-    state.sessions.lock().unwrap().insert(id.clone(), Arc::new(Mutex::new(session)));
+    state.sessions.lock().unwrap().insert(id.to_string(), session);
 
-    Ok(id)
+    Ok(())
 }
 
+// Polls the shell until it exits, then marks the session dead and tells `on_exit`. Gives up
+// quietly once the session is dropped, i.e. killed or replaced by a restart.
+fn watch_exit(session: Weak<Mutex<PtySession>>, id: String, on_exit: ExitHook) {
+    std::thread::spawn(move || loop {
+        std::thread::sleep(EXIT_POLL);
+        let Some(session_arc) = session.upgrade() else { return };
+        let status = {
+            let mut session = session_arc.lock().unwrap();
+            match session.child.try_wait() {
+                Ok(Some(status)) => {
+                    session.exit_status = Some(status.clone());
+                    status
+                }
+                Ok(None) => continue,
+                Err(_) => return,
+            }
+        };
+        on_exit(&id, &ShellExit::from_status(&status));
+        return;
+    });
+}
+
+/// Sends typed input to a session, recording completed lines in its command history.
 pub fn write_to_pty(state: &Arc<TerminalState>, session_id: &str, input: &str) -> Result<(), ShellError> {
+    write_raw(state, session_id, input)?;
+    history::record_input(session_id, input);
+    Ok(())
+}
+
+// Input the caller records itself, like run_command's sentinel-wrapped command line
+pub(crate) fn write_raw(state: &Arc<TerminalState>, session_id: &str, input: &str) -> Result<(), ShellError> {
     let sessions = state.sessions.lock().unwrap();
     if let Some(session_arc) = sessions.get(session_id) {
         let mut session = session_arc.lock().unwrap();
+        if let Some(status) = &session.exit_status {
+            return Err(ShellError::Terminated(ShellExit::from_status(status).describe()));
+        }
         session.writer.write_all(input.as_bytes()).map_err(|e| ShellError::Io(e.to_string()))?;
         session.writer.flush().map_err(|e| ShellError::Io(e.to_string()))?;
         Ok(())
@@ -100,7 +354,113 @@ pub fn write_to_pty(state: &Arc<TerminalState>, session_id: &str, input: &str) -
     }
 }
 
+/// The last `last_n_bytes` of raw output from a session (everything kept when None), to
+/// re-render a terminal or re-read output that has scrolled out of the agent's context.
+pub fn get_terminal_buffer(state: &Arc<TerminalState>, session_id: &str, last_n_bytes: Option<usize>) -> Result<String, ShellError> {
+    let sessions = state.sessions.lock().unwrap();
+    let session = sessions.get(session_id).ok_or_else(|| ShellError::NotFound("Session ID".into()))?;
+    let scrollback = session.lock().unwrap().scrollback.clone();
+    let text = scrollback.lock().unwrap().tail(last_n_bytes).to_string();
+    Ok(text)
+}
+
+/// Resizes the session's PTY so full-screen programs lay out for the frontend's view.
+pub fn resize_pty(state: &Arc<TerminalState>, session_id: &str, rows: u16, cols: u16) -> Result<(), ShellError> {
+    if rows == 0 || cols == 0 {
+        return Err(ShellError::Pty(format!("Invalid terminal size {}x{}", cols, rows)));
+    }
+    let sessions = state.sessions.lock().unwrap();
+    if let Some(session_arc) = sessions.get(session_id) {
+        let session = session_arc.lock().unwrap();
+        session.master.resize(PtySize {
+            rows,
+            cols,
+            pixel_width: 0,
+            pixel_height: 0,
+        }).map_err(|e| ShellError::Pty(e.to_string()))
+    } else {
+        Err(ShellError::NotFound("Session ID".into()))
+    }
+}
+
+/// What `interrupt_foreground` found running and how it was stopped.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Interrupted {
+    // Command line of the foreground process group, when the platform can tell
+    pub process: Option<String>,
+    // "SIGINT" when Ctrl-C was enough, "SIGKILL" when it had to be killed
+    pub signal: &'static str,
+    // False if something still holds the terminal
+    pub stopped: bool,
+}
+
+// Process group in the foreground of the session's terminal, if it isn't the shell itself
+#[cfg(unix)]
+fn foreground_group(state: &Arc<TerminalState>, session_id: &str) -> Option<i32> {
+    let sessions = state.sessions.lock().unwrap();
+    let session = sessions.get(session_id)?.lock().unwrap();
+    let leader = session.master.process_group_leader()?;
+    let shell = session.child.process_id()? as i32;
+    (leader != shell).then_some(leader)
+}
+
+#[cfg(unix)]
+fn describe_process(pid: i32) -> Option<String> {
+    let output = std::process::Command::new("ps").args(["-o", "args=", "-p", &pid.to_string()]).output().ok()?;
+    let args = String::from_utf8_lossy(&output.stdout).trim().to_string();
+    (!args.is_empty()).then_some(args)
+}
+
+fn interrupts() -> &'static Mutex<HashMap<String, u64>> {
+    static INTERRUPTS: OnceLock<Mutex<HashMap<String, u64>>> = OnceLock::new();
+    INTERRUPTS.get_or_init(|| Mutex::new(HashMap::new()))
+}
+
+/// How many times `interrupt_foreground` has been called for a session, so a command waiting
+/// on its sentinel (which Ctrl-C cancels along with the command) can tell it was stopped.
+pub fn interrupt_count(session_id: &str) -> u64 {
+    interrupts().lock().unwrap().get(session_id).copied().unwrap_or(0)
+}
+
+/// Stops whatever runs in the foreground of a session, like a hung build, without ending
+/// the session: Ctrl-C first, then on Unix SIGKILL to the foreground process group if it is
+/// still there after `grace`. Windows has no foreground process group to inspect or kill,
+/// so there it is only Ctrl-C.
+pub fn interrupt_foreground(state: &Arc<TerminalState>, session_id: &str, grace: Duration) -> Result<Interrupted, ShellError> {
+    *interrupts().lock().unwrap().entry(session_id.to_string()).or_default() += 1;
+    signal_foreground(state, session_id, grace)
+}
+
+#[cfg(unix)]
+fn signal_foreground(state: &Arc<TerminalState>, session_id: &str, grace: Duration) -> Result<Interrupted, ShellError> {
+    let group = foreground_group(state, session_id);
+    let process = group.and_then(describe_process);
+    write_raw(state, session_id, "\x03")?;
+    let wait_for_shell = |limit: Duration| {
+        let deadline = Instant::now() + limit;
+        while foreground_group(state, session_id).is_some() && Instant::now() < deadline {
+            std::thread::sleep(Duration::from_millis(50));
+        }
+        foreground_group(state, session_id).is_none()
+    };
+    if wait_for_shell(grace) {
+        return Ok(Interrupted { process, signal: "SIGINT", stopped: true });
+    }
+    if let Some(group) = foreground_group(state, session_id) {
+        unsafe { libc::kill(-group, libc::SIGKILL) };
+    }
+    Ok(Interrupted { process, signal: "SIGKILL", stopped: wait_for_shell(Duration::from_secs(1)) })
+}
+
+#[cfg(not(unix))]
+fn signal_foreground(state: &Arc<TerminalState>, session_id: &str, _grace: Duration) -> Result<Interrupted, ShellError> {
+    write_raw(state, session_id, "\x03")?;
+    Ok(Interrupted { process: None, signal: "SIGINT", stopped: true })
+}
+
 pub fn kill_session(state: &Arc<TerminalState>, session_id: &str) -> Result<(), ShellError> {
+    background::stop_all(session_id);
+    specs().lock().unwrap().remove(session_id);
     let mut sessions = state.sessions.lock().unwrap();
     if sessions.remove(session_id).is_some() {
         Ok(())
@@ -109,23 +469,318 @@ pub fn kill_session(state: &Arc<TerminalState>, session_id: &str) -> Result<(),
     }
 }
 
-pub fn run_command_internal(root: &PathBuf, program: String, args: Vec<String>) -> Result<CommandOutput, ShellError> {
-     // One-off PTY
+/// Marker the shell prints after a wrapped command, followed by its exit code.
+pub const SENTINEL: &str = "IRONGRAPH_CMD_DONE:";
+
+fn sentinel_regex() -> &'static Regex {
+    static RE: OnceLock<Regex> = OnceLock::new();
+    // Only the printed marker has digits; the echoed command line has `$?`, `%ERRORLEVEL%`...
+    RE.get_or_init(|| Regex::new(&format!(r"{}(-?\d+)", SENTINEL)).unwrap())
+}
+
+fn cursor_position_regex() -> &'static Regex {
+    static RE: OnceLock<Regex> = OnceLock::new();
+    RE.get_or_init(|| Regex::new(r"\x1b\[[0-9;]*[Hf]").unwrap())
+}
+
+/// PTY output as plain text with `\n` line ends. ConPTY (Windows) repaints with cursor
+/// positioning instead of newlines and scatters VT sequences through the text, even inside
+/// the sentinel; a jump to another row is taken as a line break.
+pub fn normalize_terminal_output(raw: &str) -> String {
+    let text = cursor_position_regex().replace_all(raw, "\n");
+    strip_ansi(&text).replace("\r\n", "\n").replace('\r', "")
+}
+
+/// Splits the raw PTY output of a sentinel-wrapped command into what the command printed
+/// and its exit code. The shell's echo of the command line (and any prompt before it) is
+/// dropped. None until the sentinel has arrived.
+pub fn parse_sentinel_output(raw: &str) -> Option<(String, i32)> {
+    let raw = &normalize_terminal_output(raw);
+    let caps = sentinel_regex().captures(raw)?;
+    let marker = caps.get(0)?;
+    // The marker must be complete: more digits may still be on their way
+    if !raw[marker.end()..].contains('\n') {
+        return None;
+    }
+    let exit_code = caps[1].parse().ok()?;
+
+    let before = &raw[..marker.start()];
+    // The echo ends on the first line break after the echoed (unexpanded) marker
+    let start = before.rfind(SENTINEL)
+        .and_then(|echo| before[echo..].find('\n').map(|nl| echo + nl + 1))
+        .unwrap_or(0);
+    Some((before[start.min(before.len())..].trim_end().to_string(), exit_code))
+}
+
+fn ansi_regex() -> &'static Regex {
+    static RE: OnceLock<Regex> = OnceLock::new();
+    // CSI (colors, cursor moves), OSC (titles, hyperlinks; BEL or ST terminated), then any
+    // other two-byte escape
+    RE.get_or_init(|| Regex::new(r"\x1b\[[0-?]*[ -/]*[@-~]|\x1b\][^\x07\x1b]*(?:\x07|\x1b\\)|\x1b[@-Z\\-_]").unwrap())
+}
+
+/// Removes ANSI escape sequences, for output that goes to the model rather than a terminal.
+pub fn strip_ansi(text: &str) -> String {
+    ansi_regex().replace_all(text, "").into_owned()
+}
+
+/// The shell command line for `program args...`, with arguments quoted POSIX-style.
+pub fn command_line(program: &str, args: &[String]) -> String {
+    std::iter::once(program.to_string())
+        .chain(args.iter().map(|a| shlex::try_quote(a).map_or_else(|_| a.clone(), |q| q.into_owned())))
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+/// Runs one command to completion in a throwaway shell and captures its output.
+///
+/// The command goes through the same sentinel wrapper as the agent's persistent session, so
+/// it sees the same shell environment, and it returns as soon as the sentinel is printed
+/// even if the command left background processes holding the terminal open. A PTY merges
+/// stderr into stdout, so `stderr` is always empty. `cwd`, relative to `root`, is where the
+/// command runs instead of the root.
+pub fn run_command_internal(root: &PathBuf, program: String, args: Vec<String>, cwd: Option<&str>) -> Result<CommandOutput, ShellError> {
+    let dir = match cwd {
+        Some(cwd) => PathBuf::from(target::command_dir(root, &TerminalTarget::Local, cwd)?),
+        None => root.clone(),
+    };
     let pty_system = NativePtySystem::default();
     let pair = pty_system.openpty(PtySize { rows: 24, cols: 80, pixel_width: 0, pixel_height: 0 }).map_err(|e| ShellError::Pty(e.to_string()))?;
-    let mut cmd = CommandBuilder::new(&program);
-    cmd.args(&args);
-    cmd.cwd(root);
+
+    let (shell, shell_type) = workspace_shell(root);
+    let mut cmd = CommandBuilder::new(&shell);
+    // No rc files: a user prompt or greeting must not be mistaken for output
+    cmd.args(shell_type.no_config_args());
+    cmd.cwd(&dir);
+    apply_workspace_env(&mut cmd, root);
     let mut child = pair.slave.spawn_command(cmd).map_err(|e| ShellError::Pty(e.to_string()))?;
     drop(pair.slave);
+
     let mut reader = pair.master.try_clone_reader().map_err(|e| ShellError::Pty(e.to_string()))?;
-    let mut output = String::new();
-    reader.read_to_string(&mut output).unwrap_or(0); // ignore err
-    let exit = child.wait().map_err(|e| ShellError::Pty(e.to_string()))?;
-
-    Ok(CommandOutput {
-        stdout: output,
-        stderr: "".into(),
-        exit_code: if exit.success() { 0 } else { 1 }
-    })
+    let mut writer = pair.master.take_writer().map_err(|e| ShellError::Pty(e.to_string()))?;
+    let (tx, rx) = std::sync::mpsc::channel::<String>();
+    std::thread::spawn(move || {
+        let mut buffer = [0u8; 4096];
+        while let Ok(n) = reader.read(&mut buffer) {
+            if n == 0 || tx.send(String::from_utf8_lossy(&buffer[..n]).to_string()).is_err() {
+                break;
+            }
+        }
+    });
+
+    let line = shell_type.format_with_sentinel(&command_line(&program, &args), None);
+    writer.write_all(line.as_bytes()).and_then(|_| writer.flush()).map_err(|e| ShellError::Io(e.to_string()))?;
+
+    let timeout = WorkspaceConfig::load(root).terminal.command_timeout(None);
+    let deadline = Instant::now() + Duration::from_secs(timeout);
+    let mut raw = String::new();
+    let result = loop {
+        if let Some((stdout, exit_code)) = parse_sentinel_output(&raw) {
+            break Ok(CommandOutput { stdout, stderr: String::new(), exit_code });
+        }
+        let remaining = deadline.saturating_duration_since(Instant::now());
+        match rx.recv_timeout(remaining) {
+            Ok(chunk) => raw.push_str(&chunk),
+            Err(RecvTimeoutError::Timeout) => break Err(ShellError::Timeout(timeout)),
+            // The shell died before printing the sentinel (e.g. the command ran `exit`)
+            Err(RecvTimeoutError::Disconnected) => {
+                let exit_code = child.wait().map(|s| s.exit_code() as i32).unwrap_or(1);
+                break Ok(CommandOutput { stdout: raw.replace("\r\n", "\n").trim_end().to_string(), stderr: String::new(), exit_code });
+            }
+        }
+    };
+    // A timed-out command runs in its own process group, which killing the shell would leave
+    #[cfg(unix)]
+    if let Some(group) = pair.master.process_group_leader().filter(|g| Some(*g as u32) != child.process_id()) {
+        unsafe { libc::kill(-group, libc::SIGKILL) };
+    }
+    let _ = child.kill();
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_sentinel_output() {
+        let raw = "$ cargo test; echo \"IRONGRAPH_CMD_DONE:$?\"\r\nrunning 2 tests\r\ntest result: ok\r\nIRONGRAPH_CMD_DONE:0\r\n$ ";
+        assert_eq!(parse_sentinel_output(raw), Some(("running 2 tests\ntest result: ok".to_string(), 0)));
+
+        // Echo only, or a marker whose digits may not all have arrived
+        assert_eq!(parse_sentinel_output("$ false; echo \"IRONGRAPH_CMD_DONE:$?\"\r\n"), None);
+        assert_eq!(parse_sentinel_output("IRONGRAPH_CMD_DONE:12"), None);
+        assert_eq!(parse_sentinel_output("boom\nIRONGRAPH_CMD_DONE:127\n"), Some(("boom".to_string(), 127)));
+    }
+
+    #[test]
+    fn test_command_line_quotes_args() {
+        assert_eq!(command_line("grep", &["-r".into(), "two words".into()]), "grep -r 'two words'");
+    }
+
+    #[test]
+    fn test_resize_pty_errors() {
+        let state = Arc::new(TerminalState::default());
+        assert!(matches!(resize_pty(&state, "missing", 40, 120), Err(ShellError::NotFound(_))));
+        assert!(matches!(resize_pty(&state, "missing", 0, 120), Err(ShellError::Pty(_))));
+    }
+
+    #[test]
+    fn test_session_path() {
+        let root = Path::new("/work/app");
+        let config = TerminalConfig { path_prepend: vec![".venv/bin".into(), "/opt/tool/bin".into()], ..Default::default() };
+        let path = session_path(root, &config, Some(OsString::from("/usr/bin"))).unwrap();
+        let entries: Vec<PathBuf> = std::env::split_paths(&path).collect();
+        assert_eq!(entries, vec![PathBuf::from("/work/app/.venv/bin"), PathBuf::from("/opt/tool/bin"), PathBuf::from("/usr/bin")]);
+        assert_eq!(session_path(root, &TerminalConfig::default(), Some(OsString::from("/usr/bin"))), None);
+    }
+
+    #[test]
+    fn test_workspace_shell() {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::create_dir(dir.path().join(".irongraph")).unwrap();
+        std::fs::write(dir.path().join(".irongraph/config.toml"), "[terminal]\nshell = \"/usr/local/bin/fish\"\n").unwrap();
+        assert_eq!(workspace_shell(dir.path()), ("/usr/local/bin/fish".to_string(), tools::ShellType::Fish));
+    }
+
+    #[test]
+    fn test_shell_type_from_program() {
+        use tools::ShellType;
+        assert_eq!(ShellType::from_program("/bin/zsh"), ShellType::Zsh);
+        assert_eq!(ShellType::from_program("C:\\Program Files\\PowerShell\\7\\pwsh.exe"), ShellType::PowerShell);
+        assert_eq!(ShellType::from_program("nu"), ShellType::Nushell);
+        assert_eq!(ShellType::from_program("/bin/sh"), ShellType::Sh);
+        assert_eq!(ShellType::from_program("bash"), ShellType::Bash);
+
+        // The echoed command line never matches the sentinel, only the printed exit code does
+        for shell in [ShellType::Bash, ShellType::Fish, ShellType::Nushell, ShellType::Cmd, ShellType::PowerShell] {
+            for cwd in [None, Some("/srv/my app")] {
+                let echo = shell.format_with_sentinel("make", cwd);
+                assert_eq!(parse_sentinel_output(&format!("{}out\nIRONGRAPH_CMD_DONE:2\n", echo)), Some(("out".to_string(), 2)));
+            }
+        }
+        assert_eq!(ShellType::Bash.format_with_sentinel("make", Some("/srv/my app")), "(cd '/srv/my app' && make); echo \"IRONGRAPH_CMD_DONE:$?\"\n");
+    }
+
+    #[test]
+    fn test_strip_ansi() {
+        let raw = "\x1b[0m\x1b[1m\x1b[38;5;9merror[E0425]\x1b[0m: cannot find value\n\x1b]0;cargo\x07\x1b]8;;https://x\x1b\\link\x1b]8;;\x1b\\\x1b[K";
+        assert_eq!(strip_ansi(raw), "error[E0425]: cannot find value\nlink");
+        assert_eq!(strip_ansi("plain [text]"), "plain [text]");
+    }
+
+    #[test]
+    fn test_scrollback_tail() {
+        let mut scrollback = Scrollback::new(8);
+        scrollback.push("hello ");
+        assert_eq!(scrollback.tail(None), "hello ");
+        scrollback.push("wörld!");
+        assert_eq!(scrollback.tail(None), " wörld!");
+        assert_eq!(scrollback.tail(Some(3)), "ld!");
+        // Never starts inside a character
+        assert_eq!(scrollback.tail(Some(5)), "rld!");
+    }
+
+    #[test]
+    fn test_parse_conpty_output() {
+        // PowerShell under ConPTY: repaint with cursor moves, colors inside the marker
+        let raw = "\x1b[?25l\x1b[2J\x1b[HPS C:\\app> cargo build; Write-Host \"IRONGRAPH_CMD_DONE:$(if ($?) { 0 } else { 1 })\"\x1b[K\x1b[2;1H\x1b[32mCompiling app\x1b[0m\x1b[3;1H\x1b[93mIRONGRAPH_CMD_DONE:\x1b[0m101\r\n\x1b[?25h";
+        assert_eq!(parse_sentinel_output(raw), Some(("Compiling app".to_string(), 101)));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_interrupt_foreground() {
+        let dir = tempfile::tempdir().unwrap();
+        let state = Arc::new(TerminalState::default());
+        let (tx, _rx) = tokio::sync::mpsc::channel(1024);
+        let id = start_terminal_session(&dir.path().to_path_buf(), &state, tx, &TerminalTarget::Local, Arc::new(|_: &str, _: &ShellExit| {})).unwrap();
+        write_raw(&state, &id, "sleep 30\n").unwrap();
+        let deadline = Instant::now() + Duration::from_secs(10);
+        while foreground_group(&state, &id).is_none() && Instant::now() < deadline {
+            std::thread::sleep(Duration::from_millis(50));
+        }
+
+        let interrupted = interrupt_foreground(&state, &id, Duration::from_secs(3)).unwrap();
+        assert!(interrupted.stopped);
+        assert!(interrupted.process.unwrap_or_default().contains("sleep"));
+        kill_session(&state, &id).unwrap();
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_exit_watcher_and_restart() {
+        let dir = tempfile::tempdir().unwrap();
+        let state = Arc::new(TerminalState::default());
+        let (tx, _rx) = tokio::sync::mpsc::channel(1024);
+        let (exit_tx, exit_rx) = std::sync::mpsc::channel();
+        let exit_tx = Mutex::new(exit_tx);
+        let on_exit: ExitHook = Arc::new(move |id: &str, exit: &ShellExit| { let _ = exit_tx.lock().unwrap().send((id.to_string(), exit.clone())); });
+        let id = start_terminal_session(&dir.path().to_path_buf(), &state, tx, &TerminalTarget::Local, on_exit).unwrap();
+
+        write_raw(&state, &id, "exit 3\n").unwrap();
+        let (closed_id, exit) = exit_rx.recv_timeout(Duration::from_secs(10)).unwrap();
+        assert_eq!((closed_id.as_str(), exit.exit_code), (id.as_str(), 3));
+        assert_eq!(session_exit(&state, &id), Some(exit));
+        assert!(matches!(write_to_pty(&state, &id, "ls\n"), Err(ShellError::Terminated(e)) if e == "exited with code 3"));
+
+        assert_eq!(list_sessions(&state)[0].exit.as_ref().map(|e| e.exit_code), Some(3));
+
+        restart_session(&state, &id).unwrap();
+        assert_eq!(session_exit(&state, &id), None);
+        write_raw(&state, &id, "mkdir sub && cd sub\n").unwrap();
+        #[cfg(target_os = "linux")]
+        {
+            let deadline = Instant::now() + Duration::from_secs(10);
+            while !list_sessions(&state)[0].cwd.ends_with("sub") && Instant::now() < deadline {
+                std::thread::sleep(Duration::from_millis(50));
+            }
+            assert!(list_sessions(&state)[0].cwd.ends_with("sub"));
+        }
+        write_to_pty(&state, &id, "echo again\n").unwrap();
+        kill_session(&state, &id).unwrap();
+        assert!(restart_session(&state, &id).is_err());
+        assert!(list_sessions(&state).is_empty());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_run_command_args_keep_shell_syntax() {
+        let line = tools::run_command_line("echo", Some("one two | tr a-z A-Z"));
+        assert_eq!(line, "echo one two | tr a-z A-Z");
+        let output = std::process::Command::new("sh").arg("-c").arg(&line).output().unwrap();
+        assert_eq!(String::from_utf8_lossy(&output.stdout), "ONE TWO\n");
+    }
+
+    #[test]
+    fn test_tail_output() {
+        assert_eq!(tools::tail_output("\x1b[32mok\x1b[0m\n"), ("ok".to_string(), false));
+        let long = format!("{}\nerror: failed", "é".repeat(tools::MAX_OUTPUT_BYTES));
+        let (text, truncated) = tools::tail_output(&long);
+        assert!(truncated);
+        assert!(text.len() <= tools::MAX_OUTPUT_BYTES && text.ends_with("error: failed"));
+        assert_eq!(tools::command_exit_code(&serde_json::json!({ "status": "completed", "exit_code": 101 })), Some(101));
+        assert_eq!(tools::command_exit_code(&serde_json::json!({ "status": "timeout" })), None);
+    }
+
+    #[test]
+    fn test_next_output_batch() {
+        let runtime = tokio::runtime::Builder::new_current_thread().enable_time().build().unwrap();
+        runtime.block_on(async {
+            let (tx, mut rx) = tokio::sync::mpsc::channel(16);
+            for chunk in ["a", "b", "c"] {
+                tx.send(chunk.to_string()).await.unwrap();
+            }
+            assert_eq!(next_output_batch(&mut rx, Duration::from_millis(20), 1024).await.as_deref(), Some("abc"));
+
+            for chunk in ["12", "34", "56"] {
+                tx.send(chunk.to_string()).await.unwrap();
+            }
+            assert_eq!(next_output_batch(&mut rx, Duration::from_millis(20), 3).await.as_deref(), Some("1234"));
+            assert_eq!(next_output_batch(&mut rx, Duration::from_millis(20), 3).await.as_deref(), Some("56"));
+
+            drop(tx);
+            assert_eq!(next_output_batch(&mut rx, Duration::from_millis(20), 3).await, None);
+        });
+    }
 }
@@ -0,0 +1,251 @@
+use std::path::Path;
+use std::process::Command;
+use std::sync::OnceLock;
+
+use portable_pty::CommandBuilder;
+use regex::Regex;
+use workspace_manager::{resolve_path_safe, FsError, TerminalConfig, WorkspaceConfig};
+pub use workspace_manager::TerminalTarget;
+
+use crate::{apply_workspace_env, workspace_shell, ShellError};
+
+// Where a terminal's process runs. Local terminals start the workspace shell directly;
+// remote ones start the system ssh client with a remote PTY, and container ones `docker exec`
+// with a container TTY, so the rest of the terminal code (sentinels, resizing, Ctrl-C) works
+// unchanged over the connection.
+
+// The remote or container login shell may be anything; sentinels need a POSIX one
+const POSIX_LOGIN_SHELL: &str = "command -v bash >/dev/null && exec bash -l || exec sh -l";
+
+fn quote(s: &str) -> String {
+    shlex::try_quote(s).map_or_else(|_| s.to_string(), |q| q.into_owned())
+}
+
+// `export` lines recreating `[terminal] env` and `path_prepend` on the remote, where the
+// local environment doesn't reach. Relative PATH entries are taken from `dir`.
+fn remote_env(config: &TerminalConfig, dir: Option<&str>) -> String {
+    let mut script = String::new();
+    for (key, value) in &config.env {
+        script.push_str(&format!("export {}={}; ", key, quote(value)));
+    }
+    if !config.path_prepend.is_empty() {
+        let entries: Vec<String> = config.path_prepend.iter()
+            .map(|p| match dir {
+                Some(dir) if !p.starts_with('/') => quote(&format!("{}/{}", dir.trim_end_matches('/'), p)),
+                _ => quote(p),
+            })
+            .collect();
+        script.push_str(&format!("export PATH={}:\"$PATH\"; ", entries.join(":")));
+    }
+    script
+}
+
+/// The running container the devcontainer CLI (or VS Code) started for the workspace at `root`.
+pub fn devcontainer_id(root: &Path) -> Result<String, ShellError> {
+    let folder = root.canonicalize().unwrap_or_else(|_| root.to_path_buf());
+    let output = Command::new("docker")
+        .args(["ps", "-q", "--filter", &format!("label=devcontainer.local_folder={}", folder.display())])
+        .output()
+        .map_err(|e| ShellError::NotFound(format!("docker ({})", e)))?;
+    if !output.status.success() {
+        return Err(ShellError::Io(String::from_utf8_lossy(&output.stderr).trim().to_string()));
+    }
+    String::from_utf8_lossy(&output.stdout).lines().next()
+        .map(|id| id.trim().to_string())
+        .filter(|id| !id.is_empty())
+        .ok_or_else(|| ShellError::NotFound(format!("Running devcontainer for {}; start it first", folder.display())))
+}
+
+/// Where the devcontainer mounts the workspace: `workspaceFolder` from
+/// `.devcontainer/devcontainer.json`, or the `/workspaces/<folder name>` default.
+pub fn devcontainer_workspace_folder(root: &Path) -> String {
+    static FOLDER: OnceLock<Regex> = OnceLock::new();
+    let basename = root.canonicalize().unwrap_or_else(|_| root.to_path_buf())
+        .file_name().map(|n| n.to_string_lossy().to_string()).unwrap_or_default();
+    // devcontainer.json allows comments, so pick the one key out rather than parse it
+    let configured = [".devcontainer/devcontainer.json", ".devcontainer.json"].iter()
+        .filter_map(|p| std::fs::read_to_string(root.join(p)).ok())
+        .find_map(|content| {
+            let re = FOLDER.get_or_init(|| Regex::new(r#""workspaceFolder"\s*:\s*"([^"]+)""#).unwrap());
+            re.captures(&content).map(|c| c[1].to_string())
+        });
+    configured
+        .map(|f| f.replace("${localWorkspaceFolderBasename}", &basename))
+        .unwrap_or_else(|| format!("/workspaces/{}", basename))
+}
+
+/// Where a command asked to run in `cwd` (relative to the workspace root) runs on `target`.
+/// The path is checked like any workspace path; on a remote target it is taken from the
+/// remote working directory, or left relative when there is none.
+pub fn command_dir(root: &Path, target: &TerminalTarget, cwd: &str) -> Result<String, ShellError> {
+    let local = matches!(target, TerminalTarget::Local);
+    let resolved = resolve_path_safe(root, cwd, local).map_err(|e| match e {
+        FsError::SecurityViolation => ShellError::Io(format!("Working directory {} is outside the workspace", cwd)),
+        _ => ShellError::NotFound(format!("Working directory {}", cwd)),
+    })?;
+    let remote_dir = match target {
+        TerminalTarget::Local => {
+            if !resolved.is_dir() {
+                return Err(ShellError::NotFound(format!("Working directory {}", cwd)));
+            }
+            return Ok(resolved.to_string_lossy().to_string());
+        }
+        TerminalTarget::Ssh { dir, .. } => dir.clone(),
+        TerminalTarget::Docker { container: Some(_), dir, .. } => dir.clone(),
+        TerminalTarget::Docker { container: None, dir, .. } => Some(dir.clone().unwrap_or_else(|| devcontainer_workspace_folder(root))),
+    };
+    let relative = cwd.replace('\\', "/");
+    let relative = relative.trim_start_matches("./").trim_end_matches('/');
+    Ok(match remote_dir {
+        Some(dir) if relative.is_empty() || relative == "." => dir,
+        Some(dir) => format!("{}/{}", dir.trim_end_matches('/'), relative),
+        None => relative.to_string(),
+    })
+}
+
+/// The process hosting a terminal for `target`, and the shell program that ends up reading
+/// commands (which decides the sentinel format). With `command` the process runs just that
+/// command; without it, an interactive shell.
+pub fn target_command(root: &Path, target: &TerminalTarget, command: Option<&str>) -> Result<(CommandBuilder, String), ShellError> {
+    match target {
+        TerminalTarget::Local => {
+            let (shell, shell_type) = workspace_shell(root);
+            let mut cmd = CommandBuilder::new(&shell);
+            if let Some(command) = command {
+                cmd.args(shell_type.no_config_args());
+                cmd.arg(shell_type.command_flag());
+                cmd.arg(command);
+            }
+            cmd.cwd(root);
+            apply_workspace_env(&mut cmd, root);
+            Ok((cmd, shell))
+        }
+        TerminalTarget::Ssh { shared_workspace: false, host, .. } => Err(ShellError::Io(format!(
+            "The SSH target {} does not share the workspace, so commands would not see the agent's edits. Set `shared_workspace = true` once its `dir` is the same files (e.g. a network mount).",
+            host
+        ))),
+        TerminalTarget::Ssh { host, user, port, identity_file, dir, options, .. } => {
+            let mut cmd = CommandBuilder::new("ssh");
+            // A remote PTY even though a command is given
+            cmd.arg("-tt");
+            if let Some(port) = port {
+                cmd.args(["-p", &port.to_string()]);
+            }
+            if let Some(identity) = identity_file {
+                cmd.args(["-i", identity]);
+            }
+            for option in options {
+                cmd.args(["-o", option]);
+            }
+            cmd.arg(match user {
+                Some(user) => format!("{}@{}", user, host),
+                None => host.clone(),
+            });
+
+            let config = WorkspaceConfig::load(root).terminal;
+            let mut script = remote_env(&config, dir.as_deref());
+            if let Some(dir) = dir {
+                script.push_str(&format!("cd {} && ", quote(dir)));
+            }
+            script.push_str(command.unwrap_or(POSIX_LOGIN_SHELL));
+            cmd.arg(script);
+            cmd.cwd(root);
+            Ok((cmd, "bash".to_string()))
+        }
+        TerminalTarget::Docker { container, user, dir } => {
+            let (container, dir) = match container {
+                Some(container) => (container.clone(), dir.clone()),
+                None => (devcontainer_id(root)?, Some(dir.clone().unwrap_or_else(|| devcontainer_workspace_folder(root)))),
+            };
+            let mut cmd = CommandBuilder::new("docker");
+            cmd.args(["exec", "-it"]);
+            if let Some(user) = user {
+                cmd.args(["-u", user]);
+            }
+            if let Some(dir) = &dir {
+                cmd.args(["-w", dir]);
+            }
+            cmd.arg(&container);
+
+            let config = WorkspaceConfig::load(root).terminal;
+            let mut script = remote_env(&config, dir.as_deref());
+            script.push_str(command.unwrap_or(POSIX_LOGIN_SHELL));
+            cmd.args(["sh", "-c", &script]);
+            cmd.cwd(root);
+            Ok((cmd, "bash".to_string()))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_ssh_command() {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::create_dir(dir.path().join(".irongraph")).unwrap();
+        std::fs::write(dir.path().join(".irongraph/config.toml"), "[terminal]\npath_prepend = [\".venv/bin\"]\n\n[terminal.env]\nAPI_URL = \"http://a b\"\n").unwrap();
+        let target = TerminalTarget::Ssh {
+            host: "devbox".into(),
+            user: Some("dev".into()),
+            port: Some(2222),
+            identity_file: None,
+            dir: Some("/srv/my app".into()),
+            options: vec!["ServerAliveInterval=30".into()],
+            shared_workspace: true,
+        };
+
+        let (cmd, shell) = target_command(dir.path(), &target, Some("npm run dev")).unwrap();
+        let argv: Vec<String> = cmd.get_argv().iter().map(|a| a.to_string_lossy().to_string()).collect();
+        assert_eq!(argv[..7], ["ssh", "-tt", "-p", "2222", "-o", "ServerAliveInterval=30", "dev@devbox"]);
+        assert_eq!(argv[7], "export API_URL='http://a b'; export PATH='/srv/my app/.venv/bin':\"$PATH\"; cd '/srv/my app' && npm run dev");
+        assert_eq!(crate::tools::ShellType::from_program(&shell), crate::tools::ShellType::Bash);
+
+        // A remote checkout of its own would run commands against files the agent never edited
+        let unshared = TerminalTarget::Ssh { host: "devbox".into(), user: None, port: None, identity_file: None, dir: None, options: vec![], shared_workspace: false };
+        assert!(matches!(target_command(dir.path(), &unshared, None), Err(ShellError::Io(_))));
+    }
+
+    #[test]
+    fn test_docker_command() {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::create_dir(dir.path().join(".irongraph")).unwrap();
+        std::fs::write(dir.path().join(".irongraph/config.toml"), "[terminal]\npath_prepend = [\"node_modules/.bin\"]\n").unwrap();
+        let target = TerminalTarget::Docker {
+            container: Some("app-dev".into()),
+            user: Some("node".into()),
+            dir: Some("/app".into()),
+        };
+
+        let (cmd, _) = target_command(dir.path(), &target, None).unwrap();
+        let argv: Vec<String> = cmd.get_argv().iter().map(|a| a.to_string_lossy().to_string()).collect();
+        assert_eq!(argv[..10], ["docker", "exec", "-it", "-u", "node", "-w", "/app", "app-dev", "sh", "-c"]);
+        assert_eq!(argv[10], format!("export PATH=/app/node_modules/.bin:\"$PATH\"; {}", POSIX_LOGIN_SHELL));
+    }
+
+    #[test]
+    fn test_devcontainer_workspace_folder() {
+        let dir = tempfile::tempdir().unwrap();
+        let name = dir.path().canonicalize().unwrap().file_name().unwrap().to_string_lossy().to_string();
+        assert_eq!(devcontainer_workspace_folder(dir.path()), format!("/workspaces/{}", name));
+
+        std::fs::create_dir(dir.path().join(".devcontainer")).unwrap();
+        std::fs::write(dir.path().join(".devcontainer/devcontainer.json"), "{\n  // mounted by compose\n  \"workspaceFolder\": \"/src/${localWorkspaceFolderBasename}\",\n}\n").unwrap();
+        assert_eq!(devcontainer_workspace_folder(dir.path()), format!("/src/{}", name));
+    }
+
+    #[test]
+    fn test_command_dir() {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::create_dir(dir.path().join("web")).unwrap();
+        let local = command_dir(dir.path(), &TerminalTarget::Local, "web").unwrap();
+        assert_eq!(std::path::PathBuf::from(local), dir.path().canonicalize().unwrap().join("web"));
+        assert!(matches!(command_dir(dir.path(), &TerminalTarget::Local, "missing"), Err(ShellError::NotFound(_))));
+        assert!(matches!(command_dir(dir.path(), &TerminalTarget::Local, "../web"), Err(ShellError::Io(_))));
+
+        let ssh = TerminalTarget::Ssh { host: "devbox".into(), user: None, port: None, identity_file: None, dir: Some("/srv/app/".into()), options: vec![], shared_workspace: true };
+        assert_eq!(command_dir(dir.path(), &ssh, "./api/").unwrap(), "/srv/app/api");
+        assert!(command_dir(dir.path(), &ssh, "/etc").is_err());
+    }
+}
@@ -4,8 +4,10 @@ use schemars::JsonSchema;
 use serde::Deserialize;
 use std::sync::{Arc, Mutex};
 use tokio::sync::mpsc;
-use crate::{write_to_pty};
+use crate::{background, get_terminal_buffer, history, policy, interrupt_count, interrupt_foreground, restart_session, session_exit, write_raw, parse_sentinel_output, strip_ansi};
 use common::{get_session, RadkitState};
+use workspace_manager::WorkspaceConfig;
+use crate::target::command_dir;
 
 // Hack for missing to_value
 trait ToValueExt {
@@ -17,21 +19,105 @@ impl ToValueExt for schemars::schema::RootSchema {
     }
 }
 
+#[derive(Debug, Clone, Copy, PartialEq)]
 pub enum ShellType {
+    // sh, dash, ksh and anything else that takes POSIX syntax
+    Sh,
     Bash,
+    Zsh,
+    Fish,
+    Nushell,
     Cmd,
     PowerShell,
 }
 
+impl Default for ShellType {
+    fn default() -> Self {
+        if cfg!(target_os = "windows") { Self::Cmd } else { Self::Bash }
+    }
+}
+
 impl ShellType {
-    pub fn format_with_sentinel(&self, command: &str) -> String {
+    /// The shell family of a program path or name such as `/usr/bin/fish` or `pwsh.exe`.
+    /// Unknown shells are assumed to be POSIX.
+    pub fn from_program(program: &str) -> Self {
+        // Split by hand: Windows paths must parse on any host
+        let file = program.rsplit(['/', '\\']).next().unwrap_or(program).to_ascii_lowercase();
+        let name = file.strip_suffix(".exe").unwrap_or(&file);
+        match name {
+            "bash" => Self::Bash,
+            "zsh" => Self::Zsh,
+            "fish" => Self::Fish,
+            "nu" | "nushell" => Self::Nushell,
+            "cmd" => Self::Cmd,
+            "pwsh" | "powershell" => Self::PowerShell,
+            _ => Self::Sh,
+        }
+    }
+
+    /// Flags that skip the user's startup files, so a prompt or greeting from them can't end
+    /// up in captured output.
+    pub fn no_config_args(&self) -> &'static [&'static str] {
+        match self {
+            Self::Sh => &[],
+            Self::Bash => &["--noprofile", "--norc"],
+            Self::Zsh => &["-f"],
+            Self::Fish => &["--no-config"],
+            Self::Nushell => &["--no-config-file"],
+            Self::Cmd => &["/D"],
+            Self::PowerShell => &["-NoLogo", "-NoProfile"],
+        }
+    }
+
+    /// The flag that makes the shell run one command string and exit.
+    pub fn command_flag(&self) -> &'static str {
+        match self {
+            Self::Cmd => "/C",
+            Self::PowerShell => "-Command",
+            _ => "-c",
+        }
+    }
+
+    /// `command` followed by the sentinel carrying its exit code. With `cwd` the command runs
+    /// there and the shell goes back to its own directory afterwards.
+    pub fn format_with_sentinel(&self, command: &str, cwd: Option<&str>) -> String {
+        if let Some(dir) = cwd {
+            return self.format_in_dir(command, dir);
+        }
         match self {
             // Unix: Use semicolon and $?
-            Self::Bash => format!("{}; echo \"IRONGRAPH_CMD_DONE:$?\"\n", command),
+            Self::Sh | Self::Bash | Self::Zsh => format!("{}; echo \"IRONGRAPH_CMD_DONE:$?\"\n", command),
+            // Fish keeps the exit code in $status
+            Self::Fish => format!("{}; echo \"IRONGRAPH_CMD_DONE:$status\"\n", command),
+            // Nushell: string interpolation of $env.LAST_EXIT_CODE
+            Self::Nushell => format!("{}; print $\"IRONGRAPH_CMD_DONE:($env.LAST_EXIT_CODE)\"\n", command),
             // Windows CMD: Use ampersand and %ERRORLEVEL%
             Self::Cmd => format!("{} & echo IRONGRAPH_CMD_DONE:%ERRORLEVEL%\r\n", command),
-            // PowerShell: Use semicolon and $LASTEXITCODE
-            Self::PowerShell => format!("{}; Write-Host \"IRONGRAPH_CMD_DONE:$LASTEXITCODE\"\r\n", command),
+            // PowerShell: $LASTEXITCODE is only set by native programs, so a failed cmdlet
+            // falls back on $? (and a successful one must not report a stale code)
+            Self::PowerShell => format!("{}; Write-Host \"IRONGRAPH_CMD_DONE:$(if ($?) {{ 0 }} elseif ($LASTEXITCODE) {{ $LASTEXITCODE }} else {{ 1 }})\"\r\n", command),
+        }
+    }
+
+    // Enter `dir`, run, keep the exit code, leave, then print the sentinel with it
+    fn format_in_dir(&self, command: &str, dir: &str) -> String {
+        match self {
+            // A subshell leaves the shell's directory alone even if the command is interrupted
+            Self::Sh | Self::Bash | Self::Zsh => {
+                let dir = shlex::try_quote(dir).map_or_else(|_| dir.to_string(), |q| q.into_owned());
+                self.format_with_sentinel(&format!("(cd {} && {})", dir, command), None)
+            }
+            Self::Fish => {
+                let dir = shlex::try_quote(dir).map_or_else(|_| dir.to_string(), |q| q.into_owned());
+                format!("pushd {}; and {}; set -l irongraph_status $status; popd; echo \"IRONGRAPH_CMD_DONE:$irongraph_status\"\n", dir, command)
+            }
+            Self::Nushell => format!("cd '{}'; {}; let irongraph_status = $env.LAST_EXIT_CODE; cd -; print $\"IRONGRAPH_CMD_DONE:($irongraph_status)\"\n", dir, command),
+            Self::Cmd => format!("pushd \"{}\" && ({}) & echo IRONGRAPH_CMD_DONE:%ERRORLEVEL% & popd\r\n", dir, command),
+            Self::PowerShell => format!(
+                "Push-Location -LiteralPath '{}'; {}; $irongraphOk = $?; Pop-Location; Write-Host \"IRONGRAPH_CMD_DONE:$(if ($irongraphOk) {{ 0 }} elseif ($LASTEXITCODE) {{ $LASTEXITCODE }} else {{ 1 }})\"\r\n",
+                dir.replace('\'', "''"),
+                command,
+            ),
         }
     }
 }
@@ -86,34 +172,101 @@ fn get_state(ctx: &ToolContext) -> Result<std::sync::Arc<RadkitState>, String> {
     get_session(session_id).ok_or("Session expired or not found".to_string())
 }
 
+/// How long a timed-out command gets to exit after Ctrl-C before it is killed.
+const INTERRUPT_GRACE: std::time::Duration = std::time::Duration::from_secs(3);
+
+// How often a waiting run_command checks whether it was interrupted
+const INTERRUPT_POLL: std::time::Duration = std::time::Duration::from_millis(250);
+
+/// Most output run_command returns; earlier output is cut and left in the terminal buffer.
+pub const MAX_OUTPUT_BYTES: usize = 32 * 1024;
+
+/// The last `MAX_OUTPUT_BYTES` of a command's ANSI-stripped output, and whether any was cut.
+/// The end is kept since that's where errors and summaries are.
+pub fn tail_output(output: &str) -> (String, bool) {
+    let text = strip_ansi(output);
+    let text = text.trim();
+    if text.len() <= MAX_OUTPUT_BYTES {
+        return (text.to_string(), false);
+    }
+    let mut cut = text.len() - MAX_OUTPUT_BYTES;
+    while !text.is_char_boundary(cut) {
+        cut += 1;
+    }
+    (text[cut..].to_string(), true)
+}
+
+/// The exit code in a run_command result, for callers deciding what a command's outcome
+/// means. None for commands that didn't finish (timeouts, interrupts) and other tools.
+pub fn command_exit_code(result: &serde_json::Value) -> Option<i32> {
+    result.get("exit_code")?.as_i64().map(|code| code as i32)
+}
+
+/// The command line run_command runs for `program` and its shell-style `args`. The args are
+/// split and joined unquoted, so pipes, globs and variables in them reach the shell, and the
+/// command policy sees the line without the quotes that would otherwise hide a denied command.
+pub fn run_command_line(program: &str, args: Option<&str>) -> String {
+    let args_vec = shlex::split(args.unwrap_or_default()).unwrap_or_default();
+    if args_vec.is_empty() {
+        program.to_string()
+    } else {
+        format!("{} {}", program, args_vec.join(" "))
+    }
+}
+
 #[derive(Deserialize, JsonSchema)]
 pub struct RunCommandArgs {
     pub program: String,
     #[serde(default)]
     pub args: Option<String>,
+    /// Seconds to wait before interrupting the command; the workspace default when omitted
+    #[serde(default)]
+    pub timeout_secs: Option<u64>,
+    /// Directory to run in, relative to the workspace root; the terminal's current directory when omitted
+    #[serde(default)]
+    pub cwd: Option<String>,
 }
 
-#[tool(description = "Run a shell command. Use this for all execution.")]
+#[tool(description = "Run a shell command. Use this for all execution. Set timeout_secs for long builds or to fail fast on quick commands; a command that runs past it is interrupted. Returns JSON with status, exit_code, duration_ms and the last 32 KB of output; if truncated is true, read_terminal_buffer has the rest.")]
 pub async fn run_command(args: RunCommandArgs, ctx: &ToolContext<'_>) -> ToolResult {
     let state = match get_state(ctx) {
         Ok(s) => s,
         Err(e) => return ToolResult::error(e),
     };
 
-    let args_vec = shlex::split(&args.args.unwrap_or_default()).unwrap_or_default();
+    let cmd_str = run_command_line(&args.program, args.args.as_deref());
+    if let Err(refusal) = policy::authorize(&state.root, &state.session_id, &cmd_str) {
+        return ToolResult::error(refusal);
+    }
 
-    let cmd_str = if args_vec.is_empty() {
-        args.program.clone()
-    } else {
-        format!("{} {}", args.program, args_vec.join(" "))
-    };
+    let config = WorkspaceConfig::load(&state.root).terminal;
 
-    #[cfg(target_os = "windows")]
-    let shell_type = ShellType::Cmd;
-    #[cfg(not(target_os = "windows"))]
-    let shell_type = ShellType::Bash;
+    // A shell that exited (`exit`, an OOM kill) can't run anything; start a new one if allowed
+    let mut restarted = None;
+    if let Some(exit) = session_exit(&state.terminal_state, &state.session_id) {
+        if !config.auto_restart {
+            return ToolResult::error(format!("Error: Shell terminated ({}) and `[terminal] auto_restart` is off", exit.describe()));
+        }
+        if let Err(e) = restart_session(&state.terminal_state, &state.session_id) {
+            return ToolResult::error(format!("Error: Shell terminated ({}) and could not be restarted: {}", exit.describe(), e));
+        }
+        restarted = Some(exit);
+    }
 
-    let sentinel_cmd = shell_type.format_with_sentinel(&cmd_str);
+    let shell_type = state.terminal_state.sessions.lock().unwrap()
+        .get(&state.session_id)
+        .map(|session| ShellType::from_program(&session.lock().unwrap().shell))
+        .unwrap_or_default();
+
+    let cwd = match &args.cwd {
+        Some(cwd) => match command_dir(&state.root, &config.target, cwd) {
+            Ok(dir) => Some(dir),
+            Err(e) => return ToolResult::error(format!("Error: {}", e)),
+        },
+        None => None,
+    };
+
+    let sentinel_cmd = shell_type.format_with_sentinel(&cmd_str, cwd.as_deref());
 
     // Setup interception
     let (tx, mut rx) = mpsc::channel(100);
@@ -122,58 +275,227 @@ pub async fn run_command(args: RunCommandArgs, ctx: &ToolContext<'_>) -> ToolRes
         *buf_lock = Some(tx);
     }
 
-    if let Err(e) = write_to_pty(&state.terminal_state, &state.session_id, &sentinel_cmd) {
+    if let Err(e) = write_raw(&state.terminal_state, &state.session_id, &sentinel_cmd) {
          return ToolResult::error(format!("Error writing to PTY: {}", e));
     }
+    let started = std::time::Instant::now();
+    let started_at = history::now_millis();
+    let record = |duration_ms: Option<u64>, exit_code: Option<i32>| history::record(&state.session_id, history::CommandRecord {
+        command: cmd_str.clone(),
+        source: history::CommandSource::Agent,
+        started_at,
+        duration_ms,
+        exit_code,
+    });
 
+    let timeout_secs = config.command_timeout(args.timeout_secs);
+    let deadline = tokio::time::Instant::now() + std::time::Duration::from_secs(timeout_secs);
     let mut output = String::new();
-    let start = std::time::Instant::now();
-    let timeout = std::time::Duration::from_secs(60); // 60s timeout
+
+    let interrupts = interrupt_count(&state.session_id);
 
     loop {
-         let chunk = match tokio::time::timeout(std::time::Duration::from_secs(5), rx.recv()).await {
+         let poll = (tokio::time::Instant::now() + INTERRUPT_POLL).min(deadline);
+         let chunk = match tokio::time::timeout_at(poll, rx.recv()).await {
              Ok(Some(s)) => s,
              Ok(None) => break, // Channel closed
+             // Stopped with interrupt_terminal; the sentinel was cancelled along with it
+             Err(_) if interrupt_count(&state.session_id) != interrupts => {
+                 record(None, None);
+                 *state.command_buffer.lock().unwrap() = None;
+                 let (output, truncated) = tail_output(&output);
+                 return ToolResult::success(serde_json::json!({
+                     "status": "interrupted",
+                     "duration_ms": started.elapsed().as_millis() as u64,
+                     "truncated": truncated,
+                     "output": output,
+                 }));
+             }
+             Err(_) if session_exit(&state.terminal_state, &state.session_id).is_some() => {
+                 record(None, None);
+                 *state.command_buffer.lock().unwrap() = None;
+                 let exit = session_exit(&state.terminal_state, &state.session_id).unwrap();
+                 return ToolResult::error(format!(
+                     "Error: Shell terminated ({}) while running the command; the next command starts a new one. Output:\n{}",
+                     exit.describe(),
+                     tail_output(&output).0,
+                 ));
+             }
+             Err(_) if tokio::time::Instant::now() < deadline => continue,
              Err(_) => {
-                 if start.elapsed() > timeout {
-                     output.push_str("\n[IronGraph: Timeout waiting for sentinel]");
-                     break;
-                 }
-                 continue;
+                 // Free the session for the next command: Ctrl-C, then kill if it won't stop
+                 record(None, None);
+                 *state.command_buffer.lock().unwrap() = None;
+                 let terminal_state = state.terminal_state.clone();
+                 let session_id = state.session_id.clone();
+                 let terminated = tokio::task::spawn_blocking(move || interrupt_foreground(&terminal_state, &session_id, INTERRUPT_GRACE))
+                     .await
+                     .map_err(|e| e.to_string())
+                     .and_then(|r| r.map_err(|e| e.to_string()));
+                 let (output, truncated) = tail_output(&output);
+                 return ToolResult::success(serde_json::json!({
+                     "status": "timeout",
+                     "timeout_secs": timeout_secs,
+                     "duration_ms": started.elapsed().as_millis() as u64,
+                     "terminated": match terminated {
+                         Ok(t) => serde_json::json!(t),
+                         Err(e) => serde_json::json!({ "error": e }),
+                     },
+                     "truncated": truncated,
+                     "output": output,
+                 }));
              }
          };
 
          output.push_str(&chunk);
 
-         if let Some(idx) = output.find("IRONGRAPH_CMD_DONE:") {
-             let ret = output[..idx].to_string();
-             let rest = &output[idx..];
-             let code_str = rest.trim_start_matches("IRONGRAPH_CMD_DONE:").trim();
-             let exit_code = code_str.parse::<i32>().unwrap_or(1);
-
+         if let Some((ret, exit_code)) = parse_sentinel_output(&output) {
+             let duration_ms = started.elapsed().as_millis() as u64;
+             record(Some(duration_ms), Some(exit_code));
              // Cleanup
              {
                  let mut buf_lock = state.command_buffer.lock().unwrap();
                  *buf_lock = None;
              }
 
-             let mut final_output = format!("{}\n(Exit Code: {})", ret.trim(), exit_code);
-
+             // The frontend got the raw stream; the model only needs the text
+             let (text, truncated) = tail_output(&ret);
+             let mut result = serde_json::json!({
+                 "status": "completed",
+                 "exit_code": exit_code,
+                 "duration_ms": duration_ms,
+                 "truncated": truncated,
+                 "output": text,
+             });
+             if let Some(exit) = &restarted {
+                 result["note"] = format!("The previous shell had terminated ({}); this ran in a new one", exit.describe()).into();
+             }
              if exit_code != 0 {
-                 if let Some(debug_ctx) = try_parse_error_context(&state.root, &ret) {
-                     final_output.push_str(&format!("\n\n[Auto-Debug] Context:\n{}", debug_ctx));
+                 if let Some(debug_ctx) = try_parse_error_context(&state.root, &strip_ansi(&ret)) {
+                     result["debug_context"] = debug_ctx.into();
                  }
              }
 
-             return ToolResult::success(final_output.into());
+             return ToolResult::success(result);
          }
     }
 
-    // Cleanup if timeout or break
+    // Cleanup if the session closed
+    record(None, None);
     {
         let mut buf_lock = state.command_buffer.lock().unwrap();
         *buf_lock = None;
     }
 
-    ToolResult::success(output.into())
+    let (output, truncated) = tail_output(&output);
+    ToolResult::success(serde_json::json!({
+        "status": "closed",
+        "duration_ms": started.elapsed().as_millis() as u64,
+        "truncated": truncated,
+        "output": output,
+    }))
+}
+
+#[derive(Deserialize, JsonSchema)]
+pub struct TerminalBufferArgs {
+    /// How much of the most recent output to return, in bytes (default 16384)
+    pub last_n_bytes: Option<usize>,
+}
+
+#[tool(description = "Re-read the recent output of your terminal, e.g. the full log of an earlier command that was truncated.")]
+pub async fn read_terminal_buffer(args: TerminalBufferArgs, ctx: &ToolContext<'_>) -> ToolResult {
+    let state = match get_state(ctx) {
+        Ok(s) => s,
+        Err(e) => return ToolResult::error(e),
+    };
+    match get_terminal_buffer(&state.terminal_state, &state.session_id, Some(args.last_n_bytes.unwrap_or(16 * 1024))) {
+        Ok(text) => ToolResult::success(strip_ansi(&text).replace("\r\n", "\n").into()),
+        Err(e) => ToolResult::error(format!("Error: {}", e)),
+    }
+}
+
+#[derive(Deserialize, JsonSchema)]
+pub struct InterruptTerminalArgs {}
+
+#[tool(description = "Send Ctrl-C to whatever is running in your terminal (killing it if it ignores that), without closing the terminal.")]
+pub async fn interrupt_terminal(_args: InterruptTerminalArgs, ctx: &ToolContext<'_>) -> ToolResult {
+    let state = match get_state(ctx) {
+        Ok(s) => s,
+        Err(e) => return ToolResult::error(e),
+    };
+    let terminal_state = state.terminal_state.clone();
+    let session_id = state.session_id.clone();
+    match tokio::task::spawn_blocking(move || interrupt_foreground(&terminal_state, &session_id, INTERRUPT_GRACE)).await {
+        Ok(Ok(interrupted)) => ToolResult::success(serde_json::json!(interrupted)),
+        Ok(Err(e)) => ToolResult::error(format!("Error: {}", e)),
+        Err(e) => ToolResult::error(format!("Error: {}", e)),
+    }
+}
+
+fn format_background(out: &background::BackgroundOutput) -> String {
+    let status = match out.exit_code {
+        Some(code) => format!("exited with code {}", code),
+        None => format!("running for {}s", out.uptime_secs),
+    };
+    let mut text = format!("[{}] `{}` {}\n", out.id, out.command, status);
+    if out.skipped_bytes > 0 {
+        text.push_str(&format!("... {} bytes of earlier output dropped ...\n", out.skipped_bytes));
+    }
+    let output = out.output.trim_end();
+    text.push_str(if output.is_empty() { "(no new output)" } else { output });
+    text
+}
+
+#[derive(Deserialize, JsonSchema)]
+pub struct StartBackgroundArgs {
+    /// Shell command line, e.g. "npm run dev"
+    pub command: String,
+}
+
+#[tool(description = "Start a long-running command such as a dev server or file watcher without waiting for it to finish. Returns an id for get_background_output and stop_background.")]
+pub async fn start_background(args: StartBackgroundArgs, ctx: &ToolContext<'_>) -> ToolResult {
+    let state = match get_state(ctx) {
+        Ok(s) => s,
+        Err(e) => return ToolResult::error(e),
+    };
+    if let Err(refusal) = policy::authorize(&state.root, &state.session_id, &args.command) {
+        return ToolResult::error(refusal);
+    }
+    match background::start(&state.root, &state.session_id, &args.command) {
+        Ok(id) => ToolResult::success(format!("Started `{}` as {}", args.command, id).into()),
+        Err(e) => ToolResult::error(format!("Error: {}", e)),
+    }
+}
+
+#[derive(Deserialize, JsonSchema)]
+pub struct BackgroundIdArgs {
+    /// Id returned by start_background
+    pub id: String,
+}
+
+#[tool(description = "Show whether a background process is still running and the output it printed since the last call.")]
+pub async fn get_background_output(args: BackgroundIdArgs, ctx: &ToolContext<'_>) -> ToolResult {
+    let state = match get_state(ctx) {
+        Ok(s) => s,
+        Err(e) => return ToolResult::error(e),
+    };
+    match background::output(&state.session_id, &args.id) {
+        Ok(out) => ToolResult::success(format_background(&out).into()),
+        Err(e) => ToolResult::error(format!("Error: {}", e)),
+    }
+}
+
+#[tool(description = "Stop a background process (Ctrl-C, then kill) and return its remaining output.")]
+pub async fn stop_background(args: BackgroundIdArgs, ctx: &ToolContext<'_>) -> ToolResult {
+    let state = match get_state(ctx) {
+        Ok(s) => s,
+        Err(e) => return ToolResult::error(e),
+    };
+    let session_id = state.session_id.clone();
+    // Waits out the grace period, so keep it off the async workers
+    match tokio::task::spawn_blocking(move || background::stop(&session_id, &args.id)).await {
+        Ok(Ok(out)) => ToolResult::success(format_background(&out).into()),
+        Ok(Err(e)) => ToolResult::error(format!("Error: {}", e)),
+        Err(e) => ToolResult::error(format!("Error: {}", e)),
+    }
 }
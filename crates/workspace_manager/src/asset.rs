@@ -0,0 +1,124 @@
+use std::path::{Path, PathBuf};
+use std::sync::OnceLock;
+
+use base64::Engine;
+use regex::Regex;
+
+use crate::{resolve_path_safe, FsError};
+
+// Images and SVGs as base64 with their MIME type and pixel size, so a multimodal model can
+// be shown the icon or screenshot it is asked to change or recreate.
+
+/// Largest asset `read_asset` returns; bigger files would swamp the context.
+pub const MAX_ASSET_BYTES: u64 = 5 * 1024 * 1024;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Asset {
+    pub path: PathBuf,
+    pub mime: &'static str,
+    // None when the header (or the SVG's attributes) doesn't say
+    pub width: Option<u32>,
+    pub height: Option<u32>,
+    pub size: u64,
+    pub base64: String,
+}
+
+pub fn asset_mime(path: &Path) -> Option<&'static str> {
+    let ext = path.extension()?.to_str()?.to_ascii_lowercase();
+    Some(match ext.as_str() {
+        "png" => "image/png",
+        "jpg" | "jpeg" => "image/jpeg",
+        "gif" => "image/gif",
+        "webp" => "image/webp",
+        "bmp" => "image/bmp",
+        "ico" => "image/x-icon",
+        "svg" => "image/svg+xml",
+        _ => return None,
+    })
+}
+
+fn svg_attr(tag: &str, name: &str) -> Option<f64> {
+    let re = Regex::new(&format!(r#"\s{}\s*=\s*["']\s*([0-9.]+)(?:px)?\s*["']"#, name)).ok()?;
+    re.captures(tag)?[1].parse().ok()
+}
+
+// From width/height on the root element, falling back to the viewBox
+fn svg_size(content: &str) -> Option<(u32, u32)> {
+    static ROOT: OnceLock<Regex> = OnceLock::new();
+    static VIEW_BOX: OnceLock<Regex> = OnceLock::new();
+    let root = ROOT.get_or_init(|| Regex::new(r"(?s)<svg\b[^>]*>").unwrap());
+    let tag = root.find(content)?.as_str();
+    if let (Some(w), Some(h)) = (svg_attr(tag, "width"), svg_attr(tag, "height")) {
+        return Some((w.round() as u32, h.round() as u32));
+    }
+    let view_box = VIEW_BOX.get_or_init(|| Regex::new(r#"viewBox\s*=\s*["']\s*[-0-9.]+[\s,]+[-0-9.]+[\s,]+([0-9.]+)[\s,]+([0-9.]+)\s*["']"#).unwrap());
+    let caps = view_box.captures(tag)?;
+    let (w, h): (f64, f64) = (caps[1].parse().ok()?, caps[2].parse().ok()?);
+    Some((w.round() as u32, h.round() as u32))
+}
+
+/// Reads an image or SVG under the size limit as base64.
+pub fn read_asset(root: &Path, file_path: &str) -> Result<Asset, FsError> {
+    let full_path = resolve_path_safe(root, file_path, true)?;
+    let mime = asset_mime(&full_path)
+        .ok_or_else(|| FsError::Edit(format!("{} is not a supported image type (png, jpeg, gif, webp, bmp, ico, svg)", file_path)))?;
+    let size = std::fs::metadata(&full_path)?.len();
+    if size > MAX_ASSET_BYTES {
+        return Err(FsError::Edit(format!("{} is {} KB; assets are limited to {} KB", file_path, size.div_ceil(1024), MAX_ASSET_BYTES / 1024)));
+    }
+
+    let bytes = std::fs::read(&full_path)?;
+    let dimensions = if mime == "image/svg+xml" {
+        svg_size(&String::from_utf8_lossy(&bytes))
+    } else {
+        imagesize::blob_size(&bytes).ok().map(|s| (s.width as u32, s.height as u32))
+    };
+    Ok(Asset {
+        path: PathBuf::from(file_path),
+        mime,
+        width: dimensions.map(|(w, _)| w),
+        height: dimensions.map(|(_, h)| h),
+        size,
+        base64: base64::engine::general_purpose::STANDARD.encode(&bytes),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::tempdir;
+
+    // 1x1 transparent PNG
+    const PIXEL: &[u8] = &[
+        0x89, 0x50, 0x4e, 0x47, 0x0d, 0x0a, 0x1a, 0x0a, 0x00, 0x00, 0x00, 0x0d, 0x49, 0x48, 0x44, 0x52,
+        0x00, 0x00, 0x00, 0x01, 0x00, 0x00, 0x00, 0x01, 0x08, 0x06, 0x00, 0x00, 0x00, 0x1f, 0x15, 0xc4,
+        0x89, 0x00, 0x00, 0x00, 0x0d, 0x49, 0x44, 0x41, 0x54, 0x78, 0x9c, 0x63, 0x00, 0x01, 0x00, 0x00,
+        0x05, 0x00, 0x01, 0x0d, 0x0a, 0x2d, 0xb4, 0x00, 0x00, 0x00, 0x00, 0x49, 0x45, 0x4e, 0x44, 0xae,
+        0x42, 0x60, 0x82,
+    ];
+
+    #[test]
+    fn test_read_asset() {
+        let dir = tempdir().unwrap();
+        let root = dir.path();
+        std::fs::write(root.join("pixel.png"), PIXEL).unwrap();
+        std::fs::write(root.join("icon.svg"), "<?xml version=\"1.0\"?>\n<svg xmlns=\"http://www.w3.org/2000/svg\"\n  viewBox=\"0 0 24 16\"><path d=\"M0 0h24\"/></svg>").unwrap();
+        std::fs::write(root.join("notes.txt"), "hi").unwrap();
+
+        let png = read_asset(root, "pixel.png").unwrap();
+        assert_eq!((png.mime, png.width, png.height, png.size), ("image/png", Some(1), Some(1), PIXEL.len() as u64));
+        assert!(png.base64.starts_with("iVBORw0KGgo"));
+
+        let svg = read_asset(root, "icon.svg").unwrap();
+        assert_eq!((svg.mime, svg.width, svg.height), ("image/svg+xml", Some(24), Some(16)));
+
+        assert!(matches!(read_asset(root, "notes.txt"), Err(FsError::Edit(_))));
+        assert!(read_asset(root, "../pixel.png").is_err());
+    }
+
+    #[test]
+    fn test_svg_size_prefers_attributes() {
+        assert_eq!(svg_size("<svg width=\"32px\" height='48' viewBox=\"0 0 16 16\">"), Some((32, 48)));
+        assert_eq!(svg_size("<svg><rect width=\"10\" height=\"10\"/></svg>"), None);
+    }
+}
@@ -0,0 +1,105 @@
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use crate::{read_text, resolve_path_safe, FileContent, FsError};
+
+// Every agent write replaces the file atomically and first copies the old content to
+// `.irongraph/backups/<path>`. Only the latest previous version is kept per file.
+
+pub const BACKUP_DIR: &str = ".irongraph/backups";
+
+static TEMP_COUNTER: AtomicUsize = AtomicUsize::new(0);
+
+/// Writes through a temp file in the same directory and a rename, so a crash leaves
+/// either the old or the new content. Existing permissions are kept.
+pub fn atomic_write(path: &Path, bytes: &[u8]) -> std::io::Result<()> {
+    let dir = path.parent().unwrap_or(Path::new("."));
+    let name = path.file_name().map_or_else(|| "file".into(), |n| n.to_string_lossy());
+    let tmp = dir.join(format!(".{}.{}-{}.tmp", name, std::process::id(), TEMP_COUNTER.fetch_add(1, Ordering::Relaxed)));
+
+    let result = (|| {
+        let mut file = std::fs::File::create(&tmp)?;
+        file.write_all(bytes)?;
+        file.sync_all()?;
+        if let Ok(meta) = std::fs::metadata(path) {
+            std::fs::set_permissions(&tmp, meta.permissions())?;
+        }
+        std::fs::rename(&tmp, path)
+    })();
+    if result.is_err() {
+        let _ = std::fs::remove_file(&tmp);
+    }
+    result
+}
+
+fn backup_path(root: &Path, rel: &Path) -> PathBuf {
+    root.join(BACKUP_DIR).join(rel)
+}
+
+/// Copies the current content of `rel`, if it exists, over its previous backup.
+pub fn backup_file(root: &Path, rel: &Path) -> std::io::Result<()> {
+    let current = root.join(rel);
+    if rel.starts_with(".irongraph") || !current.is_file() {
+        return Ok(());
+    }
+    let backup = backup_path(root, rel);
+    if let Some(parent) = backup.parent() {
+        std::fs::create_dir_all(parent)?;
+    }
+    std::fs::copy(&current, &backup).map(|_| ())
+}
+
+/// Puts the backup of `file_path` back in place. The replaced content becomes the new
+/// backup, so restoring twice undoes the restore.
+pub fn restore_backup_internal(root: &Path, file_path: String) -> Result<FileContent, FsError> {
+    let full_path = resolve_path_safe(root, &file_path, false)?;
+    let backup = backup_path(root, Path::new(&file_path));
+    let previous = std::fs::read(&backup)
+        .map_err(|_| FsError::Edit(format!("no backup of {}", file_path)))?;
+
+    if let Some(parent) = full_path.parent() {
+        std::fs::create_dir_all(parent).map_err(FsError::Io)?;
+    }
+    match std::fs::read(&full_path) {
+        Ok(current) => atomic_write(&backup, &current).map_err(FsError::Io)?,
+        Err(_) => std::fs::remove_file(&backup).map_err(FsError::Io)?,
+    }
+    atomic_write(&full_path, &previous).map_err(FsError::Io)?;
+
+    Ok(FileContent { path: PathBuf::from(file_path), content: read_text(&full_path)? })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::write_file_internal;
+    use tempfile::tempdir;
+
+    #[test]
+    fn test_backup_and_restore() {
+        let dir = tempdir().unwrap();
+        let root = dir.path();
+        write_file_internal(root, "notes.txt".into(), "one\n".into(), false).unwrap();
+        assert!(!root.join(BACKUP_DIR).join("notes.txt").exists());
+
+        write_file_internal(root, "notes.txt".into(), "two\n".into(), false).unwrap();
+        assert_eq!(std::fs::read_to_string(root.join(BACKUP_DIR).join("notes.txt")).unwrap(), "one\n");
+
+        assert_eq!(restore_backup_internal(root, "notes.txt".into()).unwrap().content, "one\n");
+        assert_eq!(restore_backup_internal(root, "notes.txt".into()).unwrap().content, "two\n");
+        assert!(matches!(restore_backup_internal(root, "missing.txt".into()), Err(FsError::Edit(_))));
+    }
+
+    #[test]
+    fn test_atomic_write_leaves_no_temp_files() {
+        let dir = tempdir().unwrap();
+        let path = dir.path().join("a.txt");
+        atomic_write(&path, b"first").unwrap();
+        atomic_write(&path, b"second").unwrap();
+
+        assert_eq!(std::fs::read(&path).unwrap(), b"second");
+        let names: Vec<_> = std::fs::read_dir(dir.path()).unwrap().map(|e| e.unwrap().file_name()).collect();
+        assert_eq!(names, vec![std::ffi::OsString::from("a.txt")]);
+    }
+}
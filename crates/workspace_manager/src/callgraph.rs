@@ -0,0 +1,273 @@
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use petgraph::graph::{DiGraph, NodeIndex};
+use petgraph::visit::{Bfs, Reversed};
+use syn::punctuated::Punctuated;
+use syn::visit::{self, Visit};
+
+use crate::ignores::walker;
+
+// Function-level call graph for the Rust files in a workspace. Calls are resolved by name
+// only: `Type::f()` prefers functions defined in `impl Type`, anything else (including
+// every method call) links to all functions of that name. That over-approximates, which
+// is the safe direction for picking tests to re-run.
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct FunctionInfo {
+    // `Type::method` inside impls and traits, otherwise the bare name
+    pub name: String,
+    pub path: PathBuf,
+    // 1-based line of the name
+    pub line: usize,
+    // A #[test] function or anything inside a #[cfg(test)] module
+    pub is_test: bool,
+}
+
+pub struct CallGraph {
+    // Edge caller -> callee
+    graph: DiGraph<FunctionInfo, ()>,
+}
+
+struct Call {
+    caller: usize,
+    name: String,
+    qualifier: Option<String>,
+}
+
+struct Collector<'a> {
+    path: &'a Path,
+    in_test: bool,
+    self_ty: Option<String>,
+    current: Option<usize>,
+    defs: Vec<(String, FunctionInfo)>,
+    calls: Vec<Call>,
+}
+
+fn is_test_attr(attrs: &[syn::Attribute]) -> bool {
+    attrs.iter().any(|a| a.path().segments.last().map_or(false, |s| s.ident == "test"))
+}
+
+fn is_cfg_test(attrs: &[syn::Attribute]) -> bool {
+    attrs.iter().any(|a| {
+        a.path().is_ident("cfg") && a.parse_args::<syn::Ident>().map_or(false, |i| i == "test")
+    })
+}
+
+impl Collector<'_> {
+    fn function(&mut self, ident: &syn::Ident, attrs: &[syn::Attribute], body: impl FnOnce(&mut Self)) {
+        let name = match &self.self_ty {
+            Some(ty) => format!("{}::{}", ty, ident),
+            None => ident.to_string(),
+        };
+        self.defs.push((ident.to_string(), FunctionInfo {
+            name,
+            path: self.path.to_path_buf(),
+            line: ident.span().start().line,
+            is_test: self.in_test || is_test_attr(attrs),
+        }));
+        let outer = self.current.replace(self.defs.len() - 1);
+        body(self);
+        self.current = outer;
+    }
+
+    fn call(&mut self, name: String, qualifier: Option<String>) {
+        if let Some(caller) = self.current {
+            let qualifier = match qualifier.as_deref() {
+                Some("Self") => self.self_ty.clone(),
+                _ => qualifier,
+            };
+            self.calls.push(Call { caller, name, qualifier });
+        }
+    }
+
+    fn with_self_ty(&mut self, ty: Option<String>, body: impl FnOnce(&mut Self)) {
+        let outer = std::mem::replace(&mut self.self_ty, ty);
+        body(self);
+        self.self_ty = outer;
+    }
+}
+
+impl<'ast> Visit<'ast> for Collector<'_> {
+    fn visit_item_mod(&mut self, i: &'ast syn::ItemMod) {
+        let outer = self.in_test;
+        self.in_test |= is_cfg_test(&i.attrs);
+        visit::visit_item_mod(self, i);
+        self.in_test = outer;
+    }
+
+    fn visit_item_impl(&mut self, i: &'ast syn::ItemImpl) {
+        let ty = match &*i.self_ty {
+            syn::Type::Path(p) => p.path.segments.last().map(|s| s.ident.to_string()),
+            _ => None,
+        };
+        self.with_self_ty(ty, |this| visit::visit_item_impl(this, i));
+    }
+
+    fn visit_item_trait(&mut self, i: &'ast syn::ItemTrait) {
+        self.with_self_ty(Some(i.ident.to_string()), |this| visit::visit_item_trait(this, i));
+    }
+
+    fn visit_item_fn(&mut self, i: &'ast syn::ItemFn) {
+        // Nested free functions are not methods of the enclosing impl
+        self.with_self_ty(None, |this| {
+            this.function(&i.sig.ident, &i.attrs, |this| visit::visit_item_fn(this, i));
+        });
+    }
+
+    fn visit_impl_item_fn(&mut self, i: &'ast syn::ImplItemFn) {
+        self.function(&i.sig.ident, &i.attrs, |this| visit::visit_impl_item_fn(this, i));
+    }
+
+    fn visit_trait_item_fn(&mut self, i: &'ast syn::TraitItemFn) {
+        if i.default.is_some() {
+            self.function(&i.sig.ident, &i.attrs, |this| visit::visit_trait_item_fn(this, i));
+        }
+    }
+
+    fn visit_expr_call(&mut self, i: &'ast syn::ExprCall) {
+        if let syn::Expr::Path(p) = &*i.func {
+            let segments: Vec<String> = p.path.segments.iter().map(|s| s.ident.to_string()).collect();
+            if let Some(name) = segments.last() {
+                let qualifier = segments.len().checked_sub(2).map(|q| segments[q].clone());
+                self.call(name.clone(), qualifier);
+            }
+        }
+        visit::visit_expr_call(self, i);
+    }
+
+    fn visit_expr_method_call(&mut self, i: &'ast syn::ExprMethodCall) {
+        self.call(i.method.to_string(), None);
+        visit::visit_expr_method_call(self, i);
+    }
+
+    // Macro arguments are unparsed tokens; most call-heavy macros (assert_eq!, println!,
+    // vec!) take comma-separated expressions, so try reading them that way
+    fn visit_macro(&mut self, i: &'ast syn::Macro) {
+        if let Ok(args) = i.parse_body_with(Punctuated::<syn::Expr, syn::Token![,]>::parse_terminated) {
+            for expr in &args {
+                self.visit_expr(expr);
+            }
+        }
+    }
+}
+
+impl CallGraph {
+    pub fn build(root: &Path) -> Self {
+        let mut defs = Vec::new();
+        let mut calls = Vec::new();
+        for entry in walker(root).build().flatten() {
+            let path = entry.path();
+            if path.extension().map_or(true, |e| e != "rs") {
+                continue;
+            }
+            let Ok(content) = std::fs::read_to_string(path) else { continue };
+            let Ok(file) = syn::parse_file(&content) else { continue };
+            let rel = path.strip_prefix(root).unwrap_or(path);
+            let mut collector = Collector { path: rel, in_test: false, self_ty: None, current: None, defs: Vec::new(), calls: Vec::new() };
+            collector.visit_file(&file);
+
+            let offset = defs.len();
+            defs.extend(collector.defs);
+            calls.extend(collector.calls.into_iter().map(|c| Call { caller: c.caller + offset, ..c }));
+        }
+
+        let mut by_ident: HashMap<String, Vec<usize>> = HashMap::new();
+        let mut by_name: HashMap<String, Vec<usize>> = HashMap::new();
+        let mut graph = DiGraph::new();
+        for (i, (ident, info)) in defs.into_iter().enumerate() {
+            by_ident.entry(ident).or_default().push(i);
+            by_name.entry(info.name.clone()).or_default().push(i);
+            graph.add_node(info);
+        }
+        for call in calls {
+            let qualified = call.qualifier.map(|q| format!("{}::{}", q, call.name));
+            let targets = qualified.and_then(|q| by_name.get(&q)).or_else(|| by_ident.get(&call.name));
+            for &callee in targets.into_iter().flatten() {
+                if callee != call.caller {
+                    graph.update_edge(NodeIndex::new(call.caller), NodeIndex::new(callee), ());
+                }
+            }
+        }
+        CallGraph { graph }
+    }
+
+    /// Every function that transitively calls `function` (a bare or `Type::`-qualified
+    /// name), in path and line order. Empty when no function has that name.
+    pub fn impact_of(&self, function: &str) -> Vec<FunctionInfo> {
+        let targets: Vec<NodeIndex> = self.graph.node_indices()
+            .filter(|&n| {
+                let info = &self.graph[n];
+                info.name == function || info.name.rsplit("::").next() == Some(function)
+            })
+            .collect();
+
+        let reversed = Reversed(&self.graph);
+        let mut seen = vec![false; self.graph.node_count()];
+        for &target in &targets {
+            let mut bfs = Bfs::new(reversed, target);
+            while let Some(n) = bfs.next(reversed) {
+                seen[n.index()] = true;
+            }
+        }
+        for target in targets {
+            seen[target.index()] = false;
+        }
+
+        let mut affected: Vec<FunctionInfo> = self.graph.node_indices()
+            .filter(|n| seen[n.index()])
+            .map(|n| self.graph[n].clone())
+            .collect();
+        affected.sort_by(|a, b| (&a.path, a.line).cmp(&(&b.path, b.line)));
+        affected
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::tempdir;
+
+    #[test]
+    fn test_impact_of() {
+        let dir = tempdir().unwrap();
+        let root = dir.path();
+        std::fs::create_dir(root.join("src")).unwrap();
+        std::fs::write(root.join("src/lib.rs"), r#"
+pub struct Parser;
+
+impl Parser {
+    pub fn parse(&self) -> u32 { tokenize() }
+    pub fn new() -> Self { Parser }
+}
+
+fn tokenize() -> u32 { 1 }
+
+pub fn run() -> u32 {
+    Parser::new().parse()
+}
+
+pub fn unrelated() {}
+
+#[cfg(test)]
+mod tests {
+    #[test]
+    fn runs() {
+        assert_eq!(super::run(), 1);
+    }
+}
+"#).unwrap();
+
+        let graph = CallGraph::build(root);
+        let names: Vec<(String, bool)> = graph.impact_of("tokenize").into_iter().map(|f| (f.name, f.is_test)).collect();
+        assert_eq!(names, vec![
+            ("Parser::parse".to_string(), false),
+            ("run".to_string(), false),
+            ("runs".to_string(), true),
+        ]);
+
+        let impact = graph.impact_of("Parser::new");
+        assert_eq!(impact.iter().map(|f| f.name.as_str()).collect::<Vec<_>>(), vec!["run", "runs"]);
+        assert_eq!(impact[0].line, 11);
+        assert!(graph.impact_of("unrelated").is_empty());
+    }
+}
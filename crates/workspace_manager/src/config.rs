@@ -0,0 +1,150 @@
+use std::collections::BTreeMap;
+use std::path::Path;
+use serde::{Deserialize, Serialize};
+
+// Per-workspace settings, read from `.irongraph/config.toml` in the workspace root. A
+// missing or unreadable file means the defaults.
+
+pub const CONFIG_PATH: &str = ".irongraph/config.toml";
+
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+#[serde(default)]
+pub struct WorkspaceConfig {
+    // Syntax-check agent writes; a single write can still opt out with `skip_validation`
+    pub validate_on_write: bool,
+    // Run rustfmt / the project's prettier after agent writes
+    pub format_on_write: bool,
+    // Keep a trigram index of the workspace so search_code can skip most files; worth it
+    // on very large repositories
+    pub search_index: bool,
+    pub terminal: TerminalConfig,
+}
+
+// `[terminal]`: environment for the agent's shells, e.g. a virtualenv or a local toolchain
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+#[serde(default)]
+pub struct TerminalConfig {
+    // Shell program, e.g. "zsh" or "/usr/local/bin/fish"; None means the user's login shell
+    pub shell: Option<String>,
+    // Set on top of the inherited environment
+    pub env: BTreeMap<String, String>,
+    // Put in front of PATH in this order; relative entries are resolved against the root
+    pub path_prepend: Vec<String>,
+    // How long a command may run when run_command isn't given a timeout
+    pub command_timeout_secs: u64,
+    // Upper bound on the timeout run_command may ask for
+    pub max_command_timeout_secs: u64,
+    // Regexes for agent commands. A match in `deny` needs the user's approval; a non-empty
+    // `allow` also needs approval for anything it doesn't match
+    pub allow: Vec<String>,
+    pub deny: Vec<String>,
+    // Also deny the built-in dangerous patterns (rm -rf /, force pushes, curl | sh, ...)
+    pub default_deny: bool,
+    // Start a new shell when run_command finds the old one has exited
+    pub auto_restart: bool,
+    // Where the agent's terminal runs: this machine unless `[terminal.target]` says otherwise
+    pub target: TerminalTarget,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Deserialize, Serialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum TerminalTarget {
+    #[default]
+    Local,
+    // A remote dev box, reached with the system ssh client (so ~/.ssh/config, agent
+    // forwarding and known_hosts all apply)
+    Ssh {
+        host: String,
+        user: Option<String>,
+        port: Option<u16>,
+        identity_file: Option<String>,
+        // Working directory on the remote; its home when unset
+        dir: Option<String>,
+        // Extra `-o` options, e.g. "ServerAliveInterval=30"
+        #[serde(default)]
+        options: Vec<String>,
+        // Set when `dir` holds the same files as the local workspace (a network mount or a
+        // sync). The file tools only ever see the local copy, so the target is refused otherwise.
+        #[serde(default)]
+        shared_workspace: bool,
+    },
+    // A running container, entered with `docker exec`
+    Docker {
+        // Container name or id; the project's running devcontainer when unset
+        container: Option<String>,
+        user: Option<String>,
+        // Working directory in the container; the devcontainer's workspace folder when unset
+        dir: Option<String>,
+    },
+}
+
+impl Default for TerminalConfig {
+    fn default() -> Self {
+        Self {
+            shell: None,
+            env: BTreeMap::new(),
+            path_prepend: Vec::new(),
+            command_timeout_secs: 60,
+            max_command_timeout_secs: 600,
+            allow: Vec::new(),
+            deny: Vec::new(),
+            default_deny: true,
+            auto_restart: true,
+            target: TerminalTarget::Local,
+        }
+    }
+}
+
+impl TerminalConfig {
+    /// The timeout for a command that asked for `requested` seconds: the default when it
+    /// didn't ask, capped at the maximum, and at least one second.
+    pub fn command_timeout(&self, requested: Option<u64>) -> u64 {
+        requested.unwrap_or(self.command_timeout_secs)
+            .min(self.max_command_timeout_secs.max(self.command_timeout_secs))
+            .max(1)
+    }
+}
+
+impl Default for WorkspaceConfig {
+    fn default() -> Self {
+        Self { validate_on_write: true, format_on_write: false, search_index: false, terminal: TerminalConfig::default() }
+    }
+}
+
+impl WorkspaceConfig {
+    pub fn load(root: &Path) -> Self {
+        std::fs::read_to_string(root.join(CONFIG_PATH))
+            .ok()
+            .and_then(|s| toml::from_str(&s).ok())
+            .unwrap_or_default()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::tempdir;
+
+    #[test]
+    fn test_load_workspace_config() {
+        let dir = tempdir().unwrap();
+        assert_eq!(WorkspaceConfig::load(dir.path()), WorkspaceConfig::default());
+
+        std::fs::create_dir(dir.path().join(".irongraph")).unwrap();
+        std::fs::write(dir.path().join(CONFIG_PATH), "validate_on_write = false\n").unwrap();
+        assert_eq!(WorkspaceConfig::load(dir.path()), WorkspaceConfig { validate_on_write: false, format_on_write: false, search_index: false, terminal: TerminalConfig::default() });
+
+        std::fs::write(dir.path().join(CONFIG_PATH), "[terminal]\npath_prepend = [\".venv/bin\"]\n\n[terminal.env]\nAPI_URL = \"http://localhost:8080\"\n").unwrap();
+        let terminal = WorkspaceConfig::load(dir.path()).terminal;
+        assert_eq!(terminal.path_prepend, vec![".venv/bin"]);
+        assert_eq!(terminal.env.get("API_URL").map(String::as_str), Some("http://localhost:8080"));
+        assert_eq!((terminal.command_timeout(None), terminal.command_timeout(Some(5)), terminal.command_timeout(Some(3600))), (60, 5, 600));
+        assert_eq!(terminal.target, TerminalTarget::Local);
+
+        std::fs::write(dir.path().join(CONFIG_PATH), "[terminal.target]\nkind = \"ssh\"\nhost = \"devbox\"\ndir = \"/srv/app\"\n").unwrap();
+        assert!(matches!(WorkspaceConfig::load(dir.path()).terminal.target, TerminalTarget::Ssh { host, dir: Some(d), shared_workspace: false, .. } if host == "devbox" && d == "/srv/app"));
+
+        std::fs::write(dir.path().join(CONFIG_PATH), "[terminal.target]\nkind = \"docker\"\n").unwrap();
+        assert_eq!(WorkspaceConfig::load(dir.path()).terminal.target, TerminalTarget::Docker { container: None, user: None, dir: None });
+    }
+}
@@ -0,0 +1,110 @@
+use encoding_rs::{Encoding, UTF_16BE, UTF_16LE, UTF_8};
+
+// Text files are handled as UTF-8 internally. Legacy encodings (Latin-1, Shift_JIS, ...)
+// and BOM-marked UTF-16 are detected on read and restored on write, so editing a file
+// never silently changes its encoding or drops its BOM.
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct TextEncoding {
+    pub encoding: &'static Encoding,
+    pub bom: bool,
+}
+
+impl TextEncoding {
+    pub const UTF8: TextEncoding = TextEncoding { encoding: UTF_8, bom: false };
+
+    pub fn name(&self) -> &'static str {
+        self.encoding.name()
+    }
+}
+
+// Control characters that do not turn up in text, whatever its encoding
+fn looks_binary(text: &str) -> bool {
+    text.chars().any(|c| c.is_control() && !matches!(c, '\t' | '\n' | '\r' | '\x0c' | '\x1b'))
+}
+
+/// Decodes `bytes` as text, detecting the encoding. None for binary content.
+pub fn decode(bytes: &[u8]) -> Option<(String, TextEncoding)> {
+    if let Some((encoding, bom_len)) = Encoding::for_bom(bytes) {
+        let (text, had_errors) = encoding.decode_without_bom_handling(&bytes[bom_len..]);
+        return (!had_errors).then(|| (text.into_owned(), TextEncoding { encoding, bom: true }));
+    }
+    // Without a BOM, NUL bytes mean binary (UTF-16 without a BOM is not detected)
+    if bytes.contains(&0) {
+        return None;
+    }
+    if let Ok(text) = std::str::from_utf8(bytes) {
+        return Some((text.to_string(), TextEncoding::UTF8));
+    }
+
+    let mut detector = chardetng::EncodingDetector::new();
+    detector.feed(bytes, true);
+    let encoding = detector.guess(None, true);
+    if encoding == UTF_8 {
+        return None;
+    }
+    let (text, had_errors) = encoding.decode_without_bom_handling(bytes);
+    if had_errors || looks_binary(&text) {
+        return None;
+    }
+    Some((text.into_owned(), TextEncoding { encoding, bom: false }))
+}
+
+/// Encodes `text` back into `encoding`. Fails if a character has no representation in it.
+pub fn encode(text: &str, encoding: TextEncoding) -> Result<Vec<u8>, String> {
+    let mut out = Vec::new();
+    if encoding.encoding == UTF_16LE || encoding.encoding == UTF_16BE {
+        // encoding_rs only decodes UTF-16
+        let le = encoding.encoding == UTF_16LE;
+        if encoding.bom {
+            out.extend(if le { [0xFF, 0xFE] } else { [0xFE, 0xFF] });
+        }
+        for unit in text.encode_utf16() {
+            out.extend(if le { unit.to_le_bytes() } else { unit.to_be_bytes() });
+        }
+        return Ok(out);
+    }
+
+    if encoding.bom && encoding.encoding == UTF_8 {
+        out.extend([0xEF, 0xBB, 0xBF]);
+    }
+    let (bytes, _, unmappable) = encoding.encoding.encode(text);
+    if unmappable {
+        return Err(format!("content has characters that cannot be written as {}", encoding.name()));
+    }
+    out.extend_from_slice(&bytes);
+    Ok(out)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_legacy_roundtrip() {
+        let latin1 = b"// Caf\xe9 cr\xe8me br\xfbl\xe9e, na\xefve fa\xe7ade\nfn main() {}\n";
+        let (text, encoding) = decode(latin1).unwrap();
+        assert_eq!(text, "// Café crème brûlée, naïve façade\nfn main() {}\n");
+        assert_eq!(encoding.name(), "windows-1252");
+        assert_eq!(encode(&text, encoding).unwrap(), latin1);
+        assert!(encode("// 日本", encoding).is_err());
+    }
+
+    #[test]
+    fn test_bom_roundtrip() {
+        let utf16: Vec<u8> = [0xFF, 0xFE].into_iter().chain("hé\n".encode_utf16().flat_map(u16::to_le_bytes)).collect();
+        let (text, encoding) = decode(&utf16).unwrap();
+        assert_eq!((text.as_str(), encoding), ("hé\n", TextEncoding { encoding: UTF_16LE, bom: true }));
+        assert_eq!(encode(&text, encoding).unwrap(), utf16);
+
+        let (text, encoding) = decode(b"\xEF\xBB\xBFkey = 1\n").unwrap();
+        assert_eq!((text.as_str(), encoding.bom), ("key = 1\n", true));
+        assert_eq!(encode(&text, encoding).unwrap(), b"\xEF\xBB\xBFkey = 1\n");
+    }
+
+    #[test]
+    fn test_binary_is_not_decoded() {
+        assert!(decode(b"\x89PNG\r\n\x1a\n\0\0\0\rIHDR").is_none());
+        assert!(decode(&[0x01, 0x02, 0x9c, 0x03]).is_none());
+    }
+}
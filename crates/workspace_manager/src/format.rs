@@ -0,0 +1,58 @@
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+// Format-on-write: rustfmt for Rust, and the project's own prettier for JS/TS. Prettier is
+// only used when installed in the workspace, so its version and config are the project's.
+
+fn prettier_bin(root: &Path) -> Option<PathBuf> {
+    let name = if cfg!(windows) { "prettier.cmd" } else { "prettier" };
+    let bin = root.join("node_modules").join(".bin").join(name);
+    bin.is_file().then_some(bin)
+}
+
+fn formatter(root: &Path, full_path: &Path) -> Option<Command> {
+    match full_path.extension().and_then(|e| e.to_str())? {
+        "rs" => {
+            let mut cmd = Command::new("rustfmt");
+            // rustfmt.toml, if any, still wins; this only replaces rustfmt's 2015 default
+            cmd.args(["--edition", "2021"]).arg(full_path);
+            Some(cmd)
+        }
+        "ts" | "tsx" | "js" | "jsx" | "mjs" | "cjs" => {
+            let mut cmd = Command::new(prettier_bin(root)?);
+            cmd.arg("--write").arg(full_path);
+            Some(cmd)
+        }
+        _ => None,
+    }
+}
+
+/// Formats a file in place. `Ok(false)` when there is no formatter for it.
+pub fn format_file(root: &Path, full_path: &Path) -> Result<bool, String> {
+    let Some(mut cmd) = formatter(root, full_path) else { return Ok(false) };
+    let output = cmd.current_dir(root).output().map_err(|e| format!("Formatter failed to start: {}", e))?;
+    if output.status.success() {
+        Ok(true)
+    } else {
+        Err(format!("Formatter failed: {}", String::from_utf8_lossy(&output.stderr).trim()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::tempdir;
+
+    #[test]
+    fn test_no_formatter() {
+        let dir = tempdir().unwrap();
+        let root = dir.path();
+        std::fs::write(root.join("notes.md"), "#  Title").unwrap();
+        std::fs::write(root.join("app.ts"), "let  x=1").unwrap();
+
+        assert_eq!(format_file(root, &root.join("notes.md")), Ok(false));
+        // prettier is not installed in this workspace
+        assert_eq!(format_file(root, &root.join("app.ts")), Ok(false));
+        assert_eq!(std::fs::read_to_string(root.join("app.ts")).unwrap(), "let  x=1");
+    }
+}
@@ -0,0 +1,115 @@
+use std::path::{Path, PathBuf};
+
+use crate::ignores::walker;
+
+// Fuzzy file lookup. Each whitespace-separated query word is matched against the file
+// name and, for less, the whole path: a substring hit scores highest (more at a word
+// start); otherwise an abbreviation of the word, i.e. a run of its letters in order
+// starting at a word boundary or right after the previous word's match, so
+// "user service" finds `usrsvc.rs`. Every word has to match for a file to be returned.
+
+pub const DEFAULT_FUZZY_LIMIT: usize = 20;
+
+const SEPARATORS: &[char] = &['/', '\\', '_', '-', '.', ' '];
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct FuzzyMatch {
+    pub path: PathBuf,
+    // Higher is better; only meaningful relative to other results of the same query
+    pub score: u32,
+}
+
+// Best (score, end) of `word` in `target`; abbreviations may also start at `cont`
+fn word_match(word: &[char], target: &[char], cont: usize) -> Option<(f64, usize)> {
+    let at_boundary = |p: usize| p == 0 || SEPARATORS.contains(&target[p - 1]);
+    if let Some(at) = target.windows(word.len()).position(|w| w == word) {
+        return Some((if at_boundary(at) { 1.2 } else { 1.0 }, at + word.len()));
+    }
+
+    let mut best: Option<(f64, usize)> = None;
+    for p in (0..target.len()).filter(|&p| (p == cont || at_boundary(p)) && target[p] == word[0]) {
+        // Longest run from p whose letters appear in `word` in order
+        let mut next = 0;
+        let run = target[p..].iter().take_while(|&&c| {
+            match word[next..].iter().position(|&w| w == c) {
+                Some(i) => { next += i + 1; true }
+                None => false,
+            }
+        }).count();
+        let score = 0.6 + 0.4 * run as f64 / word.len() as f64;
+        if run >= 2 && best.map_or(true, |(s, _)| score > s) {
+            best = Some((score, p + run));
+        }
+    }
+    best
+}
+
+fn match_all(words: &[Vec<char>], target: &str) -> Option<f64> {
+    let target: Vec<char> = target.chars().collect();
+    let mut cont = 0;
+    let mut total = 0.0;
+    for word in words {
+        let (score, end) = word_match(word, &target, cont)?;
+        total += score;
+        cont = end;
+    }
+    Some(total)
+}
+
+fn score(words: &[Vec<char>], rel: &Path) -> Option<f64> {
+    let path = rel.to_string_lossy().to_lowercase();
+    let name = rel.file_name().map_or(String::new(), |n| n.to_string_lossy().to_lowercase());
+    let best = match (match_all(words, &name), match_all(words, &path).map(|s| s * 0.8)) {
+        (Some(a), Some(b)) => a.max(b),
+        (a, b) => a.or(b)?,
+    };
+    // Prefer shorter paths among equal matches
+    Some(best / words.len() as f64 - path.len() as f64 * 0.001)
+}
+
+/// The `limit` best matches for `query` among non-ignored files, best first.
+pub fn fuzzy_find_files(root: &Path, query: &str, limit: usize) -> Vec<FuzzyMatch> {
+    let words: Vec<Vec<char>> = query.split_whitespace().map(|w| w.to_lowercase().chars().collect()).collect();
+    if words.is_empty() {
+        return Vec::new();
+    }
+
+    let mut matches: Vec<(f64, PathBuf)> = walker(root).build().flatten()
+        .filter(|e| e.file_type().map_or(false, |ft| ft.is_file()))
+        .filter_map(|e| {
+            let rel = e.path().strip_prefix(root).ok()?.to_path_buf();
+            score(&words, &rel).map(|s| (s, rel))
+        })
+        .collect();
+    matches.sort_by(|a, b| b.0.total_cmp(&a.0).then_with(|| a.1.cmp(&b.1)));
+    matches.truncate(limit);
+    matches.into_iter()
+        .map(|(s, path)| FuzzyMatch { path, score: (s.max(0.0) * 1000.0) as u32 })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::tempdir;
+
+    #[test]
+    fn test_fuzzy_find_files() {
+        let dir = tempdir().unwrap();
+        let root = dir.path();
+        for file in ["src/usrsvc.rs", "src/user_model.rs", "src/billing.rs", "docs/service.md"] {
+            let path = root.join(file);
+            std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+            std::fs::write(path, "").unwrap();
+        }
+
+        let found = |query: &str| -> Vec<String> {
+            fuzzy_find_files(root, query, 2).into_iter().map(|m| m.path.to_string_lossy().replace('\\', "/")).collect()
+        };
+        assert_eq!(found("user service")[0], "src/usrsvc.rs");
+        assert_eq!(found("model user"), vec!["src/user_model.rs"]);
+        assert_eq!(found("user"), vec!["src/user_model.rs", "src/usrsvc.rs"]);
+        assert_eq!(found("BILL"), vec!["src/billing.rs"]);
+        assert!(found("zzz").is_empty());
+    }
+}
@@ -0,0 +1,315 @@
+use std::path::{Path, PathBuf};
+
+use git2::{Delta, DiffFormat, DiffOptions, Repository, Status, StatusOptions};
+
+use crate::{read_text, resolve_path_safe, FsError};
+
+// Read-only views of the workspace's git repository: what is uncommitted and what exactly
+// changed. Paths are relative to the workspace root, which may be a subdirectory of the
+// repository; changes outside it are left out.
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum GitChange {
+    Added,
+    Modified,
+    Deleted,
+    Renamed,
+    TypeChange,
+    Untracked,
+    Conflicted,
+}
+
+impl GitChange {
+    /// The letter `git status --short` uses.
+    pub fn code(&self) -> char {
+        match self {
+            GitChange::Added => 'A',
+            GitChange::Modified => 'M',
+            GitChange::Deleted => 'D',
+            GitChange::Renamed => 'R',
+            GitChange::TypeChange => 'T',
+            GitChange::Untracked => '?',
+            GitChange::Conflicted => 'U',
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct GitFileStatus {
+    pub path: PathBuf,
+    // Staged change (HEAD -> index)
+    pub index: Option<GitChange>,
+    // Unstaged change (index -> working tree)
+    pub worktree: Option<GitChange>,
+}
+
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct GitStatus {
+    // None when HEAD is detached or the branch has no commits yet
+    pub branch: Option<String>,
+    pub files: Vec<GitFileStatus>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct BlameLine {
+    pub line: usize,
+    // Abbreviated hash; None for lines that are not committed yet
+    pub commit: Option<String>,
+    pub author: String,
+    // Author time, seconds since the Unix epoch; 0 for uncommitted lines
+    pub time: i64,
+    pub summary: String,
+    pub text: String,
+}
+
+impl BlameLine {
+    /// Author date as YYYY-MM-DD (UTC).
+    pub fn date(&self) -> String {
+        // Days to civil date, after Howard Hinnant's `civil_from_days`
+        let z = self.time.div_euclid(86_400) + 719_468;
+        let era = z.div_euclid(146_097);
+        let doe = z - era * 146_097;
+        let yoe = (doe - doe / 1460 + doe / 36_524 - doe / 146_096) / 365;
+        let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+        let mp = (5 * doy + 2) / 153;
+        let day = doy - (153 * mp + 2) / 5 + 1;
+        let month = if mp < 10 { mp + 3 } else { mp - 9 };
+        let year = yoe + era * 400 + i64::from(month <= 2);
+        format!("{:04}-{:02}-{:02}", year, month, day)
+    }
+}
+
+fn git_error(e: git2::Error) -> FsError {
+    FsError::Io(std::io::Error::new(std::io::ErrorKind::Other, e.message().to_string()))
+}
+
+// The repository containing `root`, and `root` relative to its working directory
+fn open_repo(root: &Path) -> Result<(Repository, PathBuf), FsError> {
+    let repo = Repository::discover(root).map_err(git_error)?;
+    let workdir = repo.workdir().ok_or_else(|| git_error(git2::Error::from_str("bare repository")))?;
+    let prefix = root.canonicalize()?
+        .strip_prefix(workdir.canonicalize()?)
+        .map(Path::to_path_buf)
+        .map_err(|_| FsError::InvalidPath)?;
+    Ok((repo, prefix))
+}
+
+fn index_change(status: Status) -> Option<GitChange> {
+    if status.is_conflicted() {
+        Some(GitChange::Conflicted)
+    } else if status.is_index_new() {
+        Some(GitChange::Added)
+    } else if status.is_index_modified() {
+        Some(GitChange::Modified)
+    } else if status.is_index_deleted() {
+        Some(GitChange::Deleted)
+    } else if status.is_index_renamed() {
+        Some(GitChange::Renamed)
+    } else if status.is_index_typechange() {
+        Some(GitChange::TypeChange)
+    } else {
+        None
+    }
+}
+
+fn worktree_change(status: Status) -> Option<GitChange> {
+    if status.is_conflicted() {
+        Some(GitChange::Conflicted)
+    } else if status.is_wt_new() {
+        Some(GitChange::Untracked)
+    } else if status.is_wt_modified() {
+        Some(GitChange::Modified)
+    } else if status.is_wt_deleted() {
+        Some(GitChange::Deleted)
+    } else if status.is_wt_renamed() {
+        Some(GitChange::Renamed)
+    } else if status.is_wt_typechange() {
+        Some(GitChange::TypeChange)
+    } else {
+        None
+    }
+}
+
+/// Uncommitted changes under the workspace root, like `git status`.
+pub fn git_status(root: &Path) -> Result<GitStatus, FsError> {
+    let (repo, prefix) = open_repo(root)?;
+    let mut options = StatusOptions::new();
+    options.include_untracked(true).recurse_untracked_dirs(true).renames_head_to_index(true);
+    if !prefix.as_os_str().is_empty() {
+        options.pathspec(prefix.as_path());
+    }
+
+    let statuses = repo.statuses(Some(&mut options)).map_err(git_error)?;
+    let mut files: Vec<GitFileStatus> = statuses.iter()
+        .filter_map(|entry| {
+            let path = Path::new(entry.path()?).strip_prefix(&prefix).ok()?.to_path_buf();
+            let status = entry.status();
+            let (index, worktree) = (index_change(status), worktree_change(status));
+            (index.is_some() || worktree.is_some()).then_some(GitFileStatus { path, index, worktree })
+        })
+        .collect();
+    files.sort_by(|a, b| a.path.cmp(&b.path));
+
+    let branch = repo.head().ok()
+        .filter(|head| head.is_branch())
+        .and_then(|head| head.shorthand().map(str::to_string));
+    Ok(GitStatus { branch, files })
+}
+
+/// Unified diff against HEAD of the whole workspace or one path in it. `staged` limits it to
+/// what is in the index; otherwise untracked files are included as additions.
+pub fn git_diff(root: &Path, file_path: Option<&str>, staged: bool) -> Result<String, FsError> {
+    let (repo, prefix) = open_repo(root)?;
+    let mut options = DiffOptions::new();
+    match file_path {
+        Some(file_path) => {
+            resolve_path_safe(root, file_path, false)?;
+            options.pathspec(prefix.join(file_path));
+        }
+        None if !prefix.as_os_str().is_empty() => {
+            options.pathspec(prefix.as_path());
+        }
+        None => {}
+    }
+
+    // An unborn branch diffs against the empty tree
+    let head = repo.head().ok().and_then(|h| h.peel_to_tree().ok());
+    let diff = if staged {
+        repo.diff_tree_to_index(head.as_ref(), None, Some(&mut options))
+    } else {
+        options.include_untracked(true).recurse_untracked_dirs(true).show_untracked_content(true);
+        repo.diff_tree_to_workdir_with_index(head.as_ref(), Some(&mut options))
+    }.map_err(git_error)?;
+
+    let mut out = String::new();
+    diff.print(DiffFormat::Patch, |delta, _hunk, line| {
+        if delta.status() == Delta::Unmodified {
+            return true;
+        }
+        if matches!(line.origin(), '+' | '-' | ' ') {
+            out.push(line.origin());
+        }
+        out.push_str(&String::from_utf8_lossy(line.content()));
+        true
+    }).map_err(git_error)?;
+    Ok(out)
+}
+
+/// Who last changed each line of `start_line..=end_line` (1-based, whole file by default),
+/// blaming the working-tree content so uncommitted lines show up as such.
+pub fn git_blame(root: &Path, file_path: &str, start_line: Option<usize>, end_line: Option<usize>) -> Result<Vec<BlameLine>, FsError> {
+    let full_path = resolve_path_safe(root, file_path, true)?;
+    let content = read_text(&full_path)?;
+    let lines: Vec<&str> = content.lines().collect();
+    let start = start_line.unwrap_or(1).max(1);
+    let end = end_line.unwrap_or(lines.len()).min(lines.len());
+    if start > end {
+        return Err(FsError::Edit(format!("Line range {}-{} is outside the file ({} lines)", start, end_line.unwrap_or(end), lines.len())));
+    }
+
+    let (repo, prefix) = open_repo(root)?;
+    let committed = repo.blame_file(&prefix.join(file_path), None).map_err(git_error)?;
+    let blame = committed.blame_buffer(content.as_bytes()).map_err(git_error)?;
+
+    let mut out = Vec::with_capacity(end - start + 1);
+    for line in start..=end {
+        let Some(hunk) = blame.get_line(line) else { continue };
+        let id = hunk.final_commit_id();
+        let text = lines[line - 1].to_string();
+        // Lines that differ from HEAD have a zero id and no signature
+        let commit = if id.is_zero() { None } else { repo.find_commit(id).ok() };
+        out.push(match commit {
+            Some(commit) => BlameLine {
+                line,
+                commit: Some(id.to_string()[..8].to_string()),
+                author: commit.author().name().unwrap_or_default().to_string(),
+                time: commit.author().when().seconds(),
+                summary: commit.summary().unwrap_or_default().to_string(),
+                text,
+            },
+            None => BlameLine {
+                line,
+                commit: None,
+                author: "Not Committed Yet".to_string(),
+                time: 0,
+                summary: String::new(),
+                text,
+            },
+        });
+    }
+    Ok(out)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::tempdir;
+
+    fn commit_all(repo: &Repository) {
+        let mut index = repo.index().unwrap();
+        index.add_all(["*"], git2::IndexAddOption::DEFAULT, None).unwrap();
+        index.write().unwrap();
+        let tree = repo.find_tree(index.write_tree().unwrap()).unwrap();
+        let signature = git2::Signature::now("Test", "test@example.com").unwrap();
+        repo.commit(Some("HEAD"), &signature, &signature, "initial", &tree, &[]).unwrap();
+    }
+
+    #[test]
+    fn test_git_status_and_diff() {
+        let dir = tempdir().unwrap();
+        let root = dir.path();
+        let repo = Repository::init(root).unwrap();
+        std::fs::write(root.join("a.rs"), "fn a() {}\n").unwrap();
+        std::fs::write(root.join("b.rs"), "fn b() {}\n").unwrap();
+        commit_all(&repo);
+
+        std::fs::write(root.join("a.rs"), "fn a() { changed(); }\n").unwrap();
+        std::fs::write(root.join("new.rs"), "fn new() {}\n").unwrap();
+        std::fs::write(root.join("b.rs"), "fn b() { staged(); }\n").unwrap();
+        let mut index = repo.index().unwrap();
+        index.add_path(Path::new("b.rs")).unwrap();
+        index.write().unwrap();
+
+        let status = git_status(root).unwrap();
+        assert!(status.branch.is_some());
+        assert_eq!(status.files, vec![
+            GitFileStatus { path: "a.rs".into(), index: None, worktree: Some(GitChange::Modified) },
+            GitFileStatus { path: "b.rs".into(), index: Some(GitChange::Modified), worktree: None },
+            GitFileStatus { path: "new.rs".into(), index: None, worktree: Some(GitChange::Untracked) },
+        ]);
+
+        let diff = git_diff(root, Some("a.rs"), false).unwrap();
+        assert!(diff.contains("-fn a() {}\n+fn a() { changed(); }\n"));
+        assert!(!diff.contains("b.rs"));
+
+        let all = git_diff(root, None, false).unwrap();
+        assert!(all.contains("+fn new() {}"));
+        let staged = git_diff(root, None, true).unwrap();
+        assert!(staged.contains("+fn b() { staged(); }") && !staged.contains("a.rs"));
+
+        assert!(git_diff(root, Some("../etc/passwd"), false).is_err());
+    }
+
+    #[test]
+    fn test_git_blame() {
+        let dir = tempdir().unwrap();
+        let root = dir.path();
+        let repo = Repository::init(root).unwrap();
+        std::fs::write(root.join("a.rs"), "fn a() {}\nfn b() {}\n").unwrap();
+        commit_all(&repo);
+        std::fs::write(root.join("a.rs"), "fn a() {}\nfn b() { edited(); }\nfn c() {}\n").unwrap();
+
+        let blame = git_blame(root, "a.rs", None, None).unwrap();
+        assert_eq!(blame.len(), 3);
+        assert_eq!((blame[0].author.as_str(), blame[0].summary.as_str(), blame[0].text.as_str()), ("Test", "initial", "fn a() {}"));
+        assert_eq!(blame[0].commit.as_ref().map(|c| c.len()), Some(8));
+        assert!(blame[1].commit.is_none() && blame[2].commit.is_none());
+
+        let range = git_blame(root, "a.rs", Some(2), Some(9)).unwrap();
+        assert_eq!(range.iter().map(|l| l.line).collect::<Vec<_>>(), vec![2, 3]);
+        assert!(git_blame(root, "a.rs", Some(5), None).is_err());
+
+        let epoch = BlameLine { line: 1, commit: None, author: String::new(), time: 1_709_251_200, summary: String::new(), text: String::new() };
+        assert_eq!(epoch.date(), "2024-03-01");
+    }
+}
@@ -0,0 +1,375 @@
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::{Mutex, OnceLock};
+use petgraph::algo::tarjan_scc;
+use petgraph::stable_graph::{NodeIndex, StableDiGraph};
+use petgraph::visit::EdgeRef;
+use petgraph::Direction;
+use regex::Regex;
+
+use crate::ignores::walker;
+use crate::relocate::{resolve_js, rust_module, JS_EXTS, JS_IMPORT};
+use crate::FsChange;
+
+pub mod crates;
+pub use crates::{with_crate_graph, CrateDependency, CrateGraph, CrateInfo};
+
+// File-level dependency graph: an edge a -> b means a imports b. Rust edges come from
+// `mod x;` and `use crate::/self::/super::` paths resolved within the same crate; JS/TS
+// edges from relative import specifiers. External crates and packages are not nodes.
+//
+// One graph per workspace root is built on first query and then kept current from
+// watcher events via `apply_change`. Only changed files are re-parsed, so a newly created
+// file gains incoming edges once the files importing it are next saved.
+
+pub struct DependencyGraph {
+    graph: StableDiGraph<PathBuf, ()>,
+    nodes: HashMap<PathBuf, NodeIndex>,
+}
+
+impl DependencyGraph {
+    pub fn build(root: &Path) -> Self {
+        let mut graph = DependencyGraph { graph: StableDiGraph::new(), nodes: HashMap::new() };
+        for entry in walker(root).build().flatten() {
+            if entry.file_type().map_or(false, |ft| ft.is_file()) {
+                let rel = entry.path().strip_prefix(root).unwrap_or(entry.path()).to_path_buf();
+                graph.update_file(root, &rel);
+            }
+        }
+        graph
+    }
+
+    /// Re-parses one file and replaces its outgoing edges; drops it if it no longer exists.
+    pub fn update_file(&mut self, root: &Path, rel: &Path) {
+        let full = root.join(rel);
+        if !full.is_file() {
+            self.remove_file(rel);
+            return;
+        }
+        let Some(deps) = file_dependencies(root, rel) else { return };
+
+        let node = self.node(rel);
+        let old: Vec<_> = self.graph.edges(node).map(|e| e.id()).collect();
+        for edge in old {
+            self.graph.remove_edge(edge);
+        }
+        for dep in deps {
+            let target = self.node(&dep);
+            if target != node {
+                self.graph.update_edge(node, target, ());
+            }
+        }
+    }
+
+    pub fn remove_file(&mut self, rel: &Path) {
+        if let Some(node) = self.nodes.remove(rel) {
+            self.graph.remove_node(node);
+        }
+    }
+
+    fn node(&mut self, rel: &Path) -> NodeIndex {
+        if let Some(&node) = self.nodes.get(rel) {
+            return node;
+        }
+        let node = self.graph.add_node(rel.to_path_buf());
+        self.nodes.insert(rel.to_path_buf(), node);
+        node
+    }
+
+    fn neighbors(&self, rel: &Path, direction: Direction) -> Vec<PathBuf> {
+        let Some(&node) = self.nodes.get(rel) else { return Vec::new() };
+        let mut paths: Vec<PathBuf> = self.graph.neighbors_directed(node, direction)
+            .map(|n| self.graph[n].clone())
+            .collect();
+        paths.sort();
+        paths
+    }
+
+    /// Files `rel` imports.
+    pub fn dependencies_of(&self, rel: &Path) -> Vec<PathBuf> {
+        self.neighbors(rel, Direction::Outgoing)
+    }
+
+    /// Files that import `rel`.
+    pub fn dependents_of(&self, rel: &Path) -> Vec<PathBuf> {
+        self.neighbors(rel, Direction::Incoming)
+    }
+
+    /// Each import cycle as the sorted files it spans.
+    pub fn cycles(&self) -> Vec<Vec<PathBuf>> {
+        let mut cycles: Vec<Vec<PathBuf>> = tarjan_scc(&self.graph).into_iter()
+            .filter(|scc| scc.len() > 1)
+            .map(|scc| {
+                let mut files: Vec<PathBuf> = scc.into_iter().map(|n| self.graph[n].clone()).collect();
+                files.sort();
+                files
+            })
+            .collect();
+        cycles.sort();
+        cycles
+    }
+
+    /// Every file, sorted.
+    pub fn files(&self) -> Vec<PathBuf> {
+        let mut files: Vec<PathBuf> = self.nodes.keys().cloned().collect();
+        files.sort();
+        files
+    }
+
+    /// Every (importer, imported) pair, sorted.
+    pub fn edges(&self) -> Vec<(PathBuf, PathBuf)> {
+        let mut edges: Vec<(PathBuf, PathBuf)> = self.graph.edge_indices()
+            .filter_map(|e| self.graph.edge_endpoints(e))
+            .map(|(a, b)| (self.graph[a].clone(), self.graph[b].clone()))
+            .collect();
+        edges.sort();
+        edges
+    }
+
+    /// Graphviz rendering, one node per file and one edge per import.
+    pub fn to_dot(&self) -> String {
+        let quote = |p: &Path| format!("\"{}\"", p.to_string_lossy().replace('\\', "/").replace('"', "\\\""));
+        let mut dot = String::from("digraph dependencies {\n    rankdir=LR;\n    node [shape=box];\n");
+        for file in self.files() {
+            dot.push_str(&format!("    {};\n", quote(&file)));
+        }
+        for (from, to) in self.edges() {
+            dot.push_str(&format!("    {} -> {};\n", quote(&from), quote(&to)));
+        }
+        dot.push_str("}\n");
+        dot
+    }
+}
+
+static GRAPHS: OnceLock<Mutex<HashMap<PathBuf, DependencyGraph>>> = OnceLock::new();
+
+fn graphs() -> &'static Mutex<HashMap<PathBuf, DependencyGraph>> {
+    GRAPHS.get_or_init(|| Mutex::new(HashMap::new()))
+}
+
+/// Runs `f` on the graph for `root`, building it on first use.
+pub fn with_graph<R>(root: &Path, f: impl FnOnce(&DependencyGraph) -> R) -> R {
+    let root = root.canonicalize().unwrap_or_else(|_| root.to_path_buf());
+    let mut graphs = graphs().lock().unwrap();
+    let graph = graphs.entry(root.clone()).or_insert_with(|| DependencyGraph::build(&root));
+    f(graph)
+}
+
+/// Drops the graph for `root`, e.g. when the workspace is closed.
+pub fn forget(root: &Path) {
+    let root = root.canonicalize().unwrap_or_else(|_| root.to_path_buf());
+    graphs().lock().unwrap().remove(&root);
+    crates::forget(&root);
+}
+
+/// Feeds a watcher batch into the graph for `root`, if one has been built.
+pub fn apply_change(root: &Path, change: &FsChange) {
+    let root = root.canonicalize().unwrap_or_else(|_| root.to_path_buf());
+    let mut graphs = graphs().lock().unwrap();
+    if let Some(graph) = graphs.get_mut(&root) {
+        for path in change.paths() {
+            graph.update_file(&root, path);
+        }
+    }
+    drop(graphs);
+    crates::apply_change(&root, change);
+}
+
+/// Files `rel` depends on, or None if it is not a Rust or JS/TS file.
+fn file_dependencies(root: &Path, rel: &Path) -> Option<Vec<PathBuf>> {
+    let ext = rel.extension().and_then(|e| e.to_str()).unwrap_or("");
+    let content = std::fs::read_to_string(root.join(rel)).ok()?;
+    match ext {
+        "rs" => Some(rust_dependencies(root, rel, &content)),
+        _ if JS_EXTS.contains(&ext) => Some(js_dependencies(root, rel, &content)),
+        _ => None,
+    }
+}
+
+// ---------- Rust ----------
+
+// Like `rust_module`, but crate roots map to the empty path
+fn module_of(rel: &Path) -> Option<(PathBuf, Vec<String>)> {
+    if let Some(module) = rust_module(rel) {
+        return Some(module);
+    }
+    let parent = rel.parent()?;
+    let is_root = parent.file_name().map_or(false, |n| n == "src")
+        && matches!(rel.file_name().and_then(|n| n.to_str()), Some("lib.rs" | "main.rs"));
+    is_root.then(|| (parent.to_path_buf(), Vec::new()))
+}
+
+// The file defining module `segments` of the crate rooted at `crate_src`
+fn module_file(root: &Path, crate_src: &Path, segments: &[String]) -> Option<PathBuf> {
+    let candidates = if segments.is_empty() {
+        vec![crate_src.join("lib.rs"), crate_src.join("main.rs")]
+    } else {
+        let dir: PathBuf = segments.iter().collect();
+        vec![crate_src.join(&dir).with_extension("rs"), crate_src.join(&dir).join("mod.rs")]
+    };
+    candidates.into_iter().find(|p| root.join(p).is_file())
+}
+
+fn use_paths(tree: &syn::UseTree, prefix: &mut Vec<String>, out: &mut Vec<Vec<String>>) {
+    match tree {
+        syn::UseTree::Path(p) => {
+            prefix.push(p.ident.to_string());
+            use_paths(&p.tree, prefix, out);
+            prefix.pop();
+        }
+        syn::UseTree::Name(n) => {
+            let mut path = prefix.clone();
+            path.push(n.ident.to_string());
+            out.push(path);
+        }
+        syn::UseTree::Rename(r) => {
+            let mut path = prefix.clone();
+            path.push(r.ident.to_string());
+            out.push(path);
+        }
+        syn::UseTree::Glob(_) => out.push(prefix.clone()),
+        syn::UseTree::Group(g) => {
+            for item in &g.items {
+                use_paths(item, prefix, out);
+            }
+        }
+    }
+}
+
+fn collect_rust_items(items: &[syn::Item], module: &[String], mods: &mut Vec<Vec<String>>, uses: &mut Vec<(Vec<String>, Vec<String>)>) {
+    for item in items {
+        match item {
+            syn::Item::Mod(m) => {
+                let mut child = module.to_vec();
+                child.push(m.ident.to_string());
+                match &m.content {
+                    // Inline modules live in this file; look inside them
+                    Some((_, inner)) => collect_rust_items(inner, &child, mods, uses),
+                    None => mods.push(child),
+                }
+            }
+            syn::Item::Use(u) => {
+                let mut paths = Vec::new();
+                use_paths(&u.tree, &mut Vec::new(), &mut paths);
+                uses.extend(paths.into_iter().map(|p| (module.to_vec(), p)));
+            }
+            _ => {}
+        }
+    }
+}
+
+fn rust_dependencies(root: &Path, rel: &Path, content: &str) -> Vec<PathBuf> {
+    let Some((crate_src, module)) = module_of(rel) else { return Vec::new() };
+    let Ok(file) = syn::parse_file(content) else { return Vec::new() };
+    let mut mods = Vec::new();
+    let mut uses = Vec::new();
+    collect_rust_items(&file.items, &module, &mut mods, &mut uses);
+
+    let mut deps: Vec<PathBuf> = mods.iter().filter_map(|m| module_file(root, &crate_src, m)).collect();
+    for (scope, path) in uses {
+        let mut base = match path.first().map(String::as_str) {
+            Some("crate") => Vec::new(),
+            Some("self" | "super") => scope,
+            _ => continue,
+        };
+        let mut rest = &path[..];
+        if rest[0] == "crate" || rest[0] == "self" {
+            rest = &rest[1..];
+        }
+        while rest.first().map_or(false, |s| s == "super") {
+            base.pop();
+            rest = &rest[1..];
+        }
+        // The longest prefix that names a module file; the rest are items inside it
+        let target = (0..=rest.len()).rev().find_map(|k| {
+            let mut segments = base.clone();
+            segments.extend_from_slice(&rest[..k]);
+            module_file(root, &crate_src, &segments)
+        });
+        deps.extend(target);
+    }
+    deps.retain(|d| d != rel);
+    deps.sort();
+    deps.dedup();
+    deps
+}
+
+// ---------- JS / TS ----------
+
+fn js_dependencies(root: &Path, rel: &Path, content: &str) -> Vec<PathBuf> {
+    let import = Regex::new(JS_IMPORT).unwrap();
+    let Ok(root) = root.canonicalize() else { return Vec::new() };
+    let dir = root.join(rel).parent().map(Path::to_path_buf).unwrap_or_else(|| root.clone());
+    let mut deps: Vec<PathBuf> = import.captures_iter(content)
+        .filter_map(|caps| resolve_js(&dir, &caps[3]))
+        .filter_map(|(target, _)| target.strip_prefix(&root).ok().map(Path::to_path_buf))
+        .collect();
+    deps.sort();
+    deps.dedup();
+    deps
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::tempdir;
+
+    fn write(root: &Path, rel: &str, content: &str) {
+        let path = root.join(rel);
+        std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+        std::fs::write(path, content).unwrap();
+    }
+
+    fn paths(items: &[&str]) -> Vec<PathBuf> {
+        items.iter().map(PathBuf::from).collect()
+    }
+
+    #[test]
+    fn test_rust_graph() {
+        let dir = tempdir().unwrap();
+        let root = dir.path();
+        write(root, "src/lib.rs", "mod net;\nmod util;\nuse crate::util::{parse, Config};\n");
+        write(root, "src/net.rs", "pub mod http;\nuse super::util::parse;\n");
+        write(root, "src/net/http.rs", "use crate::net::*;\nuse std::io;\n");
+        write(root, "src/util.rs", "pub fn parse() {}\nmod inline { use crate::net::http::get; }\n");
+
+        let graph = DependencyGraph::build(root);
+        assert_eq!(graph.dependencies_of(Path::new("src/lib.rs")), paths(&["src/net.rs", "src/util.rs"]));
+        assert_eq!(graph.dependencies_of(Path::new("src/util.rs")), paths(&["src/net/http.rs"]));
+        assert_eq!(graph.dependents_of(Path::new("src/util.rs")), paths(&["src/lib.rs", "src/net.rs"]));
+        assert_eq!(graph.cycles(), vec![paths(&["src/net.rs", "src/net/http.rs", "src/util.rs"])]);
+    }
+
+    #[test]
+    fn test_js_graph_updates() {
+        let dir = tempdir().unwrap();
+        let root = dir.path();
+        write(root, "src/app.ts", "import { a } from './a';\nimport React from 'react';\n");
+        write(root, "src/a.ts", "export const a = 1;\n");
+        write(root, "src/b/index.ts", "export const b = 2;\n");
+
+        let mut graph = DependencyGraph::build(root);
+        assert_eq!(graph.dependencies_of(Path::new("src/app.ts")), paths(&["src/a.ts"]));
+
+        write(root, "src/app.ts", "import { b } from './b';\n");
+        graph.update_file(root, Path::new("src/app.ts"));
+        assert_eq!(graph.dependencies_of(Path::new("src/app.ts")), paths(&["src/b/index.ts"]));
+        assert!(graph.dependents_of(Path::new("src/a.ts")).is_empty());
+
+        std::fs::remove_file(root.join("src/b/index.ts")).unwrap();
+        graph.update_file(root, Path::new("src/b/index.ts"));
+        assert!(graph.dependencies_of(Path::new("src/app.ts")).is_empty());
+        assert!(graph.cycles().is_empty());
+    }
+
+    #[test]
+    fn test_to_dot() {
+        let dir = tempdir().unwrap();
+        let root = dir.path();
+        write(root, "app.js", "require('./lib');\n");
+        write(root, "lib.js", "module.exports = {};\n");
+
+        let dot = DependencyGraph::build(root).to_dot();
+        assert_eq!(dot, "digraph dependencies {\n    rankdir=LR;\n    node [shape=box];\n    \"app.js\";\n    \"lib.js\";\n    \"app.js\" -> \"lib.js\";\n}\n");
+    }
+}
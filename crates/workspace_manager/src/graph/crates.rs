@@ -0,0 +1,268 @@
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use std::sync::{Mutex, OnceLock};
+
+use serde_json::Value;
+
+use crate::FsChange;
+
+// Crate-level dependency graph from `cargo metadata`: the packages Cargo actually resolved,
+// with versions, enabled features and dependency kinds. Complements the file graph, which
+// stops at crate boundaries. Cached per workspace root like the file graph and dropped
+// when a manifest or the lockfile changes.
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct CrateInfo {
+    // Cargo's package id, unique even when two versions of a crate are in the graph
+    pub id: String,
+    pub name: String,
+    pub version: String,
+    pub workspace_member: bool,
+    // Directory of the manifest relative to the workspace root; None outside it
+    pub path: Option<PathBuf>,
+    // Features Cargo enabled in this build, sorted
+    pub features: Vec<String>,
+    // Every feature the crate declares, sorted
+    pub available_features: Vec<String>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct CrateDependency {
+    pub from: String,
+    pub to: String,
+    // "normal", "dev" and/or "build"
+    pub kinds: Vec<String>,
+    // Version requirement as written in the manifest
+    pub req: Option<String>,
+    pub optional: bool,
+}
+
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct CrateGraph {
+    // Workspace members first, then by name and version
+    pub crates: Vec<CrateInfo>,
+    pub dependencies: Vec<CrateDependency>,
+}
+
+fn strings(value: Option<&Value>) -> Vec<String> {
+    let mut out: Vec<String> = value.and_then(Value::as_array)
+        .map(|a| a.iter().filter_map(|v| v.as_str().map(str::to_string)).collect())
+        .unwrap_or_default();
+    out.sort();
+    out
+}
+
+impl CrateGraph {
+    /// Runs `cargo metadata` for the workspace at `root`.
+    pub fn load(root: &Path) -> Result<Self, String> {
+        if !root.join("Cargo.toml").is_file() {
+            return Err("No Cargo.toml at the workspace root".to_string());
+        }
+        let output = Command::new("cargo")
+            .args(["metadata", "--format-version", "1"])
+            .current_dir(root)
+            .output()
+            .map_err(|e| format!("cargo metadata failed to start: {}", e))?;
+        if !output.status.success() {
+            return Err(format!("cargo metadata failed: {}", String::from_utf8_lossy(&output.stderr).trim()));
+        }
+        let metadata: Value = serde_json::from_slice(&output.stdout).map_err(|e| format!("Invalid cargo metadata output: {}", e))?;
+        Self::from_metadata(root, &metadata)
+    }
+
+    /// Builds the graph from parsed `cargo metadata --format-version 1` output.
+    pub fn from_metadata(root: &Path, metadata: &Value) -> Result<Self, String> {
+        let packages = metadata.get("packages").and_then(Value::as_array).ok_or("cargo metadata has no packages")?;
+        let members = strings(metadata.get("workspace_members"));
+        let root = root.canonicalize().unwrap_or_else(|_| root.to_path_buf());
+
+        // Enabled features and resolved edges; missing with --no-deps
+        let resolved: HashMap<&str, &Value> = metadata.get("resolve")
+            .and_then(|r| r.get("nodes"))
+            .and_then(Value::as_array)
+            .map(|nodes| nodes.iter().filter_map(|n| Some((n.get("id")?.as_str()?, n))).collect())
+            .unwrap_or_default();
+
+        let mut crates = Vec::new();
+        let mut by_id: HashMap<&str, &Value> = HashMap::new();
+        for package in packages {
+            let Some(id) = package.get("id").and_then(Value::as_str) else { continue };
+            by_id.insert(id, package);
+            let str_of = |key: &str| package.get(key).and_then(Value::as_str).unwrap_or_default().to_string();
+            let path = package.get("manifest_path").and_then(Value::as_str)
+                .and_then(|m| Path::new(m).parent())
+                .and_then(|dir| {
+                    let dir = dir.canonicalize().unwrap_or_else(|_| dir.to_path_buf());
+                    dir.strip_prefix(&root).ok().map(Path::to_path_buf)
+                });
+            let mut available_features: Vec<String> = package.get("features").and_then(Value::as_object)
+                .map(|f| f.keys().cloned().collect())
+                .unwrap_or_default();
+            available_features.sort();
+            crates.push(CrateInfo {
+                id: id.to_string(),
+                name: str_of("name"),
+                version: str_of("version"),
+                workspace_member: members.iter().any(|m| m == id),
+                path,
+                features: strings(resolved.get(id).and_then(|n| n.get("features"))),
+                available_features,
+            });
+        }
+        crates.sort_by(|a, b| b.workspace_member.cmp(&a.workspace_member)
+            .then_with(|| a.name.cmp(&b.name))
+            .then_with(|| a.version.cmp(&b.version)));
+
+        let names: HashMap<&str, &str> = crates.iter().map(|c| (c.id.as_str(), c.name.as_str())).collect();
+        let mut dependencies = Vec::new();
+        for (from, node) in &resolved {
+            let declared = by_id.get(from).and_then(|p| p.get("dependencies")).and_then(Value::as_array);
+            for dep in node.get("deps").and_then(Value::as_array).into_iter().flatten() {
+                let Some(to) = dep.get("pkg").and_then(Value::as_str) else { continue };
+                let mut kinds: Vec<String> = dep.get("dep_kinds").and_then(Value::as_array).into_iter().flatten()
+                    .map(|k| k.get("kind").and_then(Value::as_str).unwrap_or("normal").to_string())
+                    .collect();
+                kinds.sort();
+                kinds.dedup();
+                let target = names.get(to).copied().unwrap_or_default();
+                let declaration = declared.into_iter().flatten().find(|d| d.get("name").and_then(Value::as_str) == Some(target));
+                dependencies.push(CrateDependency {
+                    from: from.to_string(),
+                    to: to.to_string(),
+                    kinds,
+                    req: declaration.and_then(|d| d.get("req")?.as_str().map(str::to_string)),
+                    optional: declaration.and_then(|d| d.get("optional")?.as_bool()).unwrap_or(false),
+                });
+            }
+        }
+        dependencies.sort_by(|a, b| a.from.cmp(&b.from).then_with(|| a.to.cmp(&b.to)));
+
+        Ok(CrateGraph { crates, dependencies })
+    }
+
+    pub fn members(&self) -> impl Iterator<Item = &CrateInfo> {
+        self.crates.iter().filter(|c| c.workspace_member)
+    }
+
+    /// The crate called `name`, preferring a workspace member when several versions exist.
+    pub fn find(&self, name: &str) -> Option<&CrateInfo> {
+        self.crates.iter().find(|c| c.name == name)
+    }
+
+    fn by_id(&self, id: &str) -> Option<&CrateInfo> {
+        self.crates.iter().find(|c| c.id == id)
+    }
+
+    /// Crates `id` depends on directly, with the edge.
+    pub fn dependencies_of(&self, id: &str) -> Vec<(&CrateInfo, &CrateDependency)> {
+        self.dependencies.iter()
+            .filter(|d| d.from == id)
+            .filter_map(|d| Some((self.by_id(&d.to)?, d)))
+            .collect()
+    }
+
+    /// Crates that depend on `id` directly, with the edge.
+    pub fn dependents_of(&self, id: &str) -> Vec<(&CrateInfo, &CrateDependency)> {
+        self.dependencies.iter()
+            .filter(|d| d.to == id)
+            .filter_map(|d| Some((self.by_id(&d.from)?, d)))
+            .collect()
+    }
+}
+
+static CRATE_GRAPHS: OnceLock<Mutex<HashMap<PathBuf, CrateGraph>>> = OnceLock::new();
+
+fn crate_graphs() -> &'static Mutex<HashMap<PathBuf, CrateGraph>> {
+    CRATE_GRAPHS.get_or_init(|| Mutex::new(HashMap::new()))
+}
+
+/// Runs `f` on the crate graph for `root`, running `cargo metadata` on first use. Failures
+/// are not cached, so a fixed manifest is picked up on the next call.
+pub fn with_crate_graph<R>(root: &Path, f: impl FnOnce(&CrateGraph) -> R) -> Result<R, String> {
+    let root = root.canonicalize().unwrap_or_else(|_| root.to_path_buf());
+    if let Some(graph) = crate_graphs().lock().unwrap().get(&root) {
+        return Ok(f(graph));
+    }
+    // Outside the lock: cargo metadata can take a while
+    let graph = CrateGraph::load(&root)?;
+    let result = f(&graph);
+    crate_graphs().lock().unwrap().insert(root, graph);
+    Ok(result)
+}
+
+/// Drops the crate graph for `root` if the batch touched a manifest or the lockfile.
+pub fn apply_change(root: &Path, change: &FsChange) {
+    let touches_cargo = change.paths()
+        .any(|p| matches!(p.file_name().and_then(|n| n.to_str()), Some("Cargo.toml" | "Cargo.lock")));
+    if touches_cargo {
+        forget(root);
+    }
+}
+
+pub fn forget(root: &Path) {
+    let root = root.canonicalize().unwrap_or_else(|_| root.to_path_buf());
+    crate_graphs().lock().unwrap().remove(&root);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::tempdir;
+
+    #[test]
+    fn test_from_metadata() {
+        let dir = tempdir().unwrap();
+        let root = dir.path().canonicalize().unwrap();
+        std::fs::create_dir_all(root.join("crates/app")).unwrap();
+        let manifest = |p: &str| root.join(p).join("Cargo.toml").to_string_lossy().to_string();
+        let metadata = serde_json::json!({
+            "packages": [
+                {
+                    "id": "app 0.1.0 (path+file:///ws/crates/app)", "name": "app", "version": "0.1.0",
+                    "manifest_path": manifest("crates/app"), "features": { "default": ["json"], "json": ["serde/derive"] },
+                    "dependencies": [
+                        { "name": "serde", "req": "^1", "kind": null, "optional": false },
+                        { "name": "cc", "req": "^1.0", "kind": "build", "optional": true }
+                    ]
+                },
+                { "id": "serde 1.0.200 (registry+x)", "name": "serde", "version": "1.0.200", "manifest_path": "/registry/serde/Cargo.toml", "features": { "derive": [], "std": [] }, "dependencies": [] },
+                { "id": "cc 1.0.90 (registry+x)", "name": "cc", "version": "1.0.90", "manifest_path": "/registry/cc/Cargo.toml", "features": {}, "dependencies": [] }
+            ],
+            "workspace_members": ["app 0.1.0 (path+file:///ws/crates/app)"],
+            "resolve": { "nodes": [
+                { "id": "app 0.1.0 (path+file:///ws/crates/app)", "features": ["json", "default"], "deps": [
+                    { "name": "serde", "pkg": "serde 1.0.200 (registry+x)", "dep_kinds": [{ "kind": null, "target": null }] },
+                    { "name": "cc", "pkg": "cc 1.0.90 (registry+x)", "dep_kinds": [{ "kind": "build", "target": null }] }
+                ] },
+                { "id": "serde 1.0.200 (registry+x)", "features": ["derive", "std"], "deps": [] },
+                { "id": "cc 1.0.90 (registry+x)", "features": [], "deps": [] }
+            ] }
+        });
+
+        let graph = CrateGraph::from_metadata(&root, &metadata).unwrap();
+        let app = graph.find("app").unwrap();
+        assert!(app.workspace_member);
+        assert_eq!(app.path, Some(PathBuf::from("crates/app")));
+        assert_eq!(app.features, vec!["default", "json"]);
+        assert_eq!(graph.members().count(), 1);
+        assert_eq!(graph.crates[0].name, "app");
+        assert_eq!(graph.find("serde").unwrap().path, None);
+
+        let deps: Vec<_> = graph.dependencies_of(&app.id).into_iter()
+            .map(|(c, d)| (c.name.as_str(), d.kinds.clone(), d.req.clone(), d.optional))
+            .collect();
+        assert_eq!(deps, vec![
+            ("cc", vec!["build".to_string()], Some("^1.0".to_string()), true),
+            ("serde", vec!["normal".to_string()], Some("^1".to_string()), false),
+        ]);
+        let serde_id = &graph.find("serde").unwrap().id;
+        assert_eq!(graph.dependents_of(serde_id)[0].0.name, "app");
+    }
+
+    #[test]
+    fn test_load_without_manifest() {
+        let dir = tempdir().unwrap();
+        assert!(CrateGraph::load(dir.path()).is_err());
+    }
+}
@@ -0,0 +1,93 @@
+use std::path::Path;
+use std::sync::Arc;
+use ignore::gitignore::{Gitignore, GitignoreBuilder};
+use ignore::WalkBuilder;
+
+// Paths every workspace feature skips: the file tree, search, the watcher and the
+// indexers. Built-in rules come first and a `.irongraphignore` at the root (gitignore
+// syntax) is applied on top, so it can add exclusions or re-include with `!target/`.
+
+pub const IGNORE_FILE: &str = ".irongraphignore";
+
+pub const DEFAULT_IGNORES: &[&str] = &[".git/", "target/", "node_modules/", ".vscode/", ".irongraph/"];
+
+#[derive(Clone)]
+pub struct IgnoreRules {
+    matcher: Arc<Gitignore>,
+}
+
+impl IgnoreRules {
+    /// The built-in rules plus `root/.irongraphignore`, if present. Invalid lines are skipped.
+    pub fn load(root: &Path) -> Self {
+        let mut builder = GitignoreBuilder::new(root);
+        for rule in DEFAULT_IGNORES {
+            let _ = builder.add_line(None, rule);
+        }
+        if let Ok(content) = std::fs::read_to_string(root.join(IGNORE_FILE)) {
+            for line in content.lines() {
+                let _ = builder.add_line(Some(root.join(IGNORE_FILE)), line);
+            }
+        }
+        let matcher = builder.build().unwrap_or_else(|_| Gitignore::empty());
+        IgnoreRules { matcher: Arc::new(matcher) }
+    }
+
+    /// Whether `rel` (relative to the root) or one of its parent directories is excluded.
+    pub fn is_ignored(&self, rel: &Path, is_dir: bool) -> bool {
+        !rel.as_os_str().is_empty() && self.matcher.matched_path_or_any_parents(rel, is_dir).is_ignore()
+    }
+}
+
+/// A walker over `dir` (inside `root`) that honours .gitignore and the workspace rules.
+pub fn walker_in(root: &Path, dir: &Path) -> WalkBuilder {
+    let rules = IgnoreRules::load(root);
+    let root = root.to_path_buf();
+    let mut builder = WalkBuilder::new(dir);
+    builder.filter_entry(move |entry| {
+        let rel = entry.path().strip_prefix(&root).unwrap_or(entry.path());
+        !rules.is_ignored(rel, entry.file_type().map_or(false, |ft| ft.is_dir()))
+    });
+    builder
+}
+
+pub fn walker(root: &Path) -> WalkBuilder {
+    walker_in(root, root)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::tempdir;
+
+    #[test]
+    fn test_ignore_file() {
+        let dir = tempdir().unwrap();
+        let root = dir.path();
+        std::fs::write(root.join(IGNORE_FILE), "# generated\nsrc/gen/\n*.snap\n!target/\n").unwrap();
+        let rules = IgnoreRules::load(root);
+
+        assert!(rules.is_ignored(Path::new("src/gen/api.rs"), false));
+        assert!(rules.is_ignored(Path::new("tests/out.snap"), false));
+        assert!(rules.is_ignored(Path::new("node_modules/react/index.js"), false));
+        assert!(!rules.is_ignored(Path::new("target/debug/app"), false));
+        assert!(!rules.is_ignored(Path::new("src/main.rs"), false));
+    }
+
+    #[test]
+    fn test_walker_skips_ignored() {
+        let dir = tempdir().unwrap();
+        let root = dir.path();
+        std::fs::create_dir_all(root.join("fixtures")).unwrap();
+        std::fs::create_dir_all(root.join("node_modules/x")).unwrap();
+        std::fs::write(root.join("fixtures/big.json"), "{}").unwrap();
+        std::fs::write(root.join("node_modules/x/index.js"), "").unwrap();
+        std::fs::write(root.join("main.rs"), "").unwrap();
+        std::fs::write(root.join(IGNORE_FILE), "fixtures\n").unwrap();
+
+        let files: Vec<_> = walker(root).build().flatten()
+            .filter(|e| e.file_type().map_or(false, |ft| ft.is_file()))
+            .map(|e| e.path().strip_prefix(root).unwrap().to_path_buf())
+            .collect();
+        assert_eq!(files, vec![Path::new("main.rs").to_path_buf()]);
+    }
+}
@@ -1,15 +1,58 @@
 use serde::{Deserialize, Serialize};
 use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
 use thiserror::Error;
-use grep_regex::RegexMatcher;
-use grep_searcher::{Searcher, sinks::UTF8};
-use ignore::WalkBuilder;
-use syn::parse_file;
+use grep_regex::{RegexMatcher, RegexMatcherBuilder};
+use grep_matcher::Matcher;
+use grep_searcher::{Searcher, SearcherBuilder, Sink, SinkContext, SinkContextKind, SinkMatch};
+use ignore::overrides::{Override, OverrideBuilder};
 
-mod skeleton;
+pub mod skeleton;
 pub use skeleton::get_skeleton;
+mod outline;
+pub use outline::{outline, OutlineItem};
+mod references;
+pub use references::{find_references, Reference, ReferenceKind};
+mod validation;
+pub mod config;
+pub use config::{TerminalConfig, TerminalTarget, WorkspaceConfig};
+mod format;
+pub use format::format_file;
+mod relocate;
+pub use relocate::{move_file_internal, move_importers, MoveOutcome};
+mod stats;
+mod project;
+mod todos;
+mod versions;
+mod git;
+pub mod notebook;
+mod asset;
+pub use asset::{read_asset, asset_mime, Asset, MAX_ASSET_BYTES};
+pub use git::{git_blame, git_diff, git_status, BlameLine, GitChange, GitFileStatus, GitStatus};
+pub use versions::{check_version, record_version, FileVersions};
+pub use todos::{list_todos, parse_todo, Todo};
+pub use project::{detect_project, ProjectInfo, Project, Dependency, Toolchain};
+pub mod graph;
+pub use graph::DependencyGraph;
+pub mod ignores;
+pub use ignores::{IgnoreRules, IGNORE_FILE};
+mod fuzzy;
+pub use fuzzy::{fuzzy_find_files, FuzzyMatch, DEFAULT_FUZZY_LIMIT};
+mod encoding;
+mod backup;
+pub use backup::{restore_backup_internal, BACKUP_DIR};
+pub use encoding::TextEncoding;
+mod callgraph;
+pub use callgraph::{CallGraph, FunctionInfo};
+pub mod search_index;
+pub mod semantic;
+pub use semantic::{SemanticIndex, SemanticMatch};
+pub use stats::{language_of, workspace_stats, WorkspaceStats, LanguageStats, FileSize, DirectorySize};
+pub use validation::{validate_syntax, validate_with, validation_config, set_validation_config, ValidationConfig};
 
 pub mod tools;
+pub mod watcher;
+pub use watcher::{FsChange, WorkspaceWatcher};
 
 pub use common::WorkspaceState;
 
@@ -23,6 +66,12 @@ pub enum FsError {
     InvalidPath,
     #[error("Syntax Error: {0}")]
     Syntax(String),
+    #[error("Edit Error: {0}")]
+    Edit(String),
+    #[error("Binary file ({size} bytes), starts with: {preview}")]
+    BinaryFile { size: u64, preview: String },
+    #[error("Conflict: {0} was modified on disk since it was last read. Read it again before writing.")]
+    Conflict(String),
 }
 
 impl From<std::io::Error> for FsError {
@@ -37,6 +86,7 @@ pub struct FileEntry {
     pub path: PathBuf,
     pub name: String,
     pub is_dir: bool,
+    pub is_binary: bool,
     pub children: Option<Vec<FileEntry>>,
 }
 
@@ -47,11 +97,36 @@ pub struct FileContent {
     pub content: String,
 }
 
-fn validate_path(base: &Path, user_path: &str, require_exists: bool) -> Result<PathBuf, FsError> {
+/// A 1-based, inclusive run of lines out of a larger file.
+#[derive(Debug, Clone, PartialEq)]
+pub struct FileSlice {
+    pub path: PathBuf,
+    pub start_line: usize,
+    pub end_line: usize,
+    pub total_lines: usize,
+    pub lines: Vec<String>,
+}
+
+impl FileSlice {
+    /// The lines prefixed with their line numbers, the way the agent sees them.
+    pub fn numbered(&self) -> String {
+        let width = self.end_line.to_string().len();
+        self.lines.iter().enumerate()
+            .map(|(i, line)| format!("{:>width$} | {}\n", self.start_line + i, line))
+            .collect()
+    }
+}
+
+/// Resolves `user_path` relative to the workspace `base`, rejecting `..`, absolute paths
+/// and symlinks that lead outside it. With `require_exists` the result is canonical.
+pub fn resolve_path_safe(base: &Path, user_path: &str, require_exists: bool) -> Result<PathBuf, FsError> {
     let path_parts = Path::new(user_path);
     for component in path_parts.components() {
-        if let std::path::Component::ParentDir = component {
-            return Err(FsError::SecurityViolation);
+        match component {
+            std::path::Component::ParentDir
+            | std::path::Component::RootDir
+            | std::path::Component::Prefix(_) => return Err(FsError::SecurityViolation),
+            _ => {}
         }
     }
 
@@ -66,20 +141,20 @@ fn validate_path(base: &Path, user_path: &str, require_exists: bool) -> Result<P
         }
         Ok(canonical_path)
     } else {
-        if let Some(parent) = full_path.parent() {
-            if parent.exists() {
-                 let canonical_parent = parent.canonicalize().map_err(FsError::Io)?;
-                 let canonical_base = base.canonicalize().map_err(FsError::Io)?;
-                 if !canonical_parent.starts_with(&canonical_base) {
-                      return Err(FsError::SecurityViolation);
-                 }
+        // Whatever gets created under the nearest existing ancestor ends up where that
+        // ancestor really is. A dangling symlink counts as existing and fails to canonicalize.
+        if let Some(existing) = full_path.ancestors().find(|p| p.symlink_metadata().is_ok()) {
+            let canonical_existing = existing.canonicalize().map_err(|_| FsError::SecurityViolation)?;
+            let canonical_base = base.canonicalize().map_err(FsError::Io)?;
+            if !canonical_existing.starts_with(&canonical_base) {
+                return Err(FsError::SecurityViolation);
             }
         }
         Ok(full_path)
     }
 }
 
-pub fn build_file_tree(root: &Path, current_dir: &Path) -> Result<Vec<FileEntry>, FsError> {
+pub fn build_file_tree(root: &Path, current_dir: &Path, rules: &IgnoreRules) -> Result<Vec<FileEntry>, FsError> {
     let mut entries = Vec::new();
     let read_dir = std::fs::read_dir(current_dir).map_err(FsError::Io)?;
 
@@ -88,25 +163,26 @@ pub fn build_file_tree(root: &Path, current_dir: &Path) -> Result<Vec<FileEntry>
         let path = entry.path();
         let name = entry.file_name().to_string_lossy().to_string();
 
-        if name == ".git" || name == "target" || name == "node_modules" || name == ".vscode" {
-            continue;
-        }
-
         let relative_path = path.strip_prefix(root)
             .map_err(|_| FsError::InvalidPath)?
             .to_path_buf();
 
         let is_dir = path.is_dir();
+        if rules.is_ignored(&relative_path, is_dir) {
+            continue;
+        }
+        let is_binary = !is_dir && sniff_binary(&path);
         let mut children = None;
 
         if is_dir {
-            children = Some(build_file_tree(root, &path)?);
+            children = Some(build_file_tree(root, &path, rules)?);
         }
 
         entries.push(FileEntry {
             path: relative_path,
             name,
             is_dir,
+            is_binary,
             children,
         });
     }
@@ -121,13 +197,164 @@ pub fn build_file_tree(root: &Path, current_dir: &Path) -> Result<Vec<FileEntry>
     Ok(entries)
 }
 
-pub fn search_code_internal(root: &Path, query: &str) -> Result<Vec<String>, FsError> {
-    let matcher = RegexMatcher::new(query).map_err(|e| FsError::Io(std::io::Error::new(std::io::ErrorKind::Other, format!("Regex error: {}", e))))?;
+/// File tree of `rel_dir` inside the workspace ("" for the root), with paths relative to the root.
+pub fn list_files_safe(root: &Path, rel_dir: &str) -> Result<Vec<FileEntry>, FsError> {
+    let canonical_root = root.canonicalize().map_err(FsError::Io)?;
+    let start_dir = if rel_dir.is_empty() || rel_dir == "." {
+        canonical_root.clone()
+    } else {
+        resolve_path_safe(&canonical_root, rel_dir, true)?
+    };
+    if !start_dir.is_dir() {
+        return Err(FsError::InvalidPath);
+    }
+    build_file_tree(&canonical_root, &start_dir, &IgnoreRules::load(&canonical_root))
+}
+
+/// Options for `search_code_internal`; the default is a case-sensitive regex search of
+/// every non-ignored file with no context and no result cap.
+#[derive(Debug, Clone, Default, Deserialize, Serialize)]
+#[serde(default)]
+pub struct SearchOptions {
+    pub before_context: usize,
+    pub after_context: usize,
+    // Globs relative to the root, e.g. `src/**/*.rs`
+    pub include: Vec<String>,
+    pub exclude: Vec<String>,
+    pub case_insensitive: bool,
+    // Treat the query as a literal string instead of a regex
+    pub fixed_string: bool,
+    pub max_results: Option<usize>,
+    // Let matches span lines, e.g. `fn \w+\(\s*\n\s*self`
+    pub multiline: bool,
+}
+
+impl SearchOptions {
+    fn pattern(&self, query: &str) -> String {
+        if self.fixed_string { regex::escape(query) } else { query.to_string() }
+    }
+
+    fn matcher(&self, query: &str) -> Result<RegexMatcher, FsError> {
+        let pattern = self.pattern(query);
+        RegexMatcherBuilder::new()
+            .case_insensitive(self.case_insensitive)
+            .multi_line(self.multiline)
+            // Line-oriented search needs a matcher that can never match across a newline
+            .line_terminator(if self.multiline { None } else { Some(b'\n') })
+            .build(&pattern)
+            .map_err(|e| FsError::Io(std::io::Error::new(std::io::ErrorKind::Other, format!("Regex error: {}", e))))
+    }
+
+    fn searcher(&self) -> Searcher {
+        SearcherBuilder::new()
+            .before_context(self.before_context)
+            .after_context(self.after_context)
+            .multi_line(self.multiline)
+            .build()
+    }
+
+    fn overrides(&self, root: &Path) -> Result<Override, FsError> {
+        let glob_error = |e: ignore::Error| FsError::Io(std::io::Error::new(std::io::ErrorKind::Other, format!("Glob error: {}", e)));
+        let mut builder = OverrideBuilder::new(root);
+        for glob in &self.include {
+            builder.add(glob).map_err(glob_error)?;
+        }
+        for glob in &self.exclude {
+            builder.add(&format!("!{}", glob)).map_err(glob_error)?;
+        }
+        builder.build().map_err(glob_error)
+    }
+}
+
+/// One hit of `search_code_internal`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SearchMatch {
+    // Relative to the workspace root
+    pub path: PathBuf,
+    // 1-based; `end_line` differs from `line` only for multiline matches
+    pub line: usize,
+    pub end_line: usize,
+    // 1-based byte column of the match start within `line`
+    pub column: usize,
+    // The matched line(s), trimmed, joined by '\n'
+    pub text: String,
+    // Context lines requested through `SearchOptions`
+    pub before: Vec<String>,
+    pub after: Vec<String>,
+}
+
+struct MatchSink<'a> {
+    path: &'a Path,
+    matcher: &'a RegexMatcher,
+    out: Vec<SearchMatch>,
+    // Before-context seen since the last match
+    before: Vec<String>,
+    limit: usize,
+}
+
+impl Sink for MatchSink<'_> {
+    type Error = std::io::Error;
+
+    fn matched(&mut self, _searcher: &Searcher, mat: &SinkMatch<'_>) -> Result<bool, Self::Error> {
+        let text = String::from_utf8_lossy(mat.bytes());
+        let lines: Vec<&str> = text.trim_end().lines().map(str::trim).collect();
+        let line = mat.line_number().unwrap_or(0) as usize;
+        let column = self.matcher.find(mat.bytes()).ok().flatten().map_or(1, |m| m.start() + 1);
+        self.out.push(SearchMatch {
+            path: self.path.to_path_buf(),
+            line,
+            end_line: line + lines.len().max(1) - 1,
+            column,
+            text: lines.join("\n"),
+            before: std::mem::take(&mut self.before),
+            after: Vec::new(),
+        });
+        Ok(self.out.len() < self.limit)
+    }
+
+    fn context(&mut self, _searcher: &Searcher, ctx: &SinkContext<'_>) -> Result<bool, Self::Error> {
+        let text = String::from_utf8_lossy(ctx.bytes()).trim().to_string();
+        match ctx.kind() {
+            SinkContextKind::Before => self.before.push(text),
+            SinkContextKind::After => {
+                if let Some(last) = self.out.last_mut() {
+                    last.after.push(text);
+                }
+            }
+            SinkContextKind::Other => {}
+        }
+        Ok(true)
+    }
+}
+
+/// Matches sorted by path and line, at most `options.max_results` of them. Uses the
+/// workspace's search index to skip files when one is ready (see `search_index`).
+pub fn search_code_internal(root: &Path, query: &str, options: &SearchOptions) -> Result<Vec<SearchMatch>, FsError> {
+    let matcher = options.matcher(query)?;
+    let overrides = options.overrides(root)?;
+    let limit = options.max_results.unwrap_or(usize::MAX);
     let mut matches = Vec::new();
-    let matches_mutex = std::sync::Mutex::new(&mut matches);
 
-    WalkBuilder::new(root).build_parallel().run(|| {
-        let mut searcher = Searcher::new();
+    if let Some(candidates) = search_index::candidates(root, &options.pattern(query)) {
+        let mut searcher = options.searcher();
+        for rel in candidates {
+            let full_path = root.join(&rel);
+            if matches.len() >= limit {
+                break;
+            }
+            if overrides.matched(&full_path, false).is_ignore() {
+                continue;
+            }
+            let mut sink = MatchSink { path: &rel, matcher: &matcher, out: Vec::new(), before: Vec::new(), limit: limit - matches.len() };
+            let _ = searcher.search_path(&matcher, &full_path, &mut sink);
+            matches.extend(sink.out);
+        }
+        return Ok(matches);
+    }
+
+    let matches_mutex = std::sync::Mutex::new(&mut matches);
+    ignores::walker(root).overrides(overrides).build_parallel().run(|| {
+        let mut searcher = options.searcher();
         let matcher = matcher.clone();
         let matches_mutex = &matches_mutex; // Reference to mutex
         Box::new(move |result| {
@@ -136,63 +363,192 @@ pub fn search_code_internal(root: &Path, query: &str) -> Result<Vec<String>, FsE
                      return ignore::WalkState::Continue;
                 }
 
-                let _ = searcher.search_path(&matcher, entry.path(), UTF8(|lnumm, line| {
-                     let line_str = line.to_string();
-                     // Format: path:line: content
-                     let path_display = entry.path().strip_prefix(root).unwrap_or(entry.path()).to_string_lossy();
-                     let match_entry = format!("{}:{}: {}", path_display, lnumm, line_str.trim());
+                let remaining = match matches_mutex.lock() {
+                    Ok(lock) => limit.saturating_sub(lock.len()),
+                    Err(_) => return ignore::WalkState::Quit,
+                };
+                if remaining == 0 {
+                    return ignore::WalkState::Quit;
+                }
 
-                     if let Ok(mut lock) = matches_mutex.lock() {
-                         lock.push(match_entry);
-                     }
+                let rel = entry.path().strip_prefix(root).unwrap_or(entry.path());
+                let mut sink = MatchSink { path: rel, matcher: &matcher, out: Vec::new(), before: Vec::new(), limit: remaining };
+                let _ = searcher.search_path(&matcher, entry.path(), &mut sink);
 
-                     Ok(true) // Continue searching
-                }));
+                if !sink.out.is_empty() {
+                    if let Ok(mut lock) = matches_mutex.lock() {
+                        // Other threads may have used up part of the quota meanwhile
+                        let room = limit.saturating_sub(lock.len());
+                        lock.extend(sink.out.into_iter().take(room));
+                    }
+                }
             }
             ignore::WalkState::Continue
         })
     });
 
+    matches.sort_by(|a, b| (&a.path, a.line).cmp(&(&b.path, b.line)));
     Ok(matches)
 }
 
-fn validate_syntax(path: &str, content: &str) -> Result<(), String> {
-    if path.ends_with(".rs") {
-        parse_file(content).map_err(|e| format!("Rust Syntax Error: {}", e))?;
-    } else if path.ends_with(".ts") || path.ends_with(".js") || path.ends_with(".tsx") || path.ends_with(".jsx") {
-        let allocator = oxc_allocator::Allocator::default();
-        let source_type = oxc_span::SourceType::from_path(std::path::Path::new(path)).unwrap_or_default();
-        let ret = oxc_parser::Parser::new(&allocator, content, source_type).parse();
+// How much of a file is inspected to decide whether it is text, like git does
+const BINARY_SNIFF_LEN: usize = 8000;
+const BINARY_PREVIEW_LEN: usize = 32;
 
-        if !ret.errors.is_empty() {
-             return Err(format!("JS/TS Syntax Error: {:?}", ret.errors[0]));
+/// True when the first few KB decode as text in no encoding (see `encoding::decode`).
+pub fn is_binary(bytes: &[u8]) -> bool {
+    let sample = &bytes[..bytes.len().min(BINARY_SNIFF_LEN)];
+    // A multi-byte character cut off by the sample boundary is not evidence of binary
+    if let Err(e) = std::str::from_utf8(sample) {
+        if e.error_len().is_none() && !sample.contains(&0) {
+            return false;
         }
     }
-    Ok(())
+    encoding::decode(sample).is_none()
+}
+
+fn sniff_binary(path: &Path) -> bool {
+    use std::io::Read;
+    let mut buf = Vec::with_capacity(BINARY_SNIFF_LEN);
+    match std::fs::File::open(path) {
+        Ok(f) => f.take(BINARY_SNIFF_LEN as u64).read_to_end(&mut buf).is_ok() && is_binary(&buf),
+        Err(_) => false,
+    }
+}
+
+// Reads a file as text, transcoding it to UTF-8, and refuses binary content with a size
+// and hex summary
+fn read_text(full_path: &Path) -> Result<String, FsError> {
+    read_text_with_encoding(full_path).map(|(text, _)| text)
+}
+
+fn read_text_with_encoding(full_path: &Path) -> Result<(String, TextEncoding), FsError> {
+    let bytes = std::fs::read(full_path).map_err(FsError::Io)?;
+    encoding::decode(&bytes).ok_or_else(|| FsError::BinaryFile {
+        size: bytes.len() as u64,
+        preview: bytes.iter().take(BINARY_PREVIEW_LEN).map(|b| format!("{:02x}", b)).collect::<Vec<_>>().join(" "),
+    })
+}
+
+// Writes `content` in the encoding the file already has (UTF-8 for new files), atomically
+// and after backing up the old version
+fn write_text(root: &Path, file_path: &str, full_path: &Path, content: &str) -> Result<(), FsError> {
+    let target = read_text_with_encoding(full_path).map_or(TextEncoding::UTF8, |(_, e)| e);
+    let bytes = encoding::encode(content, target).map_err(FsError::Edit)?;
+    backup::backup_file(root, Path::new(file_path)).map_err(FsError::Io)?;
+    backup::atomic_write(full_path, &bytes).map_err(FsError::Io)
 }
 
 pub fn read_file_internal(root: &Path, file_path: String) -> Result<FileContent, FsError> {
-    let full_path = validate_path(root, &file_path, true)?;
-    let content = std::fs::read_to_string(&full_path).map_err(FsError::Io)?;
+    let full_path = resolve_path_safe(root, &file_path, true)?;
+    let content = read_text(&full_path)?;
     Ok(FileContent {
         path: PathBuf::from(file_path),
         content
     })
 }
 
-pub fn write_file_internal(root: &Path, file_path: String, content: String) -> Result<FileContent, FsError> {
-    let full_path = validate_path(root, &file_path, false)?;
+// The file as the agent reads it: notebooks are flattened to their cells
+fn read_file_view(root: &Path, file_path: String) -> Result<FileContent, FsError> {
+    let mut fc = read_file_internal(root, file_path)?;
+    if notebook::is_notebook(&fc.path) {
+        fc.content = notebook::flatten(&fc.content).map_err(FsError::Syntax)?;
+    }
+    Ok(fc)
+}
+
+/// Reads lines `start_line..=end_line` (1-based). Missing bounds default to the start and
+/// end of the file; an `end_line` past the end is clamped. Notebooks are read as their
+/// flattened cells.
+pub fn read_file_range(root: &Path, file_path: String, start_line: Option<usize>, end_line: Option<usize>) -> Result<FileSlice, FsError> {
+    let fc = read_file_view(root, file_path)?;
+    let all: Vec<&str> = fc.content.lines().collect();
+    let total_lines = all.len();
+    let start = start_line.unwrap_or(1).max(1);
+    let end = end_line.unwrap_or(total_lines).min(total_lines);
+    let lines = if start <= end { all[start - 1..end].iter().map(|l| l.to_string()).collect() } else { Vec::new() };
+    Ok(FileSlice { path: fc.path, start_line: start, end_line: end.max(start.saturating_sub(1)), total_lines, lines })
+}
+
+/// Cap on how much of a file one paged read returns.
+pub const DEFAULT_MAX_READ_BYTES: usize = 64 * 1024;
+
+static MAX_READ_BYTES: AtomicUsize = AtomicUsize::new(DEFAULT_MAX_READ_BYTES);
+
+pub fn max_read_bytes() -> usize {
+    MAX_READ_BYTES.load(Ordering::Relaxed)
+}
+
+pub fn set_max_read_bytes(bytes: usize) {
+    MAX_READ_BYTES.store(bytes.max(1), Ordering::Relaxed);
+}
+
+/// One page of a file. `next_offset` is where the following page starts, if any.
+#[derive(Debug, Clone, PartialEq)]
+pub struct FilePage {
+    pub path: PathBuf,
+    pub content: String,
+    pub offset: usize,
+    pub next_offset: Option<usize>,
+    pub total_bytes: usize,
+}
+
+/// Reads at most `max_bytes` starting at byte `offset`. Pages end on a line break when the
+/// page holds one, otherwise on a character boundary. Notebooks are read as their
+/// flattened cells.
+pub fn read_file_page(root: &Path, file_path: String, offset: usize, max_bytes: usize) -> Result<FilePage, FsError> {
+    let fc = read_file_view(root, file_path)?;
+    let total_bytes = fc.content.len();
+    if offset > total_bytes || !fc.content.is_char_boundary(offset) {
+        return Err(FsError::InvalidPath);
+    }
+
+    let rest = &fc.content[offset..];
+    let len = if rest.len() <= max_bytes {
+        rest.len()
+    } else {
+        let mut cut = max_bytes.max(1);
+        while !rest.is_char_boundary(cut) {
+            cut -= 1;
+        }
+        match rest[..cut].rfind('\n') {
+            Some(nl) => nl + 1,
+            // Cut fell inside the first character
+            None if cut == 0 => rest.chars().next().map_or(0, char::len_utf8),
+            None => cut,
+        }
+    };
+
+    let end = offset + len;
+    Ok(FilePage {
+        path: fc.path,
+        content: rest[..len].to_string(),
+        offset,
+        next_offset: if end < total_bytes { Some(end) } else { None },
+        total_bytes,
+    })
+}
+
+/// Writes `content`, syntax-checking it first unless `skip_validation` is set or the
+/// workspace config turns validation off. With `format_on_write` the returned content is
+/// the formatted file.
+pub fn write_file_internal(root: &Path, file_path: String, content: String, skip_validation: bool) -> Result<FileContent, FsError> {
+    let full_path = resolve_path_safe(root, &file_path, false)?;
+    let config = WorkspaceConfig::load(root);
 
     // Syntax Validation
-    if let Err(e) = validate_syntax(&file_path, &content) {
-        return Err(FsError::Syntax(e));
+    if !skip_validation && config.validate_on_write {
+        if let Err(e) = validate_syntax(&file_path, &content) {
+            return Err(FsError::Syntax(e));
+        }
     }
 
     if let Some(parent) = full_path.parent() {
         std::fs::create_dir_all(parent).map_err(FsError::Io)?;
     }
 
-    std::fs::write(&full_path, content.clone()).map_err(FsError::Io)?;
+    write_text(root, &file_path, &full_path, &content)?;
+    let content = format_written(root, &config, &full_path, content);
 
     Ok(FileContent {
         path: PathBuf::from(file_path),
@@ -200,14 +556,244 @@ pub fn write_file_internal(root: &Path, file_path: String, content: String) -> R
     })
 }
 
+// Unified diff of a pending write, with git-style a/ and b/ headers
+fn write_diff(file_path: &str, before: &str, after: &str) -> String {
+    similar::TextDiff::from_lines(before, after)
+        .unified_diff()
+        .header(&format!("a/{}", file_path), &format!("b/{}", file_path))
+        .to_string()
+}
+
+// The checks `write_text` would fail on, without writing
+fn check_encodable(full_path: &Path, content: &str) -> Result<(), FsError> {
+    let target = read_text_with_encoding(full_path).map_or(TextEncoding::UTF8, |(_, e)| e);
+    encoding::encode(content, target).map(|_| ()).map_err(FsError::Edit)
+}
+
+/// The change `write_file_internal` would make, as a unified diff (empty if the content is
+/// unchanged). Validation runs as for a real write; nothing touches the disk. Formatting
+/// from `format_on_write` is not part of the preview.
+pub fn preview_write(root: &Path, file_path: String, content: String, skip_validation: bool) -> Result<String, FsError> {
+    let full_path = resolve_path_safe(root, &file_path, false)?;
+    if !skip_validation && WorkspaceConfig::load(root).validate_on_write {
+        validate_syntax(&file_path, &content).map_err(FsError::Syntax)?;
+    }
+    let current = if full_path.is_file() { read_text(&full_path)? } else { String::new() };
+    check_encodable(&full_path, &content)?;
+    Ok(write_diff(&file_path, &current, &content))
+}
+
+/// `edit_file_internal` without the write: the diff it would produce and the number of
+/// replacements.
+pub fn preview_edit(root: &Path, file_path: String, edits: &[TextEdit]) -> Result<(String, usize), FsError> {
+    let full_path = resolve_path_safe(root, &file_path, true)?;
+    let original = read_text(&full_path)?;
+    let (content, replaced) = apply_edits(&original, edits).map_err(FsError::Edit)?;
+    if WorkspaceConfig::load(root).validate_on_write {
+        validate_syntax(&file_path, &content).map_err(FsError::Syntax)?;
+    }
+    check_encodable(&full_path, &content)?;
+    Ok((write_diff(&file_path, &original, &content), replaced))
+}
+
+// A formatter failure leaves the file as written: the write itself already succeeded
+fn format_written(root: &Path, config: &WorkspaceConfig, full_path: &Path, content: String) -> String {
+    if !config.format_on_write {
+        return content;
+    }
+    match format_file(root, full_path) {
+        Ok(true) => read_text(full_path).unwrap_or(content),
+        _ => content,
+    }
+}
+
+/// Deleted files are moved here, under a per-deletion timestamp directory, instead of
+/// being removed.
+pub const TRASH_DIR: &str = ".irongraph/trash";
+
+/// Moves a file into the workspace trash. Returns its new path relative to the root.
+pub fn delete_file_internal(root: &Path, file_path: String) -> Result<PathBuf, FsError> {
+    let canonical_root = root.canonicalize().map_err(FsError::Io)?;
+    let full_path = resolve_path_safe(&canonical_root, &file_path, true)?;
+    if !full_path.is_file() {
+        return Err(FsError::InvalidPath);
+    }
+    let rel = full_path.strip_prefix(&canonical_root).map_err(|_| FsError::InvalidPath)?;
+    if rel.starts_with(".irongraph") {
+        return Err(FsError::SecurityViolation);
+    }
+
+    let stamp = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map_or(0, |d| d.as_millis());
+    let trashed = Path::new(TRASH_DIR).join(stamp.to_string()).join(rel);
+    let target = canonical_root.join(&trashed);
+    if let Some(parent) = target.parent() {
+        std::fs::create_dir_all(parent).map_err(FsError::Io)?;
+    }
+    std::fs::rename(&full_path, &target).map_err(FsError::Io)?;
+    Ok(trashed)
+}
+
+/// Creates a directory (and any missing parents) inside the workspace.
+pub fn create_directory_internal(root: &Path, dir_path: String) -> Result<PathBuf, FsError> {
+    let full_path = resolve_path_safe(root, &dir_path, false)?;
+    std::fs::create_dir_all(&full_path).map_err(FsError::Io)?;
+    // Re-check now that the path exists, in case a symlinked ancestor led outside the root
+    resolve_path_safe(root, &dir_path, true)?;
+    Ok(PathBuf::from(dir_path))
+}
+
+/// One search-and-replace step of `edit_file_internal`.
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct TextEdit {
+    pub old_string: String,
+    pub new_string: String,
+    // Without it, `old_string` must occur exactly once
+    #[serde(default)]
+    pub replace_all: bool,
+}
+
+/// Applies `edits` in order. Returns the new content and the number of replacements.
+pub fn apply_edits(content: &str, edits: &[TextEdit]) -> Result<(String, usize), String> {
+    let mut content = content.to_string();
+    let mut replaced = 0;
+    for (i, edit) in edits.iter().enumerate() {
+        if edit.old_string.is_empty() {
+            return Err(format!("edit {}: old_string is empty", i + 1));
+        }
+        let count = content.matches(&edit.old_string).count();
+        match count {
+            0 => return Err(format!("edit {}: old_string not found", i + 1)),
+            n if n > 1 && !edit.replace_all => {
+                return Err(format!(
+                    "edit {}: old_string occurs {} times; include more surrounding context or set replace_all",
+                    i + 1, n
+                ));
+            }
+            _ => {}
+        }
+        content = content.replace(&edit.old_string, &edit.new_string);
+        replaced += count;
+    }
+    Ok((content, replaced))
+}
+
+/// Replaces the source of one notebook cell (or appends a cell at one past the end),
+/// leaving the rest of the JSON container as it was.
+pub fn write_notebook_cell(root: &Path, file_path: String, cell: usize, source: &str) -> Result<FileContent, FsError> {
+    if !notebook::is_notebook(Path::new(&file_path)) {
+        return Err(FsError::Edit(format!("{} is not a notebook (.ipynb)", file_path)));
+    }
+    rewrite_file(root, file_path, |original| notebook::set_cell(original, cell, source))
+}
+
+/// Edits a file in place with exact search-and-replace blocks. Nothing is written unless
+/// every edit applies and the result passes syntax validation.
+pub fn edit_file_internal(root: &Path, file_path: String, edits: &[TextEdit]) -> Result<(FileContent, usize), FsError> {
+    let mut replaced = 0;
+    let fc = rewrite_file(root, file_path, |original| {
+        let (content, count) = apply_edits(original, edits)?;
+        replaced = count;
+        Ok(content)
+    })?;
+    Ok((fc, replaced))
+}
+
+// Read-modify-write of an existing file; `transform` errors surface as FsError::Edit and
+// the result must pass syntax validation before anything is written.
+fn rewrite_file(root: &Path, file_path: String, transform: impl FnOnce(&str) -> Result<String, String>) -> Result<FileContent, FsError> {
+    let full_path = resolve_path_safe(root, &file_path, true)?;
+    let original = read_text(&full_path)?;
+    let content = transform(&original).map_err(FsError::Edit)?;
+
+    let config = WorkspaceConfig::load(root);
+    if config.validate_on_write {
+        validate_syntax(&file_path, &content).map_err(FsError::Syntax)?;
+    }
+    write_text(root, &file_path, &full_path, &content)?;
+    let content = format_written(root, &config, &full_path, content);
+
+    Ok(FileContent { path: PathBuf::from(file_path), content })
+}
+
+/// Replaces `remove` lines starting at 1-based line `start` with `insert`. Line endings of
+/// untouched lines are kept; `insert` gets a trailing newline if it lacks one.
+pub fn splice_lines(content: &str, start: usize, remove: usize, insert: &str) -> Result<String, String> {
+    let lines: Vec<&str> = content.split_inclusive('\n').collect();
+    let total = lines.len();
+    if start == 0 || start > total + 1 || start - 1 + remove > total {
+        return Err(format!(
+            "lines {}-{} are outside the file ({} lines)",
+            start, start + remove.max(1) - 1, total
+        ));
+    }
+
+    let mut out: String = lines[..start - 1].concat();
+    if !insert.is_empty() {
+        // Appending after a last line that has no newline
+        if !out.is_empty() && !out.ends_with('\n') {
+            out.push('\n');
+        }
+        out.push_str(insert);
+        if !insert.ends_with('\n') {
+            out.push('\n');
+        }
+    }
+    out.push_str(&lines[start - 1 + remove..].concat());
+    Ok(out)
+}
+
+/// Inserts `text` before 1-based `line`; `total_lines + 1` appends.
+pub fn insert_lines(root: &Path, file_path: String, line: usize, text: &str) -> Result<FileContent, FsError> {
+    rewrite_file(root, file_path, |content| splice_lines(content, line, 0, text))
+}
+
+/// Replaces lines `start_line..=end_line` (1-based) with `text`.
+pub fn replace_line_range(root: &Path, file_path: String, start_line: usize, end_line: usize, text: &str) -> Result<FileContent, FsError> {
+    let count = line_count(start_line, end_line)?;
+    rewrite_file(root, file_path, |content| splice_lines(content, start_line, count, text))
+}
+
+/// Deletes lines `start_line..=end_line` (1-based).
+pub fn delete_line_range(root: &Path, file_path: String, start_line: usize, end_line: usize) -> Result<FileContent, FsError> {
+    let count = line_count(start_line, end_line)?;
+    rewrite_file(root, file_path, |content| splice_lines(content, start_line, count, ""))
+}
+
+fn line_count(start_line: usize, end_line: usize) -> Result<usize, FsError> {
+    if start_line == 0 || end_line < start_line {
+        return Err(FsError::Edit(format!("invalid line range {}-{}", start_line, end_line)));
+    }
+    Ok(end_line - start_line + 1)
+}
+
 // Commands module removed or deprecated.
 // Logic functions above are now the public API.
 // We can remove the `commands` module completely as its logic is trival wrapping.
 // The `read_skeleton` logic needs to be exposed though.
 
 pub fn read_skeleton_internal(root: &Path, file_path: String) -> Result<String, FsError> {
-    let fc = read_file_internal(root, file_path.clone())?;
-    get_skeleton(Path::new(&file_path), &fc.content).map_err(|e| FsError::Io(std::io::Error::new(std::io::ErrorKind::Other, e)))
+    let full_path = resolve_path_safe(root, &file_path, true)?;
+    let content = read_text(&full_path)?;
+    skeleton::cached_skeleton(&full_path, &content).map_err(|e| FsError::Io(std::io::Error::new(std::io::ErrorKind::Other, e)))
+}
+
+/// Top-level items of a file with their line ranges.
+pub fn outline_internal(root: &Path, file_path: String) -> Result<Vec<OutlineItem>, FsError> {
+    let full_path = resolve_path_safe(root, &file_path, true)?;
+    let content = read_text(&full_path)?;
+    outline(Path::new(&file_path), &content).map_err(|e| FsError::Io(std::io::Error::new(std::io::ErrorKind::Other, e)))
+}
+
+/// Skeletons of several files, in order; one file failing does not fail the others.
+pub fn read_skeletons_internal(root: &Path, file_paths: Vec<String>) -> Vec<(String, Result<String, FsError>)> {
+    file_paths.into_iter()
+        .map(|file_path| {
+            let skeleton = read_skeleton_internal(root, file_path.clone());
+            (file_path, skeleton)
+        })
+        .collect()
 }
 
 #[cfg(test)]
@@ -223,12 +809,271 @@ mod tests {
         let outside_dir = tempdir().unwrap();
         let outside_file = outside_dir.path().join("secret.txt");
         File::create(&outside_file).unwrap();
-        let res = validate_path(root, "../secret.txt", false);
+        let res = resolve_path_safe(root, "../secret.txt", false);
         assert!(matches!(res, Err(FsError::SecurityViolation)));
         let inside_file = root.join("safe.txt");
         File::create(&inside_file).unwrap();
-        let res = validate_path(root, "safe.txt", true);
+        let res = resolve_path_safe(root, "safe.txt", true);
         assert!(res.is_ok());
+        let res = resolve_path_safe(root, "/etc/passwd", false);
+        assert!(matches!(res, Err(FsError::SecurityViolation)));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_new_paths_under_symlinked_dirs_stay_inside() {
+        let dir = tempdir().unwrap();
+        let root = dir.path();
+        let outside_dir = tempdir().unwrap();
+        std::os::unix::fs::symlink(outside_dir.path(), root.join("link_to_outside")).unwrap();
+        std::os::unix::fs::symlink(root.join("missing"), root.join("dangling")).unwrap();
+
+        for path in ["link_to_outside/file.rs", "link_to_outside/newdir/file.rs", "dangling/file.rs"] {
+            assert!(matches!(resolve_path_safe(root, path, false), Err(FsError::SecurityViolation)), "{}", path);
+        }
+        assert!(resolve_path_safe(root, "newdir/deeper/file.rs", false).is_ok());
+    }
+
+    #[test]
+    fn test_list_files_safe() {
+        let dir = tempdir().unwrap();
+        let root = dir.path();
+        std::fs::create_dir_all(root.join("src/nested")).unwrap();
+        File::create(root.join("src/main.rs")).unwrap();
+
+        let entries = list_files_safe(root, "src").unwrap();
+        let names: Vec<_> = entries.iter().map(|e| e.path.clone()).collect();
+        assert_eq!(names, vec![PathBuf::from("src/nested"), PathBuf::from("src/main.rs")]);
+
+        assert!(matches!(list_files_safe(root, "../"), Err(FsError::SecurityViolation)));
+        assert!(matches!(list_files_safe(root, "src/main.rs"), Err(FsError::InvalidPath)));
+        assert_eq!(list_files_safe(root, "").unwrap().len(), 1);
+    }
+
+    #[test]
+    fn test_read_file_range() {
+        let dir = tempdir().unwrap();
+        let root = dir.path();
+        let content: String = (1..=12).map(|i| format!("line {}\n", i)).collect();
+        std::fs::write(root.join("big.txt"), content).unwrap();
+
+        let slice = read_file_range(root, "big.txt".into(), Some(9), Some(10)).unwrap();
+        assert_eq!(slice.total_lines, 12);
+        assert_eq!(slice.lines, vec!["line 9", "line 10"]);
+        assert_eq!(slice.numbered(), " 9 | line 9\n10 | line 10\n");
+
+        let tail = read_file_range(root, "big.txt".into(), Some(11), Some(100)).unwrap();
+        assert_eq!((tail.start_line, tail.end_line, tail.lines.len()), (11, 12, 2));
+
+        let past_end = read_file_range(root, "big.txt".into(), Some(20), None).unwrap();
+        assert!(past_end.lines.is_empty());
+    }
+
+    #[test]
+    fn test_search_options() {
+        let dir = tempdir().unwrap();
+        let root = dir.path();
+        std::fs::create_dir(root.join("src")).unwrap();
+        std::fs::write(root.join("src/lib.rs"), "// setup\nfn Config() {}\n// teardown\nlet a = config(1);\n").unwrap();
+        std::fs::write(root.join("README.md"), "config(\n").unwrap();
+
+        let opts = SearchOptions { include: vec!["src/**/*.rs".into()], fixed_string: true, ..Default::default() };
+        let found = search_code_internal(root, "config(", &opts).unwrap();
+        assert_eq!(found, vec![SearchMatch {
+            path: PathBuf::from("src/lib.rs"),
+            line: 4,
+            end_line: 4,
+            column: 9,
+            text: "let a = config(1);".to_string(),
+            before: vec![],
+            after: vec![],
+        }]);
+
+        let opts = SearchOptions { case_insensitive: true, exclude: vec!["*.md".into()], before_context: 1, ..Default::default() };
+        let found = search_code_internal(root, "config\\(", &opts).unwrap();
+        let summary: Vec<_> = found.iter().map(|m| (m.line, m.column, m.before.clone())).collect();
+        assert_eq!(summary, vec![(2, 4, vec!["// setup".to_string()]), (4, 9, vec!["// teardown".to_string()])]);
+
+        let opts = SearchOptions { case_insensitive: true, max_results: Some(1), ..Default::default() };
+        assert_eq!(search_code_internal(root, "config", &opts).unwrap().len(), 1);
+    }
+
+    #[test]
+    fn test_multiline_search() {
+        let dir = tempdir().unwrap();
+        let root = dir.path();
+        std::fs::write(root.join("lib.rs"), "fn call(\n    a: u32,\n    b: u32,\n) {}\nfn other() {}\n").unwrap();
+
+        let pattern = r"fn call\(\s*a: u32,\s*b";
+        assert!(search_code_internal(root, pattern, &SearchOptions::default()).unwrap().is_empty());
+
+        let opts = SearchOptions { multiline: true, ..Default::default() };
+        let found = search_code_internal(root, pattern, &opts).unwrap();
+        assert_eq!(found.len(), 1);
+        assert_eq!((found[0].line, found[0].end_line), (1, 3));
+        assert_eq!(found[0].text, "fn call(\na: u32,\nb: u32,");
+    }
+
+    #[test]
+    fn test_read_file_page() {
+        let dir = tempdir().unwrap();
+        let root = dir.path();
+        std::fs::write(root.join("Cargo.lock"), "aaaa\nbbbb\ncccc\n").unwrap();
+
+        let first = read_file_page(root, "Cargo.lock".into(), 0, 12).unwrap();
+        assert_eq!(first.content, "aaaa\nbbbb\n");
+        assert_eq!((first.next_offset, first.total_bytes), (Some(10), 15));
+
+        let second = read_file_page(root, "Cargo.lock".into(), 10, 12).unwrap();
+        assert_eq!(second.content, "cccc\n");
+        assert_eq!(second.next_offset, None);
+
+        // No line break within the cap: split anyway, but never inside a character
+        std::fs::write(root.join("wide.txt"), "ééé").unwrap();
+        let page = read_file_page(root, "wide.txt".into(), 0, 3).unwrap();
+        assert_eq!((page.content.as_str(), page.next_offset), ("é", Some(2)));
+        assert!(matches!(read_file_page(root, "wide.txt".into(), 1, 3), Err(FsError::InvalidPath)));
+    }
+
+    #[test]
+    fn test_binary_detection() {
+        assert!(!is_binary(b"fn main() {}\n"));
+        assert!(!is_binary("héllo".as_bytes()));
+        assert!(is_binary(b"\x89PNG\r\n\x1a\n\0\0\0\rIHDR"));
+        assert!(is_binary(&[0xff, 0xfe, 0x41]));
+
+        let dir = tempdir().unwrap();
+        let root = dir.path();
+        std::fs::write(root.join("logo.png"), b"\x89PNG\r\n\x1a\n\0\0").unwrap();
+        std::fs::write(root.join("main.rs"), "fn main() {}").unwrap();
+
+        match read_file_internal(root, "logo.png".into()) {
+            Err(FsError::BinaryFile { size, preview }) => {
+                assert_eq!(size, 10);
+                assert!(preview.starts_with("89 50 4e 47"));
+            }
+            other => panic!("expected BinaryFile, got {:?}", other.map(|fc| fc.content)),
+        }
+
+        let entries = list_files_safe(root, "").unwrap();
+        let binary: Vec<_> = entries.iter().map(|e| (e.name.as_str(), e.is_binary)).collect();
+        assert_eq!(binary, vec![("logo.png", true), ("main.rs", false)]);
+    }
+
+    #[test]
+    fn test_splice_lines() {
+        let src = "a\nb\nc\n";
+        assert_eq!(splice_lines(src, 2, 0, "x").unwrap(), "a\nx\nb\nc\n");
+        assert_eq!(splice_lines(src, 4, 0, "d\n").unwrap(), "a\nb\nc\nd\n");
+        assert_eq!(splice_lines(src, 2, 2, "y").unwrap(), "a\ny\n");
+        assert_eq!(splice_lines(src, 1, 1, "").unwrap(), "b\nc\n");
+        assert_eq!(splice_lines("a\r\nb", 3, 0, "c").unwrap(), "a\r\nb\nc\n");
+        assert!(splice_lines(src, 3, 2, "").is_err());
+        assert!(splice_lines(src, 5, 0, "z").is_err());
+    }
+
+    #[test]
+    fn test_line_range_edits() {
+        let dir = tempdir().unwrap();
+        let root = dir.path();
+        std::fs::write(root.join("lib.rs"), "fn a() {}\nfn b() {}\nfn c() {}\n").unwrap();
+
+        replace_line_range(root, "lib.rs".into(), 2, 2, "fn bb() {}").unwrap();
+        insert_lines(root, "lib.rs".into(), 1, "// header").unwrap();
+        let fc = delete_line_range(root, "lib.rs".into(), 4, 4).unwrap();
+        assert_eq!(fc.content, "// header\nfn a() {}\nfn bb() {}\n");
+
+        assert!(matches!(delete_line_range(root, "lib.rs".into(), 3, 2), Err(FsError::Edit(_))));
+        assert!(matches!(insert_lines(root, "lib.rs".into(), 2, "fn ("), Err(FsError::Syntax(_))));
+        assert_eq!(std::fs::read_to_string(root.join("lib.rs")).unwrap(), fc.content);
+    }
+
+    #[test]
+    fn test_apply_edits_requires_unique_match() {
+        let edit = |old: &str, new: &str, all: bool| TextEdit { old_string: old.into(), new_string: new.into(), replace_all: all };
+        let src = "let a = 1;\nlet b = 1;\n";
+
+        assert_eq!(apply_edits(src, &[edit("a = 1", "a = 2", false)]).unwrap(), ("let a = 2;\nlet b = 1;\n".to_string(), 1));
+        assert!(apply_edits(src, &[edit("= 1", "= 2", false)]).unwrap_err().contains("occurs 2 times"));
+        assert_eq!(apply_edits(src, &[edit("= 1", "= 2", true)]).unwrap().1, 2);
+        assert!(apply_edits(src, &[edit("a = 1", "a = 2", false), edit("missing", "", false)]).unwrap_err().starts_with("edit 2"));
+    }
+
+    #[test]
+    fn test_delete_file_moves_to_trash() {
+        let dir = tempdir().unwrap();
+        let root = dir.path();
+        create_directory_internal(root, "src/old".into()).unwrap();
+        std::fs::write(root.join("src/old/dead.rs"), "fn dead() {}").unwrap();
+
+        let trashed = delete_file_internal(root, "src/old/dead.rs".into()).unwrap();
+        assert!(trashed.starts_with(TRASH_DIR));
+        assert!(trashed.ends_with("src/old/dead.rs"));
+        assert!(!root.join("src/old/dead.rs").exists());
+        assert_eq!(std::fs::read_to_string(root.join(&trashed)).unwrap(), "fn dead() {}");
+
+        assert!(matches!(delete_file_internal(root, "src/old".into()), Err(FsError::InvalidPath)));
+        assert!(matches!(delete_file_internal(root, trashed.to_string_lossy().to_string()), Err(FsError::SecurityViolation)));
+        assert!(matches!(create_directory_internal(root, "../escape".into()), Err(FsError::SecurityViolation)));
+    }
+
+    #[test]
+    fn test_write_skip_validation() {
+        let dir = tempdir().unwrap();
+        let root = dir.path();
+
+        assert!(matches!(write_file_internal(root, "a.rs".into(), "fn (".into(), false), Err(FsError::Syntax(_))));
+        assert!(write_file_internal(root, "fixture.rs".into(), "fn (".into(), true).is_ok());
+
+        std::fs::create_dir(root.join(".irongraph")).unwrap();
+        std::fs::write(root.join(config::CONFIG_PATH), "validate_on_write = false\n").unwrap();
+        assert!(write_file_internal(root, "a.rs".into(), "fn (".into(), false).is_ok());
+    }
+
+    #[test]
+    fn test_edit_file_rejects_broken_syntax() {
+        let dir = tempdir().unwrap();
+        let root = dir.path();
+        std::fs::write(root.join("lib.rs"), "fn a() {}\n").unwrap();
+        let edit = |new: &str| vec![TextEdit { old_string: "fn a() {}".into(), new_string: new.into(), replace_all: false }];
+
+        assert!(matches!(edit_file_internal(root, "lib.rs".into(), &edit("fn a( {}")), Err(FsError::Syntax(_))));
+        assert_eq!(std::fs::read_to_string(root.join("lib.rs")).unwrap(), "fn a() {}\n");
+        assert!(edit_file_internal(root, "lib.rs".into(), &edit("fn b() {}")).is_ok());
+        assert_eq!(std::fs::read_to_string(root.join("lib.rs")).unwrap(), "fn b() {}\n");
+    }
+
+    #[test]
+    fn test_edit_keeps_legacy_encoding() {
+        let dir = tempdir().unwrap();
+        let root = dir.path();
+        std::fs::write(root.join("notes.txt"), b"Caf\xe9 cr\xe8me br\xfbl\xe9e, na\xefve fa\xe7ade\n").unwrap();
+        assert_eq!(read_file_internal(root, "notes.txt".into()).unwrap().content, "Café crème brûlée, naïve façade\n");
+
+        let edit = vec![TextEdit { old_string: "Café".into(), new_string: "Thé".into(), replace_all: false }];
+        edit_file_internal(root, "notes.txt".into(), &edit).unwrap();
+        assert_eq!(std::fs::read(root.join("notes.txt")).unwrap(), b"Th\xe9 cr\xe8me br\xfbl\xe9e, na\xefve fa\xe7ade\n");
+    }
+
+    #[test]
+    fn test_preview_does_not_write() {
+        let dir = tempdir().unwrap();
+        let root = dir.path();
+        std::fs::write(root.join("lib.rs"), "fn a() {}\nfn b() {}\n").unwrap();
+
+        let diff = preview_write(root, "lib.rs".into(), "fn a() {}\nfn c() {}\n".into(), false).unwrap();
+        assert!(diff.starts_with("--- a/lib.rs\n+++ b/lib.rs\n"));
+        assert!(diff.contains("-fn b() {}\n+fn c() {}\n"));
+        assert!(preview_write(root, "new.rs".into(), "fn x() {}\n".into(), false).unwrap().contains("+fn x() {}"));
+        assert!(matches!(preview_write(root, "lib.rs".into(), "fn a( {".into(), false), Err(FsError::Syntax(_))));
+
+        let edit = vec![TextEdit { old_string: "fn b".into(), new_string: "fn d".into(), replace_all: false }];
+        let (diff, replaced) = preview_edit(root, "lib.rs".into(), &edit).unwrap();
+        assert_eq!(replaced, 1);
+        assert!(diff.contains("+fn d() {}"));
+
+        assert_eq!(std::fs::read_to_string(root.join("lib.rs")).unwrap(), "fn a() {}\nfn b() {}\n");
+        assert!(!root.join("new.rs").exists());
     }
 
     #[test]
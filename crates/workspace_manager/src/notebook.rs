@@ -0,0 +1,161 @@
+use std::path::Path;
+
+use serde_json::{Map, Value};
+
+// Jupyter notebooks are JSON containers the agent should never have to edit by hand. Reads
+// flatten them to `# %% [i] kind` separated cells, skeletons keep cell headers and
+// signatures, and writes replace the source of one cell inside the untouched container.
+
+pub fn is_notebook(path: &Path) -> bool {
+    path.extension().map_or(false, |ext| ext == "ipynb")
+}
+
+/// The notebook as JSON, checked to have a `cells` array.
+pub fn parse(content: &str) -> Result<Map<String, Value>, String> {
+    let notebook: Value = serde_json::from_str(content)
+        .map_err(|e| format!("Notebook JSON error at line {}, column {}: {}", e.line(), e.column(), e))?;
+    match notebook {
+        Value::Object(map) if map.get("cells").map_or(false, Value::is_array) => Ok(map),
+        _ => Err("Not a notebook: expected an object with a `cells` array".to_string()),
+    }
+}
+
+fn cells(notebook: &Map<String, Value>) -> &[Value] {
+    notebook.get("cells").and_then(Value::as_array).map_or(&[], Vec::as_slice)
+}
+
+fn cell_type(cell: &Value) -> &str {
+    cell.get("cell_type").and_then(Value::as_str).unwrap_or("code")
+}
+
+// `source` is either one string or a list of lines that keep their newlines
+fn cell_source(cell: &Value) -> String {
+    match cell.get("source") {
+        Some(Value::String(s)) => s.clone(),
+        Some(Value::Array(lines)) => lines.iter().filter_map(Value::as_str).collect(),
+        _ => String::new(),
+    }
+}
+
+fn cell_header(index: usize, cell: &Value) -> String {
+    format!("# %% [{}] {}", index, cell_type(cell))
+}
+
+/// Every cell's source under a `# %% [index] cell_type` header.
+pub fn flatten(content: &str) -> Result<String, String> {
+    let notebook = parse(content)?;
+    let mut out = String::new();
+    for (i, cell) in cells(&notebook).iter().enumerate() {
+        if i > 0 {
+            out.push('\n');
+        }
+        out.push_str(&cell_header(i, cell));
+        out.push('\n');
+        let source = cell_source(cell);
+        out.push_str(&source);
+        if !source.is_empty() && !source.ends_with('\n') {
+            out.push('\n');
+        }
+    }
+    Ok(out)
+}
+
+/// Cell headers with markdown headings and the `def`/`class` lines of code cells.
+pub fn skeleton(content: &str) -> Result<String, String> {
+    let notebook = parse(content)?;
+    let mut out = String::new();
+    for (i, cell) in cells(&notebook).iter().enumerate() {
+        out.push_str(&cell_header(i, cell));
+        out.push('\n');
+        let source = cell_source(cell);
+        let keep: fn(&str) -> bool = match cell_type(cell) {
+            "code" => |line| {
+                let line = line.trim_start();
+                line.starts_with("def ") || line.starts_with("async def ") || line.starts_with("class ") || line.starts_with('@')
+            },
+            "markdown" => |line| line.starts_with('#'),
+            _ => |_| false,
+        };
+        for line in source.lines().filter(|l| keep(l)) {
+            out.push_str(line.trim_end());
+            out.push('\n');
+        }
+    }
+    Ok(out)
+}
+
+// Back to nbformat's list-of-lines form
+fn source_lines(source: &str) -> Value {
+    Value::Array(source.split_inclusive('\n').map(|l| Value::String(l.to_string())).collect())
+}
+
+/// Replaces the source of cell `index`, or appends a code cell when `index` is one past the
+/// last cell. A changed code cell loses its outputs, which no longer match it. Everything
+/// else in the container is kept; the result uses nbformat's one-space indentation.
+pub fn set_cell(content: &str, index: usize, source: &str) -> Result<String, String> {
+    let mut notebook = parse(content)?;
+    let cells = notebook.get_mut("cells").and_then(Value::as_array_mut).expect("checked by parse");
+    if index == cells.len() {
+        cells.push(serde_json::json!({
+            "cell_type": "code",
+            "execution_count": null,
+            "metadata": {},
+            "outputs": [],
+            "source": [],
+        }));
+    }
+    let count = cells.len();
+    let cell = cells.get_mut(index)
+        .and_then(Value::as_object_mut)
+        .ok_or_else(|| format!("Cell {} does not exist (the notebook has {} cells)", index, count))?;
+    cell.insert("source".to_string(), source_lines(source));
+    if cell.get("cell_type").and_then(Value::as_str) == Some("code") {
+        cell.insert("outputs".to_string(), Value::Array(Vec::new()));
+        cell.insert("execution_count".to_string(), Value::Null);
+    }
+
+    let mut out = Vec::new();
+    let formatter = serde_json::ser::PrettyFormatter::with_indent(b" ");
+    let mut serializer = serde_json::Serializer::with_formatter(&mut out, formatter);
+    serde::Serialize::serialize(&Value::Object(notebook), &mut serializer).map_err(|e| e.to_string())?;
+    out.push(b'\n');
+    String::from_utf8(out).map_err(|e| e.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const NOTEBOOK: &str = r##"{
+ "cells": [
+  {"cell_type": "markdown", "metadata": {}, "source": ["# Analysis\n", "Loads the data."]},
+  {"cell_type": "code", "execution_count": 3, "metadata": {}, "outputs": [{"output_type": "stream", "text": "1\n"}],
+   "source": ["import pandas as pd\n", "\n", "def load(path):\n", "    return pd.read_csv(path)\n"]}
+ ],
+ "metadata": {"kernelspec": {"name": "python3"}},
+ "nbformat": 4,
+ "nbformat_minor": 5
+}"##;
+
+    #[test]
+    fn test_flatten_and_skeleton() {
+        assert_eq!(flatten(NOTEBOOK).unwrap(), "# %% [0] markdown\n# Analysis\nLoads the data.\n\n# %% [1] code\nimport pandas as pd\n\ndef load(path):\n    return pd.read_csv(path)\n");
+        assert_eq!(skeleton(NOTEBOOK).unwrap(), "# %% [0] markdown\n# Analysis\n# %% [1] code\ndef load(path):\n");
+        assert!(flatten("{\"cells\": 1}").is_err());
+    }
+
+    #[test]
+    fn test_set_cell() {
+        let updated = set_cell(NOTEBOOK, 1, "def load(path):\n    return path\n").unwrap();
+        let notebook = parse(&updated).unwrap();
+        let cell = &cells(&notebook)[1];
+        assert_eq!(cell["source"], serde_json::json!(["def load(path):\n", "    return path\n"]));
+        assert_eq!((cell["outputs"].clone(), cell["execution_count"].clone()), (serde_json::json!([]), Value::Null));
+        assert_eq!(notebook["metadata"]["kernelspec"]["name"], "python3");
+        assert_eq!(cells(&notebook)[0]["source"], serde_json::json!(["# Analysis\n", "Loads the data."]));
+
+        let appended = set_cell(&updated, 2, "print(1)").unwrap();
+        assert_eq!(cells(&parse(&appended).unwrap()).len(), 3);
+        assert!(set_cell(NOTEBOOK, 5, "x").is_err());
+    }
+}
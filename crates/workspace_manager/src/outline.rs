@@ -0,0 +1,232 @@
+use std::path::Path;
+use oxc_allocator::Allocator;
+use oxc_ast::ast::{Declaration, ExportDefaultDeclarationKind, Statement};
+use oxc_parser::Parser;
+use oxc_span::{GetSpan, SourceType, Span};
+use syn::spanned::Spanned;
+
+use crate::references::line_col;
+use crate::skeleton::{tree_sitter_lang, TreeSitterLang};
+
+// Top-level items of a file with their line ranges, so the agent can jump straight to
+// `read_file` / `replace_lines` on one item. Ranges include leading doc comments and
+// attributes where the parser attaches them to the item.
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct OutlineItem {
+    // "fn", "struct", "impl", "class", "export function", ...
+    pub kind: String,
+    pub name: String,
+    // 1-based, inclusive
+    pub start_line: usize,
+    pub end_line: usize,
+}
+
+pub fn outline(path: &Path, content: &str) -> Result<Vec<OutlineItem>, String> {
+    let ext = path.extension().and_then(|e| e.to_str()).unwrap_or("");
+    match ext {
+        "rs" => rust_outline(content),
+        "ts" | "tsx" | "js" | "jsx" | "mjs" | "cjs" => js_outline(path, content),
+        _ => match tree_sitter_lang(ext) {
+            Some(lang) => tree_sitter_outline(&lang, content),
+            None => Err("Unsupported file type for outline".to_string()),
+        },
+    }
+}
+
+fn type_name(ty: &syn::Type) -> String {
+    match ty {
+        syn::Type::Path(p) => p.path.segments.last().map_or_else(String::new, |s| s.ident.to_string()),
+        syn::Type::Reference(r) => type_name(&r.elem),
+        _ => "_".to_string(),
+    }
+}
+
+fn rust_outline(content: &str) -> Result<Vec<OutlineItem>, String> {
+    let file = syn::parse_file(content).map_err(|e| format!("Rust parse error: {}", e))?;
+    Ok(file.items.iter().filter_map(|item| {
+        let (kind, name) = match item {
+            syn::Item::Fn(f) => ("fn", f.sig.ident.to_string()),
+            syn::Item::Struct(s) => ("struct", s.ident.to_string()),
+            syn::Item::Enum(e) => ("enum", e.ident.to_string()),
+            syn::Item::Union(u) => ("union", u.ident.to_string()),
+            syn::Item::Trait(t) => ("trait", t.ident.to_string()),
+            syn::Item::Type(t) => ("type", t.ident.to_string()),
+            syn::Item::Const(c) => ("const", c.ident.to_string()),
+            syn::Item::Static(s) => ("static", s.ident.to_string()),
+            syn::Item::Mod(m) => ("mod", m.ident.to_string()),
+            syn::Item::Macro(m) => ("macro", m.ident.as_ref()?.to_string()),
+            syn::Item::Impl(i) => {
+                let name = match &i.trait_ {
+                    Some((_, path, _)) => format!(
+                        "{} for {}",
+                        path.segments.last().map_or_else(String::new, |s| s.ident.to_string()),
+                        type_name(&i.self_ty),
+                    ),
+                    None => type_name(&i.self_ty),
+                };
+                ("impl", name)
+            }
+            _ => return None,
+        };
+        let span = item.span();
+        Some(OutlineItem { kind: kind.to_string(), name, start_line: span.start().line, end_line: span.end().line })
+    }).collect())
+}
+
+fn js_declaration(decl: &Declaration) -> Option<(String, String)> {
+    let (kind, name) = match decl {
+        Declaration::FunctionDeclaration(f) => ("function".to_string(), f.id.as_ref()?.name.to_string()),
+        Declaration::ClassDeclaration(c) => ("class".to_string(), c.id.as_ref()?.name.to_string()),
+        Declaration::VariableDeclaration(v) => {
+            let names: Vec<String> = v.declarations.iter()
+                .filter_map(|d| d.id.get_identifier_name())
+                .map(|n| n.to_string())
+                .collect();
+            (v.kind.as_str().to_string(), names.join(", "))
+        }
+        Declaration::TSInterfaceDeclaration(i) => ("interface".to_string(), i.id.name.to_string()),
+        Declaration::TSTypeAliasDeclaration(t) => ("type".to_string(), t.id.name.to_string()),
+        Declaration::TSEnumDeclaration(e) => ("enum".to_string(), e.id.name.to_string()),
+        _ => return None,
+    };
+    Some((kind, name))
+}
+
+fn js_outline(path: &Path, content: &str) -> Result<Vec<OutlineItem>, String> {
+    let allocator = Allocator::default();
+    let source_type = SourceType::from_path(path).unwrap_or_default();
+    let ret = Parser::new(&allocator, content, source_type).parse();
+    if !ret.errors.is_empty() {
+        return Err(format!("JS Parse Error: {:?}", ret.errors[0]));
+    }
+
+    let lines = |span: Span| (
+        line_col(content, span.start as usize).0,
+        line_col(content, (span.end as usize).saturating_sub(1)).0,
+    );
+    Ok(ret.program.body.iter().filter_map(|stmt| {
+        let (kind, name) = match stmt {
+            Statement::ExportNamedDeclaration(e) => {
+                let (kind, name) = js_declaration(e.declaration.as_ref()?)?;
+                (format!("export {}", kind), name)
+            }
+            Statement::ExportDefaultDeclaration(e) => match &e.declaration {
+                ExportDefaultDeclarationKind::FunctionDeclaration(f) => (
+                    "export default function".to_string(),
+                    f.id.as_ref().map_or_else(|| "default".to_string(), |id| id.name.to_string()),
+                ),
+                ExportDefaultDeclarationKind::ClassDeclaration(c) => (
+                    "export default class".to_string(),
+                    c.id.as_ref().map_or_else(|| "default".to_string(), |id| id.name.to_string()),
+                ),
+                _ => ("export default".to_string(), "default".to_string()),
+            },
+            other => js_declaration(other.as_declaration()?)?,
+        };
+        let (start_line, end_line) = lines(stmt.span());
+        Some(OutlineItem { kind, name, start_line, end_line })
+    }).collect())
+}
+
+fn tree_sitter_kind(kind: &str) -> Option<&'static str> {
+    Some(match kind {
+        "function_definition" | "function_declaration" => "function",
+        "method_declaration" => "method",
+        "class_definition" | "class_declaration" | "class_specifier" => "class",
+        "interface_declaration" => "interface",
+        "enum_declaration" | "enum_specifier" => "enum",
+        "struct_specifier" => "struct",
+        "type_declaration" => "type",
+        "namespace_definition" => "namespace",
+        _ => return None,
+    })
+}
+
+// The `name` field, or for C-style declarators the identifier at the end of the
+// `declarator` chain, or the name of a child spec (Go `type_spec`)
+fn tree_sitter_name(node: tree_sitter::Node, content: &str) -> Option<String> {
+    if let Some(name) = node.child_by_field_name("name") {
+        return name.utf8_text(content.as_bytes()).ok().map(str::to_string);
+    }
+    if let Some(mut declarator) = node.child_by_field_name("declarator") {
+        while let Some(inner) = declarator.child_by_field_name("declarator") {
+            declarator = inner;
+        }
+        return declarator.utf8_text(content.as_bytes()).ok().map(str::to_string);
+    }
+    let mut cursor = node.walk();
+    let name = node.named_children(&mut cursor).find_map(|child| child.child_by_field_name("name"));
+    name.and_then(|n| n.utf8_text(content.as_bytes()).ok().map(str::to_string))
+}
+
+fn tree_sitter_outline(lang: &TreeSitterLang, content: &str) -> Result<Vec<OutlineItem>, String> {
+    let mut parser = tree_sitter::Parser::new();
+    parser.set_language(&(lang.language)()).map_err(|e| e.to_string())?;
+    let tree = parser.parse(content, None).ok_or("Parse error")?;
+
+    let root = tree.root_node();
+    let mut cursor = root.walk();
+    Ok(root.named_children(&mut cursor).filter_map(|node| {
+        // Python decorators wrap the definition; the range keeps them
+        let definition = if node.kind() == "decorated_definition" {
+            node.child_by_field_name("definition")?
+        } else {
+            node
+        };
+        let kind = tree_sitter_kind(definition.kind())?;
+        Some(OutlineItem {
+            kind: kind.to_string(),
+            name: tree_sitter_name(definition, content).unwrap_or_default(),
+            start_line: node.start_position().row + 1,
+            end_line: node.end_position().row + 1,
+        })
+    }).collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn summary(path: &str, content: &str) -> Vec<(String, String, usize, usize)> {
+        outline(Path::new(path), content).unwrap().into_iter()
+            .map(|i| (i.kind, i.name, i.start_line, i.end_line))
+            .collect()
+    }
+
+    fn item(kind: &str, name: &str, start: usize, end: usize) -> (String, String, usize, usize) {
+        (kind.to_string(), name.to_string(), start, end)
+    }
+
+    #[test]
+    fn test_rust_outline() {
+        let code = "use std::fmt;\n\n/// A point\n#[derive(Debug)]\npub struct Point {\n    x: i32,\n}\n\nimpl fmt::Display for Point {\n    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {\n        write!(f, \"{}\", self.x)\n    }\n}\n\nfn main() {}\n";
+        assert_eq!(summary("lib.rs", code), vec![
+            item("struct", "Point", 3, 7),
+            item("impl", "Display for Point", 9, 13),
+            item("fn", "main", 15, 15),
+        ]);
+    }
+
+    #[test]
+    fn test_js_outline() {
+        let code = "import x from './x';\n\nexport function load(id: string) {\n  return x(id);\n}\n\nconst cache = new Map();\n\nexport interface Item {\n  id: string;\n}\n\nexport default class Store {}\n";
+        assert_eq!(summary("store.ts", code), vec![
+            item("export function", "load", 3, 5),
+            item("const", "cache", 7, 7),
+            item("export interface", "Item", 9, 11),
+            item("export default class", "Store", 13, 13),
+        ]);
+    }
+
+    #[test]
+    fn test_tree_sitter_outline() {
+        let code = "import os\n\n@cache\ndef load(path):\n    return open(path)\n\nclass Store:\n    pass\n";
+        assert_eq!(summary("store.py", code), vec![
+            item("function", "load", 3, 5),
+            item("class", "Store", 7, 8),
+        ]);
+        let code = "int add(int a, int b) {\n    return a + b;\n}\n";
+        assert_eq!(summary("math.c", code), vec![item("function", "add", 1, 3)]);
+    }
+}
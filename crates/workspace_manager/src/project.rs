@@ -0,0 +1,329 @@
+use std::path::{Path, PathBuf};
+
+// What kind of project the workspace is: Cargo and npm manifests at the root and in
+// declared workspace members, plus toolchain pins. Feeds the agent's system context and
+// its default verification command.
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Dependency {
+    pub name: String,
+    // Version requirement, or the path/git source when there is none
+    pub version: String,
+    pub dev: bool,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Project {
+    // Directory of the manifest, relative to the root ("" for the root itself)
+    pub path: PathBuf,
+    // "cargo" or "npm"
+    pub kind: &'static str,
+    pub name: Option<String>,
+    pub version: Option<String>,
+    // Member directories of a Cargo or npm workspace
+    pub members: Vec<PathBuf>,
+    pub dependencies: Vec<Dependency>,
+    // npm scripts as (name, command)
+    pub scripts: Vec<(String, String)>,
+    // Cargo binary targets
+    pub bins: Vec<String>,
+}
+
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Toolchain {
+    // From rust-toolchain(.toml)
+    pub rust_channel: Option<String>,
+    // `rust-version` of the root crate or workspace
+    pub rust_version: Option<String>,
+    // From .nvmrc or `engines.node`
+    pub node_version: Option<String>,
+    // npm, pnpm, yarn or bun, from `packageManager` or the lockfile
+    pub package_manager: Option<String>,
+}
+
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ProjectInfo {
+    pub projects: Vec<Project>,
+    pub toolchain: Toolchain,
+    // Best guess at the command that builds and tests everything
+    pub verify_command: Option<String>,
+}
+
+fn read_toml(path: &Path) -> Option<toml::Value> {
+    toml::from_str(&std::fs::read_to_string(path).ok()?).ok()
+}
+
+fn read_json(path: &Path) -> Option<serde_json::Value> {
+    serde_json::from_str(&std::fs::read_to_string(path).ok()?).ok()
+}
+
+// Member patterns are directories, optionally ending in `/*`
+fn expand_members(root: &Path, dir: &Path, patterns: &[String], manifest: &str) -> Vec<PathBuf> {
+    let mut members = Vec::new();
+    for pattern in patterns {
+        let pattern = pattern.trim_end_matches('/');
+        match pattern.strip_suffix("/*") {
+            Some(parent) => {
+                let Ok(entries) = std::fs::read_dir(root.join(dir).join(parent)) else { continue };
+                let mut found: Vec<PathBuf> = entries.flatten()
+                    .filter(|e| e.path().join(manifest).is_file())
+                    .map(|e| dir.join(parent).join(e.file_name()))
+                    .collect();
+                found.sort();
+                members.extend(found);
+            }
+            None => members.push(dir.join(pattern)),
+        }
+    }
+    members
+}
+
+fn cargo_dependency(name: &str, spec: &toml::Value, dev: bool) -> Dependency {
+    let version = match spec {
+        toml::Value::String(v) => v.clone(),
+        toml::Value::Table(t) => ["version", "path", "git"].iter()
+            .find_map(|key| t.get(*key).and_then(|v| v.as_str()))
+            .map_or_else(|| "*".to_string(), |v| v.to_string()),
+        _ => "*".to_string(),
+    };
+    Dependency { name: name.to_string(), version, dev }
+}
+
+fn cargo_project(root: &Path, dir: &Path) -> Option<Project> {
+    let manifest = read_toml(&root.join(dir).join("Cargo.toml"))?;
+    let package = manifest.get("package");
+    let str_of = |table: Option<&toml::Value>, key: &str| table.and_then(|t| t.get(key)).and_then(|v| v.as_str()).map(str::to_string);
+
+    let mut dependencies = Vec::new();
+    for (section, dev) in [("dependencies", false), ("dev-dependencies", true), ("build-dependencies", true)] {
+        if let Some(deps) = manifest.get(section).and_then(|d| d.as_table()) {
+            dependencies.extend(deps.iter().map(|(name, spec)| cargo_dependency(name, spec, dev)));
+        }
+    }
+    let member_patterns: Vec<String> = manifest.get("workspace")
+        .and_then(|w| w.get("members"))
+        .and_then(|m| m.as_array())
+        .map(|m| m.iter().filter_map(|v| v.as_str().map(str::to_string)).collect())
+        .unwrap_or_default();
+    let mut bins: Vec<String> = manifest.get("bin")
+        .and_then(|b| b.as_array())
+        .map(|b| b.iter().filter_map(|t| str_of(Some(t), "name")).collect())
+        .unwrap_or_default();
+    if bins.is_empty() && root.join(dir).join("src/main.rs").is_file() {
+        bins.extend(str_of(package, "name"));
+    }
+
+    Some(Project {
+        path: dir.to_path_buf(),
+        kind: "cargo",
+        name: str_of(package, "name"),
+        version: str_of(package, "version"),
+        members: expand_members(root, dir, &member_patterns, "Cargo.toml"),
+        dependencies,
+        scripts: Vec::new(),
+        bins,
+    })
+}
+
+fn npm_project(root: &Path, dir: &Path) -> Option<Project> {
+    let manifest = read_json(&root.join(dir).join("package.json"))?;
+    let str_of = |key: &str| manifest.get(key).and_then(|v| v.as_str()).map(str::to_string);
+
+    let mut dependencies = Vec::new();
+    for (section, dev) in [("dependencies", false), ("devDependencies", true), ("peerDependencies", false)] {
+        if let Some(deps) = manifest.get(section).and_then(|d| d.as_object()) {
+            dependencies.extend(deps.iter().map(|(name, version)| Dependency {
+                name: name.clone(),
+                version: version.as_str().unwrap_or("*").to_string(),
+                dev,
+            }));
+        }
+    }
+    let scripts = manifest.get("scripts")
+        .and_then(|s| s.as_object())
+        .map(|s| s.iter().map(|(k, v)| (k.clone(), v.as_str().unwrap_or_default().to_string())).collect())
+        .unwrap_or_default();
+    // Either an array or Yarn's { packages: [...] }
+    let workspaces = manifest.get("workspaces").map(|w| w.get("packages").unwrap_or(w));
+    let member_patterns: Vec<String> = workspaces
+        .and_then(|w| w.as_array())
+        .map(|w| w.iter().filter_map(|v| v.as_str().map(str::to_string)).collect())
+        .unwrap_or_default();
+
+    Some(Project {
+        path: dir.to_path_buf(),
+        kind: "npm",
+        name: str_of("name"),
+        version: str_of("version"),
+        members: expand_members(root, dir, &member_patterns, "package.json"),
+        dependencies,
+        scripts,
+        bins: Vec::new(),
+    })
+}
+
+fn detect_toolchain(root: &Path) -> Toolchain {
+    let rust_channel = read_toml(&root.join("rust-toolchain.toml"))
+        .and_then(|t| t.get("toolchain")?.get("channel")?.as_str().map(str::to_string))
+        .or_else(|| std::fs::read_to_string(root.join("rust-toolchain")).ok().map(|s| s.trim().to_string()));
+    let rust_version = read_toml(&root.join("Cargo.toml")).and_then(|m| {
+        let version = m.get("package").and_then(|p| p.get("rust-version"))
+            .or_else(|| m.get("workspace")?.get("package")?.get("rust-version"))?;
+        version.as_str().map(str::to_string)
+    });
+
+    let package = read_json(&root.join("package.json"));
+    let node_version = std::fs::read_to_string(root.join(".nvmrc")).ok().map(|s| s.trim().to_string())
+        .or_else(|| package.as_ref()?.get("engines")?.get("node")?.as_str().map(str::to_string));
+    let package_manager = package.as_ref()
+        .and_then(|p| p.get("packageManager")?.as_str())
+        .map(|pm| pm.split('@').next().unwrap_or(pm).to_string())
+        .or_else(|| {
+            [("pnpm-lock.yaml", "pnpm"), ("yarn.lock", "yarn"), ("bun.lockb", "bun"), ("bun.lock", "bun"), ("package-lock.json", "npm")]
+                .iter()
+                .find(|(lock, _)| root.join(lock).is_file())
+                .map(|(_, pm)| pm.to_string())
+        })
+        .or_else(|| package.is_some().then(|| "npm".to_string()));
+
+    Toolchain { rust_channel, rust_version, node_version, package_manager }
+}
+
+pub fn detect_project(root: &Path) -> ProjectInfo {
+    let mut projects: Vec<Project> = Vec::new();
+    for root_project in [cargo_project(root, Path::new("")), npm_project(root, Path::new(""))].into_iter().flatten() {
+        let members = root_project.members.clone();
+        let kind = root_project.kind;
+        projects.push(root_project);
+        for member in members {
+            let project = if kind == "cargo" { cargo_project(root, &member) } else { npm_project(root, &member) };
+            projects.extend(project);
+        }
+    }
+
+    let toolchain = detect_toolchain(root);
+    let root_cargo = projects.iter().find(|p| p.kind == "cargo" && p.path.as_os_str().is_empty());
+    let root_npm = projects.iter().find(|p| p.kind == "npm" && p.path.as_os_str().is_empty());
+    let verify_command = match (root_cargo, root_npm) {
+        (Some(cargo), _) if !cargo.members.is_empty() => Some("cargo test --workspace".to_string()),
+        (Some(_), _) => Some("cargo test".to_string()),
+        (None, Some(npm)) => {
+            let pm = toolchain.package_manager.as_deref().unwrap_or("npm");
+            let has = |script: &str| npm.scripts.iter().any(|(name, _)| name == script);
+            if has("test") {
+                Some(format!("{} test", pm))
+            } else if has("build") {
+                Some(format!("{} run build", pm))
+            } else {
+                None
+            }
+        }
+        (None, None) => None,
+    };
+
+    ProjectInfo { projects, toolchain, verify_command }
+}
+
+impl ProjectInfo {
+    /// A few lines for the agent's system context; empty when nothing was detected.
+    pub fn summary(&self) -> String {
+        let mut lines = Vec::new();
+        for project in &self.projects {
+            let location = if project.path.as_os_str().is_empty() { ".".to_string() } else { project.path.display().to_string() };
+            let mut line = format!("- {} project at {}", project.kind, location);
+            if let Some(name) = &project.name {
+                line.push_str(&format!(": {}", name));
+                if let Some(version) = &project.version {
+                    line.push_str(&format!(" {}", version));
+                }
+            }
+            if !project.members.is_empty() {
+                line.push_str(&format!(" (workspace, {} members)", project.members.len()));
+            }
+            let deps: Vec<&str> = project.dependencies.iter().filter(|d| !d.dev).map(|d| d.name.as_str()).collect();
+            if !deps.is_empty() {
+                line.push_str(&format!("; depends on {}", deps.join(", ")));
+            }
+            if !project.scripts.is_empty() {
+                let scripts: Vec<&str> = project.scripts.iter().map(|(name, _)| name.as_str()).collect();
+                line.push_str(&format!("; scripts: {}", scripts.join(", ")));
+            }
+            if !project.bins.is_empty() {
+                line.push_str(&format!("; binaries: {}", project.bins.join(", ")));
+            }
+            lines.push(line);
+        }
+
+        let toolchain = &self.toolchain;
+        let pins: Vec<String> = [
+            toolchain.rust_channel.as_ref().map(|c| format!("rust toolchain {}", c)),
+            toolchain.rust_version.as_ref().map(|v| format!("rust-version {}", v)),
+            toolchain.node_version.as_ref().map(|v| format!("node {}", v)),
+            toolchain.package_manager.as_ref().map(|pm| format!("package manager {}", pm)),
+        ].into_iter().flatten().collect();
+        if !pins.is_empty() {
+            lines.push(format!("- Toolchain: {}", pins.join(", ")));
+        }
+        if let Some(command) = &self.verify_command {
+            lines.push(format!("- Verify with: `{}`", command));
+        }
+        lines.join("\n")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::tempdir;
+
+    #[test]
+    fn test_detect_cargo_workspace() {
+        let dir = tempdir().unwrap();
+        let root = dir.path();
+        std::fs::write(root.join("Cargo.toml"), "[workspace]\nmembers = [\"crates/*\"]\n\n[workspace.package]\nrust-version = \"1.75\"\n").unwrap();
+        std::fs::write(root.join("rust-toolchain.toml"), "[toolchain]\nchannel = \"stable\"\n").unwrap();
+        std::fs::create_dir_all(root.join("crates/cli/src")).unwrap();
+        std::fs::write(root.join("crates/cli/src/main.rs"), "fn main() {}\n").unwrap();
+        std::fs::write(root.join("crates/cli/Cargo.toml"), "[package]\nname = \"cli\"\nversion = \"0.2.0\"\n\n[dependencies]\nserde = { version = \"1\", features = [\"derive\"] }\ncore = { path = \"../core\" }\n\n[dev-dependencies]\ntempfile = \"3\"\n").unwrap();
+        std::fs::create_dir_all(root.join("crates/notes")).unwrap();
+
+        let info = detect_project(root);
+        assert_eq!(info.projects.len(), 2);
+        assert_eq!(info.projects[0].members, vec![PathBuf::from("crates/cli")]);
+        let cli = &info.projects[1];
+        assert_eq!((cli.name.as_deref(), cli.version.as_deref()), (Some("cli"), Some("0.2.0")));
+        assert_eq!(cli.bins, vec!["cli"]);
+        assert_eq!(cli.dependencies, vec![
+            Dependency { name: "core".into(), version: "../core".into(), dev: false },
+            Dependency { name: "serde".into(), version: "1".into(), dev: false },
+            Dependency { name: "tempfile".into(), version: "3".into(), dev: true },
+        ]);
+        assert_eq!(info.toolchain.rust_channel.as_deref(), Some("stable"));
+        assert_eq!(info.toolchain.rust_version.as_deref(), Some("1.75"));
+        assert_eq!(info.verify_command.as_deref(), Some("cargo test --workspace"));
+        assert!(info.summary().contains("- cargo project at crates/cli: cli 0.2.0; depends on core, serde; binaries: cli"));
+    }
+
+    #[test]
+    fn test_detect_npm_project() {
+        let dir = tempdir().unwrap();
+        let root = dir.path();
+        std::fs::write(root.join("package.json"), r#"{
+  "name": "web",
+  "packageManager": "pnpm@9.1.0",
+  "engines": { "node": ">=20" },
+  "scripts": { "build": "vite build", "test": "vitest" },
+  "dependencies": { "react": "^18.2.0" },
+  "devDependencies": { "vite": "^5.0.0" }
+}"#).unwrap();
+
+        let info = detect_project(root);
+        let web = &info.projects[0];
+        assert_eq!(web.scripts, vec![("build".to_string(), "vite build".to_string()), ("test".to_string(), "vitest".to_string())]);
+        assert_eq!(web.dependencies.iter().filter(|d| d.dev).map(|d| d.name.as_str()).collect::<Vec<_>>(), vec!["vite"]);
+        assert_eq!(info.toolchain.package_manager.as_deref(), Some("pnpm"));
+        assert_eq!(info.toolchain.node_version.as_deref(), Some(">=20"));
+        assert_eq!(info.verify_command.as_deref(), Some("pnpm test"));
+        assert!(detect_project(&root.join("missing")).projects.is_empty());
+    }
+}
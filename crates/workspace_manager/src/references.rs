@@ -0,0 +1,297 @@
+use std::collections::HashSet;
+use std::path::{Path, PathBuf};
+use oxc_allocator::Allocator;
+use oxc_ast::ast::{CallExpression, Expression, IdentifierReference, ImportDeclaration, StaticMemberExpression};
+use oxc_ast_visit::{walk, Visit as OxcVisit};
+use oxc_parser::Parser;
+use oxc_span::SourceType;
+use proc_macro2::{TokenStream, TokenTree};
+use syn::visit::{self, Visit};
+
+use crate::ignores::walker;
+
+// Identifier references resolved from the syntax tree rather than grepped: comments,
+// strings and longer identifiers containing the name are not hits. Resolution is by
+// name only; there is no type or scope information.
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ReferenceKind {
+    Definition,
+    Import,
+    Call,
+    Reference,
+}
+
+impl std::fmt::Display for ReferenceKind {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            ReferenceKind::Definition => "definition",
+            ReferenceKind::Import => "import",
+            ReferenceKind::Call => "call",
+            ReferenceKind::Reference => "reference",
+        })
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Reference {
+    pub path: PathBuf,
+    // 1-based
+    pub line: usize,
+    pub column: usize,
+    pub kind: ReferenceKind,
+    // The trimmed source line
+    pub snippet: String,
+}
+
+// (line, column, kind) within one file, both 1-based
+type Hit = (usize, usize, ReferenceKind);
+
+/// Every reference to `symbol` in Rust and JS/TS files under `root`, in path order.
+pub fn find_references(root: &Path, symbol: &str) -> Vec<Reference> {
+    let mut refs = Vec::new();
+    for entry in walker(root).build().flatten() {
+        let path = entry.path();
+        let ext = path.extension().and_then(|e| e.to_str()).unwrap_or("");
+        if !entry.file_type().map_or(false, |ft| ft.is_file()) {
+            continue;
+        }
+        let content = match std::fs::read_to_string(path) {
+            // Cheap pre-filter before parsing
+            Ok(c) if c.contains(symbol) => c,
+            _ => continue,
+        };
+        let hits = match ext {
+            "rs" => rust_references(&content, symbol),
+            "ts" | "tsx" | "js" | "jsx" | "mjs" | "cjs" => js_references(path, &content, symbol),
+            _ => continue,
+        };
+
+        let rel = path.strip_prefix(root).unwrap_or(path).to_path_buf();
+        let lines: Vec<&str> = content.lines().collect();
+        refs.extend(hits.into_iter().map(|(line, column, kind)| Reference {
+            path: rel.clone(),
+            line,
+            column,
+            kind,
+            snippet: lines.get(line - 1).map_or(String::new(), |l| l.trim().to_string()),
+        }));
+    }
+    refs.sort_by(|a, b| (&a.path, a.line, a.column).cmp(&(&b.path, b.line, b.column)));
+    refs
+}
+
+fn rust_references(content: &str, symbol: &str) -> Vec<Hit> {
+    let Ok(file) = syn::parse_file(content) else { return Vec::new() };
+    let mut visitor = RustRefs { symbol, hits: Vec::new(), seen: HashSet::new() };
+    visitor.visit_file(&file);
+    visitor.hits
+}
+
+// The specific visitors record first; `visit_ident` then skips positions already taken,
+// so each occurrence is reported once with its most specific kind
+struct RustRefs<'s> {
+    symbol: &'s str,
+    hits: Vec<Hit>,
+    seen: HashSet<(usize, usize)>,
+}
+
+impl RustRefs<'_> {
+    fn record(&mut self, ident: &proc_macro2::Ident, kind: ReferenceKind) {
+        if ident != self.symbol {
+            return;
+        }
+        let start = ident.span().start();
+        if self.seen.insert((start.line, start.column)) {
+            self.hits.push((start.line, start.column + 1, kind));
+        }
+    }
+
+    fn record_tokens(&mut self, tokens: TokenStream) {
+        for tt in tokens {
+            match tt {
+                TokenTree::Ident(ident) => self.record(&ident, ReferenceKind::Reference),
+                TokenTree::Group(group) => self.record_tokens(group.stream()),
+                _ => {}
+            }
+        }
+    }
+}
+
+impl<'ast> Visit<'ast> for RustRefs<'_> {
+    fn visit_ident(&mut self, i: &'ast proc_macro2::Ident) {
+        self.record(i, ReferenceKind::Reference);
+    }
+
+    fn visit_use_name(&mut self, i: &'ast syn::UseName) {
+        self.record(&i.ident, ReferenceKind::Import);
+    }
+
+    fn visit_use_rename(&mut self, i: &'ast syn::UseRename) {
+        self.record(&i.ident, ReferenceKind::Import);
+        visit::visit_use_rename(self, i);
+    }
+
+    fn visit_signature(&mut self, i: &'ast syn::Signature) {
+        self.record(&i.ident, ReferenceKind::Definition);
+        visit::visit_signature(self, i);
+    }
+
+    fn visit_item_struct(&mut self, i: &'ast syn::ItemStruct) {
+        self.record(&i.ident, ReferenceKind::Definition);
+        visit::visit_item_struct(self, i);
+    }
+
+    fn visit_item_enum(&mut self, i: &'ast syn::ItemEnum) {
+        self.record(&i.ident, ReferenceKind::Definition);
+        visit::visit_item_enum(self, i);
+    }
+
+    fn visit_item_trait(&mut self, i: &'ast syn::ItemTrait) {
+        self.record(&i.ident, ReferenceKind::Definition);
+        visit::visit_item_trait(self, i);
+    }
+
+    fn visit_expr_call(&mut self, i: &'ast syn::ExprCall) {
+        if let syn::Expr::Path(p) = &*i.func {
+            if let Some(last) = p.path.segments.last() {
+                self.record(&last.ident, ReferenceKind::Call);
+            }
+        }
+        visit::visit_expr_call(self, i);
+    }
+
+    fn visit_expr_method_call(&mut self, i: &'ast syn::ExprMethodCall) {
+        self.record(&i.method, ReferenceKind::Call);
+        visit::visit_expr_method_call(self, i);
+    }
+
+    // Macro arguments are not parsed, so look at their raw tokens
+    fn visit_macro(&mut self, i: &'ast syn::Macro) {
+        self.record_tokens(i.tokens.clone());
+        visit::visit_macro(self, i);
+    }
+}
+
+fn js_references(path: &Path, content: &str, symbol: &str) -> Vec<Hit> {
+    let allocator = Allocator::default();
+    let source_type = SourceType::from_path(path).unwrap_or_default();
+    let ret = Parser::new(&allocator, content, source_type).parse();
+    if !ret.errors.is_empty() {
+        return Vec::new();
+    }
+
+    let mut visitor = JsRefs { symbol, offsets: Vec::new(), seen: HashSet::new() };
+    visitor.visit_program(&ret.program);
+    visitor.offsets.into_iter()
+        .map(|(offset, kind)| {
+            let (line, column) = line_col(content, offset as usize);
+            (line, column, kind)
+        })
+        .collect()
+}
+
+struct JsRefs<'s> {
+    symbol: &'s str,
+    offsets: Vec<(u32, ReferenceKind)>,
+    seen: HashSet<u32>,
+}
+
+impl JsRefs<'_> {
+    fn record(&mut self, name: &str, offset: u32, kind: ReferenceKind) {
+        if name == self.symbol && self.seen.insert(offset) {
+            self.offsets.push((offset, kind));
+        }
+    }
+}
+
+impl<'a> OxcVisit<'a> for JsRefs<'_> {
+    fn visit_identifier_reference(&mut self, it: &IdentifierReference<'a>) {
+        self.record(it.name.as_str(), it.span.start, ReferenceKind::Reference);
+    }
+
+    fn visit_import_declaration(&mut self, it: &ImportDeclaration<'a>) {
+        for spec in it.specifiers.iter().flatten() {
+            let local = spec.local();
+            self.record(local.name.as_str(), local.span.start, ReferenceKind::Import);
+        }
+        walk::walk_import_declaration(self, it);
+    }
+
+    fn visit_call_expression(&mut self, it: &CallExpression<'a>) {
+        match &it.callee {
+            Expression::Identifier(id) => self.record(id.name.as_str(), id.span.start, ReferenceKind::Call),
+            Expression::StaticMemberExpression(m) => self.record(m.property.name.as_str(), m.property.span.start, ReferenceKind::Call),
+            _ => {}
+        }
+        walk::walk_call_expression(self, it);
+    }
+
+    fn visit_static_member_expression(&mut self, it: &StaticMemberExpression<'a>) {
+        self.record(it.property.name.as_str(), it.property.span.start, ReferenceKind::Reference);
+        walk::walk_static_member_expression(self, it);
+    }
+}
+
+// 1-based line and byte column of a byte offset
+pub(crate) fn line_col(content: &str, offset: usize) -> (usize, usize) {
+    let before = &content[..offset.min(content.len())];
+    let line_start = before.rfind('\n').map_or(0, |i| i + 1);
+    (before.matches('\n').count() + 1, offset - line_start + 1)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::tempdir;
+
+    #[test]
+    fn test_rust_references() {
+        let code = r#"use crate::util::parse_config;
+
+// parse_config is mentioned in a comment
+fn parse_config_twice() {}
+
+fn main() {
+    let cfg = parse_config("a");
+    println!("{:?}", parse_config);
+    let s = "parse_config";
+}
+"#;
+        let hits = rust_references(code, "parse_config");
+        assert_eq!(hits, vec![
+            (1, 18, ReferenceKind::Import),
+            (7, 15, ReferenceKind::Call),
+            (8, 22, ReferenceKind::Reference),
+        ]);
+    }
+
+    #[test]
+    fn test_js_references() {
+        let code = "import { render } from './ui';\n\nconst renderer = render;\napp.render();\n// render\n";
+        let hits = js_references(Path::new("main.ts"), code, "render");
+        assert_eq!(hits, vec![
+            (1, 10, ReferenceKind::Import),
+            (3, 18, ReferenceKind::Reference),
+            (4, 5, ReferenceKind::Call),
+        ]);
+    }
+
+    #[test]
+    fn test_find_references_across_files() {
+        let dir = tempdir().unwrap();
+        let root = dir.path();
+        std::fs::create_dir(root.join("src")).unwrap();
+        std::fs::write(root.join("src/lib.rs"), "pub fn helper() {}\n").unwrap();
+        std::fs::write(root.join("src/main.rs"), "fn main() {\n    mylib::helper();\n}\n").unwrap();
+        std::fs::write(root.join("notes.txt"), "helper").unwrap();
+
+        let refs = find_references(root, "helper");
+        let summary: Vec<_> = refs.iter().map(|r| (r.path.to_string_lossy().replace('\\', "/"), r.line, r.kind)).collect();
+        assert_eq!(summary, vec![
+            ("src/lib.rs".to_string(), 1, ReferenceKind::Definition),
+            ("src/main.rs".to_string(), 2, ReferenceKind::Call),
+        ]);
+        assert_eq!(refs[1].snippet, "mylib::helper();");
+    }
+}
@@ -0,0 +1,331 @@
+use std::collections::BTreeMap;
+use std::path::{Component, Path, PathBuf};
+use regex::Regex;
+
+use crate::backup::atomic_write;
+use crate::ignores::{walker, walker_in};
+use crate::{resolve_path_safe, FsError};
+
+// Moving a file and fixing up what pointed at it. Handled:
+// - Rust: the `mod` declaration moves to the new parent module (keeping its visibility) and
+//   `crate::old::path` becomes `crate::new::path` across the crate. Grouped imports such
+//   as `use crate::a::{b, c}` and `super::` paths are left alone.
+// - JS/TS: relative specifiers in `import`/`export ... from`, `import()` and `require()`
+//   that resolve to the moved file, plus the moved file's own relative imports.
+// Every rewrite is planned against the old layout before anything on disk changes.
+
+pub(crate) const JS_EXTS: &[&str] = &["ts", "tsx", "js", "jsx", "mjs", "cjs"];
+
+// Relative specifiers in `import`/`export ... from`, `import()` and `require()`; group 3 is the specifier
+pub(crate) const JS_IMPORT: &str = r#"(\bfrom\s*|\bimport\s*\(\s*|\brequire\s*\(\s*|\bimport\s+)(['"])(\.\.?/[^'"]*)(['"])"#;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct MoveOutcome {
+    pub to: PathBuf,
+    // Files whose imports were rewritten, relative to the root
+    pub updated: Vec<PathBuf>,
+}
+
+// Planned file contents, keyed by absolute path (the moved file under its old path)
+type Rewrites = BTreeMap<PathBuf, String>;
+
+struct MovePlan {
+    root: PathBuf,
+    from_full: PathBuf,
+    to_full: PathBuf,
+    to_rel: PathBuf,
+    rewrites: Rewrites,
+}
+
+fn plan_move(root: &Path, from: &str, to: &str) -> Result<MovePlan, FsError> {
+    let root = root.canonicalize().map_err(FsError::Io)?;
+    let from_full = resolve_path_safe(&root, from, true)?;
+    if !from_full.is_file() {
+        return Err(FsError::InvalidPath);
+    }
+    let to_full = resolve_path_safe(&root, to, false)?;
+    if to_full.exists() {
+        return Err(FsError::Edit(format!("{} already exists", to)));
+    }
+    let from_rel = from_full.strip_prefix(&root).map_err(|_| FsError::InvalidPath)?.to_path_buf();
+    let to_rel = PathBuf::from(to);
+
+    let mut rewrites = Rewrites::new();
+    match extension(&from_rel) {
+        "rs" => plan_rust(&root, &from_rel, &to_rel, &mut rewrites)?,
+        ext if JS_EXTS.contains(&ext) => plan_js(&root, &from_full, &to_full, &mut rewrites),
+        _ => {}
+    }
+    Ok(MovePlan { root, from_full, to_full, to_rel, rewrites })
+}
+
+/// Files other than the moved one whose imports a move would rewrite, relative to the
+/// root. Nothing on disk changes.
+pub fn move_importers(root: &Path, from: &str, to: &str) -> Result<Vec<PathBuf>, FsError> {
+    let plan = plan_move(root, from, to)?;
+    Ok(plan.rewrites.into_keys()
+        .filter(|path| *path != plan.from_full)
+        .map(|path| path.strip_prefix(&plan.root).unwrap_or(&path).to_path_buf())
+        .collect())
+}
+
+pub fn move_file_internal(root: &Path, from: String, to: String) -> Result<MoveOutcome, FsError> {
+    let MovePlan { root, from_full, to_full, to_rel, rewrites } = plan_move(root, &from, &to)?;
+
+    if let Some(parent) = to_full.parent() {
+        std::fs::create_dir_all(parent).map_err(FsError::Io)?;
+    }
+    std::fs::rename(&from_full, &to_full).map_err(FsError::Io)?;
+
+    let mut updated = Vec::new();
+    for (path, content) in rewrites {
+        let path = if path == from_full { to_full.clone() } else { path };
+        atomic_write(&path, content.as_bytes()).map_err(FsError::Io)?;
+        updated.push(path.strip_prefix(&root).unwrap_or(&path).to_path_buf());
+    }
+    updated.sort();
+    Ok(MoveOutcome { to: to_rel, updated })
+}
+
+fn extension(path: &Path) -> &str {
+    path.extension().and_then(|e| e.to_str()).unwrap_or("")
+}
+
+// Current planned content of a file, falling back to what is on disk
+fn planned(rewrites: &Rewrites, path: &Path) -> Option<String> {
+    rewrites.get(path).cloned().or_else(|| std::fs::read_to_string(path).ok())
+}
+
+fn plan(rewrites: &mut Rewrites, path: &Path, before: &str, after: String) {
+    if after != before {
+        rewrites.insert(path.to_path_buf(), after);
+    }
+}
+
+// ---------- Rust ----------
+
+// `src/a/b.rs` and `src/a/b/mod.rs` -> (`src`, [a, b]); None for crate roots
+pub(crate) fn rust_module(rel: &Path) -> Option<(PathBuf, Vec<String>)> {
+    let comps: Vec<String> = rel.components().map(|c| c.as_os_str().to_string_lossy().to_string()).collect();
+    let src = comps.iter().rposition(|c| c == "src")?;
+    let crate_src: PathBuf = comps[..=src].iter().collect();
+    let mut segments = comps[src + 1..].to_vec();
+    let stem = segments.pop()?.strip_suffix(".rs")?.to_string();
+    if segments.is_empty() && (stem == "lib" || stem == "main") {
+        return None;
+    }
+    if stem != "mod" {
+        segments.push(stem);
+    }
+    (!segments.is_empty()).then_some((crate_src, segments))
+}
+
+// Existing files that can declare the module `segments`
+fn parent_module_files(root: &Path, crate_src: &Path, segments: &[String]) -> Vec<PathBuf> {
+    let parent = &segments[..segments.len() - 1];
+    let src = root.join(crate_src);
+    let candidates = if parent.is_empty() {
+        vec![src.join("lib.rs"), src.join("main.rs")]
+    } else {
+        let dir: PathBuf = parent.iter().collect();
+        vec![src.join(&dir).with_extension("rs"), src.join(&dir).join("mod.rs")]
+    };
+    candidates.into_iter().filter(|p| p.is_file()).collect()
+}
+
+fn plan_rust(root: &Path, from_rel: &Path, to_rel: &Path, rewrites: &mut Rewrites) -> Result<(), FsError> {
+    let unsupported = |what: &str| FsError::Edit(format!("cannot update imports when {}", what));
+    let (old_src, old) = rust_module(from_rel).ok_or_else(|| unsupported("moving a crate root or a file outside src/"))?;
+    let (new_src, new) = rust_module(to_rel).ok_or_else(|| unsupported("moving to a crate root or outside src/"))?;
+    if old_src != new_src {
+        return Err(unsupported("moving between crates"));
+    }
+    if old == new {
+        return Ok(());
+    }
+
+    // Move the `mod` declaration
+    let old_name = old.last().unwrap();
+    let new_name = new.last().unwrap();
+    let decl = Regex::new(&format!(
+        r"(?m)^[ \t]*((?:pub(?:\([^)]*\))?[ \t]+)?)mod[ \t]+{}[ \t]*;[ \t]*\r?\n?",
+        regex::escape(old_name)
+    )).unwrap();
+    let mut visibility = String::new();
+    for parent in parent_module_files(root, &old_src, &old) {
+        let Some(content) = planned(rewrites, &parent) else { continue };
+        if let Some(caps) = decl.captures(&content) {
+            visibility = caps[1].to_string();
+            let after = decl.replace(&content, "").into_owned();
+            plan(rewrites, &parent, &content, after);
+            break;
+        }
+    }
+
+    let new_parent = parent_module_files(root, &new_src, &new).into_iter().next().ok_or_else(|| {
+        FsError::Edit(format!("no parent module file declares `{}`; create it first", new[..new.len() - 1].join("::")))
+    })?;
+    let content = planned(rewrites, &new_parent).unwrap_or_default();
+    let any_decl = Regex::new(r"(?m)^[ \t]*(?:pub(?:\([^)]*\))?[ \t]+)?mod[ \t]+\w+[ \t]*;[^\n]*\n?").unwrap();
+    let at = any_decl.find_iter(&content).last().map_or(0, |m| m.end());
+    let mut line = format!("{}mod {};\n", visibility, new_name);
+    if at > 0 && !content[..at].ends_with('\n') {
+        line.insert(0, '\n');
+    }
+    let mut after = content.clone();
+    after.insert_str(at, &line);
+    plan(rewrites, &new_parent, &content, after);
+
+    // Rewrite absolute paths across the crate
+    let old_path = Regex::new(&format!(r"\bcrate::{}\b", regex::escape(&old.join("::")))).unwrap();
+    let new_path = format!("crate::{}", new.join("::"));
+    for entry in walker_in(root, &root.join(&old_src)).build().flatten() {
+        let path = entry.path();
+        if extension(path) != "rs" {
+            continue;
+        }
+        let Some(content) = planned(rewrites, path) else { continue };
+        let after = old_path.replace_all(&content, new_path.as_str()).into_owned();
+        plan(rewrites, path, &content, after);
+    }
+    Ok(())
+}
+
+// ---------- JS / TS ----------
+
+#[derive(Clone, Copy)]
+pub(crate) enum SpecStyle {
+    // `./a.js`
+    Explicit,
+    // `./a` for a.ts
+    Extensionless,
+    // `./dir` for dir/index.ts
+    Index,
+}
+
+pub(crate) fn resolve_js(dir: &Path, spec: &str) -> Option<(PathBuf, SpecStyle)> {
+    let base = dir.join(spec);
+    if base.is_file() {
+        return base.canonicalize().ok().map(|p| (p, SpecStyle::Explicit));
+    }
+    for ext in JS_EXTS {
+        let candidate = PathBuf::from(format!("{}.{}", base.display(), ext));
+        if candidate.is_file() {
+            return candidate.canonicalize().ok().map(|p| (p, SpecStyle::Extensionless));
+        }
+    }
+    JS_EXTS.iter()
+        .map(|ext| base.join(format!("index.{}", ext)))
+        .find(|p| p.is_file())
+        .and_then(|p| p.canonicalize().ok())
+        .map(|p| (p, SpecStyle::Index))
+}
+
+fn relative_spec(from_dir: &Path, to: &Path) -> String {
+    let a: Vec<Component> = from_dir.components().filter(|c| *c != Component::CurDir).collect();
+    let b: Vec<Component> = to.components().filter(|c| *c != Component::CurDir).collect();
+    let common = a.iter().zip(&b).take_while(|(x, y)| x == y).count();
+    let mut parts: Vec<String> = vec!["..".to_string(); a.len() - common];
+    parts.extend(b[common..].iter().map(|c| c.as_os_str().to_string_lossy().to_string()));
+    let spec = parts.join("/");
+    if spec.starts_with("..") { spec } else { format!("./{}", spec) }
+}
+
+fn spec_for(importer_dir: &Path, target: &Path, style: SpecStyle) -> String {
+    let is_index = target.file_stem().map_or(false, |s| s == "index");
+    match style {
+        SpecStyle::Explicit => relative_spec(importer_dir, target),
+        SpecStyle::Index if is_index => relative_spec(importer_dir, target.parent().unwrap_or(target)),
+        _ => relative_spec(importer_dir, &target.with_extension("")),
+    }
+}
+
+fn plan_js(root: &Path, from_full: &Path, to_full: &Path, rewrites: &mut Rewrites) {
+    let import = Regex::new(JS_IMPORT).unwrap();
+    let new_dir = to_full.parent().unwrap_or(root);
+
+    for entry in walker(root).build().flatten() {
+        let path = entry.path();
+        if !JS_EXTS.contains(&extension(path)) {
+            continue;
+        }
+        let Some(content) = planned(rewrites, path) else { continue };
+        let Some(dir) = path.parent() else { continue };
+        let is_moved = path == from_full;
+
+        let after = import.replace_all(&content, |caps: &regex::Captures| {
+            let spec = &caps[3];
+            let rewritten = resolve_js(dir, spec).and_then(|(target, style)| {
+                if target == from_full {
+                    Some(spec_for(if is_moved { new_dir } else { dir }, to_full, style))
+                } else if is_moved {
+                    Some(spec_for(new_dir, &target, style))
+                } else {
+                    None
+                }
+            });
+            format!("{}{}{}{}", &caps[1], &caps[2], rewritten.as_deref().unwrap_or(spec), &caps[4])
+        }).into_owned();
+        plan(rewrites, path, &content, after);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::tempdir;
+
+    fn write(root: &Path, rel: &str, content: &str) {
+        let path = root.join(rel);
+        std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+        std::fs::write(path, content).unwrap();
+    }
+
+    fn read(root: &Path, rel: &str) -> String {
+        std::fs::read_to_string(root.join(rel)).unwrap()
+    }
+
+    #[test]
+    fn test_move_rust_module() {
+        let dir = tempdir().unwrap();
+        let root = dir.path();
+        write(root, "src/lib.rs", "mod util;\npub mod net;\n\nuse crate::util::helpers::parse;\n");
+        write(root, "src/util.rs", "pub mod helpers;\n");
+        write(root, "src/util/helpers.rs", "pub fn parse() {}\n");
+        write(root, "src/net.rs", "pub fn run() { crate::util::helpers::parse(); crate::util::helpers_v2(); }\n");
+
+        let outcome = move_file_internal(root, "src/util/helpers.rs".into(), "src/net/parsing.rs".into()).unwrap();
+        assert_eq!(outcome.updated, vec![PathBuf::from("src/lib.rs"), PathBuf::from("src/net.rs"), PathBuf::from("src/util.rs")]);
+
+        assert_eq!(read(root, "src/net/parsing.rs"), "pub fn parse() {}\n");
+        assert_eq!(read(root, "src/util.rs"), "");
+        assert_eq!(read(root, "src/net.rs"), "pub mod parsing;\npub fn run() { crate::net::parsing::parse(); crate::util::helpers_v2(); }\n");
+        assert_eq!(read(root, "src/lib.rs"), "mod util;\npub mod net;\n\nuse crate::net::parsing::parse;\n");
+    }
+
+    #[test]
+    fn test_move_rust_needs_parent_module() {
+        let dir = tempdir().unwrap();
+        let root = dir.path();
+        write(root, "src/lib.rs", "mod a;\n");
+        write(root, "src/a.rs", "");
+
+        assert!(matches!(move_file_internal(root, "src/a.rs".into(), "src/missing/a.rs".into()), Err(FsError::Edit(_))));
+        assert!(root.join("src/a.rs").exists());
+    }
+
+    #[test]
+    fn test_move_ts_file() {
+        let dir = tempdir().unwrap();
+        let root = dir.path();
+        write(root, "src/app.ts", "import { a } from './lib/a';\nconst lazy = import(\"./lib/a\");\nimport './c';\n");
+        write(root, "src/lib/a.ts", "import { c } from '../c';\nexport const a = c;\n");
+        write(root, "src/c.ts", "export const c = 1;\n");
+
+        assert_eq!(move_importers(root, "src/lib/a.ts", "src/features/x/a2.ts").unwrap(), vec![PathBuf::from("src/app.ts")]);
+        let outcome = move_file_internal(root, "src/lib/a.ts".into(), "src/features/x/a2.ts".into()).unwrap();
+        assert_eq!(outcome.updated, vec![PathBuf::from("src/app.ts"), PathBuf::from("src/features/x/a2.ts")]);
+        assert_eq!(read(root, "src/app.ts"), "import { a } from './features/x/a2';\nconst lazy = import(\"./features/x/a2\");\nimport './c';\n");
+        assert_eq!(read(root, "src/features/x/a2.ts"), "import { c } from '../../c';\nexport const a = c;\n");
+    }
+}
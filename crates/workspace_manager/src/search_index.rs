@@ -0,0 +1,370 @@
+use std::collections::{BTreeSet, HashMap};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex, OnceLock};
+use regex_syntax::hir::{Hir, HirKind};
+use tantivy::collector::DocSetCollector;
+use tantivy::directory::MmapDirectory;
+use tantivy::query::{BooleanQuery, Occur, Query, RegexQuery, TermQuery};
+use tantivy::schema::{Field, IndexRecordOption, Schema, TextFieldIndexing, TextOptions, Value, STORED, STRING};
+use tantivy::tokenizer::{LowerCaser, NgramTokenizer, TextAnalyzer};
+use tantivy::{doc, Index, IndexReader, IndexWriter, ReloadPolicy, TantivyDocument, Term};
+
+use crate::encoding::decode;
+use crate::ignores::{walker, walker_in};
+use crate::{FsChange, WorkspaceConfig};
+
+// Optional trigram index that narrows `search_code` down to the files that can match.
+// Enabled with `search_index = true` in the workspace config; it is built in the
+// background, kept current by the watcher, and stored in `.irongraph/search-index`.
+// Queries are still run by the grep searcher over the candidate files and every file too
+// big to index, so results match a full walk as far as the index is current: a change the
+// watcher has not delivered yet can be missed. Patterns without a literal run of 3+
+// characters every match must contain (`a|b`, `\w+`) fall back to the walk.
+
+pub const INDEX_DIR: &str = ".irongraph/search-index";
+
+const TOKENIZER: &str = "trigram";
+const WRITER_HEAP_BYTES: usize = 50_000_000;
+// Bigger files are left out of the index and always searched
+const MAX_FILE_BYTES: u64 = 1024 * 1024;
+
+pub struct SearchIndex {
+    reader: IndexReader,
+    writer: Mutex<IndexWriter>,
+    path: Field,
+    content: Field,
+    // Files over MAX_FILE_BYTES, relative to the root
+    oversized: Mutex<BTreeSet<PathBuf>>,
+    // Set once the first full build has been committed
+    ready: AtomicBool,
+}
+
+impl SearchIndex {
+    pub fn open(root: &Path) -> tantivy::Result<Self> {
+        let mut schema = Schema::builder();
+        let path = schema.add_text_field("path", STRING | STORED);
+        let content = schema.add_text_field("content", TextOptions::default().set_indexing_options(
+            TextFieldIndexing::default().set_tokenizer(TOKENIZER).set_index_option(IndexRecordOption::Basic),
+        ));
+        let schema = schema.build();
+
+        let dir = root.join(INDEX_DIR);
+        std::fs::create_dir_all(&dir)?;
+        let index = Index::open_or_create(MmapDirectory::open(&dir)?, schema)?;
+        index.tokenizers().register(
+            TOKENIZER,
+            TextAnalyzer::builder(NgramTokenizer::new(3, 3, false)?).filter(LowerCaser).build(),
+        );
+        let reader = index.reader_builder().reload_policy(ReloadPolicy::Manual).try_into()?;
+        // One thread: the build runs in the background and updates are a few files at a time
+        let writer = index.writer_with_num_threads(1, WRITER_HEAP_BYTES)?;
+        Ok(SearchIndex { reader, writer: Mutex::new(writer), path, content, oversized: Mutex::new(BTreeSet::new()), ready: AtomicBool::new(false) })
+    }
+
+    /// Re-indexes every file under `root`. Watcher updates may interleave: each file's
+    /// document is replaced as a whole, so whichever write comes last wins.
+    pub fn build(&self, root: &Path) -> tantivy::Result<()> {
+        self.writer.lock().unwrap().delete_all_documents()?;
+        self.oversized.lock().unwrap().clear();
+        for entry in walker(root).build().flatten() {
+            if entry.file_type().map_or(false, |ft| ft.is_file()) {
+                if let Ok(rel) = entry.path().strip_prefix(root) {
+                    self.index_file(root, rel)?;
+                }
+            }
+        }
+        self.commit()?;
+        self.ready.store(true, Ordering::Release);
+        Ok(())
+    }
+
+    pub fn is_ready(&self) -> bool {
+        self.ready.load(Ordering::Acquire)
+    }
+
+    /// Re-reads `paths` (relative to `root`); missing ones are dropped from the index.
+    /// A directory stands for everything under it, as a rename only reports the directory.
+    pub fn update<'a>(&self, root: &Path, paths: impl IntoIterator<Item = &'a PathBuf>) -> tantivy::Result<()> {
+        for rel in paths {
+            let full_path = root.join(rel);
+            if full_path.is_dir() {
+                for entry in walker_in(root, &full_path).build().flatten() {
+                    if entry.file_type().map_or(false, |ft| ft.is_file()) {
+                        if let Ok(rel) = entry.path().strip_prefix(root) {
+                            self.index_file(root, rel)?;
+                        }
+                    }
+                }
+                continue;
+            }
+            self.index_file(root, rel)?;
+            if !full_path.exists() {
+                // It may have been a directory
+                self.remove_under(rel)?;
+            }
+        }
+        self.commit()
+    }
+
+    fn remove_under(&self, dir: &Path) -> tantivy::Result<()> {
+        let dir_str = dir.to_string_lossy().replace('\\', "/");
+        let query = RegexQuery::from_pattern(&format!("{}/.*", regex::escape(&dir_str)), self.path)?;
+        self.writer.lock().unwrap().delete_query(Box::new(query))?;
+        self.oversized.lock().unwrap().retain(|path| !path.starts_with(dir));
+        Ok(())
+    }
+
+    fn index_file(&self, root: &Path, rel: &Path) -> tantivy::Result<()> {
+        let rel_str = rel.to_string_lossy().replace('\\', "/");
+        let writer = self.writer.lock().unwrap();
+        writer.delete_term(Term::from_field_text(self.path, &rel_str));
+        let mut oversized = self.oversized.lock().unwrap();
+        oversized.remove(rel);
+
+        let full_path = root.join(rel);
+        match std::fs::metadata(&full_path) {
+            Ok(meta) if meta.is_file() && meta.len() > MAX_FILE_BYTES => {
+                oversized.insert(rel.to_path_buf());
+                return Ok(());
+            }
+            Ok(meta) if meta.is_file() => {}
+            _ => return Ok(()),
+        }
+        if let Some((text, _)) = std::fs::read(&full_path).ok().and_then(|bytes| decode(&bytes)) {
+            writer.add_document(doc!(self.path => rel_str, self.content => text))?;
+        }
+        Ok(())
+    }
+
+    fn commit(&self) -> tantivy::Result<()> {
+        self.writer.lock().unwrap().commit()?;
+        self.reader.reload()
+    }
+
+    /// Files too big to index, which have to be searched whatever the pattern.
+    pub fn oversized(&self) -> Vec<PathBuf> {
+        self.oversized.lock().unwrap().iter().cloned().collect()
+    }
+
+    /// Files containing every one of `literals` (case-insensitively), sorted.
+    pub fn files_containing(&self, literals: &[String]) -> tantivy::Result<Vec<PathBuf>> {
+        let trigrams: BTreeSet<String> = literals.iter()
+            .flat_map(|literal| {
+                let chars: Vec<char> = literal.to_lowercase().chars().collect();
+                chars.windows(3).map(|w| w.iter().collect::<String>()).collect::<Vec<_>>()
+            })
+            .collect();
+        let clauses: Vec<(Occur, Box<dyn Query>)> = trigrams.into_iter()
+            .map(|t| {
+                let query: Box<dyn Query> = Box::new(TermQuery::new(Term::from_field_text(self.content, &t), IndexRecordOption::Basic));
+                (Occur::Must, query)
+            })
+            .collect();
+
+        let searcher = self.reader.searcher();
+        let mut files = Vec::new();
+        for address in searcher.search(&BooleanQuery::new(clauses), &DocSetCollector)? {
+            let doc: TantivyDocument = searcher.doc(address)?;
+            if let Some(path) = doc.get_first(self.path).and_then(|v| v.as_str()) {
+                files.push(PathBuf::from(path));
+            }
+        }
+        files.sort();
+        files.dedup();
+        Ok(files)
+    }
+}
+
+// Literal runs of 3+ characters that every match of `hir` contains
+fn required_literals(hir: &Hir) -> Vec<String> {
+    fn flush(runs: &mut Vec<String>, run: &mut Vec<u8>) {
+        if let Ok(text) = std::str::from_utf8(run) {
+            if text.chars().count() >= 3 {
+                runs.push(text.to_string());
+            }
+        }
+        run.clear();
+    }
+
+    fn collect(hir: &Hir, runs: &mut Vec<String>, run: &mut Vec<u8>) {
+        match hir.kind() {
+            HirKind::Literal(literal) => run.extend_from_slice(&literal.0),
+            HirKind::Concat(parts) => parts.iter().for_each(|part| collect(part, runs, run)),
+            HirKind::Capture(capture) => collect(&capture.sub, runs, run),
+            // Zero-width, so the literals on either side are still adjacent
+            HirKind::Look(_) | HirKind::Empty => {}
+            HirKind::Repetition(repetition) if repetition.min >= 1 => {
+                flush(runs, run);
+                collect(&repetition.sub, runs, run);
+                flush(runs, run);
+            }
+            _ => flush(runs, run),
+        }
+    }
+
+    let mut runs = Vec::new();
+    let mut run = Vec::new();
+    collect(hir, &mut runs, &mut run);
+    flush(&mut runs, &mut run);
+    runs
+}
+
+static INDEXES: OnceLock<Mutex<HashMap<PathBuf, Arc<SearchIndex>>>> = OnceLock::new();
+
+fn indexes() -> &'static Mutex<HashMap<PathBuf, Arc<SearchIndex>>> {
+    INDEXES.get_or_init(|| Mutex::new(HashMap::new()))
+}
+
+fn canonical(root: &Path) -> PathBuf {
+    root.canonicalize().unwrap_or_else(|_| root.to_path_buf())
+}
+
+/// Opens the index for `root` and builds it on a background thread, if the workspace
+/// config enables it and it is not already running.
+pub fn start(root: &Path) -> Result<(), String> {
+    let root = canonical(root);
+    if !WorkspaceConfig::load(&root).search_index || indexes().lock().unwrap().contains_key(&root) {
+        return Ok(());
+    }
+    let index = Arc::new(SearchIndex::open(&root).map_err(|e| format!("Failed to open search index: {}", e))?);
+    indexes().lock().unwrap().insert(root.clone(), index.clone());
+    std::thread::spawn(move || {
+        if let Err(e) = index.build(&root) {
+            println!("Failed to build search index for {}: {}", root.display(), e);
+        }
+    });
+    Ok(())
+}
+
+/// Drops the index for `root`, e.g. when the workspace is closed.
+pub fn forget(root: &Path) {
+    indexes().lock().unwrap().remove(&canonical(root));
+}
+
+/// Feeds a watcher batch into the index for `root`, if there is one.
+pub fn apply_change(root: &Path, change: &FsChange) {
+    let root = canonical(root);
+    let index = indexes().lock().unwrap().get(&root).cloned();
+    if let Some(index) = index {
+        if let Err(e) = index.update(&root, change.paths()) {
+            println!("Failed to update search index: {}", e);
+        }
+    }
+}
+
+/// The files that can contain a match of `pattern`, or None when there is no ready
+/// index for `root` or the pattern has no usable literal.
+pub(crate) fn candidates(root: &Path, pattern: &str) -> Option<Vec<PathBuf>> {
+    let index = indexes().lock().unwrap().get(&canonical(root)).cloned()?;
+    if !index.is_ready() {
+        return None;
+    }
+    let literals = required_literals(&regex_syntax::parse(pattern).ok()?);
+    if literals.is_empty() {
+        return None;
+    }
+    let mut files = index.files_containing(&literals).ok()?;
+    files.extend(index.oversized());
+    files.sort();
+    files.dedup();
+    Some(files)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{search_code_internal, SearchOptions};
+    use tempfile::tempdir;
+
+    fn literals(pattern: &str) -> Vec<String> {
+        required_literals(&regex_syntax::parse(pattern).unwrap())
+    }
+
+    #[test]
+    fn test_required_literals() {
+        assert_eq!(literals(r"fn\s+validate_token\("), vec!["validate_token("]);
+        assert_eq!(literals(r"\bUserService\b::new"), vec!["UserService::new"]);
+        assert_eq!(literals(r"(?:impl)+ Display"), vec!["impl", " Display"]);
+        assert!(literals(r"foo|bar").is_empty());
+        assert!(literals(r"\w+ab").is_empty());
+    }
+
+    #[test]
+    fn test_search_uses_index_and_watcher_updates() {
+        let dir = tempdir().unwrap();
+        let root = dir.path().canonicalize().unwrap();
+        std::fs::create_dir(root.join("src")).unwrap();
+        std::fs::write(root.join("src/auth.rs"), "fn validate_token() {}\n").unwrap();
+        std::fs::write(root.join("src/db.rs"), "fn connect() {}\n").unwrap();
+
+        let index = Arc::new(SearchIndex::open(&root).unwrap());
+        index.build(&root).unwrap();
+        assert_eq!(index.files_containing(&["VALIDATE".to_string()]).unwrap(), vec![PathBuf::from("src/auth.rs")]);
+        indexes().lock().unwrap().insert(root.clone(), index);
+
+        // A file the index has not seen yet is not searched
+        std::fs::write(root.join("src/api.rs"), "fn handle() { validate_token() }\n").unwrap();
+        let found = |query: &str| -> Vec<PathBuf> {
+            search_code_internal(&root, query, &SearchOptions::default()).unwrap().into_iter().map(|m| m.path).collect()
+        };
+        assert_eq!(found(r"validate_\w+"), vec![PathBuf::from("src/auth.rs")]);
+
+        apply_change(&root, &FsChange { created: vec![PathBuf::from("src/api.rs")], ..Default::default() });
+        assert_eq!(found(r"validate_\w+"), vec![PathBuf::from("src/api.rs"), PathBuf::from("src/auth.rs")]);
+
+        std::fs::remove_file(root.join("src/auth.rs")).unwrap();
+        apply_change(&root, &FsChange { removed: vec![PathBuf::from("src/auth.rs")], ..Default::default() });
+        assert_eq!(found("validate_token"), vec![PathBuf::from("src/api.rs")]);
+        // No usable literal: full walk
+        assert_eq!(found(r"\w+\(\)"), vec![PathBuf::from("src/api.rs"), PathBuf::from("src/db.rs")]);
+        forget(&root);
+    }
+
+    #[test]
+    fn test_oversized_files_are_always_searched() {
+        let dir = tempdir().unwrap();
+        let root = dir.path().canonicalize().unwrap();
+        std::fs::write(root.join("small.rs"), "fn validate_token() {}\n").unwrap();
+        let big = format!("{}fn validate_token() {{}}\n", "// padding\n".repeat(110_000));
+        std::fs::write(root.join("big.rs"), big).unwrap();
+
+        let index = Arc::new(SearchIndex::open(&root).unwrap());
+        index.build(&root).unwrap();
+        assert_eq!(index.oversized(), vec![PathBuf::from("big.rs")]);
+        indexes().lock().unwrap().insert(root.clone(), index);
+
+        assert_eq!(candidates(&root, "validate_token"), Some(vec![PathBuf::from("big.rs"), PathBuf::from("small.rs")]));
+        let mut found: Vec<PathBuf> = search_code_internal(&root, "validate_token", &SearchOptions::default()).unwrap()
+            .into_iter().map(|m| m.path).collect();
+        found.sort();
+        assert_eq!(found, vec![PathBuf::from("big.rs"), PathBuf::from("small.rs")]);
+        forget(&root);
+    }
+
+    #[test]
+    fn test_directory_rename_reindexes_subtree() {
+        let dir = tempdir().unwrap();
+        let root = dir.path().canonicalize().unwrap();
+        std::fs::create_dir_all(root.join("src/auth")).unwrap();
+        std::fs::write(root.join("src/auth/token.rs"), "fn validate_token() {}\n").unwrap();
+
+        let index = Arc::new(SearchIndex::open(&root).unwrap());
+        index.build(&root).unwrap();
+        indexes().lock().unwrap().insert(root.clone(), index);
+
+        // The watcher reports only the directory for a rename
+        std::fs::rename(root.join("src/auth"), root.join("src/login")).unwrap();
+        apply_change(&root, &FsChange {
+            created: vec![PathBuf::from("src/login")],
+            removed: vec![PathBuf::from("src/auth")],
+            ..Default::default()
+        });
+        assert_eq!(candidates(&root, "validate_token"), Some(vec![PathBuf::from("src/login/token.rs")]));
+
+        std::fs::remove_dir_all(root.join("src/login")).unwrap();
+        apply_change(&root, &FsChange { removed: vec![PathBuf::from("src/login")], ..Default::default() });
+        assert_eq!(candidates(&root, "validate_token"), Some(vec![]));
+        forget(&root);
+    }
+}
@@ -0,0 +1,313 @@
+use std::collections::{HashMap, HashSet};
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+use common::Embedder;
+use rusqlite::{params, Connection, OptionalExtension};
+use sha2::{Digest, Sha256};
+
+use crate::encoding::decode;
+use crate::ignores::walker;
+use crate::stats::language_of;
+
+// Embedding index for natural-language code search. Source files are cut into
+// overlapping line windows, embedded with the session's model and stored in
+// `.irongraph/embeddings.db`. `update` only re-embeds files whose content changed, and
+// search is a linear cosine scan, which is plenty at workspace scale.
+
+pub const INDEX_FILE: &str = ".irongraph/embeddings.db";
+
+const CHUNK_LINES: usize = 40;
+const CHUNK_OVERLAP: usize = 10;
+// Bigger files are nearly always generated or data
+const MAX_FILE_BYTES: u64 = 256 * 1024;
+// Chunks per embeddings request
+const EMBED_BATCH: usize = 64;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Chunk {
+    pub path: PathBuf,
+    // 1-based, inclusive
+    pub start_line: usize,
+    pub end_line: usize,
+    pub text: String,
+}
+
+#[derive(Debug, Clone)]
+pub struct SemanticMatch {
+    pub chunk: Chunk,
+    // Cosine similarity to the query
+    pub score: f32,
+}
+
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct IndexSummary {
+    pub embedded_files: usize,
+    pub removed_files: usize,
+}
+
+/// Windows of `CHUNK_LINES` lines overlapping by `CHUNK_OVERLAP`; blank windows are dropped.
+pub fn chunk_text(path: &Path, content: &str) -> Vec<Chunk> {
+    let lines: Vec<&str> = content.lines().collect();
+    let mut chunks = Vec::new();
+    let mut start = 0;
+    while start < lines.len() {
+        let end = (start + CHUNK_LINES).min(lines.len());
+        let text = lines[start..end].join("\n");
+        if !text.trim().is_empty() {
+            chunks.push(Chunk { path: path.to_path_buf(), start_line: start + 1, end_line: end, text });
+        }
+        if end == lines.len() {
+            break;
+        }
+        start = end - CHUNK_OVERLAP;
+    }
+    chunks
+}
+
+// The path goes into the embedded text too, it often says what the code is about
+fn embedding_input(chunk: &Chunk) -> String {
+    format!("{}\n{}", chunk.path.to_string_lossy().replace('\\', "/"), chunk.text)
+}
+
+fn to_blob(vector: &[f32]) -> Vec<u8> {
+    vector.iter().flat_map(|v| v.to_le_bytes()).collect()
+}
+
+fn from_blob(blob: &[u8]) -> Vec<f32> {
+    blob.chunks_exact(4).map(|b| f32::from_le_bytes([b[0], b[1], b[2], b[3]])).collect()
+}
+
+fn cosine(a: &[f32], b: &[f32]) -> f32 {
+    if a.len() != b.len() {
+        return 0.0;
+    }
+    let dot: f32 = a.iter().zip(b).map(|(x, y)| x * y).sum();
+    let norm = |v: &[f32]| v.iter().map(|x| x * x).sum::<f32>().sqrt();
+    let denominator = norm(a) * norm(b);
+    if denominator == 0.0 { 0.0 } else { dot / denominator }
+}
+
+// A changed file waiting to be embedded
+struct Pending {
+    path: String,
+    hash: String,
+    chunks: Vec<Chunk>,
+}
+
+pub struct SemanticIndex {
+    conn: Connection,
+}
+
+impl SemanticIndex {
+    /// Opens (creating if needed) the index of the workspace at `root`.
+    pub fn open(root: &Path) -> Result<Self, String> {
+        let path = root.join(INDEX_FILE);
+        if let Some(parent) = path.parent() {
+            std::fs::create_dir_all(parent).map_err(|e| e.to_string())?;
+        }
+        let conn = Connection::open(&path).map_err(|e| format!("Failed to open {}: {}", INDEX_FILE, e))?;
+        // Sessions on the same workspace share the file
+        conn.busy_timeout(Duration::from_secs(5)).map_err(|e| e.to_string())?;
+        conn.execute_batch("
+            CREATE TABLE IF NOT EXISTS meta (key TEXT PRIMARY KEY, value TEXT NOT NULL);
+            CREATE TABLE IF NOT EXISTS files (path TEXT PRIMARY KEY, hash TEXT NOT NULL);
+            CREATE TABLE IF NOT EXISTS chunks (
+                path TEXT NOT NULL,
+                start_line INTEGER NOT NULL,
+                end_line INTEGER NOT NULL,
+                text TEXT NOT NULL,
+                vector BLOB NOT NULL
+            );
+            CREATE INDEX IF NOT EXISTS chunks_by_path ON chunks (path);
+        ").map_err(|e| e.to_string())?;
+        Ok(SemanticIndex { conn })
+    }
+
+    /// Brings the index in line with the files under `root`: new and changed files are
+    /// embedded, deleted ones dropped. Switching models rebuilds everything.
+    pub async fn update(&mut self, root: &Path, embedder: &dyn Embedder) -> Result<IndexSummary, String> {
+        let model = embedder.model();
+        let indexed_model: Option<String> = self.conn
+            .query_row("SELECT value FROM meta WHERE key = 'model'", [], |row| row.get(0))
+            .optional()
+            .map_err(|e| e.to_string())?;
+        if indexed_model.as_deref() != Some(model.as_str()) {
+            self.conn.execute_batch("DELETE FROM files; DELETE FROM chunks;").map_err(|e| e.to_string())?;
+            self.conn.execute("INSERT OR REPLACE INTO meta (key, value) VALUES ('model', ?1)", params![model])
+                .map_err(|e| e.to_string())?;
+        }
+
+        let known = self.known_files()?;
+        let mut seen = HashSet::new();
+        let mut summary = IndexSummary::default();
+        let mut pending: Vec<Pending> = Vec::new();
+        let files: Vec<PathBuf> = walker(root).build().flatten()
+            .filter(|e| e.file_type().map_or(false, |ft| ft.is_file()))
+            .map(|e| e.into_path())
+            .filter(|path| language_of(path) != "Other")
+            .collect();
+        for path in &files {
+            if std::fs::metadata(path).map_or(true, |m| m.len() > MAX_FILE_BYTES) {
+                continue;
+            }
+            let Ok(rel) = path.strip_prefix(root) else { continue };
+            let rel_str = rel.to_string_lossy().replace('\\', "/");
+            let Ok(bytes) = std::fs::read(path) else { continue };
+            seen.insert(rel_str.clone());
+
+            let hash = format!("{:x}", Sha256::digest(&bytes));
+            if known.get(&rel_str) == Some(&hash) {
+                continue;
+            }
+            let chunks = decode(&bytes).map_or_else(Vec::new, |(text, _)| chunk_text(rel, &text));
+            pending.push(Pending { path: rel_str, hash, chunks });
+            summary.embedded_files += 1;
+
+            // Store as we go so an interrupted first run keeps its progress
+            if pending.iter().map(|p| p.chunks.len()).sum::<usize>() >= EMBED_BATCH {
+                self.store(std::mem::take(&mut pending), embedder).await?;
+            }
+        }
+        self.store(pending, embedder).await?;
+
+        let removed: Vec<&String> = known.keys().filter(|path| !seen.contains(*path)).collect();
+        summary.removed_files = removed.len();
+        let tx = self.conn.transaction().map_err(|e| e.to_string())?;
+        for path in removed {
+            tx.execute("DELETE FROM chunks WHERE path = ?1", params![path]).map_err(|e| e.to_string())?;
+            tx.execute("DELETE FROM files WHERE path = ?1", params![path]).map_err(|e| e.to_string())?;
+        }
+        tx.commit().map_err(|e| e.to_string())?;
+        Ok(summary)
+    }
+
+    fn known_files(&self) -> Result<HashMap<String, String>, String> {
+        let mut stmt = self.conn.prepare("SELECT path, hash FROM files").map_err(|e| e.to_string())?;
+        let rows = stmt.query_map([], |row| Ok((row.get(0)?, row.get(1)?))).map_err(|e| e.to_string())?;
+        rows.collect::<Result<_, _>>().map_err(|e| e.to_string())
+    }
+
+    async fn store(&mut self, files: Vec<Pending>, embedder: &dyn Embedder) -> Result<(), String> {
+        let inputs: Vec<String> = files.iter().flat_map(|f| f.chunks.iter().map(embedding_input)).collect();
+        let mut vectors = Vec::with_capacity(inputs.len());
+        for batch in inputs.chunks(EMBED_BATCH) {
+            let embedded = embedder.embed(batch).await?;
+            if embedded.len() != batch.len() {
+                return Err(format!("Expected {} embeddings, got {}", batch.len(), embedded.len()));
+            }
+            vectors.extend(embedded);
+        }
+
+        let mut vectors = vectors.into_iter();
+        let tx = self.conn.transaction().map_err(|e| e.to_string())?;
+        for file in &files {
+            tx.execute("DELETE FROM chunks WHERE path = ?1", params![file.path]).map_err(|e| e.to_string())?;
+            for (chunk, vector) in file.chunks.iter().zip(vectors.by_ref()) {
+                tx.execute(
+                    "INSERT INTO chunks (path, start_line, end_line, text, vector) VALUES (?1, ?2, ?3, ?4, ?5)",
+                    params![file.path, chunk.start_line as i64, chunk.end_line as i64, chunk.text, to_blob(&vector)],
+                ).map_err(|e| e.to_string())?;
+            }
+            tx.execute("INSERT OR REPLACE INTO files (path, hash) VALUES (?1, ?2)", params![file.path, file.hash])
+                .map_err(|e| e.to_string())?;
+        }
+        tx.commit().map_err(|e| e.to_string())
+    }
+
+    /// The `limit` chunks closest in meaning to `query`, best first.
+    pub async fn search(&mut self, query: &str, embedder: &dyn Embedder, limit: usize) -> Result<Vec<SemanticMatch>, String> {
+        let query_vector = embedder.embed(&[query.to_string()]).await?
+            .pop()
+            .ok_or("No embedding returned for the query")?;
+
+        let mut stmt = self.conn.prepare("SELECT path, start_line, end_line, text, vector FROM chunks")
+            .map_err(|e| e.to_string())?;
+        let rows = stmt.query_map([], |row| {
+            let path: String = row.get(0)?;
+            let start_line: i64 = row.get(1)?;
+            let end_line: i64 = row.get(2)?;
+            let vector: Vec<u8> = row.get(4)?;
+            Ok((Chunk { path: PathBuf::from(path), start_line: start_line as usize, end_line: end_line as usize, text: row.get(3)? }, vector))
+        }).map_err(|e| e.to_string())?;
+
+        let mut matches = Vec::new();
+        for row in rows {
+            let (chunk, vector) = row.map_err(|e| e.to_string())?;
+            let score = cosine(&query_vector, &from_blob(&vector));
+            matches.push(SemanticMatch { chunk, score });
+        }
+        matches.sort_by(|a, b| b.score.total_cmp(&a.score));
+        matches.truncate(limit);
+        Ok(matches)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use tempfile::tempdir;
+
+    // Bag of words over a small vocabulary; counts the texts it was asked to embed
+    struct WordEmbedder {
+        embedded: AtomicUsize,
+    }
+
+    const VOCABULARY: &[&str] = &["jwt", "token", "validate", "signature", "invoice", "total", "tax", "render", "button"];
+
+    #[async_trait::async_trait]
+    impl Embedder for WordEmbedder {
+        fn model(&self) -> String {
+            "words".to_string()
+        }
+
+        async fn embed(&self, inputs: &[String]) -> Result<Vec<Vec<f32>>, String> {
+            self.embedded.fetch_add(inputs.len(), Ordering::SeqCst);
+            Ok(inputs.iter().map(|text| {
+                let text = text.to_lowercase();
+                VOCABULARY.iter().map(|word| text.matches(word).count() as f32).collect()
+            }).collect())
+        }
+    }
+
+    #[test]
+    fn test_chunk_text_overlaps() {
+        let content: String = (1..=75).map(|i| format!("line {}\n", i)).collect();
+        let chunks = chunk_text(Path::new("a.rs"), &content);
+        let ranges: Vec<(usize, usize)> = chunks.iter().map(|c| (c.start_line, c.end_line)).collect();
+        assert_eq!(ranges, vec![(1, 40), (31, 70), (61, 75)]);
+        assert!(chunks[2].text.starts_with("line 61\n"));
+    }
+
+    #[tokio::test]
+    async fn test_semantic_search_and_incremental_update() {
+        let dir = tempdir().unwrap();
+        let root = dir.path();
+        std::fs::create_dir(root.join("src")).unwrap();
+        std::fs::write(root.join("src/auth.rs"), "fn check(token: &str) -> bool {\n    validate_jwt_signature(token)\n}\n").unwrap();
+        std::fs::write(root.join("src/billing.rs"), "fn invoice_total(items: &[u32]) -> u32 {\n    items.iter().sum::<u32>() + tax()\n}\n").unwrap();
+        std::fs::write(root.join("src/ui.tsx"), "export const Save = () => <button>Save</button>;\n").unwrap();
+
+        let embedder = WordEmbedder { embedded: AtomicUsize::new(0) };
+        let mut index = SemanticIndex::open(root).unwrap();
+        let summary = index.update(root, &embedder).await.unwrap();
+        assert_eq!(summary, IndexSummary { embedded_files: 3, removed_files: 0 });
+
+        let found = index.search("where do we validate JWTs?", &embedder, 1).await.unwrap();
+        assert_eq!(found[0].chunk.path, Path::new("src/auth.rs"));
+        assert_eq!((found[0].chunk.start_line, found[0].chunk.end_line), (1, 3));
+
+        // Nothing changed: no re-embedding
+        let before = embedder.embedded.load(Ordering::SeqCst);
+        assert_eq!(index.update(root, &embedder).await.unwrap(), IndexSummary::default());
+        assert_eq!(embedder.embedded.load(Ordering::SeqCst), before);
+
+        std::fs::write(root.join("src/ui.tsx"), "export const Pay = () => <button>Pay invoice total</button>;\n").unwrap();
+        std::fs::remove_file(root.join("src/billing.rs")).unwrap();
+        let mut index = SemanticIndex::open(root).unwrap();
+        assert_eq!(index.update(root, &embedder).await.unwrap(), IndexSummary { embedded_files: 1, removed_files: 1 });
+        let found = index.search("invoice total", &embedder, 5).await.unwrap();
+        assert_eq!(found[0].chunk.path, Path::new("src/ui.tsx"));
+        assert_eq!(found.len(), 2);
+    }
+}
@@ -1,4 +1,9 @@
-use std::path::Path;
+use std::collections::hash_map::DefaultHasher;
+use std::collections::HashMap;
+use std::hash::{Hash, Hasher};
+use std::ops::Range;
+use std::path::{Path, PathBuf};
+use std::sync::{Mutex, OnceLock};
 use syn::{visit_mut::VisitMut, Block, ImplItem, ItemFn, ItemImpl};
 use oxc_allocator::Allocator;
 use oxc_parser::{Parser, ParserReturn};
@@ -7,6 +12,8 @@ use oxc_codegen::{Codegen, CodegenOptions};
 use oxc_ast::ast::FunctionBody;
 use oxc_ast_visit::{VisitMut as OxcVisitMut, walk_mut};
 
+use crate::FsChange;
+
 struct RustSkeletonVisitor;
 
 impl VisitMut for RustSkeletonVisitor {
@@ -41,6 +48,171 @@ impl<'a> OxcVisitMut<'a> for JsSkeletonVisitor {
     }
 }
 
+// Languages without a syn/oxc fast path go through tree-sitter. Function bodies are
+// replaced in the original text rather than re-printed, so every remaining line can be
+// labelled with its line number in the file.
+type Stub = fn(tree_sitter::Node, &str) -> String;
+
+pub(crate) struct TreeSitterLang {
+    pub(crate) language: fn() -> tree_sitter::Language,
+    // Node kinds whose `body` field is stubbed out
+    function_kinds: &'static [&'static str],
+    stub: Stub,
+}
+
+pub(crate) fn tree_sitter_lang(ext: &str) -> Option<TreeSitterLang> {
+    let (language, function_kinds, stub): (fn() -> tree_sitter::Language, &'static [&'static str], Stub) = match ext {
+        "py" | "pyi" => (tree_sitter_python::language, &["function_definition"], python_stub),
+        "go" => (tree_sitter_go::language, &["function_declaration", "method_declaration"], brace_stub),
+        "java" => (tree_sitter_java::language, &["method_declaration", "constructor_declaration"], brace_stub),
+        "c" | "h" => (tree_sitter_c::language, &["function_definition"], brace_stub),
+        "cc" | "cpp" | "cxx" | "hh" | "hpp" | "hxx" => (tree_sitter_cpp::language, &["function_definition"], brace_stub),
+        _ => return None,
+    };
+    Some(TreeSitterLang { language, function_kinds, stub })
+}
+
+fn tree_sitter_skeleton(lang: &TreeSitterLang, content: &str) -> Result<String, String> {
+    let mut parser = tree_sitter::Parser::new();
+    parser.set_language(&(lang.language)()).map_err(|e| e.to_string())?;
+    // tree-sitter recovers from errors (macros, newer syntax), so a partial tree still gives a useful outline
+    let tree = parser.parse(content, None).ok_or("Parse error")?;
+
+    let mut bodies = Vec::new();
+    collect_bodies(tree.root_node(), lang.function_kinds, &mut bodies);
+    let stubs = bodies.into_iter().map(|body| (body.byte_range(), (lang.stub)(body, content))).collect();
+    Ok(numbered_skeleton(content, stubs))
+}
+
+fn collect_bodies<'t>(node: tree_sitter::Node<'t>, kinds: &[&str], bodies: &mut Vec<tree_sitter::Node<'t>>) {
+    let mut cursor = node.walk();
+    for child in node.named_children(&mut cursor) {
+        match child.child_by_field_name("body") {
+            Some(body) if kinds.contains(&child.kind()) => bodies.push(body),
+            _ => collect_bodies(child, kinds, bodies),
+        }
+    }
+}
+
+// Applies the stubs (disjoint, in document order) and prefixes each output line with the
+// line it starts on in `content`
+fn numbered_skeleton(content: &str, stubs: Vec<(Range<usize>, String)>) -> String {
+    let mut lines: Vec<(usize, String)> = vec![(1, String::new())];
+    let mut line = 1;
+    let mut pos = 0;
+    let keep = |text: &str, line: &mut usize, lines: &mut Vec<(usize, String)>| {
+        for (i, part) in text.split('\n').enumerate() {
+            if i > 0 {
+                *line += 1;
+                lines.push((*line, String::new()));
+            }
+            lines.last_mut().unwrap().1.push_str(part);
+        }
+    };
+
+    for (range, stub) in stubs {
+        keep(&content[pos..range.start], &mut line, &mut lines);
+        for (i, part) in stub.split('\n').enumerate() {
+            if i > 0 {
+                lines.push((line + i, String::new()));
+            }
+            lines.last_mut().unwrap().1.push_str(part);
+        }
+        line += content[range.clone()].matches('\n').count();
+        pos = range.end;
+    }
+    keep(&content[pos..], &mut line, &mut lines);
+    if lines.last().map_or(false, |(_, l)| l.is_empty()) {
+        lines.pop();
+    }
+
+    let width = lines.last().map_or(1, |(n, _)| n.to_string().len());
+    lines.iter()
+        .map(|(n, l)| format!("{:>width$} | {}", n, l).trim_end().to_string() + "\n")
+        .collect()
+}
+
+// ATX (`## Title`) and setext (`Title` over `===`/`---`) headings outside fenced code,
+// indented by level and labelled with their line numbers like the tree-sitter skeletons
+fn markdown_skeleton(content: &str) -> String {
+    let lines: Vec<&str> = content.lines().collect();
+    let mut headings: Vec<(usize, usize, &str)> = Vec::new();
+    let mut fence: Option<&str> = None;
+    for (i, line) in lines.iter().enumerate() {
+        let trimmed = line.trim_start();
+        let marker = ["```", "~~~"].into_iter().find(|m| trimmed.starts_with(m));
+        match (fence, marker) {
+            (Some(open), Some(m)) if open == m => fence = None,
+            (None, Some(m)) => fence = Some(m),
+            _ => {}
+        }
+        if fence.is_some() || marker.is_some() || line.starts_with("    ") {
+            continue;
+        }
+
+        let level = trimmed.chars().take_while(|&c| c == '#').count();
+        let title = &trimmed[level..];
+        if (1..=6).contains(&level) && (title.is_empty() || title.starts_with(' ')) {
+            headings.push((i + 1, level, title.trim().trim_end_matches('#').trim_end()));
+            continue;
+        }
+        let underline = lines.get(i + 1).map_or("", |l| l.trim());
+        if !trimmed.is_empty() && !underline.is_empty() {
+            if underline.chars().all(|c| c == '=') {
+                headings.push((i + 1, 1, line.trim()));
+            } else if underline.chars().all(|c| c == '-') && !trimmed.starts_with(['-', '*', '+']) {
+                headings.push((i + 1, 2, line.trim()));
+            }
+        }
+    }
+
+    let width = headings.last().map_or(1, |(n, _, _)| n.to_string().len());
+    headings.iter()
+        .map(|(n, level, title)| format!("{:>width$} | {}{} {}\n", n, "  ".repeat(level - 1), "#".repeat(*level), title))
+        .collect()
+}
+
+fn brace_stub(_body: tree_sitter::Node, _content: &str) -> String {
+    "{ ... }".to_string()
+}
+
+fn python_stub(body: tree_sitter::Node, content: &str) -> String {
+    // `def f(): return 1` keeps its body on the def line; only an indented block has room for a docstring
+    let own_line = content[..body.start_byte()].trim_end_matches([' ', '\t']).ends_with('\n');
+    let docstring = body.named_child(0)
+        .filter(|s| s.kind() == "expression_statement")
+        .and_then(|s| s.named_child(0))
+        .filter(|s| s.kind() == "string");
+    match docstring {
+        Some(doc) if own_line => format!(
+            "{}\n{}...",
+            docstring_first_line(&content[doc.byte_range()]),
+            " ".repeat(body.start_position().column)
+        ),
+        _ => "...".to_string(),
+    }
+}
+
+fn docstring_first_line(doc: &str) -> String {
+    if !doc.contains('\n') {
+        return doc.to_string();
+    }
+    let open = doc.find(['"', '\'']).unwrap_or(0);
+    let delim = if doc[open..].starts_with("\"\"\"") {
+        "\"\"\""
+    } else if doc[open..].starts_with("'''") {
+        "'''"
+    } else {
+        &doc[open..open + 1]
+    };
+    let prefix = &doc[..open + delim.len()];
+    let summary = doc[prefix.len()..].lines()
+        .map(|l| l.trim().trim_end_matches(delim).trim_end())
+        .find(|l| !l.is_empty())
+        .unwrap_or("");
+    format!("{}{}{}", prefix, summary, delim)
+}
+
 pub fn get_skeleton(path: &Path, content: &str) -> Result<String, String> {
     if path.extension().map_or(false, |ext| ext == "rs") {
         let mut syntax = syn::parse_file(content).map_err(|e| format!("Rust parse error: {}", e))?;
@@ -67,11 +239,65 @@ pub fn get_skeleton(path: &Path, content: &str) -> Result<String, String> {
             .build(&program);
 
         Ok(ret.code)
+    } else if path.extension().map_or(false, |ext| ext == "md" || ext == "markdown") {
+        Ok(markdown_skeleton(content))
+    } else if crate::notebook::is_notebook(path) {
+        crate::notebook::skeleton(content)
+    } else if let Some(lang) = path.extension().and_then(|ext| ext.to_str()).and_then(tree_sitter_lang) {
+        tree_sitter_skeleton(&lang, content)
     } else {
         Err("Unsupported file type for skeleton view".to_string())
     }
 }
 
+// Skeletons by absolute path, tagged with a hash of the content they were built from, so
+// an edited file is never served a stale skeleton even without a watcher. Watcher batches
+// drop entries early so deleted and rewritten files don't linger.
+type Cached = (u64, Result<String, String>);
+
+static CACHE: OnceLock<Mutex<HashMap<PathBuf, Cached>>> = OnceLock::new();
+
+// Past this the cache starts over rather than tracking recency
+const MAX_CACHED: usize = 4096;
+
+fn cache() -> &'static Mutex<HashMap<PathBuf, Cached>> {
+    CACHE.get_or_init(|| Mutex::new(HashMap::new()))
+}
+
+fn content_hash(content: &str) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    content.hash(&mut hasher);
+    hasher.finish()
+}
+
+/// `get_skeleton` for the file at `full_path`, reusing the last result while its content
+/// is unchanged.
+pub fn cached_skeleton(full_path: &Path, content: &str) -> Result<String, String> {
+    let hash = content_hash(content);
+    if let Some((cached_hash, skeleton)) = cache().lock().unwrap().get(full_path) {
+        if *cached_hash == hash {
+            return skeleton.clone();
+        }
+    }
+
+    let skeleton = get_skeleton(full_path, content);
+    let mut cache = cache().lock().unwrap();
+    if cache.len() >= MAX_CACHED {
+        cache.clear();
+    }
+    cache.insert(full_path.to_path_buf(), (hash, skeleton.clone()));
+    skeleton
+}
+
+/// Forgets the skeletons of the files in a watcher batch for `root`.
+pub fn apply_change(root: &Path, change: &FsChange) {
+    let root = root.canonicalize().unwrap_or_else(|_| root.to_path_buf());
+    let mut cache = cache().lock().unwrap();
+    for path in change.paths() {
+        cache.remove(&root.join(path));
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -119,4 +345,99 @@ mod tests {
         assert!(!skeleton.contains("console.log"));
         assert!(!skeleton.contains("const y"));
     }
+
+    #[test]
+    fn test_markdown_skeleton() {
+        let doc = "Design\n======\n\nIntro.\n\n## Goals ##\n\n```sh\n# not a heading\n```\n\nNon-goals\n---------\n\n### Storage\n- item\n---\n";
+        let skeleton = get_skeleton(Path::new("DESIGN.md"), doc).unwrap();
+        assert_eq!(skeleton, " 1 | # Design\n 6 |   ## Goals\n12 |   ## Non-goals\n15 |     ### Storage\n");
+    }
+
+    fn strip_line_numbers(skeleton: &str) -> String {
+        skeleton.lines()
+            .map(|l| l.split_once(" | ").map_or("", |(_, code)| code).to_string() + "\n")
+            .collect()
+    }
+
+    #[test]
+    fn test_go_skeleton() {
+        let code = "package main\n\nimport \"fmt\"\n\ntype Server struct {\n\tAddr string\n}\n\nfunc (s *Server) Start(port int) error {\n\tfmt.Println(\"start\")\n\treturn nil\n}\n\nfunc main() {\n\tnew(Server).Start(80)\n}\n";
+        let skeleton = get_skeleton(Path::new("main.go"), code).unwrap();
+        assert!(skeleton.contains(" 5 | type Server struct {\n 6 | \tAddr string\n 7 | }\n"));
+        assert!(skeleton.contains(" 9 | func (s *Server) Start(port int) error { ... }\n13 |\n14 | func main() { ... }\n"));
+        assert!(!skeleton.contains("Println"));
+    }
+
+    #[test]
+    fn test_java_skeleton() {
+        let code = "public class Greeter {\n    private final String name;\n\n    public Greeter(String name) {\n        this.name = name;\n    }\n\n    @Override\n    public String toString() {\n        return \"Hi \" + name;\n    }\n}\n";
+        let skeleton = get_skeleton(Path::new("Greeter.java"), code).unwrap();
+        assert!(skeleton.contains(" 2 |     private final String name;"));
+        assert!(skeleton.contains(" 4 |     public Greeter(String name) { ... }\n 7 |\n 8 |     @Override\n 9 |     public String toString() { ... }\n12 | }\n"));
+        assert!(!skeleton.contains("this.name ="));
+    }
+
+    #[test]
+    fn test_c_and_cpp_skeletons() {
+        let c = "#include <stdio.h>\n\nint add(int a, int b) {\n    return a + b;\n}\n";
+        let skeleton = get_skeleton(Path::new("add.c"), c).unwrap();
+        assert_eq!(skeleton, "1 | #include <stdio.h>\n2 |\n3 | int add(int a, int b) { ... }\n");
+
+        let cpp = "class Counter {\npublic:\n    void inc() { n++; }\nprivate:\n    int n = 0;\n};\n\nint Counter::get() const {\n    return n;\n}\n";
+        let skeleton = get_skeleton(Path::new("counter.cpp"), cpp).unwrap();
+        assert!(skeleton.contains("3 |     void inc() { ... }\n"));
+        assert!(skeleton.contains("5 |     int n = 0;\n"));
+        assert!(skeleton.contains("8 | int Counter::get() const { ... }\n"));
+    }
+
+    #[test]
+    fn test_python_skeleton() {
+        let code = r#"import os
+
+@dataclass
+class Foo(Base):
+    """A foo.
+
+    Longer description.
+    """
+    size: int = 0
+
+    @property
+    def area(self) -> int:
+        """
+        Area of the foo.
+        """
+        return self.size * self.size
+
+    def grow(self, by=1): self.size += by
+
+def main(argv):
+    for a in argv:
+        print(a)
+"#;
+        let numbered = get_skeleton(Path::new("foo.py"), code).unwrap();
+        assert!(numbered.contains("12 |     def area(self) -> int:\n13 |         \"\"\"Area of the foo.\"\"\"\n14 |         ...\n17 |\n18 |"));
+        assert!(numbered.ends_with("20 | def main(argv):\n21 |     ...\n"));
+
+        let skeleton = strip_line_numbers(&numbered);
+        assert!(skeleton.contains("@dataclass\nclass Foo(Base):"));
+        assert!(skeleton.contains("    \"\"\"A foo.\n\n    Longer description.\n    \"\"\"\n    size: int = 0"));
+        assert!(skeleton.contains("    @property\n    def area(self) -> int:\n        \"\"\"Area of the foo.\"\"\"\n        ...\n"));
+        assert!(skeleton.contains("    def grow(self, by=1): ...\n"));
+        assert!(skeleton.contains("def main(argv):\n    ...\n"));
+        assert!(!skeleton.contains("print(a)"));
+        assert!(!skeleton.contains("return self.size"));
+    }
+
+    #[test]
+    fn test_cached_skeleton_follows_content() {
+        let path = Path::new("/cache-test/lib.rs");
+        assert!(cached_skeleton(path, "fn a() { 1; }").unwrap().contains("fn a() {}"));
+        assert!(cached_skeleton(path, "fn a() { 1; }").unwrap().contains("fn a() {}"));
+        let changed = cached_skeleton(path, "fn b() { 2; }").unwrap();
+        assert!(changed.contains("fn b() {}") && !changed.contains("fn a"));
+
+        apply_change(Path::new("/cache-test"), &FsChange { modified: vec![PathBuf::from("lib.rs")], ..Default::default() });
+        assert!(!cache().lock().unwrap().contains_key(path));
+    }
 }
@@ -0,0 +1,149 @@
+use std::collections::HashMap;
+use std::path::{Component, Path, PathBuf};
+
+use crate::ignores::walker;
+use crate::is_binary;
+
+// A one-pass summary of the workspace so the Planner (and the UI) can see its shape
+// without listing and reading files. Honours .gitignore like `list_files`.
+
+const LARGEST_FILES: usize = 10;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct LanguageStats {
+    pub language: String,
+    pub files: usize,
+    // Text files only; binary files count towards `files` but have no lines
+    pub lines: usize,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct FileSize {
+    pub path: PathBuf,
+    pub bytes: u64,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct DirectorySize {
+    // Top-level directory, or "." for files directly under the root
+    pub path: PathBuf,
+    pub files: usize,
+    pub bytes: u64,
+}
+
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct WorkspaceStats {
+    pub files: usize,
+    pub lines: usize,
+    pub bytes: u64,
+    // By line count, descending
+    pub languages: Vec<LanguageStats>,
+    pub largest_files: Vec<FileSize>,
+    // By size, descending
+    pub directories: Vec<DirectorySize>,
+}
+
+/// Display name of a file's language, from its extension; "Other" when unknown.
+pub fn language_of(path: &Path) -> &'static str {
+    let ext = path.extension().and_then(|e| e.to_str()).unwrap_or("");
+    match ext {
+        "rs" => "Rust",
+        "ts" | "tsx" => "TypeScript",
+        "js" | "jsx" | "mjs" | "cjs" => "JavaScript",
+        "py" | "pyi" => "Python",
+        "go" => "Go",
+        "java" => "Java",
+        "c" | "h" => "C",
+        "cc" | "cpp" | "cxx" | "hh" | "hpp" | "hxx" => "C++",
+        "css" | "scss" => "CSS",
+        "html" | "htm" => "HTML",
+        "md" => "Markdown",
+        "json" => "JSON",
+        "toml" => "TOML",
+        "yaml" | "yml" => "YAML",
+        "sh" | "bash" => "Shell",
+        "sql" => "SQL",
+        _ => "Other",
+    }
+}
+
+fn count_lines(bytes: &[u8]) -> usize {
+    let newlines = bytes.iter().filter(|&&b| b == b'\n').count();
+    newlines + usize::from(bytes.last().map_or(false, |&b| b != b'\n'))
+}
+
+pub fn workspace_stats(root: &Path) -> WorkspaceStats {
+    let mut stats = WorkspaceStats::default();
+    let mut languages: HashMap<&'static str, LanguageStats> = HashMap::new();
+    let mut directories: HashMap<PathBuf, DirectorySize> = HashMap::new();
+    let mut sizes = Vec::new();
+
+    for entry in walker(root).build().flatten() {
+        if !entry.file_type().map_or(false, |ft| ft.is_file()) {
+            continue;
+        }
+        let path = entry.path();
+        let Ok(bytes) = std::fs::read(path) else { continue };
+        let rel = path.strip_prefix(root).unwrap_or(path).to_path_buf();
+        let size = bytes.len() as u64;
+        let lines = if is_binary(&bytes) { 0 } else { count_lines(&bytes) };
+
+        stats.files += 1;
+        stats.lines += lines;
+        stats.bytes += size;
+
+        let language = language_of(&rel);
+        let lang = languages.entry(language).or_insert_with(|| LanguageStats {
+            language: language.to_string(),
+            files: 0,
+            lines: 0,
+        });
+        lang.files += 1;
+        lang.lines += lines;
+
+        let top = match rel.components().next() {
+            Some(Component::Normal(first)) if rel.components().count() > 1 => PathBuf::from(first),
+            _ => PathBuf::from("."),
+        };
+        let dir = directories.entry(top.clone()).or_insert_with(|| DirectorySize { path: top, files: 0, bytes: 0 });
+        dir.files += 1;
+        dir.bytes += size;
+
+        sizes.push(FileSize { path: rel, bytes: size });
+    }
+
+    stats.languages = languages.into_values().collect();
+    stats.languages.sort_by(|a, b| b.lines.cmp(&a.lines).then_with(|| a.language.cmp(&b.language)));
+    sizes.sort_by(|a, b| b.bytes.cmp(&a.bytes).then_with(|| a.path.cmp(&b.path)));
+    sizes.truncate(LARGEST_FILES);
+    stats.largest_files = sizes;
+    stats.directories = directories.into_values().collect();
+    stats.directories.sort_by(|a, b| b.bytes.cmp(&a.bytes).then_with(|| a.path.cmp(&b.path)));
+    stats
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::tempdir;
+
+    #[test]
+    fn test_workspace_stats() {
+        let dir = tempdir().unwrap();
+        let root = dir.path();
+        std::fs::create_dir_all(root.join("src/nested")).unwrap();
+        std::fs::write(root.join("src/main.rs"), "fn main() {\n    run();\n}\n").unwrap();
+        std::fs::write(root.join("src/nested/util.rs"), "fn run() {}").unwrap();
+        std::fs::write(root.join("README.md"), "# Demo\n").unwrap();
+        std::fs::write(root.join("logo.png"), [0x89, b'P', b'N', b'G', 0, 0]).unwrap();
+
+        let stats = workspace_stats(root);
+        assert_eq!((stats.files, stats.lines), (4, 5));
+        assert_eq!(stats.languages[0], LanguageStats { language: "Rust".into(), files: 2, lines: 4 });
+        assert!(stats.languages.contains(&LanguageStats { language: "Other".into(), files: 1, lines: 0 }));
+        assert_eq!(stats.largest_files[0].path, PathBuf::from("src/main.rs"));
+
+        let dirs: Vec<_> = stats.directories.iter().map(|d| (d.path.clone(), d.files)).collect();
+        assert_eq!(dirs, vec![(PathBuf::from("src"), 2), (PathBuf::from("."), 2)]);
+    }
+}
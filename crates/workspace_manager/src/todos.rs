@@ -0,0 +1,133 @@
+use std::path::{Path, PathBuf};
+use std::sync::OnceLock;
+
+use regex::Regex;
+
+use crate::ignores::walker_in;
+use crate::{is_binary, resolve_path_safe, FsError};
+
+// TODO/FIXME/HACK/XXX markers across the workspace, so "clean up the TODOs in X" can be
+// planned from a list instead of a search. Recognises the usual attributions:
+// `TODO(alice): ...`, `FIXME(#12)`, `HACK [PROJ-7]`, `TODO @bob ...`.
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Todo {
+    pub path: PathBuf,
+    pub line: usize,
+    // TODO, FIXME, HACK or XXX
+    pub marker: String,
+    pub author: Option<String>,
+    // `#123` or a tracker key like `PROJ-7`
+    pub issue: Option<String>,
+    pub text: String,
+}
+
+fn marker_regex() -> &'static Regex {
+    static RE: OnceLock<Regex> = OnceLock::new();
+    RE.get_or_init(|| Regex::new(r"\b(TODO|FIXME|HACK|XXX)\b(?:\s*[(\[]([^)\]]*)[)\]])?\s*:?\s*(.*)").unwrap())
+}
+
+fn issue_regex() -> &'static Regex {
+    static RE: OnceLock<Regex> = OnceLock::new();
+    RE.get_or_init(|| Regex::new(r"#\d+|\b[A-Z][A-Z0-9]+-\d+\b").unwrap())
+}
+
+fn author_regex() -> &'static Regex {
+    static RE: OnceLock<Regex> = OnceLock::new();
+    RE.get_or_init(|| Regex::new(r"(?:^|\s)@([\w.-]+)").unwrap())
+}
+
+/// The marker on one line, if any. `path` and `line` are left for the caller.
+pub fn parse_todo(line: &str) -> Option<Todo> {
+    let caps = marker_regex().captures(line)?;
+    let tag = caps.get(2).map(|m| m.as_str().trim()).filter(|t| !t.is_empty());
+    // Trailing comment closers are noise
+    let text = caps[3].trim().trim_end_matches("*/").trim_end_matches("-->").trim().to_string();
+
+    let issue = tag.and_then(|t| issue_regex().find(t))
+        .or_else(|| issue_regex().find(&text))
+        .map(|m| m.as_str().to_string());
+    // A tag that isn't just the issue names the author
+    let author = tag.map(|t| issue_regex().replace_all(t, "").trim_matches(|c: char| c == ',' || c.is_whitespace()).trim_start_matches('@').to_string())
+        .filter(|a| !a.is_empty())
+        .or_else(|| author_regex().captures(&text).map(|c| c[1].to_string()));
+
+    Some(Todo {
+        path: PathBuf::new(),
+        line: 0,
+        marker: caps[1].to_string(),
+        author,
+        issue,
+        text,
+    })
+}
+
+/// Markers in the text files under `rel_dir` ("" for the whole workspace), by path then line.
+pub fn list_todos(root: &Path, rel_dir: &str) -> Result<Vec<Todo>, FsError> {
+    let dir = resolve_path_safe(root, rel_dir, true)?;
+    let mut todos = Vec::new();
+    for entry in walker_in(root, &dir).build().flatten() {
+        if !entry.file_type().map_or(false, |ft| ft.is_file()) {
+            continue;
+        }
+        let path = entry.path();
+        let Ok(bytes) = std::fs::read(path) else { continue };
+        if is_binary(&bytes) {
+            continue;
+        }
+        let rel = path.strip_prefix(root).unwrap_or(path);
+        for (i, line) in String::from_utf8_lossy(&bytes).lines().enumerate() {
+            if let Some(todo) = parse_todo(line) {
+                todos.push(Todo { path: rel.to_path_buf(), line: i + 1, ..todo });
+            }
+        }
+    }
+    todos.sort_by(|a, b| a.path.cmp(&b.path).then(a.line.cmp(&b.line)));
+    Ok(todos)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::tempdir;
+
+    #[test]
+    fn test_parse_todo() {
+        let todo = parse_todo("    // TODO(alice): handle the empty case").unwrap();
+        assert_eq!((todo.marker.as_str(), todo.author.as_deref(), todo.issue), ("TODO", Some("alice"), None));
+        assert_eq!(todo.text, "handle the empty case");
+
+        let todo = parse_todo("# FIXME(#42) flaky on CI").unwrap();
+        assert_eq!((todo.author, todo.issue.as_deref()), (None, Some("#42")));
+
+        let todo = parse_todo("/* HACK [bob, PROJ-7]: remove after the migration */").unwrap();
+        assert_eq!((todo.author.as_deref(), todo.issue.as_deref()), (Some("bob"), Some("PROJ-7")));
+        assert_eq!(todo.text, "remove after the migration");
+
+        let todo = parse_todo("// XXX: ask @carol, see #9").unwrap();
+        assert_eq!((todo.author.as_deref(), todo.issue.as_deref()), (Some("carol"), Some("#9")));
+
+        assert!(parse_todo("let todos = todo_list();").is_none());
+        assert!(parse_todo("// TODOS are tracked elsewhere").is_none());
+    }
+
+    #[test]
+    fn test_list_todos() {
+        let dir = tempdir().unwrap();
+        let root = dir.path();
+        std::fs::create_dir_all(root.join("src/net")).unwrap();
+        std::fs::write(root.join("src/lib.rs"), "fn a() {}\n// TODO: document\n").unwrap();
+        std::fs::write(root.join("src/net/mod.rs"), "// FIXME(dave): retry\nfn b() {}\n// HACK\n").unwrap();
+        std::fs::write(root.join("logo.png"), [0x89, b'P', b'N', b'G', 0, b'T', b'O', b'D', b'O']).unwrap();
+
+        let todos = list_todos(root, "").unwrap();
+        let found: Vec<_> = todos.iter().map(|t| (t.path.to_string_lossy().replace('\\', "/"), t.line, t.marker.as_str())).collect();
+        assert_eq!(found, vec![
+            ("src/lib.rs".to_string(), 2, "TODO"),
+            ("src/net/mod.rs".to_string(), 1, "FIXME"),
+            ("src/net/mod.rs".to_string(), 3, "HACK"),
+        ]);
+        assert_eq!(list_todos(root, "src/net").unwrap().len(), 2);
+        assert!(list_todos(root, "../").is_err());
+    }
+}
@@ -3,7 +3,7 @@ use radkit::tools::{ToolResult, ToolContext};
 use schemars::JsonSchema;
 use serde::Deserialize;
 use std::path::{Path, PathBuf};
-use crate::{read_file_internal, write_file_internal, build_file_tree, search_code_internal, get_skeleton};
+use crate::{read_file_page, read_asset as read_asset_internal, read_file_internal, write_notebook_cell, notebook, max_read_bytes, read_file_range, write_file_internal, preview_write, preview_edit, delete_file_internal, create_directory_internal, move_file_internal, restore_backup_internal, edit_file_internal, TextEdit, insert_lines as insert_lines_internal, replace_line_range, delete_line_range, list_files_safe, search_code_internal, SearchOptions, SearchMatch, read_skeleton_internal, read_skeletons_internal, outline_internal, find_references as find_references_internal, workspace_stats as workspace_stats_internal, detect_project as detect_project_internal, list_todos as list_todos_internal, check_version, record_version, FsError, git_status as git_status_internal, git_diff as git_diff_internal, git_blame as git_blame_internal, graph::with_graph, graph::with_crate_graph, CallGraph, fuzzy_find_files as fuzzy_find_files_internal, DEFAULT_FUZZY_LIMIT, SemanticIndex};
 use common::{get_session, RadkitState};
 
 // Hack for missing to_value
@@ -41,14 +41,12 @@ fn find_usages(root: &std::path::Path, file_path: &str) -> Option<Vec<String>> {
 
     if let Some(term) = search_term {
         let query = format!(r"\b{}\b", regex::escape(&term));
-        if let Ok(matches) = crate::search_code_internal(root, &query) {
+        if let Ok(matches) = crate::search_code_internal(root, &query, &Default::default()) {
              let mut consumers = Vec::new();
              for m in matches {
-                 // m format: path:line: content
-                 if let Some((path_part, _)) = m.split_once(':') {
-                     if path_part != file_path && !consumers.contains(&path_part.to_string()) {
-                         consumers.push(path_part.to_string());
-                     }
+                 let path = m.path.to_string_lossy().to_string();
+                 if m.path != path_obj && !consumers.contains(&path) {
+                     consumers.push(path);
                  }
              }
              return Some(consumers);
@@ -66,35 +64,122 @@ fn get_state(ctx: &ToolContext) -> Result<std::sync::Arc<RadkitState>, String> {
 #[derive(Deserialize, JsonSchema)]
 pub struct ReadFileArgs {
     pub file_path: String,
+    /// First line to read (1-based)
+    pub start_line: Option<usize>,
+    /// Last line to read (inclusive)
+    pub end_line: Option<usize>,
+    /// Continuation cursor from a previous truncated read
+    pub cursor: Option<String>,
 }
 
-#[tool(description = "Read file content.")]
+#[tool(description = "Read file content. Large files are returned in pages: pass the cursor from the truncation note to continue, or start_line/end_line to read a numbered slice.")]
 pub async fn read_file(args: ReadFileArgs, ctx: &ToolContext<'_>) -> ToolResult {
     let state = match get_state(ctx) {
         Ok(s) => s,
         Err(e) => return ToolResult::error(e),
     };
 
-    match read_file_internal(&state.root, args.file_path) {
-        Ok(fc) => ToolResult::success(fc.content.into()),
+    record_version(&state.file_versions, &state.root, &args.file_path);
+    if args.start_line.is_some() || args.end_line.is_some() {
+        return match read_file_range(&state.root, args.file_path, args.start_line, args.end_line) {
+            Ok(slice) => ToolResult::success(format!(
+                "[lines {}-{} of {}]\n{}",
+                slice.start_line, slice.end_line, slice.total_lines, slice.numbered()
+            ).into()),
+            Err(e) => ToolResult::error(format!("Error: {}", e))
+        };
+    }
+
+    let offset = match args.cursor.as_deref().map(str::parse::<usize>) {
+        None => 0,
+        Some(Ok(offset)) => offset,
+        Some(Err(_)) => return ToolResult::error("Error: invalid cursor".to_string()),
+    };
+    match read_file_page(&state.root, args.file_path, offset, max_read_bytes()) {
+        Ok(page) => {
+            let mut output = page.content;
+            if let Some(next) = page.next_offset {
+                output.push_str(&format!(
+                    "\n[Truncated: bytes {}-{} of {}. Call read_file again with cursor=\"{}\" for the next page, or use start_line/end_line.]",
+                    page.offset, next, page.total_bytes, next
+                ));
+            }
+            ToolResult::success(output.into())
+        },
         Err(e) => ToolResult::error(format!("Error: {}", e))
     }
 }
 
+#[derive(Deserialize, JsonSchema)]
+pub struct ReadAssetArgs {
+    pub file_path: String,
+}
+
+#[tool(description = "Read an image or SVG (png, jpeg, gif, webp, bmp, ico, svg; up to 5 MB) as base64 with its MIME type and pixel dimensions, to look at an icon or screenshot you are asked to change or recreate.")]
+pub async fn read_asset(args: ReadAssetArgs, ctx: &ToolContext<'_>) -> ToolResult {
+    let state = match get_state(ctx) {
+        Ok(s) => s,
+        Err(e) => return ToolResult::error(e),
+    };
+
+    match read_asset_internal(&state.root, &args.file_path) {
+        Ok(asset) => ToolResult::success(serde_json::json!({
+            "path": asset.path.to_string_lossy(),
+            "mime_type": asset.mime,
+            "width": asset.width,
+            "height": asset.height,
+            "size_bytes": asset.size,
+            "data": asset.base64,
+        })),
+        Err(e) => ToolResult::error(format!("Error: {}", e)),
+    }
+}
+
 #[derive(Deserialize, JsonSchema)]
 pub struct WriteFileArgs {
     pub file_path: String,
     pub content: String,
+    /// Write even if the content does not parse (templates, deliberately broken fixtures)
+    pub skip_validation: Option<bool>,
+    /// Validate and return the diff against the current content without writing
+    pub preview: Option<bool>,
+    /// For .ipynb files: replace only this cell's source (0-based, as in the `# %% [i]` headers); one past the last cell appends a code cell
+    pub cell: Option<usize>,
 }
 
-#[tool(description = "Write file content. Will analyze imports to warn about potential breakages.")]
+#[tool(description = "Write file content. Will analyze imports to warn about potential breakages. Fails with a conflict if the file changed on disk since you last read it; read it again and redo the change. With preview: true, returns the unified diff instead of writing. For notebooks, pass cell to write one cell's source.")]
 pub async fn write_file(args: WriteFileArgs, ctx: &ToolContext<'_>) -> ToolResult {
     let state = match get_state(ctx) {
         Ok(s) => s,
         Err(e) => return ToolResult::error(e),
     };
 
-    match write_file_internal(&state.root, args.file_path.clone(), args.content) {
+    if let Some(cell) = args.cell {
+        if args.preview.unwrap_or(false) {
+            let updated = read_file_internal(&state.root, args.file_path.clone())
+                .and_then(|fc| notebook::set_cell(&fc.content, cell, &args.content).map_err(FsError::Edit));
+            return match updated.and_then(|content| preview_write(&state.root, args.file_path, content, false)) {
+                Ok(diff) => ToolResult::success(preview_output(diff).into()),
+                Err(e) => ToolResult::error(format!("Error: {}", e)),
+            };
+        }
+        return match guarded_write(&state, &args.file_path, || write_notebook_cell(&state.root, args.file_path.clone(), cell, &args.content)) {
+            Ok(_) => ToolResult::success(format!("Successfully wrote cell {}.", cell).into()),
+            Err(e) => ToolResult::error(format!("Error: {}", e)),
+        };
+    }
+
+    if args.preview.unwrap_or(false) {
+        return match preview_write(&state.root, args.file_path, args.content, args.skip_validation.unwrap_or(false)) {
+            Ok(diff) => ToolResult::success(preview_output(diff).into()),
+            Err(e) => ToolResult::error(format!("Error: {}", e)),
+        };
+    }
+
+    let written = guarded_write(&state, &args.file_path, || {
+        write_file_internal(&state.root, args.file_path.clone(), args.content, args.skip_validation.unwrap_or(false))
+    });
+    match written {
         Ok(_) => {
             let mut output = "Successfully wrote file.".to_string();
             if let Some(consumers) = find_usages(&state.root, &args.file_path) {
@@ -115,6 +200,217 @@ pub async fn write_file(args: WriteFileArgs, ctx: &ToolContext<'_>) -> ToolResul
     }
 }
 
+// Refuses the write if the file changed on disk since the agent last saw it, and records
+// the written content as the new version
+fn guarded_write<T>(state: &RadkitState, file_path: &str, write: impl FnOnce() -> Result<T, FsError>) -> Result<T, FsError> {
+    check_version(&state.file_versions, &state.root, file_path)?;
+    let result = write()?;
+    record_version(&state.file_versions, &state.root, file_path);
+    Ok(result)
+}
+
+fn preview_output(diff: String) -> String {
+    if diff.is_empty() {
+        "Preview: no changes.".to_string()
+    } else {
+        format!("Preview (nothing was written):\n{}", diff)
+    }
+}
+
+#[derive(Deserialize, JsonSchema)]
+pub struct DeleteFileArgs {
+    pub file_path: String,
+}
+
+#[tool(description = "Delete a file. It is moved to .irongraph/trash, so it can be restored.")]
+pub async fn delete_file(args: DeleteFileArgs, ctx: &ToolContext<'_>) -> ToolResult {
+    let state = match get_state(ctx) {
+        Ok(s) => s,
+        Err(e) => return ToolResult::error(e),
+    };
+
+    match delete_file_internal(&state.root, args.file_path) {
+        Ok(trashed) => ToolResult::success(format!("Deleted (moved to {}).", trashed.display()).into()),
+        Err(e) => ToolResult::error(format!("Error: {}", e))
+    }
+}
+
+#[derive(Deserialize, JsonSchema)]
+pub struct RestoreBackupArgs {
+    pub file_path: String,
+}
+
+#[tool(description = "Undo the last write to a file by restoring the version it replaced. Restoring again redoes the write.")]
+pub async fn restore_backup(args: RestoreBackupArgs, ctx: &ToolContext<'_>) -> ToolResult {
+    let state = match get_state(ctx) {
+        Ok(s) => s,
+        Err(e) => return ToolResult::error(e),
+    };
+
+    match restore_backup_internal(&state.root, args.file_path.clone()) {
+        Ok(fc) => {
+            record_version(&state.file_versions, &state.root, &args.file_path);
+            ToolResult::success(format!("Restored {} ({} lines).", fc.path.display(), fc.content.lines().count()).into())
+        }
+        Err(e) => ToolResult::error(format!("Error: {}", e))
+    }
+}
+
+#[derive(Deserialize, JsonSchema)]
+pub struct CreateDirectoryArgs {
+    pub dir_path: String,
+}
+
+#[tool(description = "Create a directory, including missing parent directories.")]
+pub async fn create_directory(args: CreateDirectoryArgs, ctx: &ToolContext<'_>) -> ToolResult {
+    let state = match get_state(ctx) {
+        Ok(s) => s,
+        Err(e) => return ToolResult::error(e),
+    };
+
+    match create_directory_internal(&state.root, args.dir_path) {
+        Ok(path) => ToolResult::success(format!("Created directory {}.", path.display()).into()),
+        Err(e) => ToolResult::error(format!("Error: {}", e))
+    }
+}
+
+#[derive(Deserialize, JsonSchema)]
+pub struct MoveFileArgs {
+    pub from: String,
+    pub to: String,
+}
+
+#[tool(description = "Move or rename a file. Rust `mod`/`crate::` paths and relative JS/TS imports that point at it are updated.")]
+pub async fn move_file(args: MoveFileArgs, ctx: &ToolContext<'_>) -> ToolResult {
+    let state = match get_state(ctx) {
+        Ok(s) => s,
+        Err(e) => return ToolResult::error(e),
+    };
+
+    let result = move_file_internal(&state.root, args.from.clone(), args.to.clone());
+    if let Ok(outcome) = &result {
+        // The agent wrote these itself, so later edits must not report them as changed on disk
+        for path in [args.from.as_str(), args.to.as_str()] {
+            record_version(&state.file_versions, &state.root, path);
+        }
+        for path in &outcome.updated {
+            record_version(&state.file_versions, &state.root, &path.to_string_lossy());
+        }
+    }
+
+    match result {
+        Ok(outcome) if outcome.updated.is_empty() => ToolResult::success(format!("Moved to {}.", outcome.to.display()).into()),
+        Ok(outcome) => {
+            let updated: Vec<String> = outcome.updated.iter().map(|p| p.display().to_string()).collect();
+            ToolResult::success(format!("Moved to {}. Updated imports in: {}", outcome.to.display(), updated.join(", ")).into())
+        }
+        Err(e) => ToolResult::error(format!("Error: {}", e))
+    }
+}
+
+#[derive(Deserialize, JsonSchema)]
+pub struct EditFileArgs {
+    pub file_path: String,
+    pub old_string: String,
+    pub new_string: String,
+    pub replace_all: Option<bool>,
+    /// Validate and return the diff without writing
+    pub preview: Option<bool>,
+}
+
+#[tool(description = "Replace an exact snippet of a file. old_string must match exactly once (including whitespace) unless replace_all is true. Prefer this over write_file for changes to existing files. With preview: true, returns the unified diff instead of writing.")]
+pub async fn edit_file(args: EditFileArgs, ctx: &ToolContext<'_>) -> ToolResult {
+    let state = match get_state(ctx) {
+        Ok(s) => s,
+        Err(e) => return ToolResult::error(e),
+    };
+
+    let edits = [TextEdit {
+        old_string: args.old_string,
+        new_string: args.new_string,
+        replace_all: args.replace_all.unwrap_or(false),
+    }];
+    if args.preview.unwrap_or(false) {
+        return match preview_edit(&state.root, args.file_path, &edits) {
+            Ok((diff, _)) => ToolResult::success(preview_output(diff).into()),
+            Err(e) => ToolResult::error(format!("Error: {}", e)),
+        };
+    }
+    // Search-and-replace applies to the current content, so an external edit elsewhere in
+    // the file is kept rather than clobbered; only record the result
+    match edit_file_internal(&state.root, args.file_path.clone(), &edits) {
+        Ok((_, replaced)) => {
+            record_version(&state.file_versions, &state.root, &args.file_path);
+            ToolResult::success(format!("Successfully edited file ({} replacement{}).", replaced, if replaced == 1 { "" } else { "s" }).into())
+        }
+        Err(e) => ToolResult::error(format!("Error: {}", e))
+    }
+}
+
+#[derive(Deserialize, JsonSchema)]
+pub struct InsertLinesArgs {
+    pub file_path: String,
+    /// 1-based line the text is inserted before; one past the last line appends
+    pub line: usize,
+    pub content: String,
+}
+
+#[tool(description = "Insert lines into a file before the given 1-based line number.")]
+pub async fn insert_lines(args: InsertLinesArgs, ctx: &ToolContext<'_>) -> ToolResult {
+    let state = match get_state(ctx) {
+        Ok(s) => s,
+        Err(e) => return ToolResult::error(e),
+    };
+
+    match guarded_write(&state, &args.file_path, || insert_lines_internal(&state.root, args.file_path.clone(), args.line, &args.content)) {
+        Ok(_) => ToolResult::success(format!("Inserted before line {}.", args.line).into()),
+        Err(e) => ToolResult::error(format!("Error: {}", e))
+    }
+}
+
+#[derive(Deserialize, JsonSchema)]
+pub struct ReplaceLinesArgs {
+    pub file_path: String,
+    pub start_line: usize,
+    /// Inclusive
+    pub end_line: usize,
+    pub content: String,
+}
+
+#[tool(description = "Replace lines start_line..=end_line (1-based) of a file with new content.")]
+pub async fn replace_lines(args: ReplaceLinesArgs, ctx: &ToolContext<'_>) -> ToolResult {
+    let state = match get_state(ctx) {
+        Ok(s) => s,
+        Err(e) => return ToolResult::error(e),
+    };
+
+    match guarded_write(&state, &args.file_path, || replace_line_range(&state.root, args.file_path.clone(), args.start_line, args.end_line, &args.content)) {
+        Ok(_) => ToolResult::success(format!("Replaced lines {}-{}.", args.start_line, args.end_line).into()),
+        Err(e) => ToolResult::error(format!("Error: {}", e))
+    }
+}
+
+#[derive(Deserialize, JsonSchema)]
+pub struct DeleteLinesArgs {
+    pub file_path: String,
+    pub start_line: usize,
+    /// Inclusive
+    pub end_line: usize,
+}
+
+#[tool(description = "Delete lines start_line..=end_line (1-based) from a file.")]
+pub async fn delete_lines(args: DeleteLinesArgs, ctx: &ToolContext<'_>) -> ToolResult {
+    let state = match get_state(ctx) {
+        Ok(s) => s,
+        Err(e) => return ToolResult::error(e),
+    };
+
+    match guarded_write(&state, &args.file_path, || delete_line_range(&state.root, args.file_path.clone(), args.start_line, args.end_line)) {
+        Ok(_) => ToolResult::success(format!("Deleted lines {}-{}.", args.start_line, args.end_line).into()),
+        Err(e) => ToolResult::error(format!("Error: {}", e))
+    }
+}
+
 #[derive(Deserialize, JsonSchema)]
 pub struct ListFilesArgs {
     pub dir_path: Option<String>,
@@ -127,64 +423,555 @@ pub async fn list_files(args: ListFilesArgs, ctx: &ToolContext<'_>) -> ToolResul
         Err(e) => return ToolResult::error(e),
     };
 
-    let effective_dir = if let Some(d) = args.dir_path {
-        if d.is_empty() { state.root.clone() } else { state.root.join(d) }
-    } else {
-        state.root.clone()
-    };
-
-    match build_file_tree(&state.root, &effective_dir) {
+    match list_files_safe(&state.root, args.dir_path.as_deref().unwrap_or("")) {
         Ok(entries) => {
-             let s = entries.iter().map(|e| format!("{}{}", if e.is_dir { "[DIR] " } else { "" }, e.name)).collect::<Vec<_>>().join("\n");
+             let s = entries.iter().map(|e| format!("{}{}", if e.is_dir { "[DIR] " } else if e.is_binary { "[BIN] " } else { "" }, e.name)).collect::<Vec<_>>().join("\n");
              ToolResult::success(s.into())
         },
         Err(e) => ToolResult::error(format!("Error: {}", e))
     }
 }
 
+#[derive(Deserialize, JsonSchema)]
+pub struct WorkspaceStatsArgs {}
+
+fn format_size(bytes: u64) -> String {
+    match bytes {
+        b if b >= 1024 * 1024 => format!("{:.1} MB", b as f64 / (1024.0 * 1024.0)),
+        b if b >= 1024 => format!("{:.1} KB", b as f64 / 1024.0),
+        b => format!("{} B", b),
+    }
+}
+
+#[tool(description = "Summarize the workspace: lines and files per language, the largest files, and the size of each top-level directory.")]
+pub async fn workspace_stats(_args: WorkspaceStatsArgs, ctx: &ToolContext<'_>) -> ToolResult {
+    let state = match get_state(ctx) {
+        Ok(s) => s,
+        Err(e) => return ToolResult::error(e),
+    };
+
+    let stats = workspace_stats_internal(&state.root);
+    let mut out = format!("{} files, {} lines, {}\n\nLanguages:\n", stats.files, stats.lines, format_size(stats.bytes));
+    for lang in &stats.languages {
+        out.push_str(&format!("  {}: {} lines in {} files\n", lang.language, lang.lines, lang.files));
+    }
+    out.push_str("\nLargest files:\n");
+    for file in &stats.largest_files {
+        out.push_str(&format!("  {} ({})\n", file.path.display(), format_size(file.bytes)));
+    }
+    out.push_str("\nDirectories:\n");
+    for dir in &stats.directories {
+        out.push_str(&format!("  {} ({} files, {})\n", dir.path.display(), dir.files, format_size(dir.bytes)));
+    }
+    ToolResult::success(out.into())
+}
+
+#[derive(Deserialize, JsonSchema)]
+pub struct DetectProjectArgs {}
+
+#[tool(description = "Describe the project: Cargo and npm manifests with workspace members, dependencies, npm scripts and binary targets, toolchain pins, and the default build/test command.")]
+pub async fn detect_project(_args: DetectProjectArgs, ctx: &ToolContext<'_>) -> ToolResult {
+    let state = match get_state(ctx) {
+        Ok(s) => s,
+        Err(e) => return ToolResult::error(e),
+    };
+
+    let info = detect_project_internal(&state.root);
+    if info.projects.is_empty() {
+        return ToolResult::success("No Cargo.toml or package.json found at the workspace root.".into());
+    }
+    let mut out = info.summary();
+    for project in &info.projects {
+        let dev: Vec<String> = project.dependencies.iter().filter(|d| d.dev).map(|d| d.name.clone()).collect();
+        if !dev.is_empty() {
+            let location = if project.path.as_os_str().is_empty() { ".".to_string() } else { project.path.display().to_string() };
+            out.push_str(&format!("\nDev dependencies of {}: {}", location, dev.join(", ")));
+        }
+        for (name, command) in &project.scripts {
+            out.push_str(&format!("\n  script {}: {}", name, command));
+        }
+    }
+    ToolResult::success(out.into())
+}
+
+#[derive(Deserialize, JsonSchema)]
+pub struct ListTodosArgs {
+    /// Directory to scan, relative to the workspace root; the whole workspace when omitted
+    pub dir_path: Option<String>,
+}
+
+#[tool(description = "List TODO, FIXME, HACK and XXX comments with their file, line, author and linked issue where given. Pass a directory to scope the scan to one module.")]
+pub async fn list_todos(args: ListTodosArgs, ctx: &ToolContext<'_>) -> ToolResult {
+    let state = match get_state(ctx) {
+        Ok(s) => s,
+        Err(e) => return ToolResult::error(e),
+    };
+
+    match list_todos_internal(&state.root, args.dir_path.as_deref().unwrap_or("")) {
+        Ok(todos) if todos.is_empty() => ToolResult::success("No TODO markers found.".into()),
+        Ok(todos) => {
+            let out = todos.iter().map(|t| {
+                let mut line = format!("{}:{} {}", t.path.display(), t.line, t.marker);
+                let attribution: Vec<&str> = [t.author.as_deref(), t.issue.as_deref()].into_iter().flatten().collect();
+                if !attribution.is_empty() {
+                    line.push_str(&format!(" ({})", attribution.join(", ")));
+                }
+                if !t.text.is_empty() {
+                    line.push_str(&format!(": {}", t.text));
+                }
+                line
+            }).collect::<Vec<_>>().join("\n");
+            ToolResult::success(out.into())
+        }
+        Err(e) => ToolResult::error(format!("Error: {}", e)),
+    }
+}
+
+#[derive(Deserialize, JsonSchema)]
+pub struct GitStatusArgs {}
+
+#[tool(description = "Show uncommitted changes in the workspace, like `git status --short`: the first column is the staged change, the second the unstaged one (? = untracked).")]
+pub async fn git_status(_args: GitStatusArgs, ctx: &ToolContext<'_>) -> ToolResult {
+    let state = match get_state(ctx) {
+        Ok(s) => s,
+        Err(e) => return ToolResult::error(e),
+    };
+
+    match git_status_internal(&state.root) {
+        Ok(status) => {
+            let mut out = format!("On branch {}", status.branch.as_deref().unwrap_or("(detached or unborn)"));
+            if status.files.is_empty() {
+                out.push_str("\nNothing to commit, working tree clean.");
+            }
+            for file in &status.files {
+                let code = |c: Option<crate::GitChange>| c.map_or(' ', |c| c.code());
+                out.push_str(&format!("\n{}{} {}", code(file.index), code(file.worktree), file.path.display()));
+            }
+            ToolResult::success(out.into())
+        }
+        Err(e) => ToolResult::error(format!("Error: {}", e)),
+    }
+}
+
+#[derive(Deserialize, JsonSchema)]
+pub struct GitDiffArgs {
+    /// File or directory to diff; the whole workspace when omitted
+    pub file_path: Option<String>,
+    /// Only changes staged in the index
+    pub staged: Option<bool>,
+}
+
+#[tool(description = "Unified diff of uncommitted changes against HEAD, including untracked files. Use it to review exactly what you changed before finishing or writing a commit message.")]
+pub async fn git_diff(args: GitDiffArgs, ctx: &ToolContext<'_>) -> ToolResult {
+    let state = match get_state(ctx) {
+        Ok(s) => s,
+        Err(e) => return ToolResult::error(e),
+    };
+
+    match git_diff_internal(&state.root, args.file_path.as_deref(), args.staged.unwrap_or(false)) {
+        Ok(diff) if diff.is_empty() => ToolResult::success("No changes.".into()),
+        Ok(diff) => ToolResult::success(diff.into()),
+        Err(e) => ToolResult::error(format!("Error: {}", e)),
+    }
+}
+
+#[derive(Deserialize, JsonSchema)]
+pub struct GitBlameArgs {
+    pub file_path: String,
+    /// First line to blame (1-based)
+    pub start_line: Option<usize>,
+    /// Last line to blame (inclusive)
+    pub end_line: Option<usize>,
+}
+
+#[tool(description = "Show who last changed each line of a file and in which commit (hash, author, date, commit summary). Use it to understand why code is the way it is before criticizing or changing it.")]
+pub async fn git_blame(args: GitBlameArgs, ctx: &ToolContext<'_>) -> ToolResult {
+    let state = match get_state(ctx) {
+        Ok(s) => s,
+        Err(e) => return ToolResult::error(e),
+    };
+
+    match git_blame_internal(&state.root, &args.file_path, args.start_line, args.end_line) {
+        Ok(lines) => {
+            let width = lines.last().map_or(1, |l| l.line.to_string().len());
+            let mut out = String::new();
+            let mut previous: Option<&Option<String>> = None;
+            // One header per run of lines from the same commit
+            for line in &lines {
+                if previous != Some(&line.commit) {
+                    match &line.commit {
+                        Some(commit) => out.push_str(&format!("{} {} {}: {}\n", commit, line.date(), line.author, line.summary)),
+                        None => out.push_str("(uncommitted)\n"),
+                    }
+                    previous = Some(&line.commit);
+                }
+                out.push_str(&format!("  {:>width$} | {}\n", line.line, line.text));
+            }
+            ToolResult::success(out.into())
+        }
+        Err(e) => ToolResult::error(format!("Error: {}", e)),
+    }
+}
+
+#[derive(Deserialize, JsonSchema)]
+pub struct GraphFileArgs {
+    pub file_path: String,
+}
+
+fn render_paths(paths: &[std::path::PathBuf], empty: &str) -> String {
+    if paths.is_empty() {
+        return empty.to_string();
+    }
+    paths.iter().map(|p| p.display().to_string()).collect::<Vec<_>>().join("\n")
+}
+
+#[tool(description = "List the workspace files a file imports (Rust mod/use within the crate, relative JS/TS imports).")]
+pub async fn dependencies_of(args: GraphFileArgs, ctx: &ToolContext<'_>) -> ToolResult {
+    let state = match get_state(ctx) {
+        Ok(s) => s,
+        Err(e) => return ToolResult::error(e),
+    };
+
+    let deps = with_graph(&state.root, |g| g.dependencies_of(std::path::Path::new(&args.file_path)));
+    ToolResult::success(render_paths(&deps, "No workspace dependencies.").into())
+}
+
+#[tool(description = "List the workspace files that import a file. Use before changing a file's public API.")]
+pub async fn dependents_of(args: GraphFileArgs, ctx: &ToolContext<'_>) -> ToolResult {
+    let state = match get_state(ctx) {
+        Ok(s) => s,
+        Err(e) => return ToolResult::error(e),
+    };
+
+    let dependents = with_graph(&state.root, |g| g.dependents_of(std::path::Path::new(&args.file_path)));
+    ToolResult::success(render_paths(&dependents, "No files import this one.").into())
+}
+
+#[derive(Deserialize, JsonSchema)]
+pub struct FindCyclesArgs {}
+
+#[tool(description = "Find import cycles between workspace files.")]
+pub async fn find_cycles(_args: FindCyclesArgs, ctx: &ToolContext<'_>) -> ToolResult {
+    let state = match get_state(ctx) {
+        Ok(s) => s,
+        Err(e) => return ToolResult::error(e),
+    };
+
+    let cycles = with_graph(&state.root, |g| g.cycles());
+    if cycles.is_empty() {
+        return ToolResult::success("No import cycles.".to_string().into());
+    }
+    let out = cycles.iter().enumerate()
+        .map(|(i, files)| format!("Cycle {}:\n{}", i + 1, render_paths(files, "")))
+        .collect::<Vec<_>>()
+        .join("\n\n");
+    ToolResult::success(out.into())
+}
+
+#[derive(Deserialize, JsonSchema)]
+pub struct CrateGraphArgs {
+    /// Crate to inspect; lists the workspace members when omitted
+    pub crate_name: Option<String>,
+}
+
+#[tool(description = "Crate-level dependency graph from cargo metadata. Without crate_name, lists the workspace members and their direct dependencies; with it, shows that crate's version, enabled features, dependencies (with kind and version requirement) and dependents.")]
+pub async fn crate_graph(args: CrateGraphArgs, ctx: &ToolContext<'_>) -> ToolResult {
+    let state = match get_state(ctx) {
+        Ok(s) => s,
+        Err(e) => return ToolResult::error(e),
+    };
+
+    let rendered = with_crate_graph(&state.root, |g| {
+        let render_deps = |id: &str| g.dependencies_of(id).iter()
+            .map(|(c, d)| {
+                let mut line = format!("    {} {}", c.name, c.version);
+                if let Some(req) = &d.req {
+                    line.push_str(&format!(" (requires {})", req));
+                }
+                let kinds: Vec<&str> = d.kinds.iter().map(String::as_str).filter(|k| *k != "normal").collect();
+                if !kinds.is_empty() {
+                    line.push_str(&format!(" [{}]", kinds.join(", ")));
+                }
+                if d.optional {
+                    line.push_str(" [optional]");
+                }
+                line
+            })
+            .collect::<Vec<_>>()
+            .join("\n");
+
+        match &args.crate_name {
+            None => g.members()
+                .map(|c| {
+                    let location = c.path.as_ref().map_or_else(String::new, |p| format!(" at {}", p.display()));
+                    format!("{} {}{}\n{}", c.name, c.version, location, render_deps(&c.id))
+                })
+                .collect::<Vec<_>>()
+                .join("\n\n"),
+            Some(name) => match g.find(name) {
+                None => format!("No crate named {} in the dependency graph.", name),
+                Some(c) => {
+                    let dependents: Vec<String> = g.dependents_of(&c.id).iter().map(|(d, _)| format!("    {} {}", d.name, d.version)).collect();
+                    format!(
+                        "{} {}{}\nEnabled features: {}\nAvailable features: {}\nDependencies:\n{}\nDependents:\n{}",
+                        c.name,
+                        c.version,
+                        if c.workspace_member { " (workspace member)" } else { "" },
+                        if c.features.is_empty() { "none".to_string() } else { c.features.join(", ") },
+                        if c.available_features.is_empty() { "none".to_string() } else { c.available_features.join(", ") },
+                        render_deps(&c.id),
+                        dependents.join("\n"),
+                    )
+                }
+            },
+        }
+    });
+    match rendered {
+        Ok(out) => ToolResult::success(out.into()),
+        Err(e) => ToolResult::error(format!("Error: {}", e)),
+    }
+}
+
+#[derive(Deserialize, JsonSchema)]
+pub struct ImpactOfArgs {
+    /// Function name, optionally qualified as `Type::method`
+    pub function: String,
+}
+
+#[tool(description = "List the Rust functions that directly or transitively call a function, and the tests among them. Use it to choose which tests to run after a change.")]
+pub async fn impact_of(args: ImpactOfArgs, ctx: &ToolContext<'_>) -> ToolResult {
+    let state = match get_state(ctx) {
+        Ok(s) => s,
+        Err(e) => return ToolResult::error(e),
+    };
+
+    let affected = CallGraph::build(&state.root).impact_of(&args.function);
+    if affected.is_empty() {
+        return ToolResult::success(format!("Nothing in the workspace calls {}.", args.function).into());
+    }
+    let render = |tests: bool| affected.iter()
+        .filter(|f| f.is_test == tests)
+        .map(|f| format!("  {}:{} {}", f.path.display(), f.line, f.name))
+        .collect::<Vec<_>>();
+    let callers = render(false);
+    let tests = render(true);
+    let mut out = format!("Callers of {} ({}):\n{}", args.function, callers.len(), callers.join("\n"));
+    out.push_str(&format!("\n\nAffected tests ({}):\n{}", tests.len(), tests.join("\n")));
+    ToolResult::success(out.into())
+}
+
+#[derive(Deserialize, JsonSchema)]
+pub struct FuzzyFindFilesArgs {
+    /// Words or abbreviations from the file name or path, e.g. "user service"
+    pub query: String,
+    pub limit: Option<usize>,
+}
+
+#[tool(description = "Find files by approximate name, best match first. Faster than listing directories when you roughly know what a file is called.")]
+pub async fn fuzzy_find_files(args: FuzzyFindFilesArgs, ctx: &ToolContext<'_>) -> ToolResult {
+    let state = match get_state(ctx) {
+        Ok(s) => s,
+        Err(e) => return ToolResult::error(e),
+    };
+
+    let matches = fuzzy_find_files_internal(&state.root, &args.query, args.limit.unwrap_or(DEFAULT_FUZZY_LIMIT));
+    if matches.is_empty() {
+        return ToolResult::success(format!("No files match '{}'.", args.query).into());
+    }
+    let out = matches.iter().map(|m| m.path.display().to_string()).collect::<Vec<_>>().join("\n");
+    ToolResult::success(out.into())
+}
+
 #[derive(Deserialize, JsonSchema)]
 pub struct ReadSkeletonArgs {
     pub file_path: String,
 }
 
-#[tool(description = "Read the skeleton of a file (structure without function bodies).")]
+#[tool(description = "Read the skeleton of a file (structure without function bodies). Rust and JS/TS are reformatted; Python, Go, Java and C/C++ keep their layout with line numbers.")]
 pub async fn read_skeleton(args: ReadSkeletonArgs, ctx: &ToolContext<'_>) -> ToolResult {
     let state = match get_state(ctx) {
         Ok(s) => s,
         Err(e) => return ToolResult::error(e),
     };
 
-    let fc = read_file_internal(&state.root, args.file_path.clone());
-    match fc {
-        Ok(c) => match get_skeleton(std::path::Path::new(&args.file_path), &c.content) {
-            Ok(s) => ToolResult::success(s.into()),
-            Err(e) => ToolResult::error(format!("Error generating skeleton: {}", e)),
-        },
-        Err(e) => ToolResult::error(format!("Error reading file: {}", e)),
+    match read_skeleton_internal(&state.root, args.file_path) {
+        Ok(s) => ToolResult::success(s.into()),
+        Err(e) => ToolResult::error(format!("Error generating skeleton: {}", e)),
+    }
+}
+
+#[derive(Deserialize, JsonSchema)]
+pub struct OutlineArgs {
+    pub file_path: String,
+}
+
+#[tool(description = "List the top-level items of a file (functions, types, impls, classes, exports) with their line ranges, for use with start_line/end_line reads and replace_lines. Lighter than read_skeleton.")]
+pub async fn outline(args: OutlineArgs, ctx: &ToolContext<'_>) -> ToolResult {
+    let state = match get_state(ctx) {
+        Ok(s) => s,
+        Err(e) => return ToolResult::error(e),
+    };
+
+    match outline_internal(&state.root, args.file_path) {
+        Ok(items) if items.is_empty() => ToolResult::success("No top-level items.".into()),
+        Ok(items) => {
+            let out = items.iter()
+                .map(|i| format!("{}-{} {} {}", i.start_line, i.end_line, i.kind, i.name))
+                .collect::<Vec<_>>()
+                .join("\n");
+            ToolResult::success(out.into())
+        }
+        Err(e) => ToolResult::error(format!("Error: {}", e)),
     }
 }
 
+#[derive(Deserialize, JsonSchema)]
+pub struct ReadSkeletonsArgs {
+    pub file_paths: Vec<String>,
+}
+
+#[tool(description = "Read the skeletons of several files in one call, each under a `// path` header. Prefer this to repeated read_skeleton calls when surveying a module.")]
+pub async fn read_skeletons(args: ReadSkeletonsArgs, ctx: &ToolContext<'_>) -> ToolResult {
+    let state = match get_state(ctx) {
+        Ok(s) => s,
+        Err(e) => return ToolResult::error(e),
+    };
+
+    let sections: Vec<String> = read_skeletons_internal(&state.root, args.file_paths).into_iter()
+        .map(|(path, skeleton)| match skeleton {
+            Ok(s) => format!("// {}\n{}", path, s.trim_end()),
+            Err(e) => format!("// {}\nError generating skeleton: {}", path, e),
+        })
+        .collect();
+    ToolResult::success(sections.join("\n\n").into())
+}
+
+// grep style: `path:line:col: text` for the match, `path-line- text` for context. A
+// multiline match shows its line range with the lines joined by ⏎.
+fn render_match(m: &SearchMatch) -> String {
+    let path = m.path.display();
+    let mut out = String::new();
+    for (i, ctx) in m.before.iter().enumerate() {
+        out.push_str(&format!("{}-{}- {}\n", path, m.line - m.before.len() + i, ctx));
+    }
+    let location = if m.end_line > m.line { format!("{}-{}", m.line, m.end_line) } else { m.line.to_string() };
+    out.push_str(&format!("{}:{}:{}: {}", path, location, m.column, m.text.replace('\n', " ⏎ ")));
+    for (i, ctx) in m.after.iter().enumerate() {
+        out.push_str(&format!("\n{}-{}- {}", path, m.end_line + 1 + i, ctx));
+    }
+    out
+}
+
 #[derive(Deserialize, JsonSchema)]
 pub struct SearchCodeArgs {
     pub query: String,
+    /// Lines of context before and after each match
+    pub context_lines: Option<usize>,
+    /// Only search files matching these globs, e.g. "src/**/*.rs"
+    pub include: Option<Vec<String>>,
+    /// Skip files matching these globs
+    pub exclude: Option<Vec<String>>,
+    pub case_insensitive: Option<bool>,
+    /// Treat the query as a literal string instead of a regex
+    pub fixed_string: Option<bool>,
+    pub max_results: Option<usize>,
+    /// Allow matches spanning several lines (e.g. a signature split over lines)
+    pub multiline: Option<bool>,
 }
 
-#[tool(description = "Search code using regex.")]
+#[tool(description = "Search code using regex (or a literal string with fixed_string), optionally with context lines, include/exclude globs and multiline matching.")]
 pub async fn search_code(args: SearchCodeArgs, ctx: &ToolContext<'_>) -> ToolResult {
     let state = match get_state(ctx) {
         Ok(s) => s,
         Err(e) => return ToolResult::error(e),
     };
 
-    match search_code_internal(&state.root, &args.query) {
+    let context = args.context_lines.unwrap_or(0);
+    let options = SearchOptions {
+        before_context: context,
+        after_context: context,
+        include: args.include.unwrap_or_default(),
+        exclude: args.exclude.unwrap_or_default(),
+        case_insensitive: args.case_insensitive.unwrap_or(false),
+        fixed_string: args.fixed_string.unwrap_or(false),
+        max_results: args.max_results,
+        multiline: args.multiline.unwrap_or(false),
+    };
+    match search_code_internal(&state.root, &args.query, &options) {
         Ok(matches) => {
+            let rendered = matches.iter().take(20).map(render_match).collect::<Vec<_>>().join("\n");
             if matches.len() > 20 {
-                let s = format!("Found {} matches. First 20:\n{}", matches.len(), matches[..20].join("\n"));
+                let s = format!("Found {} matches. First 20:\n{}", matches.len(), rendered);
                 ToolResult::success(s.into())
             } else {
-                ToolResult::success(matches.join("\n").into())
+                ToolResult::success(rendered.into())
             }
         },
         Err(e) => ToolResult::error(format!("Error: {}", e))
     }
 }
+
+#[derive(Deserialize, JsonSchema)]
+pub struct SemanticSearchArgs {
+    /// What the code does, in plain words, e.g. "where do we validate JWTs?"
+    pub query: String,
+    /// Maximum number of chunks to return (default 5)
+    pub limit: Option<usize>,
+}
+
+#[tool(description = "Search code by meaning: returns the code chunks most related to a natural-language question. Use search_code instead when you know the exact identifier or text.")]
+pub async fn semantic_search(args: SemanticSearchArgs, ctx: &ToolContext<'_>) -> ToolResult {
+    let state = match get_state(ctx) {
+        Ok(s) => s,
+        Err(e) => return ToolResult::error(e),
+    };
+    let Some(embedder) = state.embedder.clone() else {
+        return ToolResult::error("Semantic search needs an embedding model, none is configured".to_string());
+    };
+
+    let mut index = match SemanticIndex::open(&state.root) {
+        Ok(index) => index,
+        Err(e) => return ToolResult::error(e),
+    };
+    if let Err(e) = index.update(&state.root, embedder.as_ref()).await {
+        return ToolResult::error(format!("Indexing failed: {}", e));
+    }
+    match index.search(&args.query, embedder.as_ref(), args.limit.unwrap_or(5)).await {
+        Ok(matches) if matches.is_empty() => ToolResult::success("No indexed code to search.".into()),
+        Ok(matches) => {
+            let rendered = matches.iter().map(|m| format!(
+                "{}:{}-{} (score {:.2})\n{}",
+                m.chunk.path.display(), m.chunk.start_line, m.chunk.end_line, m.score, m.chunk.text
+            )).collect::<Vec<_>>().join("\n\n");
+            ToolResult::success(rendered.into())
+        }
+        Err(e) => ToolResult::error(format!("Error: {}", e)),
+    }
+}
+
+#[derive(Deserialize, JsonSchema)]
+pub struct FindReferencesArgs {
+    /// Identifier to look up, e.g. a function or type name
+    pub symbol: String,
+}
+
+#[tool(description = "Find references to an identifier (imports, calls, other uses) in Rust and JS/TS files, parsed rather than grepped so comments and strings are skipped.")]
+pub async fn find_references(args: FindReferencesArgs, ctx: &ToolContext<'_>) -> ToolResult {
+    let state = match get_state(ctx) {
+        Ok(s) => s,
+        Err(e) => return ToolResult::error(e),
+    };
+
+    let refs = find_references_internal(&state.root, &args.symbol);
+    if refs.is_empty() {
+        return ToolResult::success(format!("No references to '{}' found.", args.symbol).into());
+    }
+    let lines: Vec<String> = refs.iter().take(50)
+        .map(|r| format!("{}:{}:{} [{}] {}", r.path.display(), r.line, r.column, r.kind, r.snippet))
+        .collect();
+    let mut output = lines.join("\n");
+    if refs.len() > 50 {
+        output = format!("Found {} references. First 50:\n{}", refs.len(), output);
+    }
+    ToolResult::success(output.into())
+}
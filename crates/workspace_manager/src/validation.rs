@@ -0,0 +1,165 @@
+use std::sync::RwLock;
+use syn::parse_file;
+
+// Syntax checks run before every agent write. Each language can be switched off, e.g.
+// for a project whose YAML files are really Jinja templates.
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ValidationConfig {
+    pub rust: bool,
+    pub javascript: bool,
+    pub python: bool,
+    pub json: bool,
+    pub toml: bool,
+    pub yaml: bool,
+}
+
+impl ValidationConfig {
+    pub const ALL: ValidationConfig = ValidationConfig {
+        rust: true,
+        javascript: true,
+        python: true,
+        json: true,
+        toml: true,
+        yaml: true,
+    };
+}
+
+impl Default for ValidationConfig {
+    fn default() -> Self {
+        Self::ALL
+    }
+}
+
+static CONFIG: RwLock<ValidationConfig> = RwLock::new(ValidationConfig::ALL);
+
+pub fn validation_config() -> ValidationConfig {
+    *CONFIG.read().unwrap()
+}
+
+pub fn set_validation_config(config: ValidationConfig) {
+    *CONFIG.write().unwrap() = config;
+}
+
+pub fn validate_syntax(path: &str, content: &str) -> Result<(), String> {
+    validate_with(&validation_config(), path, content)
+}
+
+/// `validate_syntax` with an explicit config instead of the global one.
+pub fn validate_with(config: &ValidationConfig, path: &str, content: &str) -> Result<(), String> {
+    let ext = std::path::Path::new(path).extension().and_then(|e| e.to_str()).unwrap_or("");
+    match ext {
+        "rs" if config.rust => {
+            parse_file(content).map_err(|e| format!("Rust Syntax Error: {}", e))?;
+        }
+        "ts" | "js" | "tsx" | "jsx" if config.javascript => {
+            let allocator = oxc_allocator::Allocator::default();
+            let source_type = oxc_span::SourceType::from_path(std::path::Path::new(path)).unwrap_or_default();
+            let ret = oxc_parser::Parser::new(&allocator, content, source_type).parse();
+
+            if !ret.errors.is_empty() {
+                 return Err(format!("JS/TS Syntax Error: {:?}", ret.errors[0]));
+            }
+        }
+        "py" | "pyi" if config.python => validate_python(content)?,
+        "json" if config.json => {
+            serde_json::from_str::<serde_json::Value>(content).map_err(|e| {
+                format!("JSON Syntax Error at line {}, column {}: {}", e.line(), e.column(), e)
+            })?;
+        }
+        // Catches a flattened `# %%` view written back in place of the JSON container
+        "ipynb" if config.json => {
+            crate::notebook::parse(content)?;
+        }
+        "toml" if config.toml => {
+            toml::from_str::<toml::Table>(content).map_err(|e| {
+                let (line, column) = e.span().map_or((0, 0), |span| line_col(content, span.start));
+                format!("TOML Syntax Error at line {}, column {}: {}", line, column, e.message())
+            })?;
+        }
+        "yaml" | "yml" if config.yaml => {
+            // A file may hold several `---` separated documents
+            for doc in serde_yaml::Deserializer::from_str(content) {
+                serde::Deserialize::deserialize(doc).map(|_: serde_yaml::Value| ()).map_err(|e| match e.location() {
+                    Some(loc) => format!("YAML Syntax Error at line {}, column {}: {}", loc.line(), loc.column(), e),
+                    None => format!("YAML Syntax Error: {}", e),
+                })?;
+            }
+        }
+        _ => {}
+    }
+    Ok(())
+}
+
+fn validate_python(content: &str) -> Result<(), String> {
+    let mut parser = tree_sitter::Parser::new();
+    parser.set_language(&tree_sitter_python::language()).map_err(|e| e.to_string())?;
+    let tree = parser.parse(content, None).ok_or("Python Syntax Error: could not parse")?;
+    match first_error(tree.root_node()) {
+        None => Ok(()),
+        Some(node) => {
+            let pos = node.start_position();
+            let what = if node.is_missing() {
+                format!("missing `{}`", node.kind())
+            } else {
+                let text = &content[node.byte_range()];
+                format!("unexpected `{}`", text.lines().next().unwrap_or("").trim())
+            };
+            Err(format!("Python Syntax Error at line {}, column {}: {}", pos.row + 1, pos.column + 1, what))
+        }
+    }
+}
+
+// The innermost first error or missing node, which points closest to the actual mistake
+fn first_error(node: tree_sitter::Node) -> Option<tree_sitter::Node> {
+    if !node.has_error() {
+        return None;
+    }
+    let mut cursor = node.walk();
+    let child_error = node.children(&mut cursor).find_map(|c| {
+        if c.is_missing() { Some(c) } else { first_error(c) }
+    });
+    child_error.or_else(|| node.is_error().then_some(node))
+}
+
+// 1-based line and column of a byte offset
+fn line_col(content: &str, offset: usize) -> (usize, usize) {
+    let before = &content[..offset.min(content.len())];
+    let line_start = before.rfind('\n').map_or(0, |i| i + 1);
+    (before.matches('\n').count() + 1, before[line_start..].chars().count() + 1)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_python_validation() {
+        assert!(validate_with(&ValidationConfig::ALL, "app.py", "def f(x):\n    return x\n").is_ok());
+        let err = validate_with(&ValidationConfig::ALL, "app.py", "def f(x):\n    return (x\n\nprint(1)\n").unwrap_err();
+        assert!(err.starts_with("Python Syntax Error at line "), "{}", err);
+    }
+
+    #[test]
+    fn test_data_format_validation() {
+        let all = ValidationConfig::ALL;
+        assert!(validate_with(&all, "package.json", "{\"name\": \"app\"}").is_ok());
+        let err = validate_with(&all, "package.json", "{\n  \"name\": \"app\",\n}").unwrap_err();
+        assert!(err.starts_with("JSON Syntax Error at line 3, column 1: trailing comma"), "{}", err);
+
+        assert!(validate_with(&all, "pyproject.toml", "[project]\nname = \"app\"\n").is_ok());
+        let err = validate_with(&all, "pyproject.toml", "[project]\nname = \"app\nversion = 1\n").unwrap_err();
+        assert!(err.starts_with("TOML Syntax Error at line 2, column "), "{}", err);
+
+        assert!(validate_with(&all, "ci.yml", "a: 1\n---\nb: [1, 2]\n").is_ok());
+        let err = validate_with(&all, "ci.yml", "a: 1\n---\nb: [1, 2\n").unwrap_err();
+        assert!(err.starts_with("YAML Syntax Error at line "), "{}", err);
+    }
+
+    #[test]
+    fn test_toggles() {
+        let no_json = ValidationConfig { json: false, ..ValidationConfig::ALL };
+        assert!(validate_with(&no_json, "broken.json", "{").is_ok());
+        assert!(validate_with(&no_json, "broken.toml", "[").is_err());
+    }
+}
@@ -0,0 +1,72 @@
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+
+use sha2::{Digest, Sha256};
+
+use crate::{resolve_path_safe, FsError};
+
+// Hashes of files as the agent last read or wrote them. If the bytes on disk no longer
+// match, someone else (usually the user's editor) changed the file in between, and a write
+// based on the agent's copy would clobber their change.
+
+/// Per-session map from file to the hash of the content the agent last saw.
+pub type FileVersions = Mutex<HashMap<PathBuf, String>>;
+
+// Canonical when the file exists, so `src/a.rs` and `./src/a.rs` share an entry
+fn version_key(root: &Path, file_path: &str) -> Result<PathBuf, FsError> {
+    let full_path = resolve_path_safe(root, file_path, false)?;
+    Ok(full_path.canonicalize().unwrap_or(full_path))
+}
+
+fn disk_hash(path: &Path) -> Option<String> {
+    std::fs::read(path).ok().map(|bytes| format!("{:x}", Sha256::digest(&bytes)))
+}
+
+/// Remembers the current on-disk content of `file_path` as the version the agent has seen.
+pub fn record_version(versions: &FileVersions, root: &Path, file_path: &str) {
+    let Ok(key) = version_key(root, file_path) else { return };
+    let mut versions = versions.lock().unwrap();
+    match disk_hash(&key) {
+        Some(hash) => versions.insert(key, hash),
+        None => versions.remove(&key),
+    };
+}
+
+/// Fails with `FsError::Conflict` if `file_path` changed on disk since it was last recorded.
+/// Files the agent never read, and files that have since been deleted, pass.
+pub fn check_version(versions: &FileVersions, root: &Path, file_path: &str) -> Result<(), FsError> {
+    let key = version_key(root, file_path)?;
+    let Some(expected) = versions.lock().unwrap().get(&key).cloned() else { return Ok(()) };
+    match disk_hash(&key) {
+        Some(actual) if actual != expected => Err(FsError::Conflict(file_path.to_string())),
+        _ => Ok(()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::tempdir;
+
+    #[test]
+    fn test_conflict_after_external_edit() {
+        let dir = tempdir().unwrap();
+        let root = dir.path();
+        let versions = FileVersions::default();
+        std::fs::write(root.join("a.rs"), "fn a() {}\n").unwrap();
+
+        // Never read: writes go through
+        assert!(check_version(&versions, root, "a.rs").is_ok());
+
+        record_version(&versions, root, "./a.rs");
+        assert!(check_version(&versions, root, "a.rs").is_ok());
+
+        std::fs::write(root.join("a.rs"), "fn a() { user_edit(); }\n").unwrap();
+        assert!(matches!(check_version(&versions, root, "a.rs"), Err(FsError::Conflict(p)) if p == "a.rs"));
+
+        // Re-reading picks up the user's version
+        record_version(&versions, root, "a.rs");
+        assert!(check_version(&versions, root, "a.rs").is_ok());
+    }
+}
@@ -0,0 +1,196 @@
+use std::collections::BTreeSet;
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::{self, Receiver, RecvTimeoutError, Sender};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use notify::{EventKind, RecommendedWatcher, RecursiveMode, Watcher};
+
+use crate::ignores::{IgnoreRules, IGNORE_FILE};
+use crate::FsError;
+
+// Recursive watcher on the workspace root. Raw notify events are coalesced over a short
+// window and delivered as one `FsChange` with paths relative to the root, so a save that
+// touches a file several times (truncate, write, rename) shows up once.
+
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct FsChange {
+    pub created: Vec<PathBuf>,
+    pub modified: Vec<PathBuf>,
+    pub removed: Vec<PathBuf>,
+}
+
+impl FsChange {
+    pub fn is_empty(&self) -> bool {
+        self.created.is_empty() && self.modified.is_empty() && self.removed.is_empty()
+    }
+
+    /// Every path touched, in no particular order.
+    pub fn paths(&self) -> impl Iterator<Item = &PathBuf> {
+        self.created.iter().chain(&self.modified).chain(&self.removed)
+    }
+}
+
+#[derive(Default)]
+struct Pending {
+    created: BTreeSet<PathBuf>,
+    modified: BTreeSet<PathBuf>,
+    removed: BTreeSet<PathBuf>,
+}
+
+impl Pending {
+    fn record(&mut self, kind: &EventKind, path: PathBuf) {
+        match kind {
+            EventKind::Create(_) => {
+                self.removed.remove(&path);
+                self.created.insert(path);
+            }
+            EventKind::Remove(_) => {
+                // Created and deleted within one window: nothing to report
+                if !self.created.remove(&path) {
+                    self.removed.insert(path.clone());
+                }
+                self.modified.remove(&path);
+            }
+            EventKind::Modify(_) => {
+                if !self.created.contains(&path) {
+                    self.modified.insert(path);
+                }
+            }
+            _ => {}
+        }
+    }
+
+    fn take(&mut self) -> FsChange {
+        let pending = std::mem::take(self);
+        FsChange {
+            created: pending.created.into_iter().collect(),
+            modified: pending.modified.into_iter().collect(),
+            removed: pending.removed.into_iter().collect(),
+        }
+    }
+}
+
+// Paths the file tree hides are not reported either
+fn relative(root: &Path, rules: &IgnoreRules, path: &Path) -> Option<PathBuf> {
+    let rel = path.strip_prefix(root).ok()?;
+    let ignored = rules.is_ignored(rel, path.is_dir());
+    if ignored || rel.as_os_str().is_empty() { None } else { Some(rel.to_path_buf()) }
+}
+
+type Subscribers = Arc<Mutex<Vec<Sender<FsChange>>>>;
+
+/// Watches a workspace until dropped.
+pub struct WorkspaceWatcher {
+    root: PathBuf,
+    subscribers: Subscribers,
+    _watcher: RecommendedWatcher,
+}
+
+impl WorkspaceWatcher {
+    pub const DEFAULT_DEBOUNCE: Duration = Duration::from_millis(200);
+
+    /// Starts watching `root`. `on_change` runs on the watcher thread for every batch.
+    pub fn start(root: &Path, debounce: Duration, on_change: impl Fn(&FsChange) + Send + 'static) -> Result<Self, FsError> {
+        let root = root.canonicalize().map_err(FsError::Io)?;
+        let (tx, rx) = mpsc::channel::<notify::Result<notify::Event>>();
+        let mut watcher = notify::recommended_watcher(tx)
+            .map_err(|e| FsError::Io(std::io::Error::other(e)))?;
+        watcher.watch(&root, RecursiveMode::Recursive)
+            .map_err(|e| FsError::Io(std::io::Error::other(e)))?;
+
+        let subscribers: Subscribers = Arc::new(Mutex::new(Vec::new()));
+        let thread_root = root.clone();
+        let thread_subscribers = subscribers.clone();
+        std::thread::spawn(move || {
+            debounce_loop(rx, &thread_root, debounce, |change| {
+                on_change(&change);
+                // Drop subscribers whose receiver is gone
+                thread_subscribers.lock().unwrap().retain(|s| s.send(change.clone()).is_ok());
+            });
+        });
+
+        Ok(Self { root, subscribers, _watcher: watcher })
+    }
+
+    pub fn root(&self) -> &Path {
+        &self.root
+    }
+
+    /// A channel receiving every subsequent batch, e.g. for an agent session that wants to
+    /// know about edits made outside of it.
+    pub fn subscribe(&self) -> Receiver<FsChange> {
+        let (tx, rx) = mpsc::channel();
+        self.subscribers.lock().unwrap().push(tx);
+        rx
+    }
+}
+
+// Runs until the watcher (the sending side) is dropped
+fn debounce_loop(rx: Receiver<notify::Result<notify::Event>>, root: &Path, debounce: Duration, emit: impl Fn(FsChange)) {
+    let mut pending = Pending::default();
+    let mut deadline: Option<Instant> = None;
+    let mut rules = IgnoreRules::load(root);
+    loop {
+        let received = match deadline {
+            Some(at) => rx.recv_timeout(at.saturating_duration_since(Instant::now())),
+            None => rx.recv().map_err(|_| RecvTimeoutError::Disconnected),
+        };
+        match received {
+            Ok(Ok(event)) => {
+                for path in &event.paths {
+                    if path.strip_prefix(root).map_or(false, |rel| rel == Path::new(IGNORE_FILE)) {
+                        rules = IgnoreRules::load(root);
+                    }
+                    if let Some(rel) = relative(root, &rules, path) {
+                        pending.record(&event.kind, rel);
+                    }
+                }
+                deadline.get_or_insert_with(|| Instant::now() + debounce);
+            }
+            // Watcher errors (e.g. a directory vanished mid-scan) only cost that event
+            Ok(Err(_)) => {}
+            Err(RecvTimeoutError::Timeout) => {
+                deadline = None;
+                let change = pending.take();
+                if !change.is_empty() {
+                    emit(change);
+                }
+            }
+            Err(RecvTimeoutError::Disconnected) => return,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use notify::event::{CreateKind, ModifyKind, RemoveKind};
+
+    #[test]
+    fn test_pending_coalesces_events() {
+        let mut pending = Pending::default();
+        pending.record(&EventKind::Create(CreateKind::File), PathBuf::from("new.rs"));
+        pending.record(&EventKind::Modify(ModifyKind::Any), PathBuf::from("new.rs"));
+        pending.record(&EventKind::Modify(ModifyKind::Any), PathBuf::from("lib.rs"));
+        pending.record(&EventKind::Create(CreateKind::File), PathBuf::from("tmp.swp"));
+        pending.record(&EventKind::Remove(RemoveKind::File), PathBuf::from("tmp.swp"));
+        pending.record(&EventKind::Remove(RemoveKind::File), PathBuf::from("old.rs"));
+
+        assert_eq!(pending.take(), FsChange {
+            created: vec![PathBuf::from("new.rs")],
+            modified: vec![PathBuf::from("lib.rs")],
+            removed: vec![PathBuf::from("old.rs")],
+        });
+        assert!(pending.take().is_empty());
+    }
+
+    #[test]
+    fn test_relative_skips_ignored_dirs() {
+        let root = Path::new("/ws");
+        let rules = IgnoreRules::load(root);
+        assert_eq!(relative(root, &rules, Path::new("/ws/src/main.rs")), Some(PathBuf::from("src/main.rs")));
+        assert_eq!(relative(root, &rules, Path::new("/ws/target/debug/app")), None);
+        assert_eq!(relative(root, &rules, Path::new("/ws/.git/index")), None);
+        assert_eq!(relative(root, &rules, Path::new("/elsewhere/x")), None);
+    }
+}